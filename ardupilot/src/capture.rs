@@ -0,0 +1,120 @@
+//! Length-prefixed capture format for raw MAVLink frames.
+//!
+//! Each record is the capture time in microseconds since the Unix epoch (`u64`, little-endian),
+//! the length of the frame in bytes (`u32`, little-endian), and then the frame exactly as it is
+//! sent on the wire.
+
+use crate::codec::MavlinkCodec;
+use bytes::BytesMut;
+use mavio::protocol::MaybeVersioned;
+use mavio::Frame;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// The largest MAVLink frame: a V2 frame with a full payload and a signature
+const MAX_FRAME_LEN: u32 = 280;
+
+pub async fn write_frame_record<V: MaybeVersioned, W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    timestamp: SystemTime,
+    frame: Frame<V>,
+) -> anyhow::Result<()> {
+    let mut frame_bytes = BytesMut::new();
+    MavlinkCodec::<V>::new().encode(frame, &mut frame_bytes)?;
+
+    let timestamp_us = u64::try_from(timestamp.duration_since(UNIX_EPOCH)?.as_micros())?;
+    writer.write_u64_le(timestamp_us).await?;
+    writer
+        .write_u32_le(u32::try_from(frame_bytes.len())?)
+        .await?;
+    writer.write_all(&frame_bytes).await?;
+
+    Ok(())
+}
+
+/// Reads the next record, or `None` at the end of the capture.
+pub async fn read_frame_record<V: MaybeVersioned, R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> anyhow::Result<Option<(SystemTime, Frame<V>)>> {
+    let timestamp_us = match reader.read_u64_le().await {
+        Ok(timestamp_us) => timestamp_us,
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let length = reader.read_u32_le().await?;
+    if length > MAX_FRAME_LEN {
+        anyhow::bail!("frame of {length} bytes in capture exceeds the maximum of {MAX_FRAME_LEN}");
+    }
+    let mut frame_bytes = BytesMut::zeroed(usize::try_from(length)?);
+    reader.read_exact(&mut frame_bytes).await?;
+
+    let frame = MavlinkCodec::<V>::new()
+        .decode(&mut frame_bytes)?
+        .ok_or_else(|| anyhow::format_err!("truncated frame in capture"))?;
+
+    Ok(Some((
+        UNIX_EPOCH + Duration::from_micros(timestamp_us),
+        frame,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mavio::dialects::common::messages::Heartbeat;
+    use mavio::protocol::{Versioned, Versionless, V1, V2};
+
+    fn heartbeat<V: Versioned>(sequence: u8) -> Frame<Versionless> {
+        Frame::builder()
+            .version(V::v())
+            .message(&Heartbeat::default())
+            .unwrap()
+            .sequence(sequence)
+            .system_id(1)
+            .component_id(1)
+            .build()
+            .into_versionless()
+    }
+
+    #[tokio::test]
+    async fn frame_records_round_trip() {
+        let timestamp = UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456);
+        let frames = [heartbeat::<V1>(1), heartbeat::<V2>(2)];
+
+        let mut capture = vec![];
+        for frame in frames.iter().cloned() {
+            write_frame_record(&mut capture, timestamp, frame)
+                .await
+                .unwrap();
+        }
+
+        let mut reader = capture.as_slice();
+        for frame in frames {
+            let (read_timestamp, read_frame) = read_frame_record::<Versionless, _>(&mut reader)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(read_timestamp, timestamp);
+            assert_eq!(read_frame.version(), frame.version());
+            assert_eq!(read_frame.sequence(), frame.sequence());
+            assert_eq!(read_frame.payload().bytes(), frame.payload().bytes());
+        }
+        assert!(read_frame_record::<Versionless, _>(&mut reader)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn oversized_frame_records_are_rejected() {
+        let mut capture = vec![];
+        capture.extend_from_slice(&0u64.to_le_bytes());
+        capture.extend_from_slice(&u32::MAX.to_le_bytes());
+
+        let mut reader = capture.as_slice();
+        assert!(read_frame_record::<Versionless, _>(&mut reader)
+            .await
+            .is_err());
+    }
+}
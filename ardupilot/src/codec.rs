@@ -1,21 +1,38 @@
 use bytes::{Buf, BufMut, BytesMut};
+use mavio::dialects::Ardupilotmega;
 use mavio::protocol::{MavLinkVersion, MavSTX, MaybeVersioned};
-use mavio::{Frame, Receiver, Sender};
+use mavio::{Dialect, Frame, Receiver, Sender};
 use std::io::Cursor;
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use tokio_util::codec::{Decoder, Encoder};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct MavlinkCodec<V: MaybeVersioned> {
+    /// Frames that could not be decoded and were skipped, shared between clones of the codec
+    decode_errors: Arc<AtomicU64>,
     phantom_data: PhantomData<V>,
 }
 
 impl<V: MaybeVersioned> MavlinkCodec<V> {
     pub fn new() -> MavlinkCodec<V> {
         MavlinkCodec {
+            decode_errors: Default::default(),
             phantom_data: PhantomData,
         }
     }
+
+    /// Number of frames that could not be decoded and were skipped.
+    pub fn decode_errors(&self) -> u64 {
+        self.decode_errors.load(Ordering::Relaxed)
+    }
+}
+
+impl<V: MaybeVersioned> Default for MavlinkCodec<V> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 fn find_frame_start<V: MaybeVersioned>(src: &BytesMut) -> Option<(usize, MavLinkVersion)> {
@@ -28,35 +45,61 @@ fn find_frame_start<V: MaybeVersioned>(src: &BytesMut) -> Option<(usize, MavLink
     None
 }
 
+/// Frames of messages that the ArduPilot dialect doesn't define can't be checked, and are
+/// accepted.
+fn has_valid_checksum<V: MaybeVersioned>(frame: &Frame<V>) -> bool {
+    match Ardupilotmega::message_info(frame.message_id()) {
+        Ok(message_info) => frame
+            .validate_checksum_with_crc_extra(message_info.crc_extra())
+            .is_ok(),
+        Err(_) => true,
+    }
+}
+
 impl<V: MaybeVersioned> Decoder for MavlinkCodec<V> {
     type Item = mavio::Frame<V>;
     // FIXME: change error type
     type Error = std::io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let Some((frame_start, _mavlink_version)) = find_frame_start::<V>(src) else {
-            return Ok(None);
-        };
-        if frame_start > 0 {
-            src.advance(frame_start);
-        }
-        let cursor = Cursor::new(&*src);
-        let mut receiver = Receiver::new::<V>(cursor);
-        match receiver.recv() {
-            Ok(frame) => {
-                let header = frame.header();
-                src.advance(header.size() + header.body_length());
-                Ok(Some(frame))
+        loop {
+            let Some((frame_start, _mavlink_version)) = find_frame_start::<V>(src) else {
+                // None of the buffered bytes can start a frame
+                src.clear();
+                return Ok(None);
+            };
+            if frame_start > 0 {
+                src.advance(frame_start);
             }
-            Err(mavio::error::Error::Io(io_error))
-                if io_error.kind() == std::io::ErrorKind::UnexpectedEof =>
-            {
-                Ok(None)
+            let cursor = Cursor::new(&*src);
+            let mut receiver = Receiver::new::<V>(cursor);
+            match receiver.recv() {
+                Ok(frame) if has_valid_checksum(&frame) => {
+                    let header = frame.header();
+                    src.advance(header.size() + header.body_length());
+                    return Ok(Some(frame));
+                }
+                Ok(frame) => {
+                    self.decode_errors.fetch_add(1, Ordering::Relaxed);
+                    tracing::debug!(
+                        message_id = frame.message_id(),
+                        "Skipping MAVLink frame with an invalid checksum"
+                    );
+                    // The start byte may have been payload rather than the start of a frame
+                    src.advance(1);
+                }
+                Err(mavio::error::Error::Io(io_error))
+                    if io_error.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    return Ok(None);
+                }
+                Err(error) => {
+                    self.decode_errors.fetch_add(1, Ordering::Relaxed);
+                    tracing::debug!(%error, "Skipping invalid MAVLink frame");
+                    // Resynchronise on the next magic byte, which may start a valid frame
+                    src.advance(1);
+                }
             }
-            Err(error) => Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                error.to_string(),
-            )),
         }
     }
 }
@@ -77,3 +120,73 @@ impl<V: MaybeVersioned> Encoder<Frame<V>> for MavlinkCodec<V> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mavio::dialects::common::messages::Heartbeat;
+    use mavio::protocol::{Versioned, Versionless, V1, V2};
+
+    fn encoded_heartbeat<V: Versioned>() -> BytesMut {
+        let frame = Frame::builder()
+            .version(V::v())
+            .message(&Heartbeat::default())
+            .unwrap()
+            .sequence(7)
+            .system_id(1)
+            .component_id(1)
+            .build();
+        let mut buf = BytesMut::new();
+        MavlinkCodec::<V>::new().encode(frame, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn versionless_codec_decodes_v1_and_v2_frames() {
+        let mut src = BytesMut::new();
+        src.extend_from_slice(&encoded_heartbeat::<V1>());
+        src.extend_from_slice(&encoded_heartbeat::<V2>());
+
+        let mut codec = MavlinkCodec::<Versionless>::new();
+        let v1_frame = codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(v1_frame.version(), MavLinkVersion::V1);
+        let v2_frame = codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(v2_frame.version(), MavLinkVersion::V2);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn bytes_that_cannot_start_a_frame_are_skipped() {
+        let mut src = BytesMut::new();
+        src.extend_from_slice(b"noise");
+        src.extend_from_slice(&encoded_heartbeat::<V2>());
+        src.extend_from_slice(b"more noise");
+        src.extend_from_slice(&encoded_heartbeat::<V2>());
+
+        let mut codec = MavlinkCodec::<V2>::new();
+        for _ in 0..2 {
+            assert_eq!(codec.decode(&mut src).unwrap().unwrap().sequence(), 7);
+        }
+        assert!(src.is_empty());
+
+        src.extend_from_slice(b"trailing noise");
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        assert!(src.is_empty());
+        assert_eq!(codec.decode_errors(), 0);
+    }
+
+    #[test]
+    fn corrupt_frames_are_counted_and_skipped() {
+        let mut corrupt_frame = encoded_heartbeat::<V2>();
+        let checksum_idx = corrupt_frame.len() - 1;
+        corrupt_frame[checksum_idx] ^= 0xff;
+        let mut src = BytesMut::new();
+        src.extend_from_slice(&corrupt_frame);
+        src.extend_from_slice(&encoded_heartbeat::<V2>());
+
+        let mut codec = MavlinkCodec::<V2>::new();
+        assert_eq!(codec.decode(&mut src).unwrap().unwrap().sequence(), 7);
+        assert!(src.is_empty());
+        assert_eq!(codec.decode_errors(), 1);
+    }
+}
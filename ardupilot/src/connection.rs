@@ -1,25 +1,45 @@
+use crate::capture;
 use crate::codec::MavlinkCodec;
+use crate::signing::{FrameSigner, SignatureVerifier};
 use futures::SinkExt;
 use mavio::prelude::MaybeVersioned;
-use mavio::protocol::{ComponentId, Sequencer, SystemId, Versioned};
+use mavio::protocol::{
+    ComponentId, MavLinkVersion, MessageId, Sequencer, SystemId, Versioned, Versionless, V1, V2,
+};
 use mavio::{Dialect, Frame, Message};
 use mavspec_rust_spec::MessageSpecStatic;
+use parking_lot::{Mutex, RwLock};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
 use std::net::SocketAddr;
-use tokio::io::{AsyncRead, AsyncWrite};
+use std::num::ParseIntError;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use thiserror::Error;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::broadcast::Sender;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
 use tokio_stream::wrappers::BroadcastStream;
 use tokio_stream::{Stream, StreamExt};
 use tokio_util::codec::{FramedRead, FramedWrite};
 use tracing::log;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
-enum ConnectionId {
+pub enum ConnectionId {
     Tcp {
         local_addr: SocketAddr,
         peer_addr: SocketAddr,
     },
     Local,
+    /// Frames forwarded from another network by the bridge with this ID
+    Bridge(u64),
 }
 
 impl ConnectionId {
@@ -45,9 +65,86 @@ pub struct RoutableFrame<V: MaybeVersioned> {
     destination: MavlinkDestination,
 }
 
+/// Per-message-type counters for frames received from connections
+#[derive(Clone, Debug, Default)]
+pub struct MessageStatistics {
+    pub counts: HashMap<MessageId, u64>,
+    pub bytes: HashMap<MessageId, u64>,
+    pub last_seen: HashMap<MessageId, Instant>,
+}
+
+fn frame_size<V: MaybeVersioned>(frame: &Frame<V>) -> u64 {
+    let header = frame.header();
+    (header.size() + header.body_length()) as u64
+}
+
+impl MessageStatistics {
+    fn record<V: MaybeVersioned>(&mut self, frame: &Frame<V>) {
+        let message_id = frame.message_id();
+
+        *self.counts.entry(message_id).or_default() += 1;
+        *self.bytes.entry(message_id).or_default() += frame_size(frame);
+        self.last_seen.insert(message_id, Instant::now());
+    }
+}
+
+/// Totals across every connection of a network
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct NetworkStats {
+    pub frames_received: u64,
+    pub frames_sent: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    /// Frames skipped by connections and subscribers that fell behind the network
+    pub frames_dropped: u64,
+}
+
+#[derive(Debug, Default)]
+struct NetworkCounters {
+    frames_received: AtomicU64,
+    frames_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    frames_dropped: AtomicU64,
+}
+
+impl NetworkCounters {
+    fn record_received<V: MaybeVersioned>(&self, frame: &Frame<V>) {
+        self.frames_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(frame_size(frame), Ordering::Relaxed);
+    }
+
+    fn record_sent<V: MaybeVersioned>(&self, frame: &Frame<V>) {
+        self.frames_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent
+            .fetch_add(frame_size(frame), Ordering::Relaxed);
+    }
+
+    fn record_dropped(&self, frames: u64) {
+        self.frames_dropped.fetch_add(frames, Ordering::Relaxed);
+    }
+}
+
+/// A peer's last-seen time is only refreshed once this long has passed, so that most frames only
+/// need to take the read lock
+const PEER_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// Peers not heard from for this long are forgotten
+const PEER_EXPIRY: Duration = Duration::from_secs(60);
+
+/// When each node was last heard from
+pub type PeerRegistry = Arc<RwLock<HashMap<NodeId, Instant>>>;
+
 #[derive(Clone, Debug)]
 pub struct Network<V: MaybeVersioned> {
     tx: Sender<RoutableFrame<V>>,
+    message_statistics: Arc<Mutex<MessageStatistics>>,
+    /// The connection each node was last heard from
+    routing_table: Arc<RwLock<HashMap<NodeId, ConnectionId>>>,
+    peers: PeerRegistry,
+    counters: Arc<NetworkCounters>,
+    /// Checks the signatures of received frames. Every frame is accepted if `None`.
+    signature_verifier: Option<Arc<SignatureVerifier>>,
 }
 
 impl<V: MaybeVersioned> Network<V> {
@@ -58,7 +155,77 @@ impl<V: MaybeVersioned> Network<V> {
 
     #[inline(always)]
     pub fn create(tx: Sender<RoutableFrame<V>>) -> Network<V> {
-        Network { tx }
+        Network {
+            tx,
+            message_statistics: Arc::new(Mutex::new(MessageStatistics::default())),
+            routing_table: Default::default(),
+            peers: Default::default(),
+            counters: Default::default(),
+            signature_verifier: None,
+        }
+    }
+
+    /// Drops the frames received from connections that `signature_verifier` rejects.
+    pub fn with_signature_verifier(self, signature_verifier: SignatureVerifier) -> Network<V> {
+        Network {
+            signature_verifier: Some(Arc::new(signature_verifier)),
+            ..self
+        }
+    }
+
+    pub fn stats(&self) -> NetworkStats {
+        NetworkStats {
+            frames_received: self.counters.frames_received.load(Ordering::Relaxed),
+            frames_sent: self.counters.frames_sent.load(Ordering::Relaxed),
+            bytes_received: self.counters.bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.counters.bytes_sent.load(Ordering::Relaxed),
+            frames_dropped: self.counters.frames_dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn message_statistics(&self) -> Arc<Mutex<MessageStatistics>> {
+        self.message_statistics.clone()
+    }
+
+    pub fn routing_table(&self) -> Arc<RwLock<HashMap<NodeId, ConnectionId>>> {
+        self.routing_table.clone()
+    }
+
+    /// Every node heard from within the last minute, with the time it was last heard from, to
+    /// within a second.
+    pub fn peer_list(&self) -> Vec<(NodeId, Instant)> {
+        let now = Instant::now();
+        self.peers
+            .read()
+            .iter()
+            .filter(|(_, last_seen)| now.saturating_duration_since(**last_seen) < PEER_EXPIRY)
+            .map(|(node_id, last_seen)| (*node_id, *last_seen))
+            .collect()
+    }
+
+    fn record_peer(&self, node_id: NodeId, now: Instant) {
+        let is_fresh = self.peers.read().get(&node_id).is_some_and(|last_seen| {
+            now.saturating_duration_since(*last_seen) < PEER_REFRESH_INTERVAL
+        });
+        if is_fresh {
+            return;
+        }
+
+        let mut peers = self.peers.write();
+        if !peers.contains_key(&node_id) {
+            peers.retain(|_, last_seen| now.saturating_duration_since(*last_seen) < PEER_EXPIRY);
+        }
+        peers.insert(node_id, now);
+    }
+
+    /// Routes frames for `target` only to the connection it was last heard from, if known.
+    fn destination_for(&self, target: Option<NodeId>) -> MavlinkDestination {
+        target
+            .and_then(|target| self.routing_table.read().get(&target).copied())
+            .map_or(
+                MavlinkDestination::All,
+                MavlinkDestination::OnlyConnectionId,
+            )
     }
 
     pub async fn accept_loop(self, listener: TcpListener) -> anyhow::Result<()> {
@@ -71,6 +238,22 @@ impl<V: MaybeVersioned> Network<V> {
         }
     }
 
+    /// Accepts connections on every listener, each in its own task. Completes when any listener
+    /// fails, at which point the others stop accepting connections.
+    pub fn accept_multiple(self, listeners: Vec<TcpListener>) -> JoinHandle<anyhow::Result<()>> {
+        let mut accept_loops = JoinSet::new();
+        for listener in listeners {
+            accept_loops.spawn(self.clone().accept_loop(listener));
+        }
+
+        tokio::spawn(async move {
+            while let Some(joined) = accept_loops.join_next().await {
+                joined??;
+            }
+            Ok(())
+        })
+    }
+
     pub async fn process_tcp(self, mut tcp_stream: TcpStream) -> anyhow::Result<()> {
         let connection_id = ConnectionId::create(&tcp_stream)?;
         let (read, write) = tcp_stream.split();
@@ -84,8 +267,15 @@ impl<V: MaybeVersioned> Network<V> {
         &self,
     ) -> impl Stream<Item = (NodeId, MessageT)> {
         let rx = self.tx.subscribe();
+        let counters = self.counters.clone();
         BroadcastStream::new(rx).filter_map(move |frame_result| {
-            let routable_frame = frame_result.ok()?;
+            let routable_frame = match frame_result {
+                Ok(routable_frame) => routable_frame,
+                Err(BroadcastStreamRecvError::Lagged(frames)) => {
+                    counters.record_dropped(frames);
+                    return None;
+                }
+            };
             let frame = routable_frame.frame;
             let origin_node_id = NodeId {
                 system_id: frame.system_id(),
@@ -115,6 +305,56 @@ impl<V: MaybeVersioned> Network<V> {
         }
     }
 
+    /// Appends every frame seen on the network to `path`, in the format described in `capture`.
+    pub async fn log_frames_to_file(self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut rx = self.tx.subscribe();
+        let mut writer = BufWriter::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?,
+        );
+        loop {
+            let routable_frame = match rx.recv().await {
+                Ok(routable_frame) => routable_frame,
+                Err(RecvError::Lagged(frames)) => {
+                    log::warn!("Dropped {frames} frames the capture could not keep up with");
+                    self.counters.record_dropped(frames);
+                    continue;
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            };
+            capture::write_frame_record(&mut writer, SystemTime::now(), routable_frame.frame)
+                .await?;
+            // Flush each frame so that the capture is complete if the process is killed
+            writer.flush().await?;
+        }
+    }
+
+    /// Publishes the frames captured in `path` to the network, preserving the time between them.
+    pub async fn replay_frames_from_file(self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut reader = BufReader::new(File::open(path).await?);
+        let replay_start = tokio::time::Instant::now();
+        let mut capture_start = None;
+
+        while let Some((timestamp, frame)) = capture::read_frame_record(&mut reader).await? {
+            let capture_start = *capture_start.get_or_insert(timestamp);
+            let offset = timestamp
+                .duration_since(capture_start)
+                .unwrap_or(Duration::ZERO);
+            tokio::time::sleep_until(replay_start + offset).await;
+
+            self.tx.send(RoutableFrame {
+                frame,
+                origin: ConnectionId::Local,
+                destination: MavlinkDestination::All,
+            })?;
+        }
+
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self, read, write))]
     async fn process<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
         self,
@@ -137,6 +377,25 @@ impl<V: MaybeVersioned> Network<V> {
                         return Ok(());
                     };
                     let frame = frame_result?;
+                    self.message_statistics.lock().record(&frame);
+                    self.counters.record_received(&frame);
+                    if let Some(signature_verifier) = &self.signature_verifier {
+                        if !signature_verifier.accepts(&frame) {
+                            log::warn!(
+                                "Dropped frame from system {} with a missing or invalid signature",
+                                frame.system_id()
+                            );
+                            continue;
+                        }
+                    }
+                    let origin_node_id = NodeId {
+                        system_id: frame.system_id(),
+                        component_id: frame.component_id(),
+                    };
+                    self.record_peer(origin_node_id, Instant::now());
+                    if self.routing_table.read().get(&origin_node_id) != Some(&connection_id) {
+                        self.routing_table.write().insert(origin_node_id, connection_id);
+                    }
 
                     let routable_frame = RoutableFrame {
                         frame, origin: connection_id, destination: MavlinkDestination::NotConnectionId(connection_id)
@@ -145,8 +404,14 @@ impl<V: MaybeVersioned> Network<V> {
                     self.tx.send(routable_frame)?;
                 }
                 channel_result = channel_rx.recv() => {
-                    let Ok(routable_frame) = channel_result else {
-                        return Ok(())
+                    let routable_frame = match channel_result {
+                        Ok(routable_frame) => routable_frame,
+                        Err(RecvError::Lagged(frames)) => {
+                            log::warn!("Dropped {frames} frames the connection could not keep up with");
+                            self.counters.record_dropped(frames);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => return Ok(()),
                     };
                     match routable_frame.destination {
                         MavlinkDestination::All => {},
@@ -161,6 +426,7 @@ impl<V: MaybeVersioned> Network<V> {
                             }
                         }}
 
+                    self.counters.record_sent(&routable_frame.frame);
                     framed_writer.send(routable_frame.frame).await?;
                 }
             }
@@ -168,29 +434,202 @@ impl<V: MaybeVersioned> Network<V> {
     }
 }
 
+/// Selects frames by message ID
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MessageIdFilter {
+    /// Only the frames of these messages
+    Allow(HashSet<MessageId>),
+    /// Every frame except those of these messages
+    Deny(HashSet<MessageId>),
+}
+
+impl MessageIdFilter {
+    pub fn accepts(&self, message_id: MessageId) -> bool {
+        match self {
+            MessageIdFilter::Allow(message_ids) => message_ids.contains(&message_id),
+            MessageIdFilter::Deny(message_ids) => !message_ids.contains(&message_id),
+        }
+    }
+}
+
+static NEXT_BRIDGE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Republishes the frames of each network on the other, to all of its connections, optionally
+/// only those `filter` accepts. Frames routed to a single connection stay on their network.
+///
+/// Bridges may be chained, but a cycle of bridges forwards frames forever.
+pub async fn bridge<V: MaybeVersioned>(
+    network_a: Network<V>,
+    network_b: Network<V>,
+    filter: Option<MessageIdFilter>,
+) -> anyhow::Result<()> {
+    let bridge_id = ConnectionId::Bridge(NEXT_BRIDGE_ID.fetch_add(1, Ordering::Relaxed));
+
+    tokio::try_join!(
+        forward_frames(&network_a, &network_b, bridge_id, filter.as_ref()),
+        forward_frames(&network_b, &network_a, bridge_id, filter.as_ref()),
+    )?;
+    Ok(())
+}
+
+async fn forward_frames<V: MaybeVersioned>(
+    from: &Network<V>,
+    to: &Network<V>,
+    bridge_id: ConnectionId,
+    filter: Option<&MessageIdFilter>,
+) -> anyhow::Result<()> {
+    let mut rx = from.tx.subscribe();
+    loop {
+        let routable_frame = match rx.recv().await {
+            Ok(routable_frame) => routable_frame,
+            Err(RecvError::Lagged(frames)) => {
+                log::warn!("Dropped {frames} frames the bridge could not keep up with");
+                from.counters.record_dropped(frames);
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+        // Frames this bridge published are not sent back where they came from
+        if routable_frame.origin == bridge_id
+            || matches!(
+                routable_frame.destination,
+                MavlinkDestination::OnlyConnectionId(_)
+            )
+        {
+            continue;
+        }
+        if filter.is_some_and(|filter| !filter.accepts(routable_frame.frame.message_id())) {
+            continue;
+        }
+
+        to.tx.send(RoutableFrame {
+            frame: routable_frame.frame,
+            origin: bridge_id,
+            destination: MavlinkDestination::All,
+        })?;
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct NodeId {
     pub system_id: SystemId,
     pub component_id: ComponentId,
 }
 
+/// Formats as `{system_id}:{component_id}`, the format parsed by `NodeId::from_str`.
+impl Display for NodeId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.system_id, self.component_id)
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ParseNodeIdError {
+    #[error("expected `<system_id>:<component_id>`, got `{0}`")]
+    MissingSeparator(String),
+    #[error("invalid {field} `{value}`: {source}")]
+    InvalidId {
+        field: &'static str,
+        value: String,
+        source: ParseIntError,
+    },
+}
+
+impl FromStr for NodeId {
+    type Err = ParseNodeIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (system_id, component_id) = s
+            .split_once(':')
+            .ok_or_else(|| ParseNodeIdError::MissingSeparator(s.to_string()))?;
+        let parse_id = |field, value: &str| {
+            value.parse().map_err(|source| ParseNodeIdError::InvalidId {
+                field,
+                value: value.to_string(),
+                source,
+            })
+        };
+
+        Ok(NodeId {
+            system_id: parse_id("system ID", system_id)?,
+            component_id: parse_id("component ID", component_id)?,
+        })
+    }
+}
+
 pub type MessageFromNode<M> = (NodeId, M);
 
-pub struct Client<V: Versioned> {
+pub struct Client<V: MaybeVersioned> {
     network: Network<V>,
     pub node_id: NodeId,
     sequencer: Sequencer,
+    /// The protocol version of frames sent by this client
+    version: MavLinkVersion,
+    /// Signs the MAVLink 2 frames sent by this client, if set
+    signer: Option<FrameSigner>,
 }
 
 impl<V: Versioned> Client<V> {
     pub fn create(mavlink_network: Network<V>, node_id: NodeId) -> Client<V> {
+        Self::create_with_version(mavlink_network, node_id, V::version())
+    }
+}
+
+impl<V: MaybeVersioned> Client<V> {
+    /// Creates a client that sends frames of `version`, which must be supported by the network.
+    pub fn create_with_version(
+        mavlink_network: Network<V>,
+        node_id: NodeId,
+        version: MavLinkVersion,
+    ) -> Client<V> {
         Client {
             network: mavlink_network,
             node_id,
             sequencer: Sequencer::new(),
+            version,
+            signer: None,
         }
     }
 
+    /// Signs the MAVLink 2 frames sent by this client with `secret_key`.
+    pub fn with_signing(self, link_id: u8, secret_key: [u8; 32]) -> Self {
+        Client {
+            signer: Some(FrameSigner::new(link_id, secret_key)),
+            ..self
+        }
+    }
+
+    pub fn network(&self) -> &Network<V> {
+        &self.network
+    }
+
+    /// Like `Network::subscribe`, but only yields the messages sent by `node_id`.
+    pub async fn subscribe_from_node<
+        MessageT: MessageSpecStatic + for<'a> TryFrom<&'a mavspec_rust_spec::Payload>,
+    >(
+        &self,
+        node_id: NodeId,
+    ) -> impl Stream<Item = MessageT> {
+        self.network
+            .subscribe::<MessageT>()
+            .await
+            .filter_map(move |(origin, message)| (origin == node_id).then_some(message))
+    }
+
+    fn build_frame<W: Versioned, RequestT: Message>(
+        &mut self,
+        request: &RequestT,
+    ) -> anyhow::Result<Frame<Versionless>> {
+        Ok(Frame::builder()
+            .version(W::v())
+            .message(request)?
+            .sequence(self.sequencer.next())
+            .system_id(self.node_id.system_id)
+            .component_id(self.node_id.component_id)
+            .build()
+            .into_versionless())
+    }
+
     pub fn response_type_message_extractor<
         ResponseT: MessageSpecStatic + for<'a> TryFrom<&'a mavspec_rust_spec::Payload> + std::fmt::Debug,
     >() -> impl Fn(&Frame<V>) -> Option<ResponseT> {
@@ -223,22 +662,79 @@ impl<V: Versioned> Client<V> {
         request: RequestT,
         response_extractor: ResponseExtractor,
     ) -> anyhow::Result<ResponseT> {
-        let tx = &mut self.network.tx;
-        let mut rx = tx.subscribe();
-        let frame = Frame::builder()
-            .version(V::v())
-            .message(&request)?
-            .sequence(self.sequencer.next())
-            .system_id(self.node_id.system_id)
-            .component_id(self.node_id.component_id)
-            .build();
+        self.send_and_await_response_via(None, request, response_extractor)
+            .await
+    }
+
+    /// Like `send_and_await_response_with_extractor`, but only sends the request towards `target`
+    /// when the connection it is reachable through is known.
+    pub async fn send_to_and_await_response_with_extractor<
+        RequestT: Message + std::fmt::Debug,
+        ResponseT: std::fmt::Debug,
+        ResponseExtractor: Fn(&Frame<V>) -> Option<ResponseT>,
+    >(
+        &mut self,
+        target: NodeId,
+        request: RequestT,
+        response_extractor: ResponseExtractor,
+    ) -> anyhow::Result<ResponseT> {
+        self.send_and_await_response_via(Some(target), request, response_extractor)
+            .await
+    }
+
+    /// Sends `request` towards `target` without waiting for a response. Subscribe to the network
+    /// first if the responses are needed.
+    pub fn send_to<RequestT: Message + std::fmt::Debug>(
+        &mut self,
+        target: NodeId,
+        request: RequestT,
+    ) -> anyhow::Result<()> {
+        let routable_frame = self.routable_frame(Some(target), &request)?;
 
         tracing::debug!(?request, "Sending request");
-        tx.send(RoutableFrame {
+        self.network.tx.send(routable_frame)?;
+
+        Ok(())
+    }
+
+    fn routable_frame<RequestT: Message>(
+        &mut self,
+        target: Option<NodeId>,
+        request: &RequestT,
+    ) -> anyhow::Result<RoutableFrame<V>> {
+        let mut frame = match self.version {
+            MavLinkVersion::V1 => self.build_frame::<V1, _>(request)?,
+            MavLinkVersion::V2 => self.build_frame::<V2, _>(request)?,
+        }
+        .try_into_versioned::<V>()
+        .map_err(mavio::error::Error::from)?;
+        if let Some(signer) = &mut self.signer {
+            signer.sign(&mut frame);
+        }
+
+        Ok(RoutableFrame {
             frame,
             origin: ConnectionId::Local,
-            destination: MavlinkDestination::All,
-        })?;
+            destination: self.network.destination_for(target),
+        })
+    }
+
+    async fn send_and_await_response_via<
+        RequestT: Message + std::fmt::Debug,
+        ResponseT: std::fmt::Debug,
+        ResponseExtractor: Fn(&Frame<V>) -> Option<ResponseT>,
+    >(
+        &mut self,
+        target: Option<NodeId>,
+        request: RequestT,
+        response_extractor: ResponseExtractor,
+    ) -> anyhow::Result<ResponseT> {
+        let routable_frame = self.routable_frame(target, &request)?;
+        let tx = &mut self.network.tx;
+        let mut rx = tx.subscribe();
+
+        tracing::debug!(?request, "Sending request");
+        tx.send(routable_frame)?;
 
         // FIXME: add timeout
         loop {
@@ -250,3 +746,342 @@ impl<V: Versioned> Client<V> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mavio::dialects::common::messages::Heartbeat;
+    use mavio::protocol::V2;
+
+    #[tokio::test(start_paused = true)]
+    async fn replayed_frames_are_published_with_original_timing() {
+        let path = std::env::temp_dir().join(format!("mavlink-replay-{}.bin", std::process::id()));
+        let capture_start = SystemTime::now();
+        let mut file = File::create(&path).await.unwrap();
+        for (sequence, offset) in [(1, Duration::ZERO), (2, Duration::from_secs(2))] {
+            let frame = Frame::builder()
+                .version(V2)
+                .message(&Heartbeat::default())
+                .unwrap()
+                .sequence(sequence)
+                .system_id(1)
+                .component_id(1)
+                .build();
+            capture::write_frame_record(&mut file, capture_start + offset, frame)
+                .await
+                .unwrap();
+        }
+        drop(file);
+
+        let network = Network::<V2>::create_with_capacity(16);
+        let mut heartbeats = network.subscribe::<Heartbeat>().await;
+        let replay_start = tokio::time::Instant::now();
+        network
+            .clone()
+            .replay_frames_from_file(&path)
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(replay_start.elapsed(), Duration::from_secs(2));
+        for _ in 0..2 {
+            let (node_id, _) = heartbeats.next().await.unwrap();
+            assert_eq!(
+                node_id,
+                NodeId {
+                    system_id: 1,
+                    component_id: 1
+                }
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn peer_list_records_nodes_heard_from() {
+        let network = Network::<V2>::create_with_capacity(16);
+        let (peer_stream, network_stream) = tokio::io::duplex(1024);
+        let (read, write) = tokio::io::split(network_stream);
+        let connection = tokio::spawn(network.clone().process(ConnectionId::Local, read, write));
+        assert!(network.peer_list().is_empty());
+
+        let mut peer = FramedWrite::new(peer_stream, MavlinkCodec::<V2>::new());
+        let frame = Frame::builder()
+            .version(V2)
+            .message(&Heartbeat::default())
+            .unwrap()
+            .sequence(0)
+            .system_id(1)
+            .component_id(1)
+            .build();
+        let before_send = Instant::now();
+        peer.send(frame).await.unwrap();
+        // Disconnecting ends the connection once the frame has been processed
+        drop(peer);
+        connection.await.unwrap().unwrap();
+
+        let peer_list = network.peer_list();
+        assert_eq!(
+            peer_list
+                .iter()
+                .map(|(node_id, _)| *node_id)
+                .collect::<Vec<_>>(),
+            vec![NodeId {
+                system_id: 1,
+                component_id: 1
+            }]
+        );
+        assert!(peer_list[0].1 >= before_send);
+    }
+
+    #[test]
+    fn peers_are_refreshed_periodically_and_expire() {
+        let network = Network::<V2>::create_with_capacity(16);
+        let node = |system_id| NodeId {
+            system_id,
+            component_id: 1,
+        };
+        let start = Instant::now();
+
+        network.record_peer(node(1), start);
+        network.record_peer(node(1), start + Duration::from_millis(500));
+        assert_eq!(network.peers.read()[&node(1)], start);
+        network.record_peer(node(1), start + PEER_REFRESH_INTERVAL);
+        assert_eq!(
+            network.peers.read()[&node(1)],
+            start + PEER_REFRESH_INTERVAL
+        );
+
+        // Recording a new peer forgets those that have expired
+        network.record_peer(node(2), start + PEER_REFRESH_INTERVAL + PEER_EXPIRY);
+        assert_eq!(
+            network.peers.read().keys().copied().collect::<Vec<_>>(),
+            vec![node(2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn subscribe_from_node_only_yields_messages_from_that_node() {
+        let network = Network::<V2>::create_with_capacity(16);
+        let node_id = |system_id| NodeId {
+            system_id,
+            component_id: 1,
+        };
+        let mut watched = Client::create(network.clone(), node_id(1));
+        let mut other = Client::create(network.clone(), node_id(2));
+        let subscriber = Client::create(network.clone(), node_id(3));
+        let mut heartbeats = subscriber
+            .subscribe_from_node::<Heartbeat>(node_id(1))
+            .await;
+
+        let heartbeat = |custom_mode| Heartbeat {
+            custom_mode,
+            ..Heartbeat::default()
+        };
+        other.send_to(node_id(3), heartbeat(2)).unwrap();
+        watched.send_to(node_id(3), heartbeat(1)).unwrap();
+
+        assert_eq!(heartbeats.next().await.unwrap().custom_mode, 1);
+    }
+
+    fn heartbeat_frame(sequence: u8) -> Frame<V2> {
+        Frame::builder()
+            .version(V2)
+            .message(&Heartbeat::default())
+            .unwrap()
+            .sequence(sequence)
+            .system_id(1)
+            .component_id(1)
+            .build()
+    }
+
+    #[tokio::test]
+    async fn stats_count_frames_received() {
+        let network = Network::<V2>::create_with_capacity(16);
+        let (peer_stream, network_stream) = tokio::io::duplex(1024);
+        let (read, write) = tokio::io::split(network_stream);
+        let connection = tokio::spawn(network.clone().process(ConnectionId::Local, read, write));
+
+        let mut peer = FramedWrite::new(peer_stream, MavlinkCodec::<V2>::new());
+        let frame_size = super::frame_size(&heartbeat_frame(0));
+        for sequence in 0..2 {
+            peer.send(heartbeat_frame(sequence)).await.unwrap();
+        }
+        drop(peer);
+        connection.await.unwrap().unwrap();
+
+        assert_eq!(
+            network.stats(),
+            NetworkStats {
+                frames_received: 2,
+                bytes_received: 2 * frame_size,
+                ..NetworkStats::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn accept_multiple_accepts_connections_on_every_listener() {
+        let network = Network::<V2>::create_with_capacity(16);
+        let mut heartbeats = network.subscribe::<Heartbeat>().await;
+        let listeners = vec![
+            TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        ];
+        let addresses = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap())
+            .collect::<Vec<_>>();
+        let accept = network.clone().accept_multiple(listeners);
+
+        let mut peers = vec![];
+        for (sequence, address) in addresses.into_iter().enumerate() {
+            let mut peer = FramedWrite::new(
+                TcpStream::connect(address).await.unwrap(),
+                MavlinkCodec::<V2>::new(),
+            );
+            peer.send(heartbeat_frame(sequence as u8)).await.unwrap();
+            peers.push(peer);
+        }
+
+        for _ in 0..2 {
+            assert!(heartbeats.next().await.is_some());
+        }
+        assert_eq!(network.stats().frames_received, 2);
+        accept.abort();
+    }
+
+    #[tokio::test]
+    async fn frames_with_invalid_signatures_are_dropped() {
+        let network = Network::<V2>::create_with_capacity(16)
+            .with_signature_verifier(SignatureVerifier::new([1; 32], false));
+        let (peer_stream, network_stream) = tokio::io::duplex(1024);
+        let (read, write) = tokio::io::split(network_stream);
+        let connection = tokio::spawn(network.clone().process(ConnectionId::Local, read, write));
+
+        let mut peer = FramedWrite::new(peer_stream, MavlinkCodec::<V2>::new());
+        let mut frame = heartbeat_frame(0);
+        FrameSigner::new(0, [2; 32]).sign(&mut frame);
+        peer.send(frame).await.unwrap();
+        drop(peer);
+        connection.await.unwrap().unwrap();
+
+        assert_eq!(network.stats().frames_received, 1);
+        assert!(network.peer_list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn stats_count_frames_dropped_by_lagging_subscribers() {
+        let network = Network::<V2>::create_with_capacity(1);
+        let mut heartbeats = network.subscribe::<Heartbeat>().await;
+        for sequence in 0..2 {
+            network
+                .tx
+                .send(RoutableFrame {
+                    frame: heartbeat_frame(sequence),
+                    origin: ConnectionId::Local,
+                    destination: MavlinkDestination::All,
+                })
+                .unwrap();
+        }
+
+        // Only the latest frame fits in the subscriber's buffer
+        assert!(heartbeats.next().await.is_some());
+        assert_eq!(network.stats().frames_dropped, 1);
+    }
+
+    #[tokio::test]
+    async fn bridge_forwards_frames_between_networks_without_echoing_them() {
+        let network_a = Network::<V2>::create_with_capacity(16);
+        let network_b = Network::<V2>::create_with_capacity(16);
+        let node_id = |system_id| NodeId {
+            system_id,
+            component_id: 1,
+        };
+        let mut client_a = Client::create(network_a.clone(), node_id(1));
+        let mut client_b = Client::create(network_b.clone(), node_id(2));
+        let custom_modes =
+            |(node_id, heartbeat): (NodeId, Heartbeat)| (node_id.system_id, heartbeat.custom_mode);
+        let mut heartbeats_a = network_a.subscribe::<Heartbeat>().await.map(custom_modes);
+        let mut heartbeats_b = network_b.subscribe::<Heartbeat>().await.map(custom_modes);
+        let bridge = tokio::spawn(bridge(network_a.clone(), network_b.clone(), None));
+        tokio::task::yield_now().await;
+
+        let heartbeat = |custom_mode| Heartbeat {
+            custom_mode,
+            ..Heartbeat::default()
+        };
+        client_a.send_to(node_id(2), heartbeat(1)).unwrap();
+        assert_eq!(heartbeats_b.next().await, Some((1, 1)));
+        client_b.send_to(node_id(1), heartbeat(2)).unwrap();
+        assert_eq!(heartbeats_a.next().await, Some((1, 1)));
+        assert_eq!(heartbeats_a.next().await, Some((2, 2)));
+        client_a.send_to(node_id(2), heartbeat(3)).unwrap();
+        assert_eq!(heartbeats_a.next().await, Some((1, 3)));
+
+        bridge.abort();
+    }
+
+    #[test]
+    fn message_id_filter_accepts_allowed_messages() {
+        let heartbeat_id = Heartbeat::message_id();
+        let allow = MessageIdFilter::Allow(HashSet::from([heartbeat_id]));
+        let deny = MessageIdFilter::Deny(HashSet::from([heartbeat_id]));
+
+        assert!(allow.accepts(heartbeat_id));
+        assert!(!allow.accepts(heartbeat_id + 1));
+        assert!(!deny.accepts(heartbeat_id));
+        assert!(deny.accepts(heartbeat_id + 1));
+    }
+
+    #[test]
+    fn node_ids_round_trip_through_strings() {
+        let node_id = NodeId {
+            system_id: 1,
+            component_id: 190,
+        };
+        assert_eq!(node_id.to_string(), "1:190");
+        assert_eq!("1:190".parse::<NodeId>(), Ok(node_id));
+
+        assert!(matches!(
+            "1".parse::<NodeId>(),
+            Err(ParseNodeIdError::MissingSeparator(_))
+        ));
+        assert!(matches!(
+            "1:256".parse::<NodeId>(),
+            Err(ParseNodeIdError::InvalidId {
+                field: "component ID",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn destination_uses_routing_table_when_target_is_known() {
+        let network = Network::<V2>::create_with_capacity(1);
+        let known = NodeId {
+            system_id: 1,
+            component_id: 1,
+        };
+        let unknown = NodeId {
+            system_id: 2,
+            component_id: 1,
+        };
+        network
+            .routing_table()
+            .write()
+            .insert(known, ConnectionId::Local);
+
+        assert!(matches!(
+            network.destination_for(Some(known)),
+            MavlinkDestination::OnlyConnectionId(ConnectionId::Local)
+        ));
+        assert!(matches!(
+            network.destination_for(Some(unknown)),
+            MavlinkDestination::All
+        ));
+        assert!(matches!(
+            network.destination_for(None),
+            MavlinkDestination::All
+        ));
+    }
+}
@@ -1,6 +1,9 @@
+pub mod capture;
 pub mod codec;
 pub mod connection;
 pub mod mission;
+pub mod parameters;
+pub mod signing;
 
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
@@ -1,7 +1,7 @@
-use ardupilot::connection::{Client, Network, NodeId};
+use ardupilot::connection::{bridge, Client, Network, NodeId};
 use ardupilot::mission::MissionProtocol;
 use mavio::dialects::Ardupilotmega;
-use mavio::protocol::V2;
+use mavio::protocol::{MavLinkVersion, Versionless};
 use std::time::Duration;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::task::JoinSet;
@@ -19,23 +19,27 @@ async fn main() -> anyhow::Result<()> {
         )
         .init();
 
-    let mavlink_network = Network::<V2>::create_with_capacity(128);
+    // The autopilot and the ground control stations are on separate networks, bridged together
+    let mavlink_network = Network::<Versionless>::create_with_capacity(128);
+    let gcs_network = Network::<Versionless>::create_with_capacity(128);
     let mut join_set = JoinSet::new();
     let socket = TcpStream::connect("127.0.0.1:5760").await?;
     join_set.spawn(mavlink_network.clone().process_tcp(socket));
 
     let listener = TcpListener::bind("127.0.0.1:5600").await?;
 
-    join_set.spawn(mavlink_network.clone().accept_loop(listener));
+    join_set.spawn(gcs_network.clone().accept_loop(listener));
+    join_set.spawn(bridge(mavlink_network.clone(), gcs_network, None));
     join_set.spawn(mavlink_network.clone().log_frames::<Ardupilotmega>());
 
     sleep(Duration::from_secs(1)).await;
-    let mut mavlink_client = Client::create(
+    let mut mavlink_client = Client::create_with_version(
         mavlink_network.clone(),
         NodeId {
             system_id: 99,
             component_id: 99,
         },
+        MavLinkVersion::V2,
     );
     join_set.spawn(async move {
         loop {
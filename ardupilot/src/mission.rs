@@ -1,11 +1,11 @@
 use crate::connection::{Client, NodeId};
 use anyhow::format_err;
 use async_trait::async_trait;
-use mavio::dialects::common::enums::MavMissionType;
+use mavio::dialects::common::enums::{MavMissionResult, MavMissionType};
 use mavio::dialects::common::messages::{
     MissionAck, MissionCount, MissionItemInt, MissionRequestInt, MissionRequestList,
 };
-use mavio::protocol::Versioned;
+use mavio::protocol::MaybeVersioned;
 use mavio::Frame;
 
 #[async_trait]
@@ -19,17 +19,47 @@ pub trait MissionProtocol {
         request_int: MissionRequestInt,
     ) -> anyhow::Result<Result<MissionItemInt, MissionAck>>;
 
+    async fn count(
+        &mut self,
+        count: MissionCount,
+    ) -> anyhow::Result<Result<MissionRequestInt, MissionAck>>;
+    async fn item_int(
+        &mut self,
+        item_int: MissionItemInt,
+    ) -> anyhow::Result<Result<MissionRequestInt, MissionAck>>;
+
     async fn fetch_mission(
         &mut self,
         target_node_id: NodeId,
     ) -> anyhow::Result<Vec<MissionItemInt>>;
+
+    /// Replaces the mission on `target_node_id`, sending each item as the autopilot requests it.
+    async fn upload_mission(
+        &mut self,
+        target_node_id: NodeId,
+        mission_items: Vec<MissionItemInt>,
+    ) -> anyhow::Result<()>;
+
+    /// Replaces the geofence on `target_node_id` with `fence_items`, following the same protocol
+    /// as `upload_mission`.
+    async fn upload_fence(
+        &mut self,
+        target_node_id: NodeId,
+        fence_items: Vec<MissionItemInt>,
+    ) -> anyhow::Result<()>;
+
+    /// Removes every geofence item from `target_node_id`.
+    async fn clear_fence(&mut self, target_node_id: NodeId) -> anyhow::Result<()>;
 }
 
-trait MissionProtocolInternal<V: Versioned> {
+trait MissionProtocolInternal<V: MaybeVersioned> {
     fn extract_mission_ack<T>(self) -> impl Fn(&Frame<V>) -> Option<Result<T, MissionAck>>;
+    fn extract_mission_request_int(
+        self,
+    ) -> impl Fn(&Frame<V>) -> Option<Result<MissionRequestInt, MissionAck>>;
 }
 
-impl<V: Versioned> MissionProtocolInternal<V> for NodeId {
+impl<V: MaybeVersioned> MissionProtocolInternal<V> for NodeId {
     fn extract_mission_ack<T>(self) -> impl Fn(&Frame<V>) -> Option<Result<T, MissionAck>> {
         move |frame: &Frame<V>| {
             if frame.message_id() == MissionAck::message_id() {
@@ -44,10 +74,30 @@ impl<V: Versioned> MissionProtocolInternal<V> for NodeId {
             None
         }
     }
+
+    fn extract_mission_request_int(
+        self,
+    ) -> impl Fn(&Frame<V>) -> Option<Result<MissionRequestInt, MissionAck>> {
+        let mission_ack_extractor = self.extract_mission_ack();
+        move |frame: &Frame<V>| {
+            mission_ack_extractor(frame).or_else(|| {
+                if frame.message_id() == MissionRequestInt::message_id() {
+                    if let Ok(mission_request_int) = MissionRequestInt::try_from(frame.payload()) {
+                        if mission_request_int.target_system == self.system_id
+                            && mission_request_int.target_component == self.component_id
+                        {
+                            return Some(Ok(mission_request_int));
+                        }
+                    }
+                }
+                None
+            })
+        }
+    }
 }
 
 #[async_trait]
-impl<V: Versioned> MissionProtocol for Client<V> {
+impl<V: MaybeVersioned> MissionProtocol for Client<V> {
     async fn request_list(
         &mut self,
         request_list: MissionRequestList,
@@ -68,7 +118,11 @@ impl<V: Versioned> MissionProtocol for Client<V> {
                 None
             })
         };
-        self.send_and_await_response_with_extractor(request_list, response_extractor)
+        let target = NodeId {
+            system_id: request_list.target_system,
+            component_id: request_list.target_component,
+        };
+        self.send_to_and_await_response_with_extractor(target, request_list, response_extractor)
             .await
     }
 
@@ -92,7 +146,37 @@ impl<V: Versioned> MissionProtocol for Client<V> {
                 None
             })
         };
-        self.send_and_await_response_with_extractor(request_int, response_extractor)
+        let target = NodeId {
+            system_id: request_int.target_system,
+            component_id: request_int.target_component,
+        };
+        self.send_to_and_await_response_with_extractor(target, request_int, response_extractor)
+            .await
+    }
+
+    async fn count(
+        &mut self,
+        count: MissionCount,
+    ) -> anyhow::Result<Result<MissionRequestInt, MissionAck>> {
+        let response_extractor = self.node_id.extract_mission_request_int();
+        let target = NodeId {
+            system_id: count.target_system,
+            component_id: count.target_component,
+        };
+        self.send_to_and_await_response_with_extractor(target, count, response_extractor)
+            .await
+    }
+
+    async fn item_int(
+        &mut self,
+        item_int: MissionItemInt,
+    ) -> anyhow::Result<Result<MissionRequestInt, MissionAck>> {
+        let response_extractor = self.node_id.extract_mission_request_int();
+        let target = NodeId {
+            system_id: item_int.target_system,
+            component_id: item_int.target_component,
+        };
+        self.send_to_and_await_response_with_extractor(target, item_int, response_extractor)
             .await
     }
 
@@ -133,4 +217,232 @@ impl<V: Versioned> MissionProtocol for Client<V> {
 
         Ok(mission_items)
     }
+
+    async fn upload_mission(
+        &mut self,
+        target_node_id: NodeId,
+        mission_items: Vec<MissionItemInt>,
+    ) -> anyhow::Result<()> {
+        upload_items(self, target_node_id, mission_items, MavMissionType::Mission).await
+    }
+
+    async fn upload_fence(
+        &mut self,
+        target_node_id: NodeId,
+        fence_items: Vec<MissionItemInt>,
+    ) -> anyhow::Result<()> {
+        upload_items(self, target_node_id, fence_items, MavMissionType::Fence).await
+    }
+
+    async fn clear_fence(&mut self, target_node_id: NodeId) -> anyhow::Result<()> {
+        // The autopilot acks a count of zero without requesting any items
+        upload_items(self, target_node_id, vec![], MavMissionType::Fence).await
+    }
+}
+
+/// Uploads `mission_items` as the items of `mission_type`, sending each item as the autopilot
+/// requests it.
+async fn upload_items<V: MaybeVersioned>(
+    client: &mut Client<V>,
+    target_node_id: NodeId,
+    mission_items: Vec<MissionItemInt>,
+    mission_type: MavMissionType,
+) -> anyhow::Result<()> {
+    let mut response = client
+        .count(MissionCount {
+            target_system: target_node_id.system_id,
+            target_component: target_node_id.component_id,
+            count: u16::try_from(mission_items.len())?,
+            mission_type,
+            opaque_id: 0,
+        })
+        .await?;
+
+    // The autopilot may request items in any order, and re-request items, until it acks
+    loop {
+        match response {
+            Ok(mission_request_int) => {
+                let seq = mission_request_int.seq;
+                let mission_item = mission_items.get(usize::from(seq)).ok_or_else(|| {
+                    format_err!(
+                        "autopilot requested mission item {seq} of {}",
+                        mission_items.len()
+                    )
+                })?;
+                response = client
+                    .item_int(MissionItemInt {
+                        target_system: target_node_id.system_id,
+                        target_component: target_node_id.component_id,
+                        seq,
+                        mission_type,
+                        ..mission_item.clone()
+                    })
+                    .await?;
+            }
+            Err(mission_ack)
+                if matches!(mission_ack.type_, MavMissionResult::MavMissionAccepted) =>
+            {
+                return Ok(());
+            }
+            Err(mission_ack) => {
+                return Err(format_err!(
+                    "{mission_type:?} upload rejected: {mission_ack:?}"
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Network;
+    use mavio::protocol::V2;
+    use tokio_stream::StreamExt;
+
+    const GROUND_STATION: NodeId = NodeId {
+        system_id: 99,
+        component_id: 17,
+    };
+    const AUTOPILOT: NodeId = NodeId {
+        system_id: 1,
+        component_id: 1,
+    };
+
+    fn mission_item(seq: u16) -> MissionItemInt {
+        MissionItemInt {
+            seq,
+            x: i32::from(seq) * 1000,
+            ..Default::default()
+        }
+    }
+
+    /// Requests every item of an uploaded mission, then acks with `result`.
+    async fn fake_autopilot(
+        network: Network<V2>,
+        result: MavMissionResult,
+    ) -> anyhow::Result<Vec<MissionItemInt>> {
+        let mut counts = network.subscribe::<MissionCount>().await;
+        let mut autopilot = Client::create(network, AUTOPILOT);
+        let (_, count) = counts
+            .next()
+            .await
+            .ok_or_else(|| format_err!("network closed"))?;
+
+        let mut mission_items = vec![];
+        for seq in 0..count.count {
+            let mission_item = autopilot
+                .send_and_await_response::<_, MissionItemInt>(MissionRequestInt {
+                    target_system: GROUND_STATION.system_id,
+                    target_component: GROUND_STATION.component_id,
+                    seq,
+                    mission_type: count.mission_type,
+                })
+                .await?;
+            mission_items.push(mission_item);
+        }
+        // Our own ack is echoed back on the local network, which completes the send
+        autopilot
+            .send_and_await_response::<_, MissionAck>(MissionAck {
+                target_system: GROUND_STATION.system_id,
+                target_component: GROUND_STATION.component_id,
+                type_: result,
+                mission_type: count.mission_type,
+                opaque_id: 0,
+            })
+            .await?;
+
+        Ok(mission_items)
+    }
+
+    #[tokio::test]
+    async fn upload_mission_sends_requested_items() {
+        let network = Network::<V2>::create_with_capacity(16);
+        let autopilot = tokio::spawn(fake_autopilot(
+            network.clone(),
+            MavMissionResult::MavMissionAccepted,
+        ));
+        tokio::task::yield_now().await;
+
+        let mission_items: Vec<_> = (0..3).map(mission_item).collect();
+        Client::create(network, GROUND_STATION)
+            .upload_mission(AUTOPILOT, mission_items.clone())
+            .await
+            .unwrap();
+
+        let received = autopilot.await.unwrap().unwrap();
+        assert_eq!(
+            received
+                .iter()
+                .map(|item| (item.seq, item.x))
+                .collect::<Vec<_>>(),
+            mission_items
+                .iter()
+                .map(|item| (item.seq, item.x))
+                .collect::<Vec<_>>()
+        );
+        assert!(received
+            .iter()
+            .all(|item| item.target_system == AUTOPILOT.system_id));
+    }
+
+    #[tokio::test]
+    async fn upload_fence_sends_fence_items() {
+        let network = Network::<V2>::create_with_capacity(16);
+        let autopilot = tokio::spawn(fake_autopilot(
+            network.clone(),
+            MavMissionResult::MavMissionAccepted,
+        ));
+        tokio::task::yield_now().await;
+
+        Client::create(network, GROUND_STATION)
+            .upload_fence(AUTOPILOT, (0..2).map(mission_item).collect())
+            .await
+            .unwrap();
+
+        let received = autopilot.await.unwrap().unwrap();
+        assert_eq!(received.len(), 2);
+        assert!(received
+            .iter()
+            .all(|item| matches!(item.mission_type, MavMissionType::Fence)));
+    }
+
+    #[tokio::test]
+    async fn clear_fence_sends_an_empty_fence() {
+        let network = Network::<V2>::create_with_capacity(16);
+        let mut counts = network.subscribe::<MissionCount>().await;
+        let autopilot = tokio::spawn(fake_autopilot(
+            network.clone(),
+            MavMissionResult::MavMissionAccepted,
+        ));
+        tokio::task::yield_now().await;
+
+        Client::create(network, GROUND_STATION)
+            .clear_fence(AUTOPILOT)
+            .await
+            .unwrap();
+
+        assert!(autopilot.await.unwrap().unwrap().is_empty());
+        let (_, count) = counts.next().await.unwrap();
+        assert_eq!(count.count, 0);
+        assert!(matches!(count.mission_type, MavMissionType::Fence));
+    }
+
+    #[tokio::test]
+    async fn upload_mission_fails_when_rejected() {
+        let network = Network::<V2>::create_with_capacity(16);
+        let _autopilot = tokio::spawn(fake_autopilot(
+            network.clone(),
+            MavMissionResult::MavMissionNoSpace,
+        ));
+        tokio::task::yield_now().await;
+
+        let result = Client::create(network, GROUND_STATION)
+            .upload_mission(AUTOPILOT, vec![mission_item(0)])
+            .await;
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("MavMissionNoSpace"));
+    }
 }
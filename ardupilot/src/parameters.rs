@@ -0,0 +1,242 @@
+use crate::connection::{Client, NodeId};
+use anyhow::format_err;
+use async_trait::async_trait;
+use mavio::dialects::common::messages::{ParamRequestList, ParamRequestRead, ParamValue};
+use mavio::protocol::MaybeVersioned;
+use mavio::Frame;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::time::timeout;
+use tokio_stream::StreamExt;
+
+/// How long to wait for the next `PARAM_VALUE` before giving up
+const PARAM_VALUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const PARAM_ID_LEN: usize = 16;
+
+#[async_trait]
+pub trait ParameterProtocol {
+    /// Fetches every parameter of `target`, keyed by parameter name.
+    async fn request_param_list(&mut self, target: NodeId) -> anyhow::Result<HashMap<String, f32>>;
+
+    /// Fetches the parameter of `target` called `name`.
+    async fn request_param_read(&mut self, target: NodeId, name: &str) -> anyhow::Result<f32>;
+}
+
+/// Parameter IDs are NUL-terminated, unless they are exactly 16 bytes long.
+pub fn param_id_to_string(param_id: &[u8; PARAM_ID_LEN]) -> String {
+    let len = param_id
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(PARAM_ID_LEN);
+    String::from_utf8_lossy(&param_id[..len]).into_owned()
+}
+
+pub fn param_id_from_str(name: &str) -> anyhow::Result<[u8; PARAM_ID_LEN]> {
+    let bytes = name.as_bytes();
+    if bytes.len() > PARAM_ID_LEN {
+        return Err(format_err!(
+            "parameter name {name:?} is longer than {PARAM_ID_LEN} bytes"
+        ));
+    }
+    let mut param_id = [0; PARAM_ID_LEN];
+    param_id[..bytes.len()].copy_from_slice(bytes);
+    Ok(param_id)
+}
+
+#[async_trait]
+impl<V: MaybeVersioned> ParameterProtocol for Client<V> {
+    async fn request_param_list(&mut self, target: NodeId) -> anyhow::Result<HashMap<String, f32>> {
+        // Subscribe before sending the request so that no parameters are missed
+        let mut param_values = self.network().subscribe::<ParamValue>().await;
+        self.send_to(
+            target,
+            ParamRequestList {
+                target_system: target.system_id,
+                target_component: target.component_id,
+            },
+        )?;
+
+        let mut parameters = HashMap::new();
+        let mut received_indices = HashSet::new();
+        let mut param_count = None;
+        while param_count.is_none_or(|param_count| received_indices.len() < param_count) {
+            let (origin, param_value) = timeout(PARAM_VALUE_TIMEOUT, param_values.next())
+                .await
+                .map_err(|_| {
+                    format_err!(
+                        "timed out after receiving {} of {} parameters",
+                        received_indices.len(),
+                        param_count.map_or("?".to_string(), |count| count.to_string())
+                    )
+                })?
+                .ok_or_else(|| format_err!("network closed"))?;
+            if origin != target {
+                continue;
+            }
+
+            param_count = Some(usize::from(param_value.param_count));
+            received_indices.insert(param_value.param_index);
+            parameters.insert(
+                param_id_to_string(&param_value.param_id),
+                param_value.param_value,
+            );
+        }
+
+        Ok(parameters)
+    }
+
+    async fn request_param_read(&mut self, target: NodeId, name: &str) -> anyhow::Result<f32> {
+        let param_id = param_id_from_str(name)?;
+        let response_extractor = move |frame: &Frame<V>| {
+            if frame.system_id() != target.system_id
+                || frame.component_id() != target.component_id
+                || frame.message_id() != ParamValue::message_id()
+            {
+                return None;
+            }
+            ParamValue::try_from(frame.payload())
+                .ok()
+                .filter(|param_value| param_value.param_id == param_id)
+        };
+        let param_value = timeout(
+            PARAM_VALUE_TIMEOUT,
+            self.send_to_and_await_response_with_extractor(
+                target,
+                ParamRequestRead {
+                    target_system: target.system_id,
+                    target_component: target.component_id,
+                    param_id,
+                    // Look the parameter up by name rather than by index
+                    param_index: -1,
+                },
+                response_extractor,
+            ),
+        )
+        .await
+        .map_err(|_| format_err!("timed out waiting for parameter {name:?}"))??;
+
+        Ok(param_value.param_value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Network;
+    use mavio::protocol::V2;
+
+    const GROUND_STATION: NodeId = NodeId {
+        system_id: 99,
+        component_id: 17,
+    };
+    const AUTOPILOT: NodeId = NodeId {
+        system_id: 1,
+        component_id: 1,
+    };
+
+    fn param_value(name: &str, value: f32, param_count: u16, param_index: u16) -> ParamValue {
+        ParamValue {
+            param_id: param_id_from_str(name).unwrap(),
+            param_value: value,
+            param_count,
+            param_index,
+            ..Default::default()
+        }
+    }
+
+    /// Answers the first parameter list request with `parameters`, claiming there are
+    /// `param_count` parameters in total.
+    async fn fake_autopilot(
+        network: Network<V2>,
+        parameters: Vec<(&'static str, f32)>,
+        param_count: u16,
+    ) -> anyhow::Result<()> {
+        let mut requests = network.subscribe::<ParamRequestList>().await;
+        let mut autopilot = Client::create(network, AUTOPILOT);
+        requests
+            .next()
+            .await
+            .ok_or_else(|| format_err!("network closed"))?;
+
+        for (param_index, (name, value)) in (0..).zip(parameters) {
+            autopilot.send_to(
+                GROUND_STATION,
+                param_value(name, value, param_count, param_index),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn param_ids_round_trip() {
+        for name in ["SYSID_THISMAV", "SIXTEEN_CHARS_ID"] {
+            assert_eq!(param_id_to_string(&param_id_from_str(name).unwrap()), name);
+        }
+        assert!(param_id_from_str("SEVENTEEN_CHARS_X").is_err());
+    }
+
+    #[tokio::test]
+    async fn request_param_list_collects_every_parameter() {
+        let network = Network::<V2>::create_with_capacity(16);
+        let autopilot = tokio::spawn(fake_autopilot(
+            network.clone(),
+            vec![("SYSID_THISMAV", 1.0), ("WPNAV_SPEED", 500.0)],
+            2,
+        ));
+        tokio::task::yield_now().await;
+
+        let parameters = Client::create(network, GROUND_STATION)
+            .request_param_list(AUTOPILOT)
+            .await
+            .unwrap();
+        autopilot.await.unwrap().unwrap();
+
+        assert_eq!(
+            parameters,
+            HashMap::from([
+                ("SYSID_THISMAV".to_string(), 1.0),
+                ("WPNAV_SPEED".to_string(), 500.0),
+            ])
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn request_param_list_times_out_when_parameters_are_missing() {
+        let network = Network::<V2>::create_with_capacity(16);
+        let _autopilot = tokio::spawn(fake_autopilot(
+            network.clone(),
+            vec![("SYSID_THISMAV", 1.0)],
+            2,
+        ));
+        tokio::task::yield_now().await;
+
+        let result = Client::create(network, GROUND_STATION)
+            .request_param_list(AUTOPILOT)
+            .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "timed out after receiving 1 of 2 parameters"
+        );
+    }
+
+    #[tokio::test]
+    async fn request_param_read_returns_named_parameter() {
+        let network = Network::<V2>::create_with_capacity(16);
+        let mut requests = network.subscribe::<ParamRequestRead>().await;
+        let mut autopilot = Client::create(network.clone(), AUTOPILOT);
+        let _autopilot = tokio::spawn(async move {
+            let (_, request) = requests.next().await.unwrap();
+            let name = param_id_to_string(&request.param_id);
+            autopilot.send_to(GROUND_STATION, param_value("OTHER_PARAM", 0.0, 2, 0))?;
+            autopilot.send_to(GROUND_STATION, param_value(&name, 500.0, 2, 1))
+        });
+
+        let value = Client::create(network, GROUND_STATION)
+            .request_param_read(AUTOPILOT, "WPNAV_SPEED")
+            .await
+            .unwrap();
+        assert_eq!(value, 500.0);
+    }
+}
@@ -0,0 +1,190 @@
+//! MAVLink 2 frame signing, as described in <https://mavlink.io/en/guide/message_signing.html>.
+
+use mavio::protocol::{
+    Checksum, ComponentId, Header, MavLinkVersion, MavTimestamp, MaybeVersioned, SecretKey,
+    Signature, SignedLinkId, Signer, SigningConf, SystemId, UpdateFrame, UpdateFrameUnsafe,
+};
+use mavio::utils::MavSha256;
+use mavio::Frame;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::SystemTime;
+use tracing::log;
+
+/// Signs the frames sent on a link with a shared secret key.
+#[derive(Clone, Debug)]
+pub struct FrameSigner {
+    link_id: SignedLinkId,
+    secret_key: SecretKey,
+    /// Timestamps must increase with every signed frame, even if several are signed at once
+    last_timestamp: u64,
+}
+
+impl FrameSigner {
+    pub fn new(link_id: SignedLinkId, secret_key: [u8; 32]) -> FrameSigner {
+        FrameSigner {
+            link_id,
+            secret_key: secret_key.into(),
+            last_timestamp: 0,
+        }
+    }
+
+    /// Signs `frame` if it is a MAVLink 2 frame. MAVLink 1 frames cannot be signed and are left
+    /// unchanged.
+    pub fn sign<V: MaybeVersioned>(&mut self, frame: &mut Frame<V>) {
+        if frame.version() != MavLinkVersion::V2 {
+            return;
+        }
+        // The checksum covers the signed flag, so it is recomputed once the frame is flagged, and
+        // the signature covers the checksum. Exactly one CRC_EXTRA reproduces the frame's checksum.
+        let Some(crc_extra) =
+            (0..=u8::MAX).find(|&crc_extra| frame.calculate_crc(crc_extra) == frame.checksum())
+        else {
+            log::warn!("Not signing a frame with an invalid checksum");
+            return;
+        };
+        let now = MavTimestamp::from(SystemTime::now()).as_raw_u64();
+        self.last_timestamp = now.max(self.last_timestamp + 1);
+        let signing_conf = SigningConf {
+            link_id: self.link_id,
+            timestamp: MavTimestamp::from_raw_u64(self.last_timestamp),
+            secret: self.secret_key.clone(),
+        };
+
+        signing_conf.apply(frame, &mut MavSha256::default());
+        ChecksumUpdater.update(frame, crc_extra);
+        signing_conf.apply(frame, &mut MavSha256::default());
+    }
+}
+
+/// Recalculates the checksum of a frame without changing its contents.
+struct ChecksumUpdater;
+
+impl<V: MaybeVersioned> UpdateFrameUnsafe<V> for ChecksumUpdater {
+    unsafe fn update_unsafe(
+        &mut self,
+        _header: Header<V>,
+        _payload: &mut [u8],
+        _checksum: &mut Checksum,
+        _signature: &mut Option<Signature>,
+    ) {
+    }
+}
+
+impl<V: MaybeVersioned> UpdateFrame<V> for ChecksumUpdater {}
+
+/// Checks the signatures of the frames received by a network.
+#[derive(Debug)]
+pub struct SignatureVerifier {
+    secret_key: SecretKey,
+    /// Whether unsigned frames are rejected. Frames with invalid signatures are always rejected.
+    enforce_signing: bool,
+    /// Timestamp of the last accepted frame of each signed stream, so that replayed frames are
+    /// rejected
+    last_timestamps: Mutex<HashMap<(SignedLinkId, SystemId, ComponentId), u64>>,
+}
+
+impl SignatureVerifier {
+    pub fn new(secret_key: [u8; 32], enforce_signing: bool) -> SignatureVerifier {
+        SignatureVerifier {
+            secret_key: secret_key.into(),
+            enforce_signing,
+            last_timestamps: Default::default(),
+        }
+    }
+
+    pub fn accepts<V: MaybeVersioned>(&self, frame: &Frame<V>) -> bool {
+        let Some(signature) = frame.signature() else {
+            return !self.enforce_signing;
+        };
+        if !Signer::new(&mut MavSha256::default()).validate(frame, signature, &self.secret_key) {
+            return false;
+        }
+
+        let timestamp = signature.timestamp.as_raw_u64();
+        let mut last_timestamps = self.last_timestamps.lock();
+        let last_timestamp = last_timestamps
+            .entry((signature.link_id, frame.system_id(), frame.component_id()))
+            .or_default();
+        if timestamp <= *last_timestamp {
+            return false;
+        }
+        *last_timestamp = timestamp;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mavio::dialects::common::messages::Heartbeat;
+    use mavio::protocol::V2;
+
+    const SECRET_KEY: [u8; 32] = [7; 32];
+
+    fn heartbeat_frame() -> Frame<V2> {
+        Frame::builder()
+            .version(V2)
+            .message(&Heartbeat::default())
+            .unwrap()
+            .sequence(0)
+            .system_id(1)
+            .component_id(1)
+            .build()
+    }
+
+    #[test]
+    fn signed_frames_are_accepted_with_the_same_key() {
+        let mut frame = heartbeat_frame();
+        FrameSigner::new(1, SECRET_KEY).sign(&mut frame);
+
+        assert!(SignatureVerifier::new(SECRET_KEY, true).accepts(&frame));
+        assert!(!SignatureVerifier::new([8; 32], false).accepts(&frame));
+    }
+
+    #[test]
+    fn signed_frames_have_valid_checksums() {
+        let mut frame = heartbeat_frame();
+        FrameSigner::new(1, SECRET_KEY).sign(&mut frame);
+
+        assert!(frame.is_signed());
+        assert!(frame.validate_checksum::<mavio::dialects::Common>().is_ok());
+    }
+
+    #[test]
+    fn unsigned_frames_are_rejected_only_when_signing_is_enforced() {
+        let frame = heartbeat_frame();
+
+        assert!(SignatureVerifier::new(SECRET_KEY, false).accepts(&frame));
+        assert!(!SignatureVerifier::new(SECRET_KEY, true).accepts(&frame));
+    }
+
+    #[test]
+    fn replayed_frames_are_rejected() {
+        let mut other_link = heartbeat_frame();
+        FrameSigner::new(2, SECRET_KEY).sign(&mut other_link);
+        let mut signer = FrameSigner::new(1, SECRET_KEY);
+        let mut first = heartbeat_frame();
+        let mut second = heartbeat_frame();
+        signer.sign(&mut first);
+        signer.sign(&mut second);
+        let verifier = SignatureVerifier::new(SECRET_KEY, true);
+
+        assert!(verifier.accepts(&second));
+        assert!(!verifier.accepts(&second));
+        assert!(!verifier.accepts(&first));
+        // Timestamps are only compared within the stream of each link, system and component
+        assert!(verifier.accepts(&other_link));
+    }
+
+    #[test]
+    fn timestamps_increase_with_every_signed_frame() {
+        let mut signer = FrameSigner::new(1, SECRET_KEY);
+        let mut first = heartbeat_frame();
+        let mut second = heartbeat_frame();
+        signer.sign(&mut first);
+        signer.sign(&mut second);
+
+        assert!(second.timestamp().unwrap().as_raw_u64() > first.timestamp().unwrap().as_raw_u64());
+    }
+}
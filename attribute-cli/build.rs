@@ -4,6 +4,17 @@ use std::path::PathBuf;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
+/// Mavlink messages stored as protobuf attributes named after their fully-qualified message name
+const MAVLINK_TYPED_ATTRIBUTES: &[&str] = &[
+    "me.grahamdennis.attribute.mavlink.GlobalPosition",
+    "me.grahamdennis.attribute.mavlink.Attitude",
+    "me.grahamdennis.attribute.mavlink.MissionCurrent",
+    "me.grahamdennis.attribute.mavlink.Mission",
+    "me.grahamdennis.attribute.mavlink.BatteryStatus",
+    "me.grahamdennis.attribute.mavlink.Fence",
+    "me.grahamdennis.attribute.mavlink.Parameters",
+];
+
 fn build_attribute_protos() -> Result<()> {
     let mut config = Config::new();
 
@@ -18,7 +29,7 @@ fn build_attribute_protos() -> Result<()> {
         PathBuf::from(env::var("OUT_DIR").unwrap()).join("file_descriptor_set.attribute.bin");
     tonic_build::configure()
         .file_descriptor_set_path(file_descriptor_path)
-        .compile_with_config(config, protos, includes)?;
+        .compile_protos_with_config(config, protos, includes)?;
     Ok(())
 }
 
@@ -28,6 +39,15 @@ fn build_mavlink_protos() -> Result<()> {
     let protos = &["proto/mavlink.proto"];
     let includes = &["proto/", "../proto/"];
 
+    for message_name in MAVLINK_TYPED_ATTRIBUTES {
+        config.message_attribute(
+            format!(".{message_name}"),
+            format!(
+                "#[derive(attribute_derive::TypedAttribute)]\n#[attribute(name = \"{message_name}\")]"
+            ),
+        );
+    }
+
     prost_reflect_build::Builder::new()
         .file_descriptor_set_bytes("crate::pb::mavlink::FILE_DESCRIPTOR_SET")
         .configure(&mut config, protos, includes)?;
@@ -36,7 +56,7 @@ fn build_mavlink_protos() -> Result<()> {
         PathBuf::from(env::var("OUT_DIR").unwrap()).join("file_descriptor_set.mavlink.bin");
     tonic_build::configure()
         .file_descriptor_set_path(file_descriptor_path)
-        .compile_with_config(config, protos, includes)?;
+        .compile_protos_with_config(config, protos, includes)?;
     Ok(())
 }
 
@@ -0,0 +1,165 @@
+use crate::pb::attribute_store_client::AttributeStoreClient;
+use crate::pb::{
+    entity_query_node, EntityQueryNode, EntityRow, HasAttributeTypesNode, QueryEntityRowsRequest,
+};
+use crate::StatusError;
+use attribute_store::store::SymbolRef;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use tonic::transport::Channel;
+
+const SYMBOL_NAME: &str = "@symbolName";
+const VALUE_TYPE: &str = "@valueType";
+const DESCRIPTION: &str = "@description";
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct AttributeTypeSummary<'a> {
+    local_name: &'a str,
+    value_type: &'a str,
+    description: Option<&'a str>,
+}
+
+/// Writes the attribute types in the store grouped by namespace, with their value types and
+/// descriptions.
+pub async fn list_attribute_types(
+    mut attribute_store_client: AttributeStoreClient<Channel>,
+    output: &mut dyn Write,
+) -> anyhow::Result<()> {
+    // Value types are entities with symbols too, so a single query finds both the attribute types
+    // and the names of their value types
+    let response = attribute_store_client
+        .query_entity_rows(QueryEntityRowsRequest {
+            root: Some(EntityQueryNode {
+                query: Some(entity_query_node::Query::HasAttributeTypes(
+                    HasAttributeTypesNode {
+                        attribute_types: vec![SYMBOL_NAME.to_string()],
+                    },
+                )),
+            }),
+            attribute_types: [SYMBOL_NAME, VALUE_TYPE, DESCRIPTION]
+                .map(ToString::to_string)
+                .to_vec(),
+            include_deleted: false,
+            filter_nulls: false,
+            filter_rows_where_null: vec![],
+        })
+        .await
+        .map_err(StatusError::from)?
+        .into_inner();
+
+    write_attribute_types(&response.rows, output)
+}
+
+/// Writes the attribute types among `rows` of `@symbolName`, `@valueType` and `@description`,
+/// grouped by namespace.
+fn write_attribute_types(rows: &[EntityRow], output: &mut dyn Write) -> anyhow::Result<()> {
+    let symbols_by_entity_id: HashMap<&str, &str> = rows
+        .iter()
+        .filter_map(|row| Some((row.entity_id.as_str(), row.string_value(0)?.as_str())))
+        .collect();
+    let mut namespaces: BTreeMap<Option<&str>, Vec<AttributeTypeSummary>> = BTreeMap::new();
+    for row in rows {
+        let (Some(symbol), Some(value_type_id)) = (row.string_value(0), row.entity_id_value(1))
+        else {
+            continue;
+        };
+        let symbol = SymbolRef::try_from(symbol.as_str())?;
+        let value_type = symbols_by_entity_id
+            .get(value_type_id.as_str())
+            .map_or(value_type_id.as_str(), |value_type| {
+                value_type.strip_prefix("@valueType/").unwrap_or(value_type)
+            });
+        namespaces
+            .entry(symbol.namespace())
+            .or_default()
+            .push(AttributeTypeSummary {
+                local_name: symbol.local_name(),
+                value_type,
+                description: row.string_value(2).map(String::as_str),
+            });
+    }
+
+    for (namespace, mut attribute_types) in namespaces {
+        writeln!(output, "{}", namespace.unwrap_or("(no namespace)"))?;
+        attribute_types.sort();
+        for AttributeTypeSummary {
+            local_name,
+            value_type,
+            description,
+        } in attribute_types
+        {
+            write!(output, "  {local_name}: {value_type}")?;
+            if let Some(description) = description {
+                write!(output, " - {description}")?;
+            }
+            writeln!(output)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::{AttributeValue, NullableAttributeValue};
+
+    fn row(entity_id: &str, values: [Option<AttributeValue>; 3]) -> EntityRow {
+        EntityRow {
+            entity_id: entity_id.to_string(),
+            values: values
+                .into_iter()
+                .map(|value| NullableAttributeValue { value })
+                .collect(),
+            column_names: [SYMBOL_NAME, VALUE_TYPE, DESCRIPTION]
+                .map(ToString::to_string)
+                .to_vec(),
+        }
+    }
+
+    #[test]
+    fn attribute_types_are_grouped_by_namespace() {
+        let text = |value: &str| Some(AttributeValue::from_string(value));
+        let rows = [
+            row("1", [text("@valueType/text"), None, None]),
+            row(
+                "2",
+                [
+                    text("mavlink/heartbeat"),
+                    Some(AttributeValue::from_entity_id("1")),
+                    text("Latest heartbeat"),
+                ],
+            ),
+            row(
+                "3",
+                [
+                    text("mavlink/altitude"),
+                    Some(AttributeValue::from_entity_id("1")),
+                    None,
+                ],
+            ),
+            row(
+                "4",
+                [
+                    text("name"),
+                    Some(AttributeValue::from_entity_id("1")),
+                    None,
+                ],
+            ),
+        ];
+
+        let mut output = vec![];
+        write_attribute_types(&rows, &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+(no namespace)
+  name: text
+mavlink
+  altitude: text
+  heartbeat: text - Latest heartbeat
+"
+        );
+    }
+}
@@ -5,6 +5,7 @@ use crate::pb::{
     AttributeType, AttributeValue, CreateAttributeTypeRequest, EntityLocator, UpdateEntityRequest,
     ValueType,
 };
+use crate::StatusError;
 use prost_reflect::{DescriptorPool, MessageDescriptor, ReflectMessage};
 use tonic::transport::Channel;
 
@@ -14,6 +15,70 @@ pub trait TypedAttribute {
 }
 
 impl AttributeStoreClient<Channel> {
+    /// Loads the protobuf message descriptor registered for a protobuf attribute type.
+    pub async fn fetch_attribute_type_message_descriptor(
+        &mut self,
+        attribute_type: &str,
+    ) -> anyhow::Result<MessageDescriptor> {
+        let attribute_type_entity = self
+            .get_entity_by_locator(EntityLocator::builder().symbol(attribute_type).build())
+            .await?;
+        let message_name = match attribute_type_entity
+            .attributes
+            .get(AttributeTypes::MessageName.as_str())
+            .and_then(|value| value.attribute_value.as_ref())
+        {
+            Some(pb::attribute_value::AttributeValue::StringValue(message_name)) => message_name,
+            _ => anyhow::bail!("`{attribute_type}` is not a protobuf attribute type"),
+        };
+        let file_descriptor_set_entity_id = match attribute_type_entity
+            .attributes
+            .get(AttributeTypes::FileDescriptorSetRef.as_str())
+            .and_then(|value| value.attribute_value.as_ref())
+        {
+            Some(pb::attribute_value::AttributeValue::EntityIdValue(entity_id)) => entity_id,
+            _ => anyhow::bail!("`{attribute_type}` has no file descriptor set"),
+        };
+
+        let file_descriptor_set_entity = self
+            .get_entity_by_locator(
+                EntityLocator::builder()
+                    .entity_id(file_descriptor_set_entity_id)
+                    .build(),
+            )
+            .await?;
+        let file_descriptor_set_bytes = match file_descriptor_set_entity
+            .attributes
+            .get(AttributeTypes::FileDescriptorSet.as_str())
+            .and_then(|value| value.attribute_value.as_ref())
+        {
+            Some(pb::attribute_value::AttributeValue::BytesValue(bytes)) => bytes,
+            _ => {
+                anyhow::bail!("entity {file_descriptor_set_entity_id} is not a file descriptor set")
+            }
+        };
+
+        DescriptorPool::decode(file_descriptor_set_bytes.as_slice())?
+            .get_message_by_name(message_name)
+            .ok_or_else(|| {
+                anyhow::format_err!("message `{message_name}` not found in file descriptor set")
+            })
+    }
+
+    async fn get_entity_by_locator(
+        &mut self,
+        entity_locator: EntityLocator,
+    ) -> anyhow::Result<pb::Entity> {
+        self.get_entity(pb::GetEntityRequest {
+            entity_locator: Some(entity_locator),
+        })
+        .await
+        .map_err(StatusError::from)?
+        .into_inner()
+        .entity
+        .ok_or_else(|| anyhow::format_err!("missing entity in response"))
+    }
+
     pub async fn upload_protobuf_message_specs(
         &mut self,
         file_descriptor_set_bytes: &[u8],
@@ -55,21 +120,28 @@ impl AttributeStoreClient<Channel> {
                 .collect();
             tracing::info!(file_descriptor=file_descriptor.package_name(), messages=?message_full_names, "Uploading file descriptor");
             let create_fdset_request = UpdateEntityRequest {
-                entity_locator: Some(EntityLocator::from_symbol(file_descriptor.package_name())),
+                entity_locator: Some(
+                    EntityLocator::builder()
+                        .symbol(file_descriptor.package_name())
+                        .build(),
+                ),
                 attributes_to_update: vec![
                     pb::AttributeToUpdate {
                         attribute_type: "@symbolName".to_string(),
                         attribute_value: Some(AttributeValue::from_string(
                             file_descriptor.package_name(),
                         )),
+                        delete: false,
                     },
                     pb::AttributeToUpdate {
                         attribute_type: AttributeTypes::FileDescriptorSet.as_str().to_string(),
                         attribute_value: Some(AttributeValue::from_bytes(
                             file_descriptor_set_bytes.to_vec(),
                         )),
+                        delete: false,
                     },
                 ],
+                dry_run: false,
             };
             let fdset_response = self.update_entity(create_fdset_request).await?.into_inner();
             let fdset_entity = fdset_response
@@ -105,6 +177,8 @@ impl AttributeStoreClient<Channel> {
             attribute_type: Some(AttributeType {
                 symbol: symbol_name.to_string(),
                 value_type: ValueType::Bytes.into(),
+                list: false,
+                description: Some(format!("Serialized `{}` protobuf message", symbol_name)),
             }),
         };
         let create_attribute_result = self
@@ -121,51 +195,60 @@ impl AttributeStoreClient<Channel> {
         }
 
         let update_entity_request = UpdateEntityRequest {
-            entity_locator: Some(EntityLocator::from_symbol(symbol_name)),
+            entity_locator: Some(EntityLocator::builder().symbol(symbol_name).build()),
             attributes_to_update: vec![
                 pb::AttributeToUpdate {
                     attribute_type: "@symbolName".to_string(),
                     attribute_value: Some(AttributeValue::from_string(symbol_name)),
+                    delete: false,
                 },
                 pb::AttributeToUpdate {
                     attribute_type: AttributeTypes::MessageName.as_str().to_string(),
                     attribute_value: Some(AttributeValue::from_string(symbol_name)),
+                    delete: false,
                 },
                 pb::AttributeToUpdate {
                     attribute_type: AttributeTypes::FileDescriptorSetRef.as_str().to_string(),
                     attribute_value: Some(AttributeValue::from_entity_id(
                         file_descriptor_entity_id,
                     )),
+                    delete: false,
                 },
             ],
+            dry_run: false,
         };
         self.update_entity(update_entity_request).await
     }
 
+    #[allow(dead_code)]
     pub async fn update_protobuf_attribute_type<T: TypedAttribute + ReflectMessage + Default>(
         &mut self,
         file_descriptor_entity_id: &str,
     ) -> Result<tonic::Response<pb::UpdateEntityResponse>, tonic::Status> {
         let update_entity_request = UpdateEntityRequest {
-            entity_locator: Some(EntityLocator::from_symbol(T::attribute_name())),
+            entity_locator: Some(EntityLocator::builder().symbol(T::attribute_name()).build()),
             attributes_to_update: vec![
                 pb::AttributeToUpdate {
                     attribute_type: "@symbolName".to_string(),
                     attribute_value: Some(AttributeValue::from_string(T::attribute_name())),
+                    delete: false,
                 },
                 pb::AttributeToUpdate {
                     attribute_type: AttributeTypes::MessageName.as_str().to_string(),
                     attribute_value: Some(AttributeValue::from_string(
                         T::default().descriptor().full_name(),
                     )),
+                    delete: false,
                 },
                 pb::AttributeToUpdate {
                     attribute_type: AttributeTypes::FileDescriptorSetRef.as_str().to_string(),
                     attribute_value: Some(AttributeValue::from_entity_id(
                         file_descriptor_entity_id,
                     )),
+                    delete: false,
                 },
             ],
+            dry_run: false,
         };
         self.update_entity(update_entity_request).await
     }
@@ -177,17 +260,20 @@ impl AttributeStoreClient<Channel> {
         value: T,
     ) -> Result<tonic::Response<pb::UpdateEntityResponse>, tonic::Status> {
         self.update_entity(pb::UpdateEntityRequest {
-            entity_locator: Some(EntityLocator::from_symbol(symbol_id)),
+            entity_locator: Some(EntityLocator::builder().symbol(symbol_id).build()),
             attributes_to_update: vec![
                 pb::AttributeToUpdate {
                     attribute_type: "@symbolName".to_string(),
                     attribute_value: Some(AttributeValue::from_string(symbol_id)),
+                    delete: false,
                 },
                 pb::AttributeToUpdate {
                     attribute_type: T::attribute_name().to_string(),
                     attribute_value: Some(AttributeValue::from_bytes(value.as_bytes())),
+                    delete: false,
                 },
             ],
+            dry_run: false,
         })
         .await
     }
@@ -1,43 +1,309 @@
-use crate::json::to_json;
-use crate::pb::entity_query_node::Query;
-use crate::pb::{EntityQueryNode, HasAttributeTypesNode, WatchEntitiesRequest};
-use crate::{Cli, StatusError};
+use crate::json;
+use crate::pb::attribute_store_client::AttributeStoreClient;
+use crate::pb::watch_entities_event::Event;
+use crate::pb::{
+    AddedEvent, Entity, EntityLocator, EntityQueryNode, ModifiedEvent, UpdateEntityRequest,
+    WatchEntitiesRequest, WatchEventType,
+};
+use crate::StatusError;
+use anyhow::Context;
+use serde::Deserialize;
+use std::io::Write;
+use std::path::Path;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tonic::transport::Channel;
 
 // See the Bevy query system for a nice way of structuring reading queries.
 // Bevy defers updates via 'commands', which is more or less what we need to do here as well.
-async fn control_loop_iteration(_query: &[()]) -> anyhow::Result<()> {
-    Ok(())
+
+/// Applies `action` to each entity that matches `query`, both when the control loop starts and
+/// whenever a matching entity is added or modified.
+pub struct Rule {
+    pub query: EntityQueryNode,
+    pub action: Action,
+}
+
+pub enum Action {
+    /// Updates the matching entity. The entity locator of the request is ignored.
+    UpdateEntity(UpdateEntityRequest),
+    /// Sends the request as given, typically to create an entity by symbol.
+    CreateEntity(UpdateEntityRequest),
 }
 
-pub async fn control_loop(cli: &Cli) -> anyhow::Result<()> {
-    let request: WatchEntitiesRequest = WatchEntitiesRequest {
-        query: Some(EntityQueryNode {
-            query: Some(Query::HasAttributeTypes(HasAttributeTypesNode {
-                // Insert attribute types here
-                attribute_types: vec![],
-            })),
-        }),
+impl Action {
+    fn update_entity_request(&self, entity: &Entity) -> Option<UpdateEntityRequest> {
+        let update_entity_request = match self {
+            Action::UpdateEntity(update_entity_request) => UpdateEntityRequest {
+                entity_locator: Some(
+                    EntityLocator::builder()
+                        .entity_id(&entity.entity_id)
+                        .build(),
+                ),
+                ..update_entity_request.clone()
+            },
+            Action::CreateEntity(update_entity_request) => update_entity_request.clone(),
+        };
+
+        // Updates that wouldn't change the matching entity are skipped, otherwise a rule that
+        // updates the entities it matches would be re-triggered by its own update
+        let is_noop = update_entity_request
+            .entity_locator
+            .as_ref()
+            .and_then(EntityLocator::as_entity_id)
+            == Some(entity.entity_id.as_str())
+            && update_entity_request
+                .attributes_to_update
+                .iter()
+                .all(|attribute_to_update| {
+                    entity.attributes.get(&attribute_to_update.attribute_type)
+                        == attribute_to_update.attribute_value.as_ref()
+                });
+
+        (!is_noop).then_some(update_entity_request)
+    }
+}
+
+/// A rule as written in a rules file, with exactly one of the action fields set.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct RuleConfig {
+    query: serde_json::Value,
+    update_entity: Option<serde_json::Value>,
+    create_entity: Option<serde_json::Value>,
+}
+
+impl TryFrom<RuleConfig> for Rule {
+    type Error = anyhow::Error;
+
+    fn try_from(rule_config: RuleConfig) -> Result<Self, Self::Error> {
+        let query = json::parse_from_json_value(rule_config.query).context("invalid query")?;
+        let action = match (rule_config.update_entity, rule_config.create_entity) {
+            (Some(update_entity), None) => Action::UpdateEntity(
+                json::parse_from_json_value(update_entity).context("invalid updateEntity")?,
+            ),
+            (None, Some(create_entity)) => Action::CreateEntity(
+                json::parse_from_json_value(create_entity).context("invalid createEntity")?,
+            ),
+            _ => anyhow::bail!("exactly one of updateEntity or createEntity must be set"),
+        };
+
+        Ok(Rule { query, action })
+    }
+}
+
+/// Reads a JSON array of rules, each an object with a `query` and either an `updateEntity` or a
+/// `createEntity` request.
+pub fn load_rules(rules_file: &Path) -> anyhow::Result<Vec<Rule>> {
+    let rule_configs: Vec<RuleConfig> =
+        json::deserialize_from_json_argument(&format!("@{}", rules_file.display()))?;
+
+    rule_configs
+        .into_iter()
+        .enumerate()
+        .map(|(idx, rule_config)| {
+            Rule::try_from(rule_config).with_context(|| format!("invalid rule {idx}"))
+        })
+        .collect()
+}
+
+/// Forwards the entities that match `query` as they are added or modified.
+async fn watch_rule(
+    mut attribute_store_client: AttributeStoreClient<Channel>,
+    rule_idx: usize,
+    query: EntityQueryNode,
+    matches: mpsc::Sender<(usize, Entity)>,
+) -> anyhow::Result<()> {
+    let request = WatchEntitiesRequest {
+        query: Some(query),
         send_initial_events: true,
+        event_types: vec![
+            WatchEventType::Added.into(),
+            WatchEventType::Modified.into(),
+        ],
+        attribute_types: vec![],
+        batch_window_ms: 0,
     };
-
-    let mut attribute_store_client = crate::create_attribute_store_client(&cli.endpoint).await?;
     let response = attribute_store_client
         .watch_entities(request)
         .await
         .map_err(StatusError::from)?;
     let mut stream = response.into_inner();
 
+    while let Some(event) = stream.message().await? {
+        let entity = match event.event {
+            Some(Event::Added(AddedEvent { entity })) => entity,
+            Some(Event::Modified(ModifiedEvent { entity, .. })) => entity,
+            _ => None,
+        };
+        if let Some(entity) = entity {
+            if matches.send((rule_idx, entity)).await.is_err() {
+                // The control loop has stopped
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Watches the query of each rule and applies its action to the matching entities, writing each
+/// update response to `output`. Runs until every watch ends or fails.
+pub async fn control_loop(
+    attribute_store_client: AttributeStoreClient<Channel>,
+    rules: Vec<Rule>,
+    output: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let (matches_tx, mut matches_rx) = mpsc::channel(rules.len().max(1));
+    let mut watches = JoinSet::new();
+    for (rule_idx, rule) in rules.iter().enumerate() {
+        watches.spawn(watch_rule(
+            attribute_store_client.clone(),
+            rule_idx,
+            rule.query.clone(),
+            matches_tx.clone(),
+        ));
+    }
+    drop(matches_tx);
+
+    let mut attribute_store_client = attribute_store_client;
     loop {
         tokio::select! {
-            message = stream.message() => {
-                if let Some(event) = message? {
-                    println!("{}", to_json(&event)?);
-                } else {
+            Some(joined) = watches.join_next() => {
+                joined?.context("rule watch failed")?;
+            }
+            matched = matches_rx.recv() => {
+                let Some((rule_idx, entity)) = matched else {
                     break;
-                }
+                };
+                let Some(request) = rules[rule_idx].action.update_entity_request(&entity) else {
+                    continue;
+                };
+                let response = attribute_store_client
+                    .update_entity(request)
+                    .await
+                    .map_err(StatusError::from)
+                    .with_context(|| format!("rule {rule_idx} failed"))?;
+                writeln!(output, "{}", json::to_json(response.get_ref())?)?;
             }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::{AttributeToUpdate, AttributeValue};
+
+    fn entity(entity_id: &str, description: &str) -> Entity {
+        Entity {
+            entity_id: entity_id.to_string(),
+            entity_version: "1".to_string(),
+            attributes: [(
+                "@description".to_string(),
+                AttributeValue::from_string(description),
+            )]
+            .into(),
+        }
+    }
+
+    fn describe_request(entity_locator: EntityLocator, description: &str) -> UpdateEntityRequest {
+        UpdateEntityRequest {
+            entity_locator: Some(entity_locator),
+            attributes_to_update: vec![AttributeToUpdate {
+                attribute_type: "@description".to_string(),
+                attribute_value: Some(AttributeValue::from_string(description)),
+                delete: false,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn update_entity_action_locates_the_matching_entity() {
+        let action = Action::UpdateEntity(describe_request(
+            EntityLocator::builder().symbol("ignored").build(),
+            "updated",
+        ));
+
+        let request = action
+            .update_entity_request(&entity("7", "original"))
+            .unwrap();
+
+        assert_eq!(
+            request,
+            describe_request(EntityLocator::builder().entity_id("7").build(), "updated")
+        );
+    }
+
+    #[test]
+    fn updates_that_would_not_change_the_matching_entity_are_skipped() {
+        let action = Action::UpdateEntity(describe_request(EntityLocator::default(), "same"));
+
+        assert_eq!(action.update_entity_request(&entity("7", "same")), None);
+    }
+
+    #[test]
+    fn create_entity_action_sends_the_request_as_given() {
+        let create_request =
+            describe_request(EntityLocator::builder().symbol("new").build(), "same");
+        let action = Action::CreateEntity(create_request.clone());
+
+        assert_eq!(
+            action.update_entity_request(&entity("7", "same")),
+            Some(create_request)
+        );
+    }
+
+    fn load_rules_from(name: &str, json: &str) -> anyhow::Result<Vec<Rule>> {
+        let path =
+            std::env::temp_dir().join(format!("attribute-cli-{}-{name}.json", std::process::id()));
+        std::fs::write(&path, json).unwrap();
+        let rules = load_rules(&path);
+        std::fs::remove_file(&path).unwrap();
+        rules
+    }
+
+    #[test]
+    fn load_rules_reads_each_kind_of_action() {
+        let rules = load_rules_from(
+            "rules",
+            r#"[
+                {
+                    "query": {"hasAttributeTypes": {"attributeTypes": ["@description"]}},
+                    "updateEntity": {"attributesToUpdate": [{"attributeType": "@description", "delete": true}]}
+                },
+                {
+                    "query": {"matchAll": {}},
+                    "createEntity": {"entityLocator": {"symbol": "seen"}}
+                }
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(rules.len(), 2);
+        assert!(matches!(rules[0].action, Action::UpdateEntity(_)));
+        assert!(matches!(
+            &rules[1].action,
+            Action::CreateEntity(UpdateEntityRequest { entity_locator: Some(entity_locator), .. })
+                if entity_locator.as_symbol() == Some("seen")
+        ));
+    }
+
+    #[test]
+    fn load_rules_requires_exactly_one_action() {
+        let err = load_rules_from(
+            "invalid-rules",
+            r#"[{"query": {"matchAll": {}}, "updateEntity": {}, "createEntity": {}}]"#,
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(
+            format!("{err:#}"),
+            "invalid rule 0: exactly one of updateEntity or createEntity must be set"
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Compares two snapshots of entities, each a JSON `BatchGetEntitiesResponse` as written by
+//! `batch-get-entities`.
+
+use crate::json;
+use crate::pb::BatchGetEntitiesResponse;
+use anyhow::Context;
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use similar::{ChangeTag, TextDiff};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::Path;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffFormat {
+    /// Lines prefixed with `+` for added, `-` for removed and `~` for modified entities
+    Text,
+    /// A single JSON object of the added, removed and modified entities
+    Json,
+}
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Attribute values of each entity, keyed by entity ID and then attribute type
+type Snapshot = BTreeMap<String, BTreeMap<String, Value>>;
+
+fn read_snapshot(path: &Path) -> anyhow::Result<Snapshot> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("failed to read {path:?}"))?;
+    let response: BatchGetEntitiesResponse =
+        json::parse_from_json(&contents).with_context(|| format!("invalid snapshot {path:?}"))?;
+
+    response
+        .entities
+        .into_iter()
+        .map(|entity| {
+            let attributes = entity
+                .attributes
+                .iter()
+                .map(|(attribute_type, attribute_value)| {
+                    Ok((
+                        attribute_type.clone(),
+                        serde_json::from_str(&json::to_json(attribute_value)?)?,
+                    ))
+                })
+                .collect::<anyhow::Result<_>>()?;
+            Ok((entity.entity_id, attributes))
+        })
+        .collect()
+}
+
+/// Writes the entities added, removed and modified between the `before` and `after` snapshots.
+/// Text output is colored if `color` is set.
+pub fn diff(
+    before: &Path,
+    after: &Path,
+    format: DiffFormat,
+    color: bool,
+    output: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let before = read_snapshot(before)?;
+    let after = read_snapshot(after)?;
+    let entity_ids: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+
+    match format {
+        DiffFormat::Text => {
+            let paint = |color_code: &str, line: String| {
+                if color {
+                    format!("{color_code}{line}{RESET}")
+                } else {
+                    line
+                }
+            };
+            for entity_id in entity_ids {
+                match (before.get(entity_id), after.get(entity_id)) {
+                    (None, Some(attributes)) => {
+                        writeln!(output, "{}", paint(GREEN, format!("+ entity {entity_id}")))?;
+                        for line in attribute_lines(attributes).lines() {
+                            writeln!(output, "{}", paint(GREEN, format!("+   {line}")))?;
+                        }
+                    }
+                    (Some(attributes), None) => {
+                        writeln!(output, "{}", paint(RED, format!("- entity {entity_id}")))?;
+                        for line in attribute_lines(attributes).lines() {
+                            writeln!(output, "{}", paint(RED, format!("-   {line}")))?;
+                        }
+                    }
+                    (Some(before_attributes), Some(after_attributes))
+                        if before_attributes != after_attributes =>
+                    {
+                        writeln!(output, "{}", paint(YELLOW, format!("~ entity {entity_id}")))?;
+                        let before_lines = attribute_lines(before_attributes);
+                        let after_lines = attribute_lines(after_attributes);
+                        let text_diff = TextDiff::from_lines(&before_lines, &after_lines);
+                        for change in text_diff.iter_all_changes() {
+                            let line = change.value().trim_end();
+                            match change.tag() {
+                                ChangeTag::Delete => {
+                                    writeln!(output, "{}", paint(RED, format!("-   {line}")))?
+                                }
+                                ChangeTag::Insert => {
+                                    writeln!(output, "{}", paint(GREEN, format!("+   {line}")))?
+                                }
+                                ChangeTag::Equal => {}
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        DiffFormat::Json => {
+            let mut added = vec![];
+            let mut removed = vec![];
+            let mut modified = vec![];
+            for entity_id in entity_ids {
+                match (before.get(entity_id), after.get(entity_id)) {
+                    (None, Some(attributes)) => {
+                        added.push(json!({ "entityId": entity_id, "attributes": attributes }));
+                    }
+                    (Some(attributes), None) => {
+                        removed.push(json!({ "entityId": entity_id, "attributes": attributes }));
+                    }
+                    (Some(before_attributes), Some(after_attributes))
+                        if before_attributes != after_attributes =>
+                    {
+                        let attribute_types: BTreeSet<&String> = before_attributes
+                            .keys()
+                            .chain(after_attributes.keys())
+                            .collect();
+                        let changes: BTreeMap<&String, Value> = attribute_types
+                            .into_iter()
+                            .filter_map(|attribute_type| {
+                                let before_value = before_attributes.get(attribute_type);
+                                let after_value = after_attributes.get(attribute_type);
+                                (before_value != after_value).then(|| {
+                                    (
+                                        attribute_type,
+                                        json!({ "before": before_value, "after": after_value }),
+                                    )
+                                })
+                            })
+                            .collect();
+                        modified.push(json!({ "entityId": entity_id, "attributes": changes }));
+                    }
+                    _ => {}
+                }
+            }
+            writeln!(
+                output,
+                "{}",
+                json!({ "added": added, "removed": removed, "modified": modified })
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// One `attribute_type: value` line per attribute, in attribute type order.
+fn attribute_lines(attributes: &BTreeMap<String, Value>) -> String {
+    attributes
+        .iter()
+        .map(|(attribute_type, value)| format!("{attribute_type}: {value}\n"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEFORE: &str = r#"{"entities": [
+        {"entityId": "1", "attributes": {"@symbolName": {"stringValue": "kept"}, "@description": {"stringValue": "old"}}},
+        {"entityId": "2", "attributes": {"@symbolName": {"stringValue": "removed"}}}
+    ]}"#;
+    const AFTER: &str = r#"{"entities": [
+        {"entityId": "1", "attributes": {"@symbolName": {"stringValue": "kept"}, "@description": {"stringValue": "new"}}},
+        {"entityId": "3", "attributes": {"@symbolName": {"stringValue": "added"}}}
+    ]}"#;
+
+    fn diff_snapshots(name: &str, format: DiffFormat) -> String {
+        let path = |suffix: &str| {
+            std::env::temp_dir().join(format!(
+                "attribute-cli-{}-{name}-{suffix}.json",
+                std::process::id()
+            ))
+        };
+        let (before, after) = (path("before"), path("after"));
+        std::fs::write(&before, BEFORE).unwrap();
+        std::fs::write(&after, AFTER).unwrap();
+        let mut output = vec![];
+        let result = diff(&before, &after, format, false, &mut output);
+        std::fs::remove_file(&before).unwrap();
+        std::fs::remove_file(&after).unwrap();
+        result.unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn text_diff_lists_added_removed_and_modified_entities() {
+        assert_eq!(
+            diff_snapshots("text-diff", DiffFormat::Text),
+            "\
+~ entity 1
+-   @description: {\"stringValue\":\"old\"}
++   @description: {\"stringValue\":\"new\"}
+- entity 2
+-   @symbolName: {\"stringValue\":\"removed\"}
++ entity 3
++   @symbolName: {\"stringValue\":\"added\"}
+"
+        );
+    }
+
+    #[test]
+    fn json_diff_lists_only_the_changed_attributes_of_modified_entities() {
+        let output: Value =
+            serde_json::from_str(&diff_snapshots("json-diff", DiffFormat::Json)).unwrap();
+
+        assert_eq!(
+            output,
+            json!({
+                "added": [{"entityId": "3", "attributes": {"@symbolName": {"stringValue": "added"}}}],
+                "removed": [{"entityId": "2", "attributes": {"@symbolName": {"stringValue": "removed"}}}],
+                "modified": [{
+                    "entityId": "1",
+                    "attributes": {
+                        "@description": {"before": {"stringValue": "old"}, "after": {"stringValue": "new"}}
+                    }
+                }],
+            })
+        );
+    }
+}
@@ -2,15 +2,27 @@ use crate::pb;
 use crate::pb::watch_entity_rows_event::Event;
 use crate::pb::{AttributeValue, EntityRow, NullableAttributeValue, WatchEntityRowsEvent};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
+use clap::ValueEnum;
 use prost_reflect::{DynamicMessage, SerializeOptions};
 use serde::ser::{SerializeSeq, SerializeStruct};
 use serde::{ser, Serialize, Serializer};
 use std::fmt::Debug;
 use std::iter;
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One JSON object per event
+    Json,
+    /// A table of the current rows, redrawn in place as events arrive
+    Table,
+}
+
 #[derive(Debug, Clone)]
 pub enum ColumnMetadata {
     MessageDescriptor(prost_reflect::MessageDescriptor),
+    /// Bytes holding UTF-8 encoded JSON, which is embedded in the output as is. Bytes that aren't
+    /// valid JSON are formatted as base64 like any other bytes.
+    JsonBlob,
 }
 
 #[derive(Debug, Clone)]
@@ -25,6 +37,14 @@ pub fn wrap_watch_entity_rows_event<'a>(
     CustomFormat(event, metadata)
 }
 
+/// Formats an entity row as a JSON array, with the entity ID in the leading column.
+pub fn wrap_entity_row<'a>(
+    entity_row: &'a EntityRow,
+    metadata: &'a EntityRowMetadata,
+) -> impl Serialize + 'a {
+    CustomFormat(entity_row, metadata)
+}
+
 struct WithSerializeOptions<'a, T>(T, &'a SerializeOptions);
 
 impl<'a> Serialize for WithSerializeOptions<'a, DynamicMessage> {
@@ -117,7 +137,10 @@ impl<'a> Serialize for CustomFormat<'a, &EntityRow> {
     {
         let CustomFormat(entity_row, metadata) = self;
 
-        let mut state = serializer.serialize_seq(Some(entity_row.values.len()))?;
+        let mut state = serializer.serialize_seq(Some(entity_row.values.len() + 1))?;
+
+        // The entity ID is always the leading column, ahead of the requested attribute types
+        state.serialize_element(&entity_row.entity_id)?;
 
         for (entry, column) in iter::zip(&entity_row.values, &metadata.columns) {
             if let (
@@ -148,27 +171,114 @@ impl<'a> Serialize for CustomFormat<'a, &EntityRow> {
                 continue;
             }
 
+            if let (
+                NullableAttributeValue {
+                    value:
+                        Some(AttributeValue {
+                            attribute_value:
+                                Some(pb::attribute_value::AttributeValue::BytesValue(bytes)),
+                        }),
+                },
+                Some(ColumnMetadata::JsonBlob),
+            ) = (entry, column)
+            {
+                if let Some(json_value) = std::str::from_utf8(bytes)
+                    .ok()
+                    .and_then(|json| serde_json::from_str::<serde_json::Value>(json).ok())
+                {
+                    state.serialize_element(&json_value)?;
+                    continue;
+                }
+            }
+
             let attribute_value = entry
                 .value
                 .as_ref()
                 .and_then(|attribute_value| attribute_value.attribute_value.as_ref());
 
-            match attribute_value {
-                None => {
-                    state.serialize_element(&None::<String>)?;
-                }
-                Some(pb::attribute_value::AttributeValue::StringValue(s)) => {
-                    state.serialize_element(&s)?;
-                }
-                Some(pb::attribute_value::AttributeValue::EntityIdValue(entity_id)) => {
-                    state.serialize_element(&entity_id)?;
-                }
-                Some(pb::attribute_value::AttributeValue::BytesValue(bytes)) => {
-                    state.serialize_element(&STANDARD.encode(&bytes))?;
+            state.serialize_element(&attribute_value.map(PlainAttributeValue))?;
+        }
+
+        state.end()
+    }
+}
+
+/// Formats an attribute value that isn't decoded with a message descriptor.
+struct PlainAttributeValue<'a>(&'a pb::attribute_value::AttributeValue);
+
+impl<'a> Serialize for PlainAttributeValue<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let PlainAttributeValue(attribute_value) = self;
+        match attribute_value {
+            pb::attribute_value::AttributeValue::StringValue(s) => serializer.serialize_str(s),
+            pb::attribute_value::AttributeValue::EntityIdValue(entity_id) => {
+                serializer.serialize_str(entity_id)
+            }
+            pb::attribute_value::AttributeValue::BytesValue(bytes) => {
+                serializer.serialize_str(&STANDARD.encode(bytes))
+            }
+            pb::attribute_value::AttributeValue::TimestampValue(timestamp) => {
+                // RFC 3339, as in the protobuf JSON mapping of `Timestamp`
+                serializer.serialize_str(&timestamp.to_string())
+            }
+            pb::attribute_value::AttributeValue::Int64Value(value) => {
+                serializer.serialize_i64(*value)
+            }
+            pb::attribute_value::AttributeValue::ListValue(list) => {
+                let mut state = serializer.serialize_seq(Some(list.values.len()))?;
+                for value in &list.values {
+                    state.serialize_element(
+                        &value.attribute_value.as_ref().map(PlainAttributeValue),
+                    )?;
                 }
+                state.end()
             }
         }
+    }
+}
 
-        state.end()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn bytes_row(bytes: &[u8]) -> EntityRow {
+        EntityRow {
+            entity_id: "7".to_string(),
+            values: vec![NullableAttributeValue {
+                value: Some(AttributeValue {
+                    attribute_value: Some(pb::attribute_value::AttributeValue::BytesValue(
+                        bytes.to_vec(),
+                    )),
+                }),
+            }],
+            column_names: vec!["blob".to_string()],
+        }
+    }
+
+    fn format_row(entity_row: &EntityRow) -> serde_json::Value {
+        let metadata = EntityRowMetadata {
+            columns: vec![Some(ColumnMetadata::JsonBlob)],
+        };
+        serde_json::to_value(wrap_entity_row(entity_row, &metadata)).unwrap()
+    }
+
+    #[test]
+    fn json_blobs_are_embedded_as_json() {
+        assert_eq!(
+            format_row(&bytes_row(br#"{"a": [1, 2]}"#)),
+            json!(["7", {"a": [1, 2]}])
+        );
+    }
+
+    #[test]
+    fn json_blobs_that_are_not_json_are_formatted_as_base64() {
+        assert_eq!(
+            format_row(&bytes_row(&[0xff, 0x00])),
+            json!(["7", STANDARD.encode([0xff, 0x00])])
+        );
     }
 }
@@ -0,0 +1,92 @@
+use crate::json;
+use crate::pb::attribute_store_client::AttributeStoreClient;
+use crate::pb::UpdateEntityRequest;
+use crate::StatusError;
+use anyhow::format_err;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::num::NonZeroUsize;
+use std::path::Path;
+use tokio::task::JoinSet;
+use tonic::transport::Channel;
+
+/// Number of imported lines between progress reports
+const PROGRESS_INTERVAL: usize = 1000;
+
+#[derive(Default)]
+struct ImportProgress {
+    lines: usize,
+    errors: usize,
+}
+
+impl ImportProgress {
+    fn record(&mut self, line_number: usize, result: anyhow::Result<()>) {
+        self.lines += 1;
+        if let Err(err) = result {
+            self.errors += 1;
+            eprintln!("line {line_number}: {err:#}");
+        }
+        if self.lines.is_multiple_of(PROGRESS_INTERVAL) {
+            eprintln!("imported {} lines ({} errors)", self.lines, self.errors);
+        }
+    }
+}
+
+/// Sends each line of `file`, a JSON `UpdateEntityRequest`, with up to `parallel` requests in
+/// flight at once. Blank lines are skipped.
+pub async fn import(
+    attribute_store_client: AttributeStoreClient<Channel>,
+    file: &Path,
+    parallel: NonZeroUsize,
+) -> anyhow::Result<()> {
+    let mut progress = ImportProgress::default();
+    let mut join_set = JoinSet::new();
+
+    for (idx, line) in BufReader::new(File::open(file)?).lines().enumerate() {
+        let line_number = idx + 1;
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        while join_set.len() >= parallel.get() {
+            if let Some(joined) = join_set.join_next().await {
+                let (line_number, result) = joined?;
+                progress.record(line_number, result);
+            }
+        }
+
+        let request: UpdateEntityRequest = match json::parse_from_json(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                progress.record(line_number, Err(err));
+                continue;
+            }
+        };
+        let mut attribute_store_client = attribute_store_client.clone();
+        join_set.spawn(async move {
+            let result = attribute_store_client
+                .update_entity(request)
+                .await
+                .map(|_| ())
+                .map_err(|status| StatusError::from(status).into());
+            (line_number, result)
+        });
+    }
+
+    while let Some(joined) = join_set.join_next().await {
+        let (line_number, result) = joined?;
+        progress.record(line_number, result);
+    }
+
+    eprintln!(
+        "imported {} of {} lines",
+        progress.lines - progress.errors,
+        progress.lines
+    );
+    if progress.errors > 0 {
+        return Err(format_err!("{} lines failed to import", progress.errors));
+    }
+
+    Ok(())
+}
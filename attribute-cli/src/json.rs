@@ -1,8 +1,11 @@
+use anyhow::Context;
 use prost_reflect::{DynamicMessage, ReflectMessage, SerializeOptions};
+use serde::de::DeserializeOwned;
 use serde::{Deserializer, Serialize};
 use serde_path_to_error::Track;
 use std::fs::File;
 use std::io::BufReader;
+use std::path::Path;
 
 pub fn to_json<T: ReflectMessage>(message: &T) -> anyhow::Result<String> {
     let mut buffer = vec![];
@@ -32,25 +35,107 @@ pub fn serialize_to_json<T: Serialize>(value: &T) -> anyhow::Result<String> {
     Ok(String::from_utf8(buffer)?)
 }
 
+/// File named by an `@`-prefixed argument. `@-` reads JSON from stdin.
+enum ArgumentFile<'a> {
+    Stdin,
+    Json(&'a Path),
+    Yaml(&'a Path),
+}
+
+fn argument_file(json_argument: &str) -> Option<ArgumentFile<'_>> {
+    let path = Path::new(json_argument.strip_prefix('@')?);
+    if path.as_os_str() == "-" {
+        return Some(ArgumentFile::Stdin);
+    }
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("yaml" | "yml") => Some(ArgumentFile::Yaml(path)),
+        _ => Some(ArgumentFile::Json(path)),
+    }
+}
+
+/// Parses a message from a literal JSON argument, an `@file` reference to a JSON or YAML file, or
+/// `@-` for JSON on stdin.
 pub fn parse_from_json_argument<T: ReflectMessage + Default>(
     json_argument: &str,
 ) -> anyhow::Result<T> {
-    let parsed = if let Some(json_file) = json_argument.strip_prefix('@') {
-        let mut deserializer =
-            serde_json::de::Deserializer::from_reader(BufReader::new(File::open(json_file)?));
-        let result = parse_from_deserializer(&mut deserializer)?;
-        deserializer.end()?;
-        result
-    } else {
-        let mut deserializer = serde_json::de::Deserializer::from_str(json_argument);
-        let result = parse_from_deserializer(&mut deserializer)?;
-        deserializer.end()?;
-        result
+    let parsed = match argument_file(json_argument) {
+        None => parse_from_json(json_argument)?,
+        Some(ArgumentFile::Stdin) => {
+            let mut deserializer = serde_json::de::Deserializer::from_reader(std::io::stdin());
+            let result = parse_from_deserializer(&mut deserializer)?;
+            deserializer.end()?;
+            result
+        }
+        Some(ArgumentFile::Json(json_file)) => {
+            let mut deserializer =
+                serde_json::de::Deserializer::from_reader(BufReader::new(File::open(json_file)?));
+            let result = parse_from_deserializer(&mut deserializer)?;
+            deserializer.end()?;
+            result
+        }
+        Some(ArgumentFile::Yaml(yaml_file)) => parse_from_yaml_file(yaml_file)?,
     };
 
     Ok(parsed)
 }
 
+pub fn parse_from_yaml_file<T: ReflectMessage + Default>(yaml_file: &Path) -> anyhow::Result<T> {
+    let reader = BufReader::new(
+        File::open(yaml_file).with_context(|| format!("failed to open {yaml_file:?}"))?,
+    );
+
+    parse_from_deserializer(serde_yaml::Deserializer::from_reader(reader))
+}
+
+pub fn parse_from_json<T: ReflectMessage + Default>(json: &str) -> anyhow::Result<T> {
+    let mut deserializer = serde_json::de::Deserializer::from_str(json);
+    let result = parse_from_deserializer(&mut deserializer)?;
+    deserializer.end()?;
+
+    Ok(result)
+}
+
+/// Deserializes a value from either a literal JSON argument or an `@file` reference, as in
+/// `parse_from_json_argument`.
+pub fn deserialize_from_json_argument<T: DeserializeOwned>(
+    json_argument: &str,
+) -> anyhow::Result<T> {
+    let value = match argument_file(json_argument) {
+        None => serde_json::from_str(json_argument)?,
+        Some(ArgumentFile::Stdin) => serde_json::from_reader(std::io::stdin())?,
+        Some(ArgumentFile::Json(json_file)) => {
+            serde_json::from_reader(BufReader::new(File::open(json_file)?))?
+        }
+        Some(ArgumentFile::Yaml(yaml_file)) => {
+            serde_yaml::from_reader(BufReader::new(File::open(yaml_file)?))?
+        }
+    };
+
+    Ok(value)
+}
+
+/// Parses a JSON array of messages, from either a literal argument or an `@file` reference.
+pub fn parse_list_from_json_argument<T: ReflectMessage + Default>(
+    json_argument: &str,
+) -> anyhow::Result<Vec<T>> {
+    let elements: Vec<serde_json::Value> = deserialize_from_json_argument(json_argument)?;
+
+    elements
+        .into_iter()
+        .enumerate()
+        .map(|(idx, element)| {
+            parse_from_deserializer(element).with_context(|| format!("invalid element {idx}"))
+        })
+        .collect()
+}
+
+/// Parses a message from an already deserialized JSON value.
+pub fn parse_from_json_value<T: ReflectMessage + Default>(
+    value: serde_json::Value,
+) -> anyhow::Result<T> {
+    parse_from_deserializer(value)
+}
+
 fn parse_from_deserializer<'de, T: ReflectMessage + Default, D: Deserializer<'de>>(
     deserializer: D,
 ) -> anyhow::Result<T>
@@ -64,3 +149,65 @@ where
 
     Ok(message.transcode_to()?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb;
+
+    fn parse_yaml<T: ReflectMessage + Default>(name: &str, yaml: &str) -> T {
+        let path =
+            std::env::temp_dir().join(format!("attribute-cli-{}-{name}.yaml", std::process::id()));
+        std::fs::write(&path, yaml).unwrap();
+        let parsed = parse_from_json_argument(&format!("@{}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        parsed.unwrap()
+    }
+
+    #[test]
+    fn parses_update_entity_request_from_yaml() {
+        let request: pb::UpdateEntityRequest = parse_yaml(
+            "update-entity",
+            "entityLocator:\n  symbol: foo\nattributesToUpdate:\n  - attributeType: \"@symbolName\"\n    attributeValue:\n      stringValue: foo\n",
+        );
+        assert_eq!(
+            request.entity_locator,
+            Some(pb::EntityLocator::builder().symbol("foo").build())
+        );
+        assert_eq!(
+            request.attributes_to_update,
+            vec![pb::AttributeToUpdate {
+                attribute_type: "@symbolName".to_string(),
+                attribute_value: Some(pb::AttributeValue::from_string("foo")),
+                delete: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_query_entity_rows_request_from_yaml() {
+        let request: pb::QueryEntityRowsRequest = parse_yaml(
+            "query-entity-rows",
+            "root:\n  hasAttributeTypes:\n    attributeTypes: [\"@symbolName\"]\nattributeTypes:\n  - \"@id\"\n  - \"@symbolName\"\nfilterNulls: true\n",
+        );
+        assert_eq!(request.attribute_types, vec!["@id", "@symbolName"]);
+        assert!(request.filter_nulls);
+        assert!(matches!(
+            request.root.and_then(|root| root.query),
+            Some(pb::entity_query_node::Query::HasAttributeTypes(_))
+        ));
+    }
+
+    #[test]
+    fn parses_watch_entities_request_from_yaml() {
+        let request: pb::WatchEntitiesRequest = parse_yaml(
+            "watch-entities",
+            "query:\n  matchAll: {}\nsendInitialEvents: true\n",
+        );
+        assert!(request.send_initial_events);
+        assert!(matches!(
+            request.query.and_then(|query| query.query),
+            Some(pb::entity_query_node::Query::MatchAll(_))
+        ));
+    }
+}
@@ -1,27 +1,50 @@
+mod attribute_types;
 mod attributes;
 mod control_loop;
+mod diff;
 mod fmt;
+mod import;
 mod json;
 mod mavlink;
 mod pb;
+mod query;
+mod repl;
+mod retry;
+mod schema;
+mod table;
 
 use crate::control_loop::control_loop;
-use crate::fmt::{wrap_watch_entity_rows_event, ColumnMetadata, EntityRowMetadata};
-use crate::mavlink::{mavlink_run, AttributeTypes, MavlinkArgs};
+use crate::diff::DiffFormat;
+use crate::fmt::{wrap_watch_entity_rows_event, ColumnMetadata, EntityRowMetadata, OutputFormat};
+use crate::mavlink::{
+    mavlink_clear_fence, mavlink_run, mavlink_upload_fence, mavlink_upload_mission, AttributeTypes,
+    AutopilotArgs, MavlinkArgs, UploadFenceArgs, UploadMissionArgs,
+};
 use crate::pb::attribute_store_client::AttributeStoreClient;
 use crate::pb::{
-    CreateAttributeTypeRequest, EntityQueryNode, PingRequest, QueryEntityRowsRequest,
-    UpdateEntityRequest, WatchEntitiesRequest, WatchEntityRowsRequest,
+    AtomicQueryAndUpdateRequest, BatchGetEntitiesRequest, CreateAttributeTypeRequest,
+    EntityLocator, EntityQueryNode, GetAttributeTypeRequest, MergeEntitiesRequest, PingRequest,
+    QueryEntityRowsRequest, UpdateEntityRequest, Validate, WatchEntitiesRequest,
+    WatchEntityRequest, WatchEntityRowsRequest, WatchEventType,
 };
+use crate::query::EntityRowsRequestArgs;
+use crate::retry::RetryPolicy;
+use crate::table::EntityRowTable;
 use anyhow::format_err;
-use clap::{CommandFactory, Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use clap_complete::Shell;
+use hyper_util::rt::TokioIo;
 use prost_reflect::{DescriptorPool, ReflectMessage};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
-use std::future::Future;
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, LineWriter, Write};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::time::Duration;
 use thiserror::Error;
-use tonic::transport::{Channel, Endpoint};
+use tokio::net::UnixStream;
+use tonic::transport::{Channel, Endpoint, Uri};
 use tonic::Status;
 use tonic_types::{ErrorDetail, StatusExt};
 use tracing::level_filters::LevelFilter;
@@ -31,10 +54,31 @@ use tracing_subscriber::EnvFilter;
 #[command(version, about, long_about = None)]
 #[command(propagate_version = true)]
 struct Cli {
-    /// Endpoint to connect to
+    /// Endpoint to connect to, or `unix:///path/to/socket` for a Unix domain socket
     #[arg(short, long, default_value = "http://[::1]:50051")]
     endpoint: String,
 
+    /// Milliseconds to wait for the connection to the endpoint to be established
+    #[arg(long, default_value_t = 5000)]
+    connect_timeout_ms: u64,
+
+    /// Milliseconds to wait for the response to each request
+    #[arg(long, default_value_t = 30000)]
+    request_timeout_ms: u64,
+
+    /// Number of times to retry connecting, and unary requests that fail with `UNAVAILABLE` or
+    /// `DEADLINE_EXCEEDED`, with exponential backoff
+    #[arg(long, default_value_t = 0)]
+    max_retries: u32,
+
+    /// File to write command output to instead of stdout
+    #[arg(long, global = true)]
+    output_file: Option<PathBuf>,
+
+    /// Append to the output file rather than truncating it
+    #[arg(long, global = true, requires = "output_file")]
+    append: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -48,33 +92,121 @@ enum Commands {
         #[clap(short, long)]
         json: String,
     },
-    /// Query for entities
-    QueryEntityRows {
+    /// Get an attribute type by symbol
+    GetAttributeType {
         #[clap(short, long)]
         json: String,
     },
+    /// List the attribute types in the store, grouped by namespace
+    ListAttributeTypes,
+    /// Get several entities by locator in one request
+    BatchGetEntities {
+        /// JSON array of entity locators
+        #[clap(short, long)]
+        json: String,
+    },
+    /// Query for entities
+    QueryEntityRows {
+        #[clap(flatten)]
+        request: EntityRowsRequestArgs,
+        /// Stream rows as they are read rather than waiting for the whole result
+        #[clap(long)]
+        stream: bool,
+    },
     /// Update entity
     UpdateEntity {
         #[clap(short, long)]
         json: String,
+        /// Validate the update and print the resulting entity without committing it
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Copy attributes from one entity to another
+    MergeEntities {
+        #[clap(short, long)]
+        json: String,
+    },
+    /// Update an entity with values computed from its current attributes
+    AtomicQueryAndUpdate {
+        #[clap(short, long)]
+        json: String,
     },
     /// Watch for changes to entities
     WatchEntities {
         #[clap(short, long)]
         json: String,
+        /// Only watch for added entities. May be combined with the other `--only-*` flags
+        #[clap(long)]
+        only_added: bool,
+        /// Only watch for modified entities. May be combined with the other `--only-*` flags
+        #[clap(long)]
+        only_modified: bool,
+        /// Only watch for removed entities. May be combined with the other `--only-*` flags
+        #[clap(long)]
+        only_removed: bool,
+        /// Don't print bookmark events
+        #[clap(long)]
+        no_bookmark: bool,
     },
     /// Watch for changes to entity rows
     WatchEntityRows {
+        #[clap(flatten)]
+        request: EntityRowsRequestArgs,
+        /// Format of the output. Tables are only drawn to a terminal, falling back to JSON otherwise
+        #[clap(long, value_enum, default_value_t = OutputFormat::Json)]
+        output_format: OutputFormat,
+    },
+    /// Watch for changes to a single entity
+    WatchEntity {
         #[clap(short, long)]
         json: String,
     },
-    ControlLoop {},
+    /// Create or update entities from a file with one `UpdateEntityRequest` JSON object per line
+    Import {
+        #[clap(short, long)]
+        file: PathBuf,
+        /// Number of requests to send concurrently
+        #[clap(long, default_value_t = NonZeroUsize::MIN)]
+        parallel: NonZeroUsize,
+    },
+    /// Compare two snapshots of entities written by `batch-get-entities`
+    Diff {
+        before: PathBuf,
+        after: PathBuf,
+        #[clap(long, value_enum, default_value_t = DiffFormat::Text)]
+        format: DiffFormat,
+    },
+    /// Generate a JSON Schema for the values of a protobuf attribute type
+    GenerateSchema {
+        #[clap(short, long)]
+        attribute_type: String,
+        /// File to write the schema to instead of stdout
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Apply the rules in a file to matching entities as they are added or modified
+    ControlLoop {
+        /// JSON array of rules, each with a `query` and an `updateEntity` or `createEntity` request
+        #[clap(long)]
+        rules_file: PathBuf,
+    },
     Mavlink(MavlinkArgs),
+    /// Upload a mission to an autopilot and record it in the attribute store
+    UploadMission(UploadMissionArgs),
+    /// Upload a geofence to an autopilot and record it in the attribute store
+    UploadFence(UploadFenceArgs),
+    /// Remove the geofence from an autopilot and record that it has none
+    ClearFence(AutopilotArgs),
+    /// Start an interactive shell that accepts these commands over a single connection
+    Repl,
     /// Generate shell completions script
     GenerateCompletions {
-        /// shell to generate completions for
-        #[clap(short, long)]
+        /// shell to generate completions for. Detected from the environment if not given
+        #[clap(short, long, conflicts_with = "list")]
         shell: Option<Shell>,
+        /// Print the names of the supported shells instead
+        #[clap(long)]
+        list: bool,
     },
 }
 
@@ -116,22 +248,116 @@ impl Display for StatusError {
     }
 }
 
-fn print_completions<G: clap_complete::Generator>(gen: G, cmd: &mut clap::Command) {
-    clap_complete::generate(gen, cmd, cmd.get_name().to_string(), &mut std::io::stdout());
+fn print_completions<G: clap_complete::Generator>(
+    gen: G,
+    cmd: &mut clap::Command,
+    output: &mut dyn Write,
+) {
+    clap_complete::generate(gen, cmd, cmd.get_name().to_string(), output);
+}
+
+fn query_entity_rows_request(
+    root: EntityQueryNode,
+    attribute_types: Vec<String>,
+) -> QueryEntityRowsRequest {
+    QueryEntityRowsRequest {
+        root: Some(root),
+        attribute_types,
+        ..Default::default()
+    }
 }
 
-async fn send_request<T: ReflectMessage + Default, R: ReflectMessage, Fut>(
+/// Detects the shell from `$SHELL`, falling back to the version variables that shells set, which
+/// are present where `$SHELL` isn't set or names a different shell to the one running.
+fn detect_shell() -> Option<Shell> {
+    Shell::from_env().or_else(|| {
+        [
+            ("FISH_VERSION", Shell::Fish),
+            ("ZSH_VERSION", Shell::Zsh),
+            ("BASH_VERSION", Shell::Bash),
+        ]
+        .into_iter()
+        .find_map(|(variable, shell)| std::env::var_os(variable).map(|_| shell))
+    })
+}
+
+/// Destination of command output: stdout, or the file given by `--output-file`.
+pub type Output = Box<dyn Write>;
+
+fn open_output(cli: &Cli) -> anyhow::Result<Output> {
+    let Some(path) = &cli.output_file else {
+        return Ok(Box::new(std::io::stdout()));
+    };
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(cli.append)
+        .truncate(!cli.append)
+        .open(path)?;
+
+    // Flush each line so that streamed events reach the file as they arrive
+    Ok(Box::new(LineWriter::new(file)))
+}
+
+/// The event types to watch for given the `watch-entities` flags, which replace any event types in
+/// the request. Every type of entity event is watched for if none of the `only_*` flags are set.
+fn watch_event_types(
+    only_added: bool,
+    only_modified: bool,
+    only_removed: bool,
+    no_bookmark: bool,
+) -> Vec<WatchEventType> {
+    let all_entity_events = !(only_added || only_modified || only_removed);
+    [
+        (WatchEventType::Added, only_added || all_entity_events),
+        (WatchEventType::Modified, only_modified || all_entity_events),
+        (WatchEventType::Removed, only_removed || all_entity_events),
+        (WatchEventType::Bookmark, !no_bookmark),
+    ]
+    .into_iter()
+    .filter_map(|(event_type, watched)| watched.then_some(event_type))
+    .collect()
+}
+
+fn warn_entity_not_found(entity_locator: EntityLocator) {
+    if let Some(symbol) = entity_locator.clone().try_into_symbol() {
+        tracing::warn!("No entity found with symbol `{symbol}`");
+    } else if let Some(entity_id) = entity_locator.try_into_entity_id() {
+        tracing::warn!("No entity found with ID `{entity_id}`");
+    }
+}
+
+/// Rejects `request` before it is sent if it fails client-side validation
+fn validate_request(request: &impl Validate) -> anyhow::Result<()> {
+    request
+        .validate()
+        .map_err(|errors| format_err!("Invalid request: {}", errors.join("; ")))
+}
+
+async fn send_request<T: ReflectMessage + Default + Clone + Validate, R: ReflectMessage>(
     json: &str,
-    call: impl FnOnce(T) -> Fut,
-) -> anyhow::Result<()>
-where
-    Fut: Future<Output = Result<tonic::Response<R>, Status>>,
-{
+    output: &mut dyn Write,
+    retry_policy: RetryPolicy,
+    call: impl AsyncFnMut(T) -> Result<tonic::Response<R>, Status>,
+) -> anyhow::Result<()> {
     let request: T = json::parse_from_json_argument(json)?;
+    send_parsed_request(request, output, retry_policy, call).await
+}
 
-    let response = call(request).await.map_err(StatusError::from)?;
+async fn send_parsed_request<T: Clone + Validate, R: ReflectMessage>(
+    request: T,
+    output: &mut dyn Write,
+    retry_policy: RetryPolicy,
+    mut call: impl AsyncFnMut(T) -> Result<tonic::Response<R>, Status>,
+) -> anyhow::Result<()> {
+    validate_request(&request)?;
+
+    let response = retry_policy
+        .call(async || call(request.clone()).await)
+        .await
+        .map_err(StatusError::from)?;
     let response = response.into_inner();
-    println!("{}", json::to_json(&response)?);
+    writeln!(output, "{}", json::to_json(&response)?)?;
 
     Ok(())
 }
@@ -147,143 +373,276 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     let cli = Cli::parse();
+    let mut connection = Connection::new(&cli)?;
+    let mut output = open_output(&cli)?;
+
+    match &cli.command {
+        Commands::Repl => repl::run(&cli, &mut connection, output.as_mut()).await,
+        command => run_command(&cli, command, &mut connection, output.as_mut()).await,
+    }
+}
 
+/// Lazily connects to the attribute server, reusing the channel across commands.
+pub struct Connection {
+    endpoint: Endpoint,
+    unix_socket: Option<PathBuf>,
+    retry_policy: RetryPolicy,
+    client: Option<AttributeStoreClient<Channel>>,
+}
+
+impl Connection {
+    fn new(cli: &Cli) -> anyhow::Result<Self> {
+        let unix_socket = cli.endpoint.strip_prefix("unix://").map(PathBuf::from);
+        // The URI of a Unix domain socket endpoint is only used for request headers
+        let uri = match unix_socket {
+            Some(_) => "http://localhost".to_string(),
+            None => cli.endpoint.clone(),
+        };
+        Ok(Connection {
+            endpoint: Endpoint::from_shared(uri)?
+                .connect_timeout(Duration::from_millis(cli.connect_timeout_ms))
+                .timeout(Duration::from_millis(cli.request_timeout_ms)),
+            unix_socket,
+            retry_policy: RetryPolicy {
+                max_retries: cli.max_retries,
+                retry_deadline_exceeded: true,
+            },
+            client: None,
+        })
+    }
+
+    async fn client(&mut self) -> anyhow::Result<AttributeStoreClient<Channel>> {
+        if let Some(client) = &self.client {
+            return Ok(client.clone());
+        }
+        // Connection failures are always retried, as nothing has been sent yet
+        let channel = self
+            .retry_policy
+            .retry(
+                |_| true,
+                async || match &self.unix_socket {
+                    Some(unix_socket) => {
+                        let unix_socket = unix_socket.clone();
+                        self.endpoint
+                            .connect_with_connector(tower::service_fn(move |_: Uri| {
+                                let unix_socket = unix_socket.clone();
+                                async move {
+                                    Ok::<_, std::io::Error>(TokioIo::new(
+                                        UnixStream::connect(unix_socket).await?,
+                                    ))
+                                }
+                            }))
+                            .await
+                    }
+                    None => self.endpoint.connect().await,
+                },
+            )
+            .await?;
+        let client = AttributeStoreClient::new(channel);
+        self.client = Some(client.clone());
+        Ok(client)
+    }
+}
+
+async fn run_command(
+    cli: &Cli,
+    command: &Commands,
+    connection: &mut Connection,
+    output: &mut dyn Write,
+) -> anyhow::Result<()> {
     // You can check for the existence of subcommands, and if found use their
     // matches just as you would the top level cmd
-    match &cli.command {
+    match command {
         Commands::Ping => {
-            let mut attribute_store_client = create_attribute_store_client(&cli.endpoint).await?;
-            let response = attribute_store_client.ping(PingRequest {}).await?;
-            println!("response: {:?}", response);
+            let mut attribute_store_client = connection.client().await?;
+            let response = connection
+                .retry_policy
+                .call(async || attribute_store_client.ping(PingRequest {}).await)
+                .await?;
+            writeln!(output, "response: {:?}", response)?;
 
             Ok(())
         }
         Commands::CreateAttributeType { json } => {
-            let mut client = create_attribute_store_client(&cli.endpoint).await?;
-            send_request(json, |request: CreateAttributeTypeRequest| {
-                client.create_attribute_type(request)
-            })
+            let mut client = connection.client().await?;
+            send_request(
+                json,
+                output,
+                connection.retry_policy,
+                async |request: CreateAttributeTypeRequest| {
+                    client.create_attribute_type(request).await
+                },
+            )
             .await
         }
-        Commands::QueryEntityRows { json } => {
-            let mut client = create_attribute_store_client(&cli.endpoint).await?;
-            send_request(json, |request: QueryEntityRowsRequest| {
-                client.query_entity_rows(request)
-            })
+        Commands::GetAttributeType { json } => {
+            let mut client = connection.client().await?;
+            send_request(
+                json,
+                output,
+                connection.retry_policy,
+                async |request: GetAttributeTypeRequest| client.get_attribute_type(request).await,
+            )
             .await
         }
-        Commands::UpdateEntity { json } => {
-            let mut client = create_attribute_store_client(&cli.endpoint).await?;
-            send_request(json, |request: UpdateEntityRequest| {
-                client.update_entity(request)
-            })
+        Commands::BatchGetEntities { json } => {
+            let entity_locators: Vec<EntityLocator> = json::parse_list_from_json_argument(json)?;
+
+            let mut attribute_store_client = connection.client().await?;
+            let response = connection
+                .retry_policy
+                .call(async || {
+                    attribute_store_client
+                        .batch_get_entities(BatchGetEntitiesRequest {
+                            entity_locators: entity_locators.clone(),
+                        })
+                        .await
+                })
+                .await
+                .map_err(StatusError::from)?
+                .into_inner();
+            writeln!(output, "{}", json::to_json(&response)?)?;
+            for entity_locator in response.not_found {
+                warn_entity_not_found(entity_locator);
+            }
+
+            Ok(())
+        }
+        Commands::QueryEntityRows {
+            request,
+            stream: false,
+        } => {
+            let request = request.parse(query_entity_rows_request)?;
+            let mut client = connection.client().await?;
+            send_parsed_request(
+                request,
+                output,
+                connection.retry_policy,
+                async |request: QueryEntityRowsRequest| client.query_entity_rows(request).await,
+            )
+            .await
+        }
+        Commands::QueryEntityRows {
+            request,
+            stream: true,
+        } => {
+            let request = request.parse(query_entity_rows_request)?;
+            validate_request(&request)?;
+
+            let mut attribute_store_client = connection.client().await?;
+            let response = attribute_store_client
+                .stream_entity_rows(request)
+                .await
+                .map_err(StatusError::from)?;
+            let mut stream = response.into_inner();
+            while let Some(entity_row) = stream.message().await? {
+                writeln!(output, "{}", json::to_json(&entity_row)?)?;
+            }
+
+            Ok(())
+        }
+        Commands::UpdateEntity { json, dry_run } => {
+            let request: UpdateEntityRequest = json::parse_from_json_argument(json)?;
+            // Retrying a creation that timed out after being applied would create a second entity
+            let retry_policy = if request
+                .entity_locator
+                .as_ref()
+                .is_some_and(EntityLocator::is_create_new)
+            {
+                connection.retry_policy.for_non_idempotent_requests()
+            } else {
+                connection.retry_policy
+            };
+            let mut client = connection.client().await?;
+            send_parsed_request(
+                request,
+                output,
+                retry_policy,
+                async |request: UpdateEntityRequest| {
+                    client
+                        .update_entity(UpdateEntityRequest {
+                            dry_run: request.dry_run || *dry_run,
+                            ..request
+                        })
+                        .await
+                },
+            )
+            .await
+        }
+        Commands::MergeEntities { json } => {
+            let mut client = connection.client().await?;
+            send_request(
+                json,
+                output,
+                connection.retry_policy,
+                async |request: MergeEntitiesRequest| client.merge_entities(request).await,
+            )
             .await
         }
-        Commands::WatchEntities { json } => {
-            let request: WatchEntitiesRequest = json::parse_from_json_argument(json)?;
+        Commands::AtomicQueryAndUpdate { json } => {
+            let mut client = connection.client().await?;
+            // Not retried, as an update whose response was lost would be applied twice
+            send_request(
+                json,
+                output,
+                RetryPolicy {
+                    max_retries: 0,
+                    retry_deadline_exceeded: false,
+                },
+                async |request: AtomicQueryAndUpdateRequest| {
+                    client.atomic_query_and_update(request).await
+                },
+            )
+            .await
+        }
+        Commands::WatchEntities {
+            json,
+            only_added,
+            only_modified,
+            only_removed,
+            no_bookmark,
+        } => {
+            let mut request: WatchEntitiesRequest = json::parse_from_json_argument(json)?;
+            if *only_added || *only_modified || *only_removed || *no_bookmark {
+                request.event_types =
+                    watch_event_types(*only_added, *only_modified, *only_removed, *no_bookmark)
+                        .into_iter()
+                        .map(Into::into)
+                        .collect();
+            }
+            validate_request(&request)?;
 
-            let mut attribute_store_client = create_attribute_store_client(&cli.endpoint).await?;
+            let mut attribute_store_client = connection.client().await?;
             let response = attribute_store_client
                 .watch_entities(request)
                 .await
                 .map_err(StatusError::from)?;
             let mut stream = response.into_inner();
             while let Some(event) = stream.message().await? {
-                println!("{}", json::to_json(&event)?);
+                writeln!(output, "{}", json::to_json(&event)?)?;
             }
 
             Ok(())
         }
-        Commands::WatchEntityRows { json } => {
-            let request: WatchEntityRowsRequest = json::parse_from_json_argument(json)?;
-
-            let mut attribute_store_client = create_attribute_store_client(&cli.endpoint).await?;
-
-            let protobuf_metadata_attribute_types = vec![
-                "@symbolName".to_string(),
-                AttributeTypes::FileDescriptorSetRef.as_str().to_string(),
-                AttributeTypes::MessageName.as_str().to_string(),
-            ];
-            let file_descriptor_set_attribute_types = vec![
-                "@id".to_string(),
-                AttributeTypes::FileDescriptorSet.as_str().to_string(),
-            ];
-
-            let query_protobuf_metadata = QueryEntityRowsRequest {
-                root: Some(EntityQueryNode {
-                    query: Some(pb::entity_query_node::Query::HasAttributeTypes(
-                        pb::HasAttributeTypesNode {
-                            attribute_types: protobuf_metadata_attribute_types.clone(),
-                        },
-                    )),
-                }),
-                attribute_types: protobuf_metadata_attribute_types.clone(),
-            };
-
-            // attribute_type => (file_descriptor_set_entity_id, message_name)
-            let protobuf_metadatas: HashMap<String, (String, String)> = attribute_store_client
-                .query_entity_rows(query_protobuf_metadata)
-                .await?
-                .into_inner()
-                .rows
-                .into_iter()
-                .filter_map(|row| {
-                    let attribute_type = row.string_value(0)?.clone();
-                    let file_descriptor_set_entity_id = row.entity_id_value(1)?.clone();
-
-                    let message_name = row.string_value(2)?.clone();
-
-                    Some((
-                        attribute_type,
-                        (file_descriptor_set_entity_id, message_name),
-                    ))
-                })
-                .collect();
-
-            let file_descriptor_sets: HashMap<String, DescriptorPool> = attribute_store_client
-                .query_entity_rows(QueryEntityRowsRequest {
-                    root: Some(EntityQueryNode {
-                        query: Some(pb::entity_query_node::Query::HasAttributeTypes(
-                            pb::HasAttributeTypesNode {
-                                attribute_types: vec![AttributeTypes::FileDescriptorSet
-                                    .as_str()
-                                    .to_string()],
-                            },
-                        )),
-                    }),
-                    attribute_types: file_descriptor_set_attribute_types.clone(),
-                })
-                .await?
-                .into_inner()
-                .rows
-                .into_iter()
-                .filter_map(|row| {
-                    let entity_id = row.entity_id_value(0)?.clone();
-                    let file_descriptor_set_bytes = row.bytes_value(1)?;
-
-                    let descriptor_pool =
-                        DescriptorPool::decode(file_descriptor_set_bytes.as_slice()).ok()?;
-
-                    Some((entity_id, descriptor_pool))
-                })
-                .collect();
-
-            let entity_row_metadata = EntityRowMetadata {
-                columns: request
-                    .attribute_types
-                    .iter()
-                    .map(|attribute_type| {
-                        let (file_descriptor_set_entity_id, message_name) =
-                            protobuf_metadatas.get(attribute_type)?;
-
-                        let descriptor_pool =
-                            file_descriptor_sets.get(file_descriptor_set_entity_id)?;
-                        let message_descriptor =
-                            descriptor_pool.get_message_by_name(message_name)?;
-                        Some(ColumnMetadata::MessageDescriptor(message_descriptor))
-                    })
-                    .collect(),
-            };
+        Commands::WatchEntityRows {
+            request,
+            output_format,
+        } => {
+            let request = request.parse(|query, attribute_types| WatchEntityRowsRequest {
+                query: Some(query),
+                attribute_types,
+                ..Default::default()
+            })?;
+            let mut table = (*output_format == OutputFormat::Table
+                && cli.output_file.is_none()
+                && std::io::stdout().is_terminal())
+            .then(|| EntityRowTable::new(&request.attribute_types));
+
+            let mut attribute_store_client = connection.client().await?;
+
+            let entity_row_metadata =
+                fetch_entity_row_metadata(&mut attribute_store_client, &request.attribute_types)
+                    .await?;
             let response = attribute_store_client
                 .watch_entity_rows(request)
                 .await
@@ -291,42 +650,292 @@ async fn main() -> anyhow::Result<()> {
 
             let mut stream = response.into_inner();
             while let Some(event) = stream.message().await? {
-                println!(
+                if let Some(table) = &mut table {
+                    if table.apply(&event, &entity_row_metadata)? {
+                        let terminal_height = crossterm::terminal::size()
+                            .map_or(0, |(_columns, rows)| usize::from(rows));
+                        table.redraw(output, terminal_height)?;
+                    }
+                    continue;
+                }
+                writeln!(
+                    output,
                     "{}",
                     json::serialize_to_json(&wrap_watch_entity_rows_event(
                         &event,
                         &entity_row_metadata
                     ))?
-                );
+                )?;
             }
 
             Ok(())
         }
-        Commands::GenerateCompletions { shell } => Ok(print_completions(
-            shell
-                .or_else(|| Shell::from_env())
-                .ok_or_else(|| format_err!("specify shell with `--shell`"))?,
-            &mut Cli::command(),
-        )),
-        Commands::ControlLoop { .. } => {
-            let _ = control_loop(&cli).await?;
+        Commands::WatchEntity { json } => {
+            let request: WatchEntityRequest = json::parse_from_json_argument(json)?;
+
+            let mut attribute_store_client = connection.client().await?;
+            let entity_row_metadata =
+                fetch_entity_row_metadata(&mut attribute_store_client, &request.attribute_types)
+                    .await?;
+            let response = attribute_store_client
+                .watch_entity(request)
+                .await
+                .map_err(StatusError::from)?;
+
+            let mut stream = response.into_inner();
+            while let Some(event) = stream.message().await? {
+                writeln!(
+                    output,
+                    "{}",
+                    json::serialize_to_json(&wrap_watch_entity_rows_event(
+                        &event,
+                        &entity_row_metadata
+                    ))?
+                )?;
+            }
+
+            Ok(())
+        }
+        Commands::ListAttributeTypes => {
+            let attribute_store_client = connection.client().await?;
+            attribute_types::list_attribute_types(attribute_store_client, output).await
+        }
+        Commands::Import { file, parallel } => {
+            let attribute_store_client = connection.client().await?;
+            import::import(attribute_store_client, file, *parallel).await
+        }
+        Commands::Diff {
+            before,
+            after,
+            format,
+        } => {
+            // Only color output that is shown in a terminal
+            let color = cli.output_file.is_none() && std::io::stdout().is_terminal();
+            diff::diff(before, after, *format, color, output)
+        }
+        Commands::GenerateSchema {
+            attribute_type,
+            output: schema_file,
+        } => {
+            let mut attribute_store_client = connection.client().await?;
+            let message_descriptor = attribute_store_client
+                .fetch_attribute_type_message_descriptor(attribute_type)
+                .await?;
+            let schema =
+                serde_json::to_string_pretty(&schema::message_json_schema(&message_descriptor))?;
+
+            match schema_file {
+                Some(path) => std::fs::write(path, schema + "\n")?,
+                None => writeln!(output, "{}", schema)?,
+            }
+
+            Ok(())
+        }
+        Commands::GenerateCompletions { shell, list } => {
+            if *list {
+                for shell in Shell::value_variants() {
+                    writeln!(output, "{shell}")?;
+                }
+                return Ok(());
+            }
+
+            print_completions(
+                shell
+                    .or_else(detect_shell)
+                    .ok_or_else(|| format_err!("specify shell with `--shell`"))?,
+                &mut Cli::command(),
+                output,
+            );
+
+            Ok(())
+        }
+        Commands::Repl => Err(format_err!("already running the REPL")),
+        Commands::ControlLoop { rules_file } => {
+            let rules = control_loop::load_rules(rules_file)?;
+            let attribute_store_client = connection.client().await?;
+            control_loop(attribute_store_client, rules, output).await?;
 
             Ok(())
         }
         Commands::Mavlink(mavlink_args) => {
-            let _ = mavlink_run(&cli, mavlink_args).await?;
+            mavlink_run(cli, mavlink_args, output).await?;
 
             Ok(())
         }
+        Commands::UploadMission(upload_mission_args) => {
+            mavlink_upload_mission(cli, upload_mission_args).await?;
+
+            Ok(())
+        }
+        Commands::UploadFence(upload_fence_args) => {
+            mavlink_upload_fence(cli, upload_fence_args).await
+        }
+        Commands::ClearFence(autopilot_args) => mavlink_clear_fence(cli, autopilot_args).await,
     }
 }
 
-async fn create_attribute_store_client(
-    endpoint: &str,
-) -> anyhow::Result<AttributeStoreClient<Channel>> {
-    let channel = Endpoint::from_shared(endpoint.to_string())?
-        .connect()
-        .await?;
+/// Looks up the protobuf message descriptors of `attribute_types` so that their values can be
+/// printed as JSON. Bytes attribute types without a message descriptor are printed as JSON blobs.
+async fn fetch_entity_row_metadata(
+    attribute_store_client: &mut AttributeStoreClient<Channel>,
+    attribute_types: &[String],
+) -> anyhow::Result<EntityRowMetadata> {
+    let protobuf_metadata_attribute_types = vec![
+        "@symbolName".to_string(),
+        AttributeTypes::FileDescriptorSetRef.as_str().to_string(),
+        AttributeTypes::MessageName.as_str().to_string(),
+    ];
+    let file_descriptor_set_attribute_types = vec![
+        "@id".to_string(),
+        AttributeTypes::FileDescriptorSet.as_str().to_string(),
+    ];
+
+    let query_protobuf_metadata = QueryEntityRowsRequest {
+        root: Some(EntityQueryNode {
+            query: Some(pb::entity_query_node::Query::HasAttributeTypes(
+                pb::HasAttributeTypesNode {
+                    attribute_types: protobuf_metadata_attribute_types.clone(),
+                },
+            )),
+        }),
+        attribute_types: protobuf_metadata_attribute_types.clone(),
+        include_deleted: false,
+        filter_nulls: false,
+        filter_rows_where_null: vec![],
+    };
+
+    // attribute_type => (file_descriptor_set_entity_id, message_name)
+    let protobuf_metadatas: HashMap<String, (String, String)> = attribute_store_client
+        .query_entity_rows(query_protobuf_metadata)
+        .await?
+        .into_inner()
+        .rows
+        .into_iter()
+        .filter_map(|row| {
+            let attribute_type = row.string_value(0)?.clone();
+            let file_descriptor_set_entity_id = row.entity_id_value(1)?.clone();
+
+            let message_name = row.string_value(2)?.clone();
+
+            Some((
+                attribute_type,
+                (file_descriptor_set_entity_id, message_name),
+            ))
+        })
+        .collect();
+
+    let file_descriptor_sets: HashMap<String, DescriptorPool> = attribute_store_client
+        .query_entity_rows(QueryEntityRowsRequest {
+            root: Some(EntityQueryNode {
+                query: Some(pb::entity_query_node::Query::HasAttributeTypes(
+                    pb::HasAttributeTypesNode {
+                        attribute_types: vec![AttributeTypes::FileDescriptorSet
+                            .as_str()
+                            .to_string()],
+                    },
+                )),
+            }),
+            attribute_types: file_descriptor_set_attribute_types.clone(),
+            include_deleted: false,
+            filter_nulls: false,
+            filter_rows_where_null: vec![],
+        })
+        .await?
+        .into_inner()
+        .rows
+        .into_iter()
+        .filter_map(|row| {
+            let entity_id = row.entity_id_value(0)?.clone();
+            let file_descriptor_set_bytes = row.bytes_value(1)?;
+
+            let descriptor_pool =
+                DescriptorPool::decode(file_descriptor_set_bytes.as_slice()).ok()?;
+
+            Some((entity_id, descriptor_pool))
+        })
+        .collect();
+
+    // Bytes attribute types without a `FileDescriptorSetRef` are assumed to hold JSON
+    let untyped_attribute_types: Vec<&str> = attribute_types
+        .iter()
+        .filter(|attribute_type| !protobuf_metadatas.contains_key(*attribute_type))
+        .map(String::as_str)
+        .collect();
+    let bytes_attribute_types = if untyped_attribute_types.is_empty() {
+        HashSet::new()
+    } else {
+        fetch_bytes_attribute_types(attribute_store_client, &untyped_attribute_types).await?
+    };
+
+    Ok(EntityRowMetadata {
+        columns: attribute_types
+            .iter()
+            .map(|attribute_type| {
+                let Some((file_descriptor_set_entity_id, message_name)) =
+                    protobuf_metadatas.get(attribute_type)
+                else {
+                    return bytes_attribute_types
+                        .contains(attribute_type)
+                        .then_some(ColumnMetadata::JsonBlob);
+                };
+
+                let descriptor_pool = file_descriptor_sets.get(file_descriptor_set_entity_id)?;
+                let message_descriptor = descriptor_pool.get_message_by_name(message_name)?;
+                Some(ColumnMetadata::MessageDescriptor(message_descriptor))
+            })
+            .collect(),
+    })
+}
+
+/// Returns those of `attribute_types` that have the bytes value type.
+async fn fetch_bytes_attribute_types(
+    attribute_store_client: &mut AttributeStoreClient<Channel>,
+    attribute_types: &[&str],
+) -> anyhow::Result<HashSet<String>> {
+    const BYTES_VALUE_TYPE: &str = "@valueType/bytes";
+
+    let symbol_name_is = |symbol: &str| EntityQueryNode {
+        query: Some(pb::entity_query_node::Query::HasAttributeValue(
+            pb::HasAttributeValueNode {
+                attribute_type: "@symbolName".to_string(),
+                value: Some(pb::AttributeValue::from_string(symbol)),
+            },
+        )),
+    };
+    let value_type_rows = attribute_store_client
+        .query_entity_rows(QueryEntityRowsRequest {
+            root: Some(EntityQueryNode {
+                query: Some(pb::entity_query_node::Query::Or(pb::OrQueryNode {
+                    clauses: attribute_types
+                        .iter()
+                        .copied()
+                        .chain([BYTES_VALUE_TYPE])
+                        .map(symbol_name_is)
+                        .collect(),
+                })),
+            }),
+            attribute_types: vec!["@symbolName".to_string(), "@valueType".to_string()],
+            include_deleted: false,
+            filter_nulls: false,
+            filter_rows_where_null: vec![],
+        })
+        .await?
+        .into_inner()
+        .rows;
+
+    let Some(bytes_value_type_entity_id) = value_type_rows
+        .iter()
+        .find_map(|row| (row.string_value(0)? == BYTES_VALUE_TYPE).then_some(&row.entity_id))
+    else {
+        return Ok(HashSet::new());
+    };
+    Ok(value_type_rows
+        .iter()
+        .filter(|row| row.entity_id_value(1) == Some(bytes_value_type_entity_id))
+        .filter_map(|row| row.string_value(0).cloned())
+        .collect())
+}
 
-    Ok(AttributeStoreClient::new(channel))
+async fn create_attribute_store_client(cli: &Cli) -> anyhow::Result<AttributeStoreClient<Channel>> {
+    Connection::new(cli)?.client().await
 }
@@ -1,86 +1,161 @@
 use crate::attributes::TypedAttribute;
 use crate::pb::attribute_store_client::AttributeStoreClient;
-use crate::pb::mavlink::{Autopilot, GlobalPosition, Mission, MissionCurrent, MissionItem};
-use crate::pb::{
-    AttributeType, AttributeTypeOptions, AttributeValue, CreateAttributeTypeRequest, EntityLocator,
-    UpdateEntityRequest, ValueType,
+use crate::pb::mavlink::{
+    Attitude, Autopilot, BatteryStatus, Fence, GlobalPosition, Mission, MissionCurrent,
+    MissionItem, Parameters,
 };
-use crate::{pb, Cli};
+use crate::pb::{AttributeType, CreateAttributeTypesRequest, ValueType};
+use crate::{json, pb, Cli};
 use anyhow::format_err;
-use ardupilot::connection::{Client, MessageFromNode, Network, NodeId};
+use ardupilot::connection::{Client, MessageFromNode, Network, NetworkStats, NodeId};
 use ardupilot::mission::MissionProtocol;
-use clap::Args;
+use ardupilot::parameters::ParameterProtocol;
+use ardupilot::signing::SignatureVerifier;
+use clap::{Args, Subcommand, ValueEnum};
 use mavio::dialects::common::messages;
 use mavio::dialects::common::messages::{Heartbeat, MissionItemInt};
-use mavio::protocol::{ComponentId, SystemId, Versioned, V2};
+use mavio::protocol::{MavLinkVersion, MaybeVersioned, MessageId, Versioned, Versionless, V1, V2};
 use mavspec_rust_spec::{IntoPayload, SpecError};
 use prost::Message;
-use prost_reflect::{DescriptorPool, MessageDescriptor, ReflectMessage};
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::Into;
+use std::io::Write;
+use std::num::NonZeroU64;
+use std::path::PathBuf;
 use std::string::ToString;
 use std::sync::LazyLock;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::task::JoinSet;
+use tokio::task::{self, AbortHandle, JoinHandle, JoinSet};
 use tokio::time;
 use tonic::codegen::tokio_stream::{Stream, StreamExt};
 use tonic::transport::Channel;
-use tonic::Code;
 use tracing::log;
 
 #[derive(Args)]
 pub struct MavlinkArgs {
+    #[command(subcommand)]
+    command: Option<MavlinkCommands>,
     #[arg(long = "server-endpoint")]
     server_endpoints: Vec<String>,
     #[arg(long = "client-endpoint")]
     client_endpoints: Vec<String>,
-    #[arg(long, default_value_t = 99)]
-    system_id: SystemId,
-    #[arg(long, default_value_t = 17)]
-    component_id: ComponentId,
+    /// Node ID of this client, as `<system_id>:<component_id>`
+    #[arg(long = "node", default_value = "99:17")]
+    node_id: NodeId,
+    /// Periodically log per-message frame statistics
+    #[arg(long)]
+    stats_interval_s: Option<NonZeroU64>,
+    /// Periodically log frame and byte totals across all connections
+    #[arg(long)]
+    stats_log_interval_s: Option<NonZeroU64>,
+    /// MAVLink protocol version to accept. Frames are sent as V2 unless restricted to V1.
+    #[arg(long, value_enum, default_value_t = MavlinkVersionArg::Auto)]
+    mavlink_version: MavlinkVersionArg,
+    /// Append every frame seen on the network to this file
+    #[arg(long)]
+    capture_file: Option<PathBuf>,
+    /// Publish the frames from a capture file to the network, with their original timing
+    #[arg(long)]
+    replay_file: Option<PathBuf>,
+    /// MAVLink 2 signing key as 64 hex digits. Sent frames are signed and received frames with
+    /// invalid signatures are dropped.
+    #[arg(long, value_parser = parse_signing_key)]
+    signing_key: Option<[u8; 32]>,
+    /// Link ID included in the signatures of sent frames
+    #[arg(long, default_value_t = 0, requires = "signing_key")]
+    signing_link_id: u8,
+    /// Also drop received frames that are unsigned
+    #[arg(long, requires = "signing_key")]
+    enforce_signing: bool,
+    /// Stop fetching the missions of a node once no heartbeat has been received from it for this
+    /// long
+    #[arg(long, default_value_t = 10)]
+    heartbeat_timeout_s: u64,
 }
 
-pub enum AttributeTypes {
-    FileDescriptorSet,
-    FileDescriptorSetRef,
-    MessageName,
+fn parse_signing_key(value: &str) -> Result<[u8; 32], String> {
+    // `from_str_radix` would also accept a leading `+`
+    if value.len() != 64 || !value.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err("expected 64 hex digits".to_string());
+    }
+    let mut signing_key = [0; 32];
+    for (idx, byte) in signing_key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[2 * idx..2 * idx + 2], 16)
+            .map_err(|err| format!("expected 64 hex digits: {err}"))?;
+    }
+
+    Ok(signing_key)
 }
 
-impl TypedAttribute for pb::mavlink::Autopilot {
-    fn attribute_name() -> &'static str {
-        "me.grahamdennis.attribute.mavlink.Autopilot"
-    }
+#[derive(Subcommand)]
+enum MavlinkCommands {
+    /// Fetch every parameter of an autopilot and record them in the attribute store
+    ParamList(AutopilotArgs),
+    /// Print a single parameter of an autopilot
+    ParamGet(ParamGetArgs),
+}
 
-    fn as_bytes(&self) -> Vec<u8> {
-        self.encode_to_vec()
-    }
+/// Connection to a single autopilot, for one-off requests
+#[derive(Args)]
+pub struct AutopilotArgs {
+    /// Endpoint of the autopilot to connect to
+    #[arg(long = "client-endpoint")]
+    client_endpoint: String,
+    /// Node ID of this client, as `<system_id>:<component_id>`
+    #[arg(long = "node", default_value = "99:17")]
+    node_id: NodeId,
+    /// Node ID of the autopilot, as `<system_id>:<component_id>`
+    #[arg(long = "target-node", default_value = "1:1")]
+    target_node_id: NodeId,
 }
 
-impl TypedAttribute for GlobalPosition {
-    fn attribute_name() -> &'static str {
-        "me.grahamdennis.attribute.mavlink.GlobalPosition"
-    }
+#[derive(Args)]
+struct ParamGetArgs {
+    #[command(flatten)]
+    autopilot: AutopilotArgs,
+    /// Name of the parameter, e.g. `SYSID_THISMAV`
+    name: String,
+}
 
-    fn as_bytes(&self) -> Vec<u8> {
-        self.encode_to_vec()
-    }
+#[derive(Args)]
+pub struct UploadMissionArgs {
+    #[command(flatten)]
+    autopilot: AutopilotArgs,
+    /// JSON array of MAVLink `MISSION_ITEM_INT` messages, or `@file` to read it from a file. Their
+    /// target and sequence fields are replaced when the mission is sent.
+    #[clap(short, long)]
+    json: String,
 }
 
-impl TypedAttribute for MissionCurrent {
-    fn attribute_name() -> &'static str {
-        "me.grahamdennis.attribute.mavlink.MissionCurrent"
-    }
+#[derive(Args)]
+pub struct UploadFenceArgs {
+    #[command(flatten)]
+    autopilot: AutopilotArgs,
+    /// JSON array of MAVLink `MISSION_ITEM_INT` messages describing the fence, or `@file` to read
+    /// it from a file. Their target, sequence and mission type fields are replaced when the fence
+    /// is sent.
+    #[clap(short, long)]
+    json: String,
+}
 
-    fn as_bytes(&self) -> Vec<u8> {
-        self.encode_to_vec()
-    }
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum MavlinkVersionArg {
+    V1,
+    V2,
+    Auto,
+}
+
+pub enum AttributeTypes {
+    FileDescriptorSet,
+    FileDescriptorSetRef,
+    MessageName,
 }
 
-impl TypedAttribute for Mission {
+impl TypedAttribute for pb::mavlink::Autopilot {
     fn attribute_name() -> &'static str {
-        "me.grahamdennis.attribute.mavlink.Mission"
+        "me.grahamdennis.attribute.mavlink.Autopilot"
     }
 
     fn as_bytes(&self) -> Vec<u8> {
@@ -98,10 +173,12 @@ impl AttributeTypes {
     }
 }
 
+#[allow(dead_code)]
 enum EntityNames {
     MavlinkFileDescriptorSet,
 }
 
+#[allow(dead_code)]
 impl EntityNames {
     fn as_str(&self) -> &'static str {
         match self {
@@ -110,25 +187,29 @@ impl EntityNames {
     }
 }
 
-static ATTRIBUTE_TYPES: LazyLock<Vec<CreateAttributeTypeRequest>> = LazyLock::new(|| {
+static ATTRIBUTE_TYPES: LazyLock<Vec<AttributeType>> = LazyLock::new(|| {
     vec![
-        CreateAttributeTypeRequest {
-            attribute_type: Some(AttributeType {
-                symbol: AttributeTypes::FileDescriptorSet.as_str().to_string(),
-                value_type: ValueType::Bytes.into(),
-            }),
+        AttributeType {
+            symbol: AttributeTypes::FileDescriptorSet.as_str().to_string(),
+            value_type: ValueType::Bytes.into(),
+            list: false,
+            description: Some("Serialized protobuf FileDescriptorSet".to_string()),
         },
-        CreateAttributeTypeRequest {
-            attribute_type: Some(AttributeType {
-                symbol: AttributeTypes::FileDescriptorSetRef.as_str().to_string(),
-                value_type: ValueType::EntityReference.into(),
-            }),
+        AttributeType {
+            symbol: AttributeTypes::FileDescriptorSetRef.as_str().to_string(),
+            value_type: ValueType::EntityReference.into(),
+            list: false,
+            description: Some(
+                "Entity whose FileDescriptorSet defines this attribute type's message".to_string(),
+            ),
         },
-        CreateAttributeTypeRequest {
-            attribute_type: Some(AttributeType {
-                symbol: AttributeTypes::MessageName.as_str().to_string(),
-                value_type: ValueType::Text.into(),
-            }),
+        AttributeType {
+            symbol: AttributeTypes::MessageName.as_str().to_string(),
+            value_type: ValueType::Text.into(),
+            list: false,
+            description: Some(
+                "Fully-qualified protobuf message name of this attribute type's values".to_string(),
+            ),
         },
     ]
 });
@@ -174,6 +255,34 @@ impl From<(NodeId, messages::GlobalPositionInt)> for pb::mavlink::GlobalPosition
     }
 }
 
+impl From<(NodeId, messages::Attitude)> for pb::mavlink::Attitude {
+    fn from((_node_id, value): (NodeId, messages::Attitude)) -> Self {
+        Attitude {
+            roll_rad: value.roll,
+            pitch_rad: value.pitch,
+            yaw_rad: value.yaw,
+            rollspeed_rad_s: value.rollspeed,
+            pitchspeed_rad_s: value.pitchspeed,
+            yawspeed_rad_s: value.yawspeed,
+        }
+    }
+}
+
+impl From<(NodeId, messages::BatteryStatus)> for pb::mavlink::BatteryStatus {
+    fn from((_node_id, value): (NodeId, messages::BatteryStatus)) -> Self {
+        BatteryStatus {
+            voltage_mv: value
+                .voltages
+                .iter()
+                .filter(|&&cell_voltage| cell_voltage != u16::MAX)
+                .map(|&cell_voltage| u32::from(cell_voltage))
+                .sum(),
+            current_ca: value.current_battery.into(),
+            remaining_pct: value.battery_remaining.into(),
+        }
+    }
+}
+
 impl From<(NodeId, messages::MissionCurrent)> for pb::mavlink::MissionCurrent {
     fn from((_node_id, value): (NodeId, messages::MissionCurrent)) -> Self {
         MissionCurrent {
@@ -200,54 +309,130 @@ impl TryFrom<messages::MissionItemInt> for pb::mavlink::MissionItem {
 }
 
 fn symbol_for_node(node_id: NodeId) -> String {
-    format!("mavlink/id/{}:{}", node_id.system_id, node_id.component_id)
+    format!("mavlink/id/{node_id}")
 }
 
-pub async fn mavlink_run(cli: &Cli, args: &MavlinkArgs) -> anyhow::Result<()> {
-    let mut attribute_store_client = crate::create_attribute_store_client(&cli.endpoint).await?;
-
+async fn register_mavlink_attribute_types(
+    attribute_store_client: &mut AttributeStoreClient<Channel>,
+) -> anyhow::Result<()> {
     log::info!("Creating attribute types");
 
-    for create_attribute_type_request in ATTRIBUTE_TYPES.iter() {
-        let result = attribute_store_client
-            .create_attribute_type(create_attribute_type_request.clone())
-            .await;
-        match result {
-            Ok(_) => {}
-            Err(status) if status.code() == Code::AlreadyExists => {
-                log::debug!("skipping attribute because it already exists");
-            }
-            Err(status) => {
-                return Err(status)?;
-            }
-        }
+    let response = attribute_store_client
+        .create_attribute_types(CreateAttributeTypesRequest {
+            attribute_types: ATTRIBUTE_TYPES.clone(),
+        })
+        .await?
+        .into_inner();
+    for symbol in response.already_existed {
+        log::debug!("skipping attribute {symbol} because it already exists");
     }
 
     log::info!("Creating entities");
 
-    {
-        attribute_store_client
-            .upload_protobuf_message_specs(pb::mavlink::FILE_DESCRIPTOR_SET)
-            .await?;
+    attribute_store_client
+        .upload_protobuf_message_specs(pb::mavlink::FILE_DESCRIPTOR_SET)
+        .await
+}
+
+pub async fn mavlink_run(
+    cli: &Cli,
+    args: &MavlinkArgs,
+    output: &mut dyn Write,
+) -> anyhow::Result<()> {
+    match &args.command {
+        Some(MavlinkCommands::ParamList(autopilot_args)) => {
+            return mavlink_param_list(cli, autopilot_args, output).await;
+        }
+        Some(MavlinkCommands::ParamGet(param_get_args)) => {
+            return mavlink_param_get(param_get_args, output).await;
+        }
+        None => {}
     }
 
+    let mut attribute_store_client = crate::create_attribute_store_client(cli).await?;
+
+    register_mavlink_attribute_types(&mut attribute_store_client).await?;
+
     println!("Mavlink running...");
 
     println!("Server endpoints: {:?}", args.server_endpoints);
     println!("Client endpoints: {:?}", args.client_endpoints);
 
-    let network = Network::<V2>::create_with_capacity(128);
+    match args.mavlink_version {
+        MavlinkVersionArg::V1 => {
+            run_network(
+                Network::<V1>::create_with_capacity(128),
+                MavLinkVersion::V1,
+                args,
+                attribute_store_client,
+            )
+            .await
+        }
+        MavlinkVersionArg::V2 => {
+            run_network(
+                Network::<V2>::create_with_capacity(128),
+                MavLinkVersion::V2,
+                args,
+                attribute_store_client,
+            )
+            .await
+        }
+        MavlinkVersionArg::Auto => {
+            run_network(
+                Network::<Versionless>::create_with_capacity(128),
+                MavLinkVersion::V2,
+                args,
+                attribute_store_client,
+            )
+            .await
+        }
+    }
+}
+
+async fn run_network<V: MaybeVersioned>(
+    network: Network<V>,
+    client_version: MavLinkVersion,
+    args: &MavlinkArgs,
+    attribute_store_client: AttributeStoreClient<Channel>,
+) -> anyhow::Result<()> {
+    let network = match args.signing_key {
+        Some(signing_key) => network
+            .with_signature_verifier(SignatureVerifier::new(signing_key, args.enforce_signing)),
+        None => network,
+    };
     let mut join_set = JoinSet::new();
 
+    let mut listeners = vec![];
     for server_address in &args.server_endpoints {
-        let listener = TcpListener::bind(server_address).await?;
-        join_set.spawn(network.clone().accept_loop(listener));
+        listeners.push(TcpListener::bind(server_address).await?);
     }
+    let accept = network.clone().accept_multiple(listeners);
+    join_set.spawn(async move { accept.await? });
     for client_address in &args.client_endpoints {
         let socket = TcpStream::connect(client_address).await?;
         join_set.spawn(network.clone().process_tcp(socket));
     }
 
+    if let Some(capture_file) = &args.capture_file {
+        join_set.spawn(network.clone().log_frames_to_file(capture_file.clone()));
+    }
+    if let Some(replay_file) = &args.replay_file {
+        join_set.spawn(network.clone().replay_frames_from_file(replay_file.clone()));
+    }
+
+    if let Some(stats_interval_s) = args.stats_interval_s {
+        join_set.spawn(log_message_statistics(
+            network.clone(),
+            Duration::from_secs(stats_interval_s.get()),
+        ));
+    }
+    if let Some(stats_log_interval_s) = args.stats_log_interval_s {
+        join_set.spawn(log_network_stats(
+            network.clone(),
+            Duration::from_secs(stats_log_interval_s.get()),
+        ));
+    }
+
     join_set.spawn(publish_to_attribute_server::<Autopilot, _>(
         network.subscribe::<messages::Heartbeat>().await,
         attribute_store_client.clone(),
@@ -257,66 +442,147 @@ pub async fn mavlink_run(cli: &Cli, args: &MavlinkArgs) -> anyhow::Result<()> {
         network.subscribe::<messages::GlobalPositionInt>().await,
         attribute_store_client.clone(),
     ));
+    join_set.spawn(publish_to_attribute_server::<Attitude, _>(
+        network.subscribe::<messages::Attitude>().await,
+        attribute_store_client.clone(),
+    ));
     join_set.spawn(publish_to_attribute_server::<MissionCurrent, _>(
         network.subscribe::<messages::MissionCurrent>().await,
         attribute_store_client.clone(),
     ));
+    join_set.spawn(publish_to_attribute_server::<BatteryStatus, _>(
+        network.subscribe::<messages::BatteryStatus>().await,
+        attribute_store_client.clone(),
+    ));
 
-    let node_id = NodeId {
-        system_id: 1,
-        component_id: 1,
-    };
-    let mut mission_fetcher = MissionFetcher {
-        mavlink_client: Client::create(
-            network.clone(),
-            NodeId {
-                system_id: args.system_id,
-                component_id: args.component_id,
-            },
-        ),
-        attribute_store_client: attribute_store_client.clone(),
+    join_set.spawn(register_peers(
+        network.clone(),
+        attribute_store_client.clone(),
+        Duration::from_secs(1),
+    ));
+
+    let client_node_id = args.node_id;
+    let signing = args
+        .signing_key
+        .map(|signing_key| (args.signing_link_id, signing_key));
+    let client_network = network.clone();
+    let mission_fetcher_manager = MissionFetcherManager {
+        network,
+        create_client: Box::new(move || {
+            let mavlink_client =
+                Client::create_with_version(client_network.clone(), client_node_id, client_version);
+            match signing {
+                Some((link_id, signing_key)) => mavlink_client.with_signing(link_id, signing_key),
+                None => mavlink_client,
+            }
+        }),
+        attribute_store_client,
+        heartbeat_timeout: Duration::from_secs(args.heartbeat_timeout_s),
     };
-    join_set.spawn(async move {
-        let mut mission_current_subscription = network.subscribe::<messages::MissionCurrent>().await;
-        let mut last_mission_currents: HashMap<NodeId, messages::MissionCurrent> = HashMap::new();
-        let mut update_timer = time::interval(Duration::from_secs(30));
-        loop {
-            tokio::select! {
-                _ = update_timer.tick() => {
-                    // update
-                }
-                Some((node_id, mission_current)) = mission_current_subscription.next() => {
-                    match last_mission_currents.entry(node_id) {
-                        Entry::Occupied(mut occupied) => {
-                            let last_mission_current = occupied.get();
-                            let update = (last_mission_current.total != mission_current.total) || (last_mission_current.mission_id != mission_current.mission_id);
-                            occupied.insert(mission_current);
-                            if !update { continue }
-                        }
-                        Entry::Vacant(vacant) => {
-                            vacant.insert(mission_current);
-                        }}
-                }
-                else => {
-                    return Ok(());
-                }
+    join_set.spawn(mission_fetcher_manager.run());
+
+    join_set.join_all().await;
+
+    Ok(())
+}
+
+/// Creates an entity for each node as soon as it is heard from on the network.
+async fn register_peers<V: MaybeVersioned>(
+    network: Network<V>,
+    mut attribute_store_client: AttributeStoreClient<Channel>,
+    period: Duration,
+) -> anyhow::Result<()> {
+    let mut timer = time::interval(period);
+    let mut registered_peers: HashSet<NodeId> = HashSet::new();
+    loop {
+        timer.tick().await;
+        for (node_id, _last_seen) in network.peer_list() {
+            if registered_peers.contains(&node_id) {
+                continue;
             }
+            let symbol_id = symbol_for_node(node_id);
+            log::info!("Creating entity {symbol_id} for newly discovered peer");
+            attribute_store_client
+                .update_entity(pb::UpdateEntityRequest {
+                    entity_locator: Some(pb::EntityLocator::builder().symbol(&symbol_id).build()),
+                    attributes_to_update: vec![pb::AttributeToUpdate {
+                        attribute_type: "@symbolName".to_string(),
+                        attribute_value: Some(pb::AttributeValue::from_string(&symbol_id)),
+                        delete: false,
+                    }],
+                    dry_run: false,
+                })
+                .await?;
+            registered_peers.insert(node_id);
+        }
+    }
+}
 
-            mission_fetcher.update(node_id).await?;
+async fn log_message_statistics<V: MaybeVersioned>(
+    network: Network<V>,
+    period: Duration,
+) -> anyhow::Result<()> {
+    let mut timer = time::interval(period);
+    let mut last_counts: HashMap<MessageId, u64> = HashMap::new();
+    loop {
+        timer.tick().await;
+        let message_statistics = network.message_statistics().lock().clone();
+
+        let mut message_ids: Vec<_> = message_statistics.counts.keys().copied().collect();
+        message_ids.sort();
+        for message_id in message_ids {
+            let count = message_statistics.counts[&message_id];
+            let bytes = message_statistics.bytes[&message_id];
+            let last_count = last_counts.get(&message_id).copied().unwrap_or_default();
+            let rate_hz = (count - last_count) as f64 / period.as_secs_f64();
+            let last_seen_s = message_statistics.last_seen[&message_id]
+                .elapsed()
+                .as_secs_f64();
+            tracing::info!(
+                message_id,
+                count,
+                bytes,
+                rate_hz,
+                last_seen_s,
+                "Message statistics"
+            );
         }
-    });
 
-    join_set.join_all().await;
+        last_counts = message_statistics.counts;
+    }
+}
 
-    Ok(())
+async fn log_network_stats<V: MaybeVersioned>(
+    network: Network<V>,
+    period: Duration,
+) -> anyhow::Result<()> {
+    let mut timer = time::interval(period);
+    loop {
+        timer.tick().await;
+        let NetworkStats {
+            frames_received,
+            frames_sent,
+            bytes_received,
+            bytes_sent,
+            frames_dropped,
+        } = network.stats();
+        tracing::info!(
+            frames_received,
+            frames_sent,
+            bytes_received,
+            bytes_sent,
+            frames_dropped,
+            "Network statistics"
+        );
+    }
 }
 
-async fn publish_to_attribute_server<A: TypedAttribute, M: mavspec_rust_spec::Message>(
+async fn publish_to_attribute_server<A, M: mavspec_rust_spec::Message>(
     mut rx: impl Stream<Item = (NodeId, M)> + Unpin,
     mut attribute_store_client: AttributeStoreClient<Channel>,
 ) -> anyhow::Result<()>
 where
-    A: From<MessageFromNode<M>>,
+    A: TypedAttribute + From<MessageFromNode<M>>,
 {
     while let Some((origin, message)) = rx.next().await {
         let symbol_id = symbol_for_node(origin);
@@ -329,22 +595,147 @@ where
     Ok(())
 }
 
-struct MissionFetcher {
-    mavlink_client: Client<V2>,
+struct MissionFetcher<V: MaybeVersioned> {
+    mavlink_client: Client<V>,
+    attribute_store_client: AttributeStoreClient<Channel>,
+}
+
+/// Runs a `MissionFetcher` for each node heard sending heartbeats, and stops it once the node has
+/// been silent for longer than `heartbeat_timeout`.
+struct MissionFetcherManager<V: MaybeVersioned> {
+    network: Network<V>,
+    /// Creates the MAVLink client of each fetcher
+    create_client: Box<dyn Fn() -> Client<V> + Send + Sync>,
     attribute_store_client: AttributeStoreClient<Channel>,
+    heartbeat_timeout: Duration,
+}
+
+struct FetchedNode {
+    last_heartbeat: Instant,
+    fetcher: AbortHandle,
 }
 
-impl MissionFetcher {
+impl<V: MaybeVersioned> MissionFetcherManager<V> {
+    async fn run(self) -> anyhow::Result<()> {
+        let mut heartbeat_subscription = self.network.subscribe::<messages::Heartbeat>().await;
+        let mut fetchers: JoinSet<anyhow::Result<()>> = JoinSet::new();
+        let mut fetched_nodes: HashMap<NodeId, FetchedNode> = HashMap::new();
+        let mut fetcher_nodes: HashMap<task::Id, NodeId> = HashMap::new();
+        let mut expiry_timer = time::interval(Duration::from_secs(1));
+        loop {
+            tokio::select! {
+                Some((node_id, _heartbeat)) = heartbeat_subscription.next() => {
+                    match fetched_nodes.entry(node_id) {
+                        Entry::Occupied(mut occupied) => {
+                            occupied.get_mut().last_heartbeat = Instant::now();
+                        }
+                        Entry::Vacant(vacant) => {
+                            log::info!("Starting mission fetcher for {node_id:?}");
+                            let mission_fetcher = MissionFetcher {
+                                mavlink_client: (self.create_client)(),
+                                attribute_store_client: self.attribute_store_client.clone(),
+                            };
+                            let fetcher = fetchers.spawn(mission_fetcher.run(node_id));
+                            fetcher_nodes.insert(fetcher.id(), node_id);
+                            vacant.insert(FetchedNode {
+                                last_heartbeat: Instant::now(),
+                                fetcher,
+                            });
+                        }
+                    }
+                }
+                _ = expiry_timer.tick() => {
+                    fetched_nodes.retain(|node_id, fetched_node| {
+                        let alive = fetched_node.last_heartbeat.elapsed() <= self.heartbeat_timeout;
+                        if !alive {
+                            log::info!("Stopping mission fetcher for silent node {node_id:?}");
+                            fetched_node.fetcher.abort();
+                        }
+                        alive
+                    });
+                }
+                Some(join_result) = fetchers.join_next_with_id() => {
+                    let (task_id, result) = match join_result {
+                        Ok((task_id, result)) => (task_id, result),
+                        Err(err) if err.is_cancelled() => (err.id(), Ok(())),
+                        Err(err) => return Err(err.into()),
+                    };
+                    let Some(node_id) = fetcher_nodes.remove(&task_id) else {
+                        continue;
+                    };
+                    if let Err(err) = result {
+                        log::warn!("Mission fetcher for {node_id:?} failed: {err:#}");
+                    }
+                    // The next heartbeat from the node starts a new fetcher
+                    if fetched_nodes
+                        .get(&node_id)
+                        .is_some_and(|fetched_node| fetched_node.fetcher.id() == task_id)
+                    {
+                        fetched_nodes.remove(&node_id);
+                    }
+                }
+                else => {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn mission_item_protos(mission_items: Vec<MissionItemInt>) -> anyhow::Result<Vec<MissionItem>> {
+    let converted: Result<Vec<MissionItem>, _> = mission_items
+        .into_iter()
+        .map(|mission_item_int| mission_item_int.try_into())
+        .collect();
+    converted.map_err(|err| format_err!("{err:?}"))
+}
+
+fn mission_proto(mission: Vec<MissionItemInt>) -> anyhow::Result<Mission> {
+    Ok(Mission {
+        mission_items: mission_item_protos(mission)?,
+    })
+}
+
+impl<V: MaybeVersioned> MissionFetcher<V> {
+    /// Fetches the mission of `node_id` whenever its `MISSION_CURRENT` reports a different
+    /// mission, and periodically once it has reported one.
+    async fn run(mut self, node_id: NodeId) -> anyhow::Result<()> {
+        let mut mission_current_subscription = self
+            .mavlink_client
+            .subscribe_from_node::<messages::MissionCurrent>(node_id)
+            .await;
+        let mut last_mission_current: Option<messages::MissionCurrent> = None;
+        let mut update_timer = time::interval(Duration::from_secs(30));
+        loop {
+            tokio::select! {
+                _ = update_timer.tick() => {
+                    if last_mission_current.is_none() {
+                        continue;
+                    }
+                }
+                Some(mission_current) = mission_current_subscription.next() => {
+                    let changed = last_mission_current.as_ref().is_none_or(|last_mission_current| {
+                        last_mission_current.total != mission_current.total
+                            || last_mission_current.mission_id != mission_current.mission_id
+                    });
+                    last_mission_current = Some(mission_current);
+                    if !changed {
+                        continue;
+                    }
+                }
+                else => {
+                    return Ok(());
+                }
+            }
+
+            self.update(node_id).await?;
+        }
+    }
+
     async fn update(&mut self, node_id: NodeId) -> Result<(), anyhow::Error> {
         let mission = self.mavlink_client.fetch_mission(node_id).await?;
 
-        let converted: Result<Vec<MissionItem>, _> = mission
-            .into_iter()
-            .map(|mission_item_int| mission_item_int.try_into())
-            .collect();
-        let mission_proto: pb::mavlink::Mission = Mission {
-            mission_items: converted.map_err(|err| format_err!("{err:?}"))?,
-        };
+        let mission_proto = mission_proto(mission)?;
         let _response = self
             .attribute_store_client
             .simple_update_entity(&symbol_for_node(node_id), mission_proto)
@@ -353,3 +744,139 @@ impl MissionFetcher {
         Ok(())
     }
 }
+
+impl AutopilotArgs {
+    /// Connects to the autopilot. Abort the returned task to close the connection.
+    async fn connect(
+        &self,
+    ) -> anyhow::Result<(Client<Versionless>, JoinHandle<anyhow::Result<()>>)> {
+        let network = Network::<Versionless>::create_with_capacity(128);
+        let socket = TcpStream::connect(&self.client_endpoint).await?;
+        let connection = tokio::spawn(network.clone().process_tcp(socket));
+
+        let mavlink_client = Client::create_with_version(network, self.node_id, MavLinkVersion::V2);
+        Ok((mavlink_client, connection))
+    }
+}
+
+async fn mavlink_param_list(
+    cli: &Cli,
+    args: &AutopilotArgs,
+    output: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let target_node_id = args.target_node_id;
+    let (mut mavlink_client, connection) = args.connect().await?;
+    let parameters = mavlink_client.request_param_list(target_node_id).await?;
+    connection.abort();
+
+    writeln!(
+        output,
+        "{}",
+        serde_json::to_string_pretty(&parameters.iter().collect::<BTreeMap<_, _>>())?
+    )?;
+
+    let mut attribute_store_client = crate::create_attribute_store_client(cli).await?;
+    register_mavlink_attribute_types(&mut attribute_store_client).await?;
+    attribute_store_client
+        .simple_update_entity(
+            &symbol_for_node(target_node_id),
+            Parameters { values: parameters },
+        )
+        .await?;
+
+    Ok(())
+}
+
+async fn mavlink_param_get(args: &ParamGetArgs, output: &mut dyn Write) -> anyhow::Result<()> {
+    let (mut mavlink_client, connection) = args.autopilot.connect().await?;
+    let value = mavlink_client
+        .request_param_read(args.autopilot.target_node_id, &args.name)
+        .await?;
+    connection.abort();
+
+    writeln!(output, "{value}")?;
+
+    Ok(())
+}
+
+pub async fn mavlink_upload_mission(cli: &Cli, args: &UploadMissionArgs) -> anyhow::Result<()> {
+    let mission_items: Vec<MissionItemInt> = json::deserialize_from_json_argument(&args.json)?;
+    let target_node_id = args.autopilot.target_node_id;
+
+    let (mut mavlink_client, connection) = args.autopilot.connect().await?;
+    log::info!("Uploading {} mission items", mission_items.len());
+    mavlink_client
+        .upload_mission(target_node_id, mission_items.clone())
+        .await?;
+    connection.abort();
+
+    let mut attribute_store_client = crate::create_attribute_store_client(cli).await?;
+    register_mavlink_attribute_types(&mut attribute_store_client).await?;
+    attribute_store_client
+        .simple_update_entity(
+            &symbol_for_node(target_node_id),
+            mission_proto(mission_items)?,
+        )
+        .await?;
+
+    Ok(())
+}
+
+pub async fn mavlink_upload_fence(cli: &Cli, args: &UploadFenceArgs) -> anyhow::Result<()> {
+    let fence_items: Vec<MissionItemInt> = json::deserialize_from_json_argument(&args.json)?;
+    let target_node_id = args.autopilot.target_node_id;
+
+    let (mut mavlink_client, connection) = args.autopilot.connect().await?;
+    log::info!("Uploading {} fence items", fence_items.len());
+    mavlink_client
+        .upload_fence(target_node_id, fence_items.clone())
+        .await?;
+    connection.abort();
+
+    record_fence(cli, target_node_id, fence_items).await
+}
+
+pub async fn mavlink_clear_fence(cli: &Cli, args: &AutopilotArgs) -> anyhow::Result<()> {
+    let target_node_id = args.target_node_id;
+
+    let (mut mavlink_client, connection) = args.connect().await?;
+    mavlink_client.clear_fence(target_node_id).await?;
+    connection.abort();
+
+    record_fence(cli, target_node_id, vec![]).await
+}
+
+/// Records the fence now on `node_id` as its `Fence` attribute.
+async fn record_fence(
+    cli: &Cli,
+    node_id: NodeId,
+    fence_items: Vec<MissionItemInt>,
+) -> anyhow::Result<()> {
+    let mut attribute_store_client = crate::create_attribute_store_client(cli).await?;
+    register_mavlink_attribute_types(&mut attribute_store_client).await?;
+    attribute_store_client
+        .simple_update_entity(
+            &symbol_for_node(node_id),
+            Fence {
+                fence_items: mission_item_protos(fence_items)?,
+            },
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signing_keys_must_be_hex_digits() {
+        let signing_key = "0f".repeat(32);
+        assert_eq!(parse_signing_key(&signing_key), Ok([0x0f; 32]));
+
+        let signed_digits = format!("+f{}", &signing_key[2..]);
+        assert!(parse_signing_key(&signed_digits).is_err());
+        assert!(parse_signing_key(&signing_key[2..]).is_err());
+    }
+}
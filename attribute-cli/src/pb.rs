@@ -1,3 +1,5 @@
+#![allow(clippy::enum_variant_names)]
+
 tonic::include_proto!("me.grahamdennis.attribute");
 
 impl EntityRow {
@@ -30,21 +32,79 @@ impl EntityRow {
             _ => None,
         }
     }
+
+    #[allow(dead_code)]
+    pub fn timestamp_value(&self, idx: usize) -> Option<&prost_types::Timestamp> {
+        match self.attribute_value(idx)? {
+            attribute_value::AttributeValue::TimestampValue(value) => Some(value),
+            _ => None,
+        }
+    }
 }
 
 impl EntityLocator {
+    pub fn builder() -> EntityLocatorBuilder {
+        EntityLocatorBuilder::default()
+    }
+
     #[allow(dead_code)]
-    pub fn from_symbol(symbol: impl ToString) -> Self {
+    pub fn as_symbol(&self) -> Option<&str> {
+        match self.locator.as_ref()? {
+            entity_locator::Locator::Symbol(symbol) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    pub fn as_entity_id(&self) -> Option<&str> {
+        match self.locator.as_ref()? {
+            entity_locator::Locator::EntityId(entity_id) => Some(entity_id),
+            _ => None,
+        }
+    }
+
+    pub fn is_create_new(&self) -> bool {
+        matches!(self.locator, Some(entity_locator::Locator::CreateNew(_)))
+    }
+
+    pub fn try_into_symbol(self) -> Option<String> {
+        match self.locator? {
+            entity_locator::Locator::Symbol(symbol) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    pub fn try_into_entity_id(self) -> Option<String> {
+        match self.locator? {
+            entity_locator::Locator::EntityId(entity_id) => Some(entity_id),
+            _ => None,
+        }
+    }
+}
+
+/// Builds an `EntityLocator`; the last of `symbol` or `entity_id` to be called wins.
+#[derive(Default, Debug, Clone)]
+pub struct EntityLocatorBuilder {
+    locator: Option<entity_locator::Locator>,
+}
+
+impl EntityLocatorBuilder {
+    pub fn symbol(self, symbol: impl ToString) -> Self {
         Self {
             locator: Some(entity_locator::Locator::Symbol(symbol.to_string())),
         }
     }
-    #[allow(dead_code)]
-    pub fn from_entity_id(entity_id: impl ToString) -> Self {
+
+    pub fn entity_id(self, entity_id: impl ToString) -> Self {
         Self {
             locator: Some(entity_locator::Locator::EntityId(entity_id.to_string())),
         }
     }
+
+    pub fn build(self) -> EntityLocator {
+        EntityLocator {
+            locator: self.locator,
+        }
+    }
 }
 
 impl AttributeValue {
@@ -74,9 +134,341 @@ impl AttributeValue {
     }
 }
 
+/// Client-side checks for requests that the server would reject, to save a round trip.
+pub trait Validate {
+    /// Describes each problem found with the request, if any
+    fn validate(&self) -> Result<(), Vec<String>> {
+        Ok(())
+    }
+}
+
+fn check(errors: &mut Vec<String>, is_valid: bool, message: impl FnOnce() -> String) {
+    if !is_valid {
+        errors.push(message());
+    }
+}
+
+fn into_result(errors: Vec<String>) -> Result<(), Vec<String>> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn check_query(errors: &mut Vec<String>, field: &str, query: Option<&EntityQueryNode>) {
+    check(
+        errors,
+        query.is_some_and(|query| query.query.is_some()),
+        || format!("`{field}` is required"),
+    );
+}
+
+fn check_attribute_types(errors: &mut Vec<String>, field: &str, attribute_types: &[String]) {
+    for (idx, attribute_type) in attribute_types.iter().enumerate() {
+        check(errors, !attribute_type.is_empty(), || {
+            format!("`{field}[{idx}]` must not be empty")
+        });
+    }
+}
+
+impl Validate for CreateAttributeTypeRequest {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = vec![];
+        match &self.attribute_type {
+            None => errors.push("`attribute_type` is required".to_string()),
+            Some(attribute_type) => {
+                check(&mut errors, !attribute_type.symbol.is_empty(), || {
+                    "`attribute_type.symbol` must not be empty".to_string()
+                });
+                check(
+                    &mut errors,
+                    ValueType::try_from(attribute_type.value_type)
+                        .is_ok_and(|value_type| value_type != ValueType::Invalid),
+                    || {
+                        format!(
+                            "`attribute_type.value_type` has invalid value {}",
+                            attribute_type.value_type
+                        )
+                    },
+                );
+            }
+        }
+        into_result(errors)
+    }
+}
+
+impl Validate for UpdateEntityRequest {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = vec![];
+        check(
+            &mut errors,
+            self.entity_locator
+                .as_ref()
+                .and_then(|entity_locator| entity_locator.locator.as_ref())
+                .is_some_and(|locator| match locator {
+                    entity_locator::Locator::Symbol(symbol) => !symbol.is_empty(),
+                    entity_locator::Locator::EntityId(entity_id) => !entity_id.is_empty(),
+                    entity_locator::Locator::CreateNew(_) => true,
+                }),
+            || "`entity_locator` is required".to_string(),
+        );
+        for (idx, attribute_to_update) in self.attributes_to_update.iter().enumerate() {
+            check(
+                &mut errors,
+                !attribute_to_update.attribute_type.is_empty(),
+                || format!("`attributes_to_update[{idx}].attribute_type` must not be empty"),
+            );
+            check_delete(
+                &mut errors,
+                &format!("attributes_to_update[{idx}].attribute_value"),
+                attribute_to_update
+                    .attribute_value
+                    .as_ref()
+                    .map(|value| value.attribute_value.is_some()),
+                attribute_to_update.delete,
+            );
+        }
+        into_result(errors)
+    }
+}
+
+/// Attributes are removed with `delete`, which excludes a value. `value` is `None` if `field` is
+/// unset, and otherwise whether it is non-empty.
+fn check_delete(errors: &mut Vec<String>, field: &str, value: Option<bool>, delete: bool) {
+    match (value, delete) {
+        (Some(is_non_empty), false) => {
+            check(errors, is_non_empty, || format!("`{field}` is empty"))
+        }
+        (None, false) => errors.push(format!("`{field}` is required unless `delete` is set")),
+        (Some(_), true) => errors.push(format!("`{field}` must not be set with `delete`")),
+        (None, true) => {}
+    }
+}
+
+impl Validate for QueryEntityRowsRequest {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = vec![];
+        check_query(&mut errors, "root", self.root.as_ref());
+        check_attribute_types(&mut errors, "attribute_types", &self.attribute_types);
+        for (idx, column) in self.filter_rows_where_null.iter().enumerate() {
+            check(
+                &mut errors,
+                usize::try_from(*column).is_ok_and(|column| column < self.attribute_types.len()),
+                || format!("`filter_rows_where_null[{idx}]` is not a column index"),
+            );
+        }
+        into_result(errors)
+    }
+}
+
+impl Validate for WatchEntitiesRequest {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = vec![];
+        check_query(&mut errors, "query", self.query.as_ref());
+        for (idx, event_type) in self.event_types.iter().enumerate() {
+            check(
+                &mut errors,
+                WatchEventType::try_from(*event_type)
+                    .is_ok_and(|event_type| event_type != WatchEventType::Invalid),
+                || format!("`event_types[{idx}]` has invalid value {event_type}"),
+            );
+        }
+        check_attribute_types(&mut errors, "attribute_types", &self.attribute_types);
+        into_result(errors)
+    }
+}
+
+impl Validate for GetAttributeTypeRequest {}
+
+impl Validate for MergeEntitiesRequest {}
+
+impl Validate for AtomicQueryAndUpdateRequest {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = vec![];
+        check(&mut errors, self.entity_locator.is_some(), || {
+            "`entity_locator` is required".to_string()
+        });
+        for (idx, attribute_to_update) in self.attributes_to_update.iter().enumerate() {
+            check(
+                &mut errors,
+                !attribute_to_update.attribute_type.is_empty(),
+                || format!("`attributes_to_update[{idx}].attribute_type` must not be empty"),
+            );
+            check_delete(
+                &mut errors,
+                &format!("attributes_to_update[{idx}].value"),
+                attribute_to_update
+                    .value
+                    .as_ref()
+                    .map(|value| value.expression.is_some()),
+                attribute_to_update.delete,
+            );
+        }
+        into_result(errors)
+    }
+}
+
 pub mod mavlink {
     pub const FILE_DESCRIPTOR_SET: &[u8] =
         tonic::include_file_descriptor_set!("file_descriptor_set.mavlink");
 
     tonic::include_proto!("me.grahamdennis.attribute.mavlink");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn match_all() -> Option<EntityQueryNode> {
+        Some(EntityQueryNode {
+            query: Some(entity_query_node::Query::MatchAll(MatchAllQueryNode {})),
+        })
+    }
+
+    fn errors(request: &impl Validate) -> Vec<String> {
+        request.validate().unwrap_err()
+    }
+
+    #[test]
+    fn entity_locators_convert_into_their_symbol_or_entity_id() {
+        let symbol = || EntityLocator::builder().symbol("foo").build();
+        let entity_id = || EntityLocator::builder().entity_id("CAc=").build();
+
+        assert_eq!(symbol().try_into_symbol(), Some("foo".to_string()));
+        assert_eq!(symbol().try_into_entity_id(), None);
+        assert_eq!(entity_id().try_into_entity_id(), Some("CAc=".to_string()));
+        assert_eq!(entity_id().try_into_symbol(), None);
+        assert_eq!(EntityLocator::default().try_into_symbol(), None);
+    }
+
+    #[test]
+    fn create_attribute_type_requires_a_valid_value_type() {
+        let request = |value_type: i32| CreateAttributeTypeRequest {
+            attribute_type: Some(AttributeType {
+                symbol: "foo".to_string(),
+                value_type,
+                description: None,
+                list: false,
+            }),
+        };
+
+        assert_eq!(request(ValueType::Text.into()).validate(), Ok(()));
+        assert_eq!(
+            errors(&request(ValueType::Invalid.into())),
+            ["`attribute_type.value_type` has invalid value 0"]
+        );
+        assert_eq!(
+            errors(&CreateAttributeTypeRequest {
+                attribute_type: None
+            }),
+            ["`attribute_type` is required"]
+        );
+    }
+
+    #[test]
+    fn update_entity_requires_exactly_one_of_a_value_and_delete() {
+        let request = |attribute_value: Option<AttributeValue>, delete| UpdateEntityRequest {
+            entity_locator: Some(EntityLocator::builder().symbol("foo").build()),
+            attributes_to_update: vec![AttributeToUpdate {
+                attribute_type: "@description".to_string(),
+                attribute_value,
+                delete,
+            }],
+            dry_run: false,
+        };
+        let value = || Some(AttributeValue::from_string("bar"));
+
+        assert_eq!(request(value(), false).validate(), Ok(()));
+        assert_eq!(request(None, true).validate(), Ok(()));
+        assert_eq!(
+            errors(&request(None, false)),
+            ["`attributes_to_update[0].attribute_value` is required unless `delete` is set"]
+        );
+        assert_eq!(
+            errors(&request(value(), true)),
+            ["`attributes_to_update[0].attribute_value` must not be set with `delete`"]
+        );
+        assert_eq!(
+            errors(&request(Some(AttributeValue::default()), false)),
+            ["`attributes_to_update[0].attribute_value` is empty"]
+        );
+    }
+
+    #[test]
+    fn query_entity_rows_requires_a_query_and_valid_null_filters() {
+        let request =
+            |root: Option<EntityQueryNode>, filter_rows_where_null| QueryEntityRowsRequest {
+                root,
+                attribute_types: vec!["@id".to_string()],
+                include_deleted: false,
+                filter_nulls: false,
+                filter_rows_where_null,
+            };
+
+        assert_eq!(request(match_all(), vec![0]).validate(), Ok(()));
+        assert_eq!(errors(&request(None, vec![])), ["`root` is required"]);
+        assert_eq!(
+            errors(&request(match_all(), vec![1, u32::MAX])),
+            [
+                "`filter_rows_where_null[0]` is not a column index",
+                "`filter_rows_where_null[1]` is not a column index",
+            ]
+        );
+    }
+
+    #[test]
+    fn watch_entities_requires_a_query_and_valid_event_types() {
+        let request = |query: Option<EntityQueryNode>, event_types| WatchEntitiesRequest {
+            query,
+            send_initial_events: false,
+            event_types,
+            attribute_types: vec![],
+            batch_window_ms: 0,
+        };
+
+        assert_eq!(
+            request(match_all(), vec![WatchEventType::Added.into()]).validate(),
+            Ok(())
+        );
+        assert_eq!(errors(&request(None, vec![])), ["`query` is required"]);
+        assert_eq!(
+            errors(&request(match_all(), vec![WatchEventType::Invalid.into()])),
+            ["`event_types[0]` has invalid value 0"]
+        );
+    }
+
+    #[test]
+    fn atomic_query_and_update_requires_exactly_one_of_a_value_and_delete() {
+        let request = |value: Option<ExpressionTree>, delete| AtomicQueryAndUpdateRequest {
+            entity_locator: Some(EntityLocator::builder().symbol("foo").build()),
+            attributes_to_update: vec![AttributeUpdateExpression {
+                attribute_type: "@description".to_string(),
+                value,
+                delete,
+            }],
+        };
+        let value = || {
+            Some(ExpressionTree {
+                expression: Some(expression_tree::Expression::Attribute(
+                    "@symbolName".to_string(),
+                )),
+            })
+        };
+
+        assert_eq!(request(value(), false).validate(), Ok(()));
+        assert_eq!(request(None, true).validate(), Ok(()));
+        assert_eq!(
+            errors(&request(None, false)),
+            ["`attributes_to_update[0].value` is required unless `delete` is set"]
+        );
+        assert_eq!(
+            errors(&request(value(), true)),
+            ["`attributes_to_update[0].value` must not be set with `delete`"]
+        );
+        assert_eq!(
+            errors(&request(Some(ExpressionTree::default()), false)),
+            ["`attributes_to_update[0].value` is empty"]
+        );
+    }
+}
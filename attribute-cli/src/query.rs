@@ -0,0 +1,38 @@
+use crate::pb;
+use crate::pb::entity_query_node::Query;
+use anyhow::bail;
+use clap::Args;
+
+/// A request given either as JSON, or as a query string and the attribute types to return
+#[derive(Args)]
+pub struct EntityRowsRequestArgs {
+    #[clap(short, long, required_unless_present = "query")]
+    json: Option<String>,
+    /// Query in the query language, e.g. `has(@symbolName) AND (has(foo) OR has(bar))`, as an
+    /// alternative to `--json`. Entity IDs are written as quoted text, e.g. `entity_id_in("CAc=")`
+    #[clap(short, long, conflicts_with = "json")]
+    query: Option<String>,
+    /// Attribute type to return for each entity matching `--query`. May be repeated
+    #[clap(short, long, requires = "query")]
+    attribute_type: Vec<String>,
+}
+
+impl EntityRowsRequestArgs {
+    /// Parses the `--json` request, or builds one from `--query` with `from_query`.
+    pub fn parse<T: prost_reflect::ReflectMessage + Default>(
+        &self,
+        from_query: impl FnOnce(pb::EntityQueryNode, Vec<String>) -> T,
+    ) -> anyhow::Result<T> {
+        match (&self.json, &self.query) {
+            (Some(json), _) => crate::json::parse_from_json_argument(json),
+            // The query is parsed by the server, which knows the entity IDs written in it
+            (None, Some(query)) => Ok(from_query(
+                pb::EntityQueryNode {
+                    query: Some(Query::QueryString(query.clone())),
+                },
+                self.attribute_type.clone(),
+            )),
+            (None, None) => bail!("specify a request with `--json` or `--query`"),
+        }
+    }
+}
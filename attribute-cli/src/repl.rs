@@ -0,0 +1,102 @@
+use crate::{run_command, Cli, Commands, Connection};
+use anyhow::format_err;
+use clap::Parser;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::io::Write;
+
+/// A single line of REPL input, parsed as a CLI subcommand without the binary name
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct ReplLine {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+pub async fn run(
+    cli: &Cli,
+    connection: &mut Connection,
+    output: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    let mut history: Vec<String> = vec![];
+
+    println!(
+        "Connected to {}. Type `help` for commands, `quit` to exit.",
+        cli.endpoint
+    );
+
+    loop {
+        let line = match editor.readline("attribute> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(err) => return Err(err)?,
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line)?;
+
+        let Some(words) = shlex::split(line) else {
+            eprintln!("error: unbalanced quotes");
+            continue;
+        };
+
+        let line = match words.as_slice() {
+            [quit] if quit == "quit" || quit == "exit" => return Ok(()),
+            [history_command] if history_command == "history" => {
+                for (idx, entry) in history.iter().enumerate() {
+                    println!("{:>4}  {}", idx + 1, entry);
+                }
+                continue;
+            }
+            [history_command, index] if history_command == "history" => {
+                match replay_history_entry(&history, index) {
+                    Ok(entry) => {
+                        println!("{}", entry);
+                        entry
+                    }
+                    Err(err) => {
+                        eprintln!("error: {err:#}");
+                        continue;
+                    }
+                }
+            }
+            _ => line.to_string(),
+        };
+
+        if let Err(err) = run_line(cli, connection, output, &line).await {
+            eprintln!("error: {err:#}");
+        }
+        history.push(line);
+    }
+}
+
+fn replay_history_entry(history: &[String], index: &str) -> anyhow::Result<String> {
+    let index: usize = index.parse()?;
+    index
+        .checked_sub(1)
+        .and_then(|index| history.get(index))
+        .cloned()
+        .ok_or_else(|| format_err!("no history entry {index}"))
+}
+
+async fn run_line(
+    cli: &Cli,
+    connection: &mut Connection,
+    output: &mut dyn Write,
+    line: &str,
+) -> anyhow::Result<()> {
+    let words = shlex::split(line).ok_or_else(|| format_err!("unbalanced quotes"))?;
+    let repl_line = match ReplLine::try_parse_from(words) {
+        Ok(repl_line) => repl_line,
+        Err(err) => {
+            err.print()?;
+            return Ok(());
+        }
+    };
+
+    run_command(cli, &repl_line.command, connection, output).await
+}
@@ -0,0 +1,97 @@
+use std::fmt::Display;
+use std::time::Duration;
+use tonic::{Code, Status};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Retries requests that fail with a transient error, doubling the delay between attempts.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Whether requests that exceeded their deadline are retried. The server may have applied them
+    /// anyway, so this is only safe for idempotent requests.
+    pub retry_deadline_exceeded: bool,
+}
+
+impl RetryPolicy {
+    /// This policy for requests that aren't idempotent, which are only retried if they didn't
+    /// reach the server.
+    pub fn for_non_idempotent_requests(self) -> Self {
+        RetryPolicy {
+            retry_deadline_exceeded: false,
+            ..self
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        INITIAL_BACKOFF
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_BACKOFF)
+    }
+
+    /// Calls `call` until it succeeds, fails with a non-transient error, or `max_retries` retries
+    /// have been made.
+    pub async fn retry<R, E: Display>(
+        &self,
+        is_transient: impl Fn(&E) -> bool,
+        mut call: impl AsyncFnMut() -> Result<R, E>,
+    ) -> Result<R, E> {
+        let mut attempt = 0;
+        loop {
+            match call().await {
+                Err(err) if attempt < self.max_retries && is_transient(&err) => {
+                    let backoff = self.backoff(attempt);
+                    tracing::warn!("{err}, retrying in {backoff:?}");
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Retries gRPC requests that fail with `UNAVAILABLE`, or with `DEADLINE_EXCEEDED` if
+    /// `retry_deadline_exceeded` is set.
+    pub async fn call<R>(&self, call: impl AsyncFnMut() -> Result<R, Status>) -> Result<R, Status> {
+        self.retry(
+            |status: &Status| match status.code() {
+                Code::Unavailable => true,
+                Code::DeadlineExceeded => self.retry_deadline_exceeded,
+                _ => false,
+            },
+            call,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn attempts(retry_policy: RetryPolicy, code: Code) -> u32 {
+        let mut attempts = 0;
+        let _ = retry_policy
+            .call(async || {
+                attempts += 1;
+                Err::<(), _>(Status::new(code, "failed"))
+            })
+            .await;
+        attempts
+    }
+
+    #[tokio::test]
+    async fn non_idempotent_requests_are_only_retried_if_unavailable() {
+        let retry_policy = RetryPolicy {
+            max_retries: 1,
+            retry_deadline_exceeded: true,
+        };
+        assert_eq!(attempts(retry_policy, Code::DeadlineExceeded).await, 2);
+        assert_eq!(attempts(retry_policy, Code::InvalidArgument).await, 1);
+
+        let retry_policy = retry_policy.for_non_idempotent_requests();
+        assert_eq!(attempts(retry_policy, Code::DeadlineExceeded).await, 1);
+        assert_eq!(attempts(retry_policy, Code::Unavailable).await, 2);
+    }
+}
@@ -0,0 +1,211 @@
+use prost_reflect::{Cardinality, FieldDescriptor, Kind, MessageDescriptor};
+use serde_json::{json, Map, Value};
+
+/// Generates a JSON Schema for the JSON encoding of `message_descriptor` used by `json::to_json`.
+///
+/// Nested messages are emitted once under `$defs`, keyed by their full name, so recursive messages
+/// are supported.
+pub fn message_json_schema(message_descriptor: &MessageDescriptor) -> Value {
+    let mut definitions = Map::new();
+    let root = message_schema(message_descriptor);
+    collect_definitions(message_descriptor, &mut definitions);
+
+    let mut schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": message_descriptor.full_name(),
+    });
+    let schema_object = schema.as_object_mut().unwrap();
+    schema_object.extend(root.as_object().unwrap().clone());
+    if !definitions.is_empty() {
+        schema_object.insert("$defs".to_string(), Value::Object(definitions));
+    }
+    schema
+}
+
+fn collect_definitions(
+    message_descriptor: &MessageDescriptor,
+    definitions: &mut Map<String, Value>,
+) {
+    for field in message_descriptor.fields() {
+        let Kind::Message(field_message) = field.kind() else {
+            continue;
+        };
+        let field_message = if field.is_map() {
+            match field_message.map_entry_value_field().kind() {
+                Kind::Message(value_message) => value_message,
+                _ => continue,
+            }
+        } else {
+            field_message
+        };
+        if definitions.contains_key(field_message.full_name())
+            || well_known_type_schema(&field_message).is_some()
+        {
+            continue;
+        }
+        // Inserted before recursing so that recursive messages terminate
+        definitions.insert(
+            field_message.full_name().to_string(),
+            message_schema(&field_message),
+        );
+        collect_definitions(&field_message, definitions);
+    }
+}
+
+fn message_schema(message_descriptor: &MessageDescriptor) -> Value {
+    let properties: Map<String, Value> = message_descriptor
+        .fields()
+        .map(|field| (field.json_name().to_string(), field_schema(&field)))
+        .collect();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+fn field_schema(field: &FieldDescriptor) -> Value {
+    if field.is_map() {
+        let Kind::Message(map_entry) = field.kind() else {
+            unreachable!("map fields are always map entry messages");
+        };
+        return json!({
+            "type": "object",
+            "additionalProperties": kind_schema(&map_entry.map_entry_value_field().kind()),
+        });
+    }
+
+    let schema = kind_schema(&field.kind());
+    if field.cardinality() == Cardinality::Repeated {
+        json!({ "type": "array", "items": schema })
+    } else {
+        schema
+    }
+}
+
+fn kind_schema(kind: &Kind) -> Value {
+    match kind {
+        Kind::Double | Kind::Float => json!({ "type": "number" }),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => json!({ "type": "integer" }),
+        Kind::Uint32 | Kind::Fixed32 => json!({ "type": "integer", "minimum": 0 }),
+        // 64-bit integers are serialized as strings to avoid losing precision
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => {
+            json!({ "type": "string", "pattern": "^-?[0-9]+$" })
+        }
+        Kind::Uint64 | Kind::Fixed64 => json!({ "type": "string", "pattern": "^[0-9]+$" }),
+        Kind::Bool => json!({ "type": "boolean" }),
+        Kind::String => json!({ "type": "string" }),
+        Kind::Bytes => json!({ "type": "string", "contentEncoding": "base64" }),
+        Kind::Message(message_descriptor) => well_known_type_schema(message_descriptor)
+            .unwrap_or_else(
+                || json!({ "$ref": format!("#/$defs/{}", message_descriptor.full_name()) }),
+            ),
+        Kind::Enum(enum_descriptor) => json!({
+            "type": "string",
+            "enum": enum_descriptor
+                .values()
+                .map(|value| value.name().to_string())
+                .collect::<Vec<_>>(),
+        }),
+    }
+}
+
+/// Schemas for the well-known types with a special JSON encoding, which isn't that of their fields.
+fn well_known_type_schema(message_descriptor: &MessageDescriptor) -> Option<Value> {
+    let schema = match message_descriptor.full_name() {
+        "google.protobuf.Timestamp" => json!({ "type": "string", "format": "date-time" }),
+        "google.protobuf.Duration" => {
+            json!({ "type": "string", "pattern": "^-?[0-9]+(\\.[0-9]{1,9})?s$" })
+        }
+        "google.protobuf.FieldMask" => json!({ "type": "string" }),
+        "google.protobuf.Struct" => json!({ "type": "object" }),
+        "google.protobuf.ListValue" => json!({ "type": "array" }),
+        "google.protobuf.Value" => json!({}),
+        "google.protobuf.Any" => json!({
+            "type": "object",
+            "properties": { "@type": { "type": "string" } },
+            "required": ["@type"],
+        }),
+        "google.protobuf.DoubleValue"
+        | "google.protobuf.FloatValue"
+        | "google.protobuf.Int64Value"
+        | "google.protobuf.UInt64Value"
+        | "google.protobuf.Int32Value"
+        | "google.protobuf.UInt32Value"
+        | "google.protobuf.BoolValue"
+        | "google.protobuf.StringValue"
+        | "google.protobuf.BytesValue" => {
+            kind_schema(&message_descriptor.get_field_by_name("value")?.kind())
+        }
+        _ => return None,
+    };
+    Some(schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb;
+    use prost_reflect::ReflectMessage;
+
+    fn schema_of<T: ReflectMessage + Default>() -> Value {
+        message_json_schema(&T::default().descriptor())
+    }
+
+    #[test]
+    fn scalar_and_enum_fields() {
+        let schema = schema_of::<pb::WatchEntitiesRequest>();
+
+        assert_eq!(
+            schema["title"],
+            "me.grahamdennis.attribute.WatchEntitiesRequest"
+        );
+        assert_eq!(schema["additionalProperties"], false);
+        let properties = &schema["properties"];
+        assert_eq!(
+            properties["sendInitialEvents"],
+            json!({ "type": "boolean" })
+        );
+        assert_eq!(
+            properties["batchWindowMs"],
+            json!({ "type": "integer", "minimum": 0 })
+        );
+        assert_eq!(
+            properties["attributeTypes"],
+            json!({ "type": "array", "items": { "type": "string" } })
+        );
+        assert_eq!(properties["eventTypes"]["type"], "array");
+        assert_eq!(properties["eventTypes"]["items"]["type"], "string");
+        assert!(properties["eventTypes"]["items"]["enum"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("WATCH_EVENT_TYPE_ADDED")));
+    }
+
+    #[test]
+    fn recursive_messages_are_referenced_from_defs() {
+        let schema = schema_of::<pb::WatchEntitiesRequest>();
+
+        assert_eq!(
+            schema["properties"]["query"],
+            json!({ "$ref": "#/$defs/me.grahamdennis.attribute.EntityQueryNode" })
+        );
+        let and_query_node = &schema["$defs"]["me.grahamdennis.attribute.AndQueryNode"];
+        assert_eq!(
+            and_query_node["properties"]["clauses"]["items"],
+            json!({ "$ref": "#/$defs/me.grahamdennis.attribute.EntityQueryNode" })
+        );
+    }
+
+    #[test]
+    fn well_known_types_use_their_json_encoding() {
+        let schema = schema_of::<pb::AttributeValueAfterNode>();
+
+        assert_eq!(
+            schema["properties"]["timestamp"],
+            json!({ "type": "string", "format": "date-time" })
+        );
+        assert!(schema.get("$defs").is_none());
+    }
+}
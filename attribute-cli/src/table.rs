@@ -0,0 +1,183 @@
+use crate::fmt::{wrap_entity_row, EntityRowMetadata};
+use crate::pb::watch_entity_rows_event::Event;
+use crate::pb::{EntityRow, WatchEntityRowsEvent};
+use comfy_table::{ContentArrangement, Table};
+use serde_json::Value;
+use std::io::Write;
+
+/// The latest value of each watched entity row, rendered as a table that is redrawn in place.
+pub struct EntityRowTable {
+    header: Vec<String>,
+    /// Rows in the order their entities were first seen, keyed by entity ID
+    rows: Vec<(String, Vec<String>)>,
+    /// Number of terminal lines taken by the last table drawn
+    lines_drawn: usize,
+}
+
+impl EntityRowTable {
+    pub fn new(attribute_types: &[String]) -> EntityRowTable {
+        EntityRowTable {
+            header: std::iter::once("@id".to_string())
+                .chain(attribute_types.iter().cloned())
+                .collect(),
+            rows: vec![],
+            lines_drawn: 0,
+        }
+    }
+
+    /// Applies `event` to the table, returning whether any row changed.
+    pub fn apply(
+        &mut self,
+        event: &WatchEntityRowsEvent,
+        metadata: &EntityRowMetadata,
+    ) -> anyhow::Result<bool> {
+        match &event.event {
+            Some(Event::Added(added_event)) => self.upsert(&added_event.entity_row, metadata),
+            Some(Event::Modified(modified_event)) => {
+                self.upsert(&modified_event.entity_row, metadata)
+            }
+            Some(Event::Removed(removed_event)) => {
+                let Some(entity_row) = &removed_event.entity_row else {
+                    return Ok(false);
+                };
+                let row_count = self.rows.len();
+                self.rows
+                    .retain(|(entity_id, _)| *entity_id != entity_row.entity_id);
+                Ok(self.rows.len() != row_count)
+            }
+            Some(Event::Bookmark(_)) | None => Ok(false),
+        }
+    }
+
+    fn upsert(
+        &mut self,
+        entity_row: &Option<EntityRow>,
+        metadata: &EntityRowMetadata,
+    ) -> anyhow::Result<bool> {
+        let Some(entity_row) = entity_row else {
+            return Ok(false);
+        };
+        let cells = match serde_json::to_value(wrap_entity_row(entity_row, metadata))? {
+            Value::Array(values) => values.into_iter().map(format_cell).collect(),
+            value => vec![format_cell(value)],
+        };
+
+        match self
+            .rows
+            .iter_mut()
+            .find(|(entity_id, _)| *entity_id == entity_row.entity_id)
+        {
+            Some((_, row)) => *row = cells,
+            None => self.rows.push((entity_row.entity_id.clone(), cells)),
+        }
+        Ok(true)
+    }
+
+    /// Draws the table over the one drawn previously, if that one fit within `terminal_height`
+    /// lines. Otherwise the table is drawn below it.
+    pub fn redraw(
+        &mut self,
+        output: &mut dyn Write,
+        terminal_height: usize,
+    ) -> std::io::Result<()> {
+        let mut table = Table::new();
+        // Wrap cells to the terminal width, so that each table line is one terminal line
+        table
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(&self.header);
+        for (_, row) in &self.rows {
+            table.add_row(row);
+        }
+        let rendered = table.to_string();
+
+        if self.lines_drawn > 0 {
+            // Move the cursor to the start of the previous table and clear everything below it
+            write!(output, "\x1b[{}F\x1b[J", self.lines_drawn)?;
+        }
+        writeln!(output, "{rendered}")?;
+        output.flush()?;
+        // The cursor can't be moved back above the top of the terminal, so a table taller than the
+        // terminal is left in place rather than partially cleared by the next redraw
+        let lines_drawn = rendered.lines().count();
+        self.lines_drawn = if lines_drawn < terminal_height {
+            lines_drawn
+        } else {
+            0
+        };
+
+        Ok(())
+    }
+}
+
+fn format_cell(value: Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s,
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::attribute_value::AttributeValue as Value;
+    use crate::pb::AddedEntityRowEvent;
+    use crate::pb::{AttributeValue, NullableAttributeValue};
+
+    fn added_event(entity_id: &str, name: &str) -> WatchEntityRowsEvent {
+        WatchEntityRowsEvent {
+            entity_id: entity_id.to_string(),
+            event: Some(Event::Added(AddedEntityRowEvent {
+                entity_id: entity_id.to_string(),
+                entity_row: Some(EntityRow {
+                    entity_id: entity_id.to_string(),
+                    values: vec![NullableAttributeValue {
+                        value: Some(AttributeValue {
+                            attribute_value: Some(Value::StringValue(name.to_string())),
+                        }),
+                    }],
+                    column_names: vec!["@symbolName".to_string()],
+                }),
+            })),
+        }
+    }
+
+    fn draw(table: &mut EntityRowTable, terminal_height: usize) -> String {
+        let mut output = vec![];
+        table.redraw(&mut output, terminal_height).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn redraw_replaces_the_previous_table() {
+        let metadata = EntityRowMetadata {
+            columns: vec![None],
+        };
+        let mut table = EntityRowTable::new(&["@symbolName".to_string()]);
+
+        assert!(table.apply(&added_event("a", "foo"), &metadata).unwrap());
+        let first = draw(&mut table, 24);
+        assert!(first.starts_with('+'));
+        assert!(first.contains("@symbolName") && first.contains("foo"));
+
+        assert!(table.apply(&added_event("b", "bar"), &metadata).unwrap());
+        let second = draw(&mut table, 24);
+        let clear_previous = format!("\x1b[{}F\x1b[J", first.lines().count());
+        let redrawn = second.strip_prefix(&clear_previous).unwrap();
+        assert!(redrawn.contains("foo") && redrawn.contains("bar"));
+    }
+
+    #[test]
+    fn tables_taller_than_the_terminal_are_not_redrawn_over() {
+        let metadata = EntityRowMetadata {
+            columns: vec![None],
+        };
+        let mut table = EntityRowTable::new(&["@symbolName".to_string()]);
+        table.apply(&added_event("a", "foo"), &metadata).unwrap();
+
+        let first = draw(&mut table, 3);
+        assert!(first.lines().count() >= 3);
+        let second = draw(&mut table, 3);
+        assert_eq!(second, first);
+    }
+}
@@ -0,0 +1,223 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::parse::ParseStream;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, LitStr, Path, Token, Type};
+
+/// Derive `TryFromProto` for a type defined in another crate.
+///
+/// The derive is applied to a local mirror of the target type, in the same style as serde's
+/// remote derives:
+///
+/// ```ignore
+/// #[derive(TryFromProto)]
+/// #[try_from_proto(source = pb::AttributeToUpdate, target = AttributeToUpdate)]
+/// struct AttributeToUpdateDef {
+///     #[proto_field("attribute_type")]
+///     symbol: Symbol,
+///     #[proto_field("attribute_value")]
+///     value: Option<AttributeValue>,
+/// }
+/// ```
+///
+/// Struct fields are converted from the named proto field, with `#[proto_field("name", required)]`
+/// reporting a missing field error when the proto field is `None`. The errors of every field that
+/// fails to convert are reported together. Enum variants each wrap a
+/// single value and are converted from the identically-named variant of a proto `oneof`.
+///
+/// The generated code refers to `crate::convert`, so it is only usable within attribute-server.
+#[proc_macro_derive(TryFromProto, attributes(try_from_proto, proto_field))]
+pub fn derive_try_from_proto(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+struct ContainerOptions {
+    source: Path,
+    target: Path,
+}
+
+struct ProtoField {
+    name: LitStr,
+    required: bool,
+}
+
+fn parse_container_options(input: &DeriveInput) -> syn::Result<ContainerOptions> {
+    let mut source = None;
+    let mut target = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("try_from_proto"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("source") {
+                source = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("target") {
+                target = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `source` or `target`"))
+            }
+        })?;
+    }
+
+    let source = source.ok_or_else(|| {
+        syn::Error::new_spanned(
+            &input.ident,
+            "missing `#[try_from_proto(source = ...)]` attribute",
+        )
+    })?;
+    let target = target.unwrap_or_else(|| input.ident.clone().into());
+
+    Ok(ContainerOptions { source, target })
+}
+
+fn parse_proto_field(
+    attrs: &[syn::Attribute],
+    span: &dyn quote::ToTokens,
+) -> syn::Result<ProtoField> {
+    let attr = attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("proto_field"))
+        .ok_or_else(|| {
+            syn::Error::new_spanned(span, "missing `#[proto_field(\"...\")]` attribute")
+        })?;
+
+    attr.parse_args_with(|input: ParseStream| {
+        let name: LitStr = input.parse()?;
+        let mut required = false;
+        while input.peek(Token![,]) {
+            input.parse::<Token![,]>()?;
+            let flag: Ident = input.parse()?;
+            if flag == "required" {
+                required = true;
+            } else {
+                return Err(syn::Error::new_spanned(flag, "expected `required`"));
+            }
+        }
+        Ok(ProtoField { name, required })
+    })
+}
+
+fn convert_value(ty: &Type, value: TokenStream2) -> TokenStream2 {
+    quote! {
+        <#ty as crate::convert::TryFromProto<_>>::try_from_proto_with(#value, &mut path)
+    }
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let ContainerOptions { source, target } = parse_container_options(&input)?;
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let Fields::Named(fields) = &data.fields else {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "TryFromProto can only be derived for structs with named fields",
+                ));
+            };
+            let field_idents = fields
+                .named
+                .iter()
+                .map(|field| field.ident.as_ref().expect("named field"))
+                .collect::<Vec<_>>();
+            let fields = fields
+                .named
+                .iter()
+                .map(|field| {
+                    let field_ident = field.ident.as_ref().expect("named field");
+                    let ProtoField { name, required } = parse_proto_field(&field.attrs, field)?;
+                    let proto_ident = Ident::new(&name.value(), name.span());
+                    let conversion = if required {
+                        let converted = convert_value(&field.ty, quote!(proto));
+                        quote! {
+                            value
+                                .#proto_ident
+                                .ok_or_else(|| crate::convert::FieldError::FieldMissing.at_path(path()))
+                                .and_then(|proto| #converted)
+                        }
+                    } else {
+                        convert_value(&field.ty, quote!(value.#proto_ident))
+                    };
+
+                    Ok(quote! {
+                        let #field_ident = {
+                            let mut path = garde::util::nested_path!(parent, #name);
+                            errors.field(#conversion)
+                        };
+                    })
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+
+            // Every field is converted, so that all invalid fields are reported together
+            quote! {
+                let mut errors = crate::convert::ConversionErrorBuilder::default();
+                #(#fields)*
+                match (#(#field_idents,)*) {
+                    (#(Some(#field_idents),)*) => Ok(#target {
+                        #(#field_idents,)*
+                    }),
+                    _ => Err(errors.build()),
+                }
+            }
+        }
+        Data::Enum(data) => {
+            let variants = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let variant_ident = &variant.ident;
+                    let Fields::Unnamed(fields) = &variant.fields else {
+                        return Err(syn::Error::new_spanned(
+                            variant,
+                            "TryFromProto variants must wrap exactly one value",
+                        ));
+                    };
+                    if fields.unnamed.len() != 1 {
+                        return Err(syn::Error::new_spanned(
+                            variant,
+                            "TryFromProto variants must wrap exactly one value",
+                        ));
+                    }
+                    let ProtoField { name, .. } = parse_proto_field(&variant.attrs, variant)?;
+                    let conversion = convert_value(&fields.unnamed[0].ty, quote!(inner));
+
+                    Ok(quote! {
+                        #source::#variant_ident(inner) => {
+                            let mut path = garde::util::nested_path!(parent, #name);
+                            #target::#variant_ident(#conversion?)
+                        }
+                    })
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+
+            quote! {
+                Ok(match value {
+                    #(#variants)*
+                })
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "TryFromProto cannot be derived for unions",
+            ));
+        }
+    };
+
+    Ok(quote! {
+        impl crate::convert::TryFromProto<#source> for #target {
+            fn try_from_proto_with(
+                value: #source,
+                mut parent: &mut dyn FnMut() -> garde::Path,
+            ) -> crate::convert::ConversionResult<Self> {
+                #body
+            }
+        }
+    })
+}
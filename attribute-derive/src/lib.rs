@@ -0,0 +1,69 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, LitStr};
+
+/// Derive `TypedAttribute` for a protobuf message, stored as the encoded message under the
+/// attribute type named by `#[attribute(name = "...")]`:
+///
+/// ```ignore
+/// #[derive(prost::Message, TypedAttribute)]
+/// #[attribute(name = "me.grahamdennis.attribute.mavlink.GlobalPosition")]
+/// struct GlobalPosition {
+///     ...
+/// }
+/// ```
+///
+/// The type must implement `prost::Message`. The generated code refers to
+/// `crate::attributes::TypedAttribute`, so it is only usable within attribute-cli.
+#[proc_macro_derive(TypedAttribute, attributes(attribute))]
+pub fn derive_typed_attribute(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn parse_attribute_name(input: &DeriveInput) -> syn::Result<LitStr> {
+    let mut name = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("attribute"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `name`"))
+            }
+        })?;
+    }
+
+    name.ok_or_else(|| {
+        syn::Error::new_spanned(
+            &input.ident,
+            "missing `#[attribute(name = \"...\")]` attribute",
+        )
+    })
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = parse_attribute_name(&input)?;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics crate::attributes::TypedAttribute for #ident #ty_generics #where_clause {
+            fn attribute_name() -> &'static str {
+                #name
+            }
+
+            fn as_bytes(&self) -> Vec<u8> {
+                ::prost::Message::encode_to_vec(self)
+            }
+        }
+    })
+}
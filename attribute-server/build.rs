@@ -1,7 +1,17 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::configure().compile(
+    tonic_build::configure().compile_protos(
         &["proto/internal.proto", "../proto/attribute.proto"],
         &["proto/", "../proto"],
     )?;
+
+    // Recorded on the `@server` entity
+    let rustc_version = std::process::Command::new(std::env::var("RUSTC")?)
+        .arg("--version")
+        .output()?
+        .stdout;
+    println!(
+        "cargo:rustc-env=RUSTC_VERSION={}",
+        String::from_utf8(rustc_version)?.trim()
+    );
     Ok(())
 }
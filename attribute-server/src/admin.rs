@@ -0,0 +1,142 @@
+use axum::routing::get;
+use axum::{Json, Router};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The watch RPC that opened a stream, named after it.
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamKind {
+    WatchEntities,
+    WatchEntityRows,
+    WatchEntity,
+}
+
+impl StreamKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StreamKind::WatchEntities => "watch_entities",
+            StreamKind::WatchEntityRows => "watch_entity_rows",
+            StreamKind::WatchEntity => "watch_entity",
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct StreamStats {
+    events_sent: AtomicU64,
+    /// Watch events buffered for the stream when it last received one
+    lag: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct StreamEntry {
+    kind: StreamKind,
+    client_address: Option<SocketAddr>,
+    query: String,
+    start_time: SystemTime,
+    stats: Arc<StreamStats>,
+}
+
+/// The watch streams currently open, keyed by the order in which they were opened.
+#[derive(Default, Debug)]
+pub struct StreamRegistry {
+    next_stream_id: u64,
+    streams: BTreeMap<u64, StreamEntry>,
+}
+
+impl StreamRegistry {
+    /// Registers a stream, which is unregistered when the returned registration is dropped.
+    pub fn register(
+        registry: &Arc<Mutex<StreamRegistry>>,
+        kind: StreamKind,
+        client_address: Option<SocketAddr>,
+        query: String,
+    ) -> StreamRegistration {
+        let stats = Arc::new(StreamStats::default());
+        let mut guard = registry.lock();
+        let stream_id = guard.next_stream_id;
+        guard.next_stream_id += 1;
+        guard.streams.insert(
+            stream_id,
+            StreamEntry {
+                kind,
+                client_address,
+                query,
+                start_time: SystemTime::now(),
+                stats: stats.clone(),
+            },
+        );
+
+        StreamRegistration {
+            registry: registry.clone(),
+            stream_id,
+            stats,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Array(
+            self.streams
+                .values()
+                .map(|stream| {
+                    json!({
+                        "kind": stream.kind.as_str(),
+                        "client_address": stream.client_address.map(|address| address.to_string()),
+                        "query": stream.query,
+                        "start_time_ms": stream
+                            .start_time
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_millis() as u64,
+                        "events_sent": stream.stats.events_sent.load(Ordering::Relaxed),
+                        "lag": stream.stats.lag.load(Ordering::Relaxed),
+                    })
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Tracks the progress of a registered stream and unregisters it when dropped.
+#[derive(Debug)]
+pub struct StreamRegistration {
+    registry: Arc<Mutex<StreamRegistry>>,
+    stream_id: u64,
+    stats: Arc<StreamStats>,
+}
+
+impl StreamRegistration {
+    pub fn record_event_sent(&self) {
+        self.stats.events_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_lag(&self, lag: usize) {
+        self.stats.lag.store(lag, Ordering::Relaxed);
+    }
+}
+
+impl Drop for StreamRegistration {
+    fn drop(&mut self) {
+        self.registry.lock().streams.remove(&self.stream_id);
+    }
+}
+
+/// Serves the open watch streams as JSON at `GET /admin/streams`.
+pub fn admin_router(stream_registry: Arc<Mutex<StreamRegistry>>) -> Router {
+    Router::new().route(
+        "/admin/streams",
+        get(move || {
+            let stream_registry = stream_registry.clone();
+            async move {
+                log::info!("Received admin streams request");
+                Json(stream_registry.lock().to_json())
+            }
+        }),
+    )
+}
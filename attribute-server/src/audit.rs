@@ -0,0 +1,76 @@
+use crate::convert::IntoProto;
+use attribute_store::audit::{AuditEntry, AuditLog, AuditOperation};
+use attribute_store::store::{AttributeValue, EntityLocator};
+use axum::routing::get;
+use axum::{Json, Router};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use parking_lot::Mutex;
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+/// Serves the store's audit log as JSON at `GET /audit`.
+pub fn audit_log_router(audit_log: Arc<Mutex<AuditLog>>) -> Router {
+    Router::new().route(
+        "/audit",
+        get(move || {
+            let audit_log = audit_log.clone();
+            async move {
+                log::info!("Received audit log request");
+                Json(Value::Array(
+                    audit_log
+                        .lock()
+                        .entries()
+                        .map(audit_entry_to_json)
+                        .collect(),
+                ))
+            }
+        }),
+    )
+}
+
+fn audit_entry_to_json(audit_entry: &AuditEntry) -> Value {
+    let operation = match audit_entry.operation {
+        AuditOperation::CreateAttributeType => "create_attribute_type",
+        AuditOperation::UpdateEntity => "update_entity",
+    };
+    let entity_locator = match &audit_entry.entity_locator {
+        EntityLocator::EntityId(entity_id) => json!({ "entity_id": entity_id.into_proto() }),
+        EntityLocator::Symbol(symbol) => json!({ "symbol": symbol.as_str() }),
+        EntityLocator::CreateNew => json!({ "create_new": {} }),
+    };
+    let attributes = audit_entry
+        .attributes
+        .iter()
+        .map(|(symbol, value)| (symbol.as_str().into(), attribute_value_to_json(value)))
+        .collect::<Map<_, _>>();
+
+    json!({
+        "timestamp_ms": audit_entry
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64,
+        "operation": operation,
+        "entity_locator": entity_locator,
+        "attributes": attributes,
+    })
+}
+
+fn attribute_value_to_json(attribute_value: &AttributeValue) -> Value {
+    match attribute_value {
+        AttributeValue::String(value) => json!({ "string_value": value }),
+        AttributeValue::EntityId(entity_id) => {
+            json!({ "entity_id_value": entity_id.into_proto() })
+        }
+        AttributeValue::Bytes(bytes) => json!({ "bytes_value": STANDARD.encode(bytes) }),
+        AttributeValue::Timestamp(timestamp) => {
+            let timestamp: prost_types::Timestamp = timestamp.into_proto();
+            json!({ "timestamp_value": timestamp.to_string() })
+        }
+        AttributeValue::Int64(value) => json!({ "int64_value": value }),
+        AttributeValue::List(values) => json!({
+            "list_value": values.iter().map(attribute_value_to_json).collect::<Vec<_>>()
+        }),
+    }
+}
@@ -0,0 +1,59 @@
+use attribute_store::store::EntityQueryNode;
+use std::time::Duration;
+
+/// Upper bound on `iterations`, so a single request cannot monopolise the store
+pub const MAX_BENCHMARK_ITERATIONS: u32 = 10_000;
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct BenchmarkQueryRequest {
+    pub iterations: u32,
+    pub query: EntityQueryNode,
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct BenchmarkStatistics {
+    pub total_duration: Duration,
+    pub mean_duration: Duration,
+    pub p99_duration: Duration,
+}
+
+impl BenchmarkStatistics {
+    /// Computes statistics for a non-empty set of durations.
+    pub fn from_durations(mut durations: Vec<Duration>) -> BenchmarkStatistics {
+        assert!(!durations.is_empty(), "no durations to summarise");
+        durations.sort();
+
+        let total_duration: Duration = durations.iter().sum();
+        // Nearest-rank percentile
+        let p99_rank = (durations.len() * 99).div_ceil(100);
+
+        BenchmarkStatistics {
+            total_duration,
+            mean_duration: total_duration / durations.len() as u32,
+            p99_duration: durations[p99_rank - 1],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_statistics() {
+        let statistics = BenchmarkStatistics::from_durations(
+            (1..=200).rev().map(Duration::from_millis).collect(),
+        );
+        assert_eq!(
+            statistics,
+            BenchmarkStatistics {
+                total_duration: Duration::from_millis(20_100),
+                mean_duration: Duration::from_micros(100_500),
+                p99_duration: Duration::from_millis(198),
+            }
+        );
+
+        let single = BenchmarkStatistics::from_durations(vec![Duration::from_millis(3)]);
+        assert_eq!(single.p99_duration, Duration::from_millis(3));
+    }
+}
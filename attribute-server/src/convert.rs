@@ -1,28 +1,48 @@
+use crate::benchmark::{BenchmarkQueryRequest, BenchmarkStatistics, MAX_BENCHMARK_ITERATIONS};
+use crate::expression::{AtomicQueryAndUpdateRequest, AttributeUpdateExpression, Expression};
 use crate::internal_pb;
 use crate::pb;
 use anyhow::format_err;
+use attribute_convert_derive::TryFromProto;
+use attribute_store::query_language::EntityIdFormat;
 use attribute_store::store::{
-    AndQueryNode, AttributeToUpdate, AttributeType, AttributeValue, CreateAttributeTypeRequest,
-    Entity, EntityId, EntityLocator, EntityQueryNode, EntityRow, EntityRowQuery, EntityVersion,
-    HasAttributeTypesNode, MatchAllQueryNode, MatchNoneQueryNode, OrQueryNode, Symbol,
-    UpdateEntityRequest, ValueType, WatchEntitiesEvent, WatchEntitiesRequest, WatchEntityRowsEvent,
-    WatchEntityRowsRequest,
+    AndQueryNode, AttributeToUpdate, AttributeType, AttributeValue, AttributeValueAfterNode,
+    AttributeValueBeforeNode, BatchGetEntitiesResult, CreateAttributeTypeRequest,
+    CreateAttributeTypesResult, ElementAtNode, Entity, EntityDiff, EntityId, EntityIdInNode,
+    EntityLocator, EntityQueryNode, EntityRow, EntityRowQuery, EntityVersion, GraphTraverseNode,
+    HasAttributeTypesNode, HasAttributeValueNode, MatchAllQueryNode, MatchNoneQueryNode,
+    MergeEntitiesRequest, OrQueryNode, Symbol, TextContainsNode, TextMatchesRegexNode,
+    TextStartsWithNode, UpdateEntityRequest, ValueType, WatchEntitiesEvent, WatchEntitiesRequest,
+    WatchEntityRequest, WatchEntityRowsEvent, WatchEntityRowsRequest, WatchEventType,
 };
 use base64::{engine::general_purpose::URL_SAFE, Engine as _};
 use prost::Message;
 use std::collections::HashMap;
+use std::time::SystemTime;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum FieldError {
     #[error("missing field")]
     FieldMissing,
+    #[error("field must not be set")]
+    UnexpectedField,
     #[error("error decoding entity id")]
     InvalidEntityId(#[source] anyhow::Error),
     #[error("invalid symbol")]
     InvalidSymbol(#[source] anyhow::Error),
     #[error("invalid value type")]
     InvalidValueType(#[source] anyhow::Error),
+    #[error("invalid watch event type")]
+    InvalidWatchEventType(#[source] anyhow::Error),
+    #[error("invalid regex")]
+    InvalidRegex(#[source] anyhow::Error),
+    #[error("value out of range: {0}")]
+    OutOfRange(String),
+    #[error("invalid timestamp")]
+    InvalidTimestamp(#[source] anyhow::Error),
+    #[error("invalid query string")]
+    InvalidQueryString(#[source] anyhow::Error),
 }
 
 impl FieldError {
@@ -35,10 +55,53 @@ impl FieldError {
 pub enum ConversionError {
     #[error("error in field `{0}`")]
     InField(garde::Path, #[source] FieldError),
+    #[error("errors in {} fields", .0.len())]
+    Multiple(Vec<(garde::Path, FieldError)>),
+}
+
+impl ConversionError {
+    pub fn into_field_errors(self) -> Vec<(garde::Path, FieldError)> {
+        match self {
+            ConversionError::InField(path, field_error) => vec![(path, field_error)],
+            ConversionError::Multiple(field_errors) => field_errors,
+        }
+    }
 }
 
 type ConversionResult<T> = Result<T, ConversionError>;
 
+/// Collects the errors of converting each field, so that every invalid field is reported rather
+/// than only the first.
+#[derive(Default)]
+pub struct ConversionErrorBuilder {
+    field_errors: Vec<(garde::Path, FieldError)>,
+}
+
+impl ConversionErrorBuilder {
+    /// Returns the converted field, or records its errors and returns `None`.
+    pub fn field<T>(&mut self, result: ConversionResult<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(conversion_error) => {
+                self.field_errors
+                    .extend(conversion_error.into_field_errors());
+                None
+            }
+        }
+    }
+
+    /// Builds the error once at least one field has failed to convert.
+    pub fn build(self) -> ConversionError {
+        let mut field_errors = self.field_errors;
+        if field_errors.len() == 1 {
+            let (path, field_error) = field_errors.remove(0);
+            ConversionError::InField(path, field_error)
+        } else {
+            ConversionError::Multiple(field_errors)
+        }
+    }
+}
+
 pub trait TryFromProto<T>: Sized {
     fn try_from_proto(value: T) -> ConversionResult<Self> {
         Self::try_from_proto_with(value, &mut garde::Path::empty)
@@ -54,6 +117,16 @@ pub trait IntoProto<T>: Sized {
     fn into_proto(self) -> T;
 }
 
+impl TryFromProto<pb::GetAttributeTypeRequest> for Symbol {
+    fn try_from_proto_with(
+        value: pb::GetAttributeTypeRequest,
+        mut parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        let mut path = garde::util::nested_path!(parent, "symbol");
+        Symbol::try_from_proto_with(value.symbol, &mut path)
+    }
+}
+
 impl TryFromProto<pb::GetEntityRequest> for EntityLocator {
     fn try_from_proto_with(
         value: pb::GetEntityRequest,
@@ -71,6 +144,17 @@ impl TryFromProto<pb::GetEntityRequest> for EntityLocator {
     }
 }
 
+impl TryFromProto<pb::BatchGetEntitiesRequest> for Vec<EntityLocator> {
+    fn try_from_proto_with(
+        value: pb::BatchGetEntitiesRequest,
+        mut parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        let mut path = garde::util::nested_path!(parent, "entity_locators");
+
+        Vec::try_from_proto_with(value.entity_locators, &mut path)
+    }
+}
+
 impl TryFromProto<pb::EntityLocator> for EntityLocator {
     fn try_from_proto_with(
         value: pb::EntityLocator,
@@ -93,16 +177,17 @@ impl TryFromProto<pb::entity_locator::Locator> for EntityLocator {
     ) -> ConversionResult<Self> {
         use pb::entity_locator::Locator;
 
-        match value {
+        Ok(match value {
             Locator::EntityId(entity_id) => {
                 let mut path = garde::util::nested_path!(parent, "entity_id");
-                EntityId::try_from_proto_with(entity_id, &mut path).map(EntityLocator::EntityId)
+                EntityLocator::EntityId(EntityId::try_from_proto_with(entity_id, &mut path)?)
             }
             Locator::Symbol(symbol) => {
                 let mut path = garde::util::nested_path!(parent, "symbol");
-                Symbol::try_from_proto_with(symbol, &mut path).map(EntityLocator::Symbol)
+                EntityLocator::Symbol(Symbol::try_from_proto_with(symbol, &mut path)?)
             }
-        }
+            Locator::CreateNew(_) => EntityLocator::CreateNew,
+        })
     }
 }
 
@@ -124,6 +209,19 @@ impl TryFromProto<String> for EntityId {
     }
 }
 
+/// Writes entity IDs in queries as the opaque strings sent to clients.
+pub struct OpaqueEntityIds;
+
+impl EntityIdFormat for OpaqueEntityIds {
+    fn parse_entity_id(&self, text: &str) -> Option<EntityId> {
+        EntityId::try_from_proto(text.to_owned()).ok()
+    }
+
+    fn format_entity_id(&self, entity_id: EntityId) -> String {
+        entity_id.into_proto()
+    }
+}
+
 impl TryFromProto<String> for Symbol {
     fn try_from_proto_with(
         value: String,
@@ -135,6 +233,52 @@ impl TryFromProto<String> for Symbol {
     }
 }
 
+impl IntoProto<pb::EntityLocator> for EntityLocator {
+    fn into_proto(self) -> pb::EntityLocator {
+        pb::EntityLocator {
+            locator: Some(match self {
+                EntityLocator::EntityId(entity_id) => {
+                    pb::entity_locator::Locator::EntityId(entity_id.into_proto())
+                }
+                EntityLocator::Symbol(symbol) => pb::entity_locator::Locator::Symbol(symbol.into()),
+                EntityLocator::CreateNew => {
+                    pb::entity_locator::Locator::CreateNew(pb::CreateNewLocator {})
+                }
+            }),
+        }
+    }
+}
+
+impl IntoProto<pb::CreateAttributeTypesResponse> for CreateAttributeTypesResult {
+    fn into_proto(self) -> pb::CreateAttributeTypesResponse {
+        pb::CreateAttributeTypesResponse {
+            entities: self
+                .entities
+                .into_iter()
+                .map(IntoProto::into_proto)
+                .collect(),
+            already_existed: self.already_existed.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl IntoProto<pb::BatchGetEntitiesResponse> for BatchGetEntitiesResult {
+    fn into_proto(self) -> pb::BatchGetEntitiesResponse {
+        pb::BatchGetEntitiesResponse {
+            entities: self
+                .entities
+                .into_iter()
+                .map(IntoProto::into_proto)
+                .collect(),
+            not_found: self
+                .not_found
+                .into_iter()
+                .map(IntoProto::into_proto)
+                .collect(),
+        }
+    }
+}
+
 impl IntoProto<pb::Entity> for Entity {
     fn into_proto(self) -> pb::Entity {
         pb::Entity {
@@ -145,6 +289,28 @@ impl IntoProto<pb::Entity> for Entity {
     }
 }
 
+impl IntoProto<pb::EntityDiff> for EntityDiff {
+    fn into_proto(self) -> pb::EntityDiff {
+        pb::EntityDiff {
+            added: self.added.into_proto(),
+            removed: self.removed.into_iter().map(Into::into).collect(),
+            modified: self
+                .modified
+                .into_iter()
+                .map(|(symbol, (before, after))| {
+                    (
+                        symbol.into(),
+                        pb::AttributeValueChange {
+                            before: Some(before.into_proto()),
+                            after: Some(after.into_proto()),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
 impl IntoProto<String> for EntityId {
     fn into_proto(self) -> String {
         let EntityId(database_id) = self;
@@ -187,10 +353,36 @@ impl IntoProto<pb::attribute_value::AttributeValue> for AttributeValue {
                 pb::attribute_value::AttributeValue::EntityIdValue(entity_id.into_proto())
             }
             AttributeValue::Bytes(bytes) => pb::attribute_value::AttributeValue::BytesValue(bytes),
+            AttributeValue::Timestamp(timestamp) => {
+                pb::attribute_value::AttributeValue::TimestampValue(timestamp.into_proto())
+            }
+            AttributeValue::Int64(value) => pb::attribute_value::AttributeValue::Int64Value(value),
+            AttributeValue::List(values) => {
+                pb::attribute_value::AttributeValue::ListValue(pb::AttributeValueList {
+                    values: values.into_iter().map(IntoProto::into_proto).collect(),
+                })
+            }
         }
     }
 }
 
+impl IntoProto<prost_types::Timestamp> for SystemTime {
+    fn into_proto(self) -> prost_types::Timestamp {
+        self.into()
+    }
+}
+
+impl TryFromProto<prost_types::Timestamp> for SystemTime {
+    fn try_from_proto_with(
+        value: prost_types::Timestamp,
+        parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        use FieldError::*;
+
+        SystemTime::try_from(value).map_err(|err| InvalidTimestamp(err.into()).at_path(parent()))
+    }
+}
+
 impl TryFromProto<pb::QueryEntityRowsRequest> for EntityRowQuery {
     fn try_from_proto_with(
         value: pb::QueryEntityRowsRequest,
@@ -198,19 +390,46 @@ impl TryFromProto<pb::QueryEntityRowsRequest> for EntityRowQuery {
     ) -> ConversionResult<Self> {
         use FieldError::*;
 
-        Ok(EntityRowQuery {
-            root: {
-                let mut path = garde::util::nested_path!(parent, "root");
-                let entity_query_node_proto =
-                    value.root.ok_or_else(|| FieldMissing.at_path(path()))?;
-                EntityQueryNode::try_from_proto_with(entity_query_node_proto, &mut path)?
-            },
-            attribute_types: {
-                let mut path = garde::util::nested_path!(parent, "attribute_types");
-
-                Vec::try_from_proto_with(value.attribute_types, &mut path)?
-            },
-        })
+        let mut errors = ConversionErrorBuilder::default();
+        let root = {
+            let mut path = garde::util::nested_path!(parent, "root");
+            errors.field(
+                value
+                    .root
+                    .ok_or_else(|| FieldMissing.at_path(path()))
+                    .and_then(|entity_query_node_proto| {
+                        EntityQueryNode::try_from_proto_with(entity_query_node_proto, &mut path)
+                    }),
+            )
+        };
+        let attribute_types = {
+            let mut path = garde::util::nested_path!(parent, "attribute_types");
+            errors.field(Vec::try_from_proto_with(value.attribute_types, &mut path))
+        };
+        let filter_rows_where_null = {
+            let mut path = garde::util::nested_path!(parent, "filter_rows_where_null");
+            errors.field(
+                value
+                    .filter_rows_where_null
+                    .into_iter()
+                    .map(usize::try_from)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| OutOfRange(err.to_string()).at_path(path())),
+            )
+        };
+
+        match (root, attribute_types, filter_rows_where_null) {
+            (Some(root), Some(attribute_types), Some(filter_rows_where_null)) => {
+                Ok(EntityRowQuery {
+                    root,
+                    attribute_types,
+                    include_deleted: value.include_deleted,
+                    filter_nulls: value.filter_nulls,
+                    filter_rows_where_null,
+                })
+            }
+            _ => Err(errors.build()),
+        }
     }
 }
 
@@ -256,6 +475,76 @@ impl TryFromProto<pb::entity_query_node::Query> for EntityQueryNode {
                     &mut path,
                 )?)
             }
+            Query::EntityIdIn(entity_id_in_node) => {
+                let mut path = garde::util::nested_path!(parent, "entity_id_in");
+                EntityQueryNode::EntityIdIn(EntityIdInNode::try_from_proto_with(
+                    entity_id_in_node,
+                    &mut path,
+                )?)
+            }
+            Query::TextContains(text_contains_node) => {
+                let mut path = garde::util::nested_path!(parent, "text_contains");
+                EntityQueryNode::TextContains(TextContainsNode::try_from_proto_with(
+                    text_contains_node,
+                    &mut path,
+                )?)
+            }
+            Query::TextStartsWith(text_starts_with_node) => {
+                let mut path = garde::util::nested_path!(parent, "text_starts_with");
+                EntityQueryNode::TextStartsWith(TextStartsWithNode::try_from_proto_with(
+                    text_starts_with_node,
+                    &mut path,
+                )?)
+            }
+            Query::TextMatchesRegex(text_matches_regex_node) => {
+                let mut path = garde::util::nested_path!(parent, "text_matches_regex");
+                EntityQueryNode::TextMatchesRegex(TextMatchesRegexNode::try_from_proto_with(
+                    text_matches_regex_node,
+                    &mut path,
+                )?)
+            }
+            Query::AttributeValueAfter(attribute_value_after_node) => {
+                let mut path = garde::util::nested_path!(parent, "attribute_value_after");
+                EntityQueryNode::AttributeValueAfter(AttributeValueAfterNode::try_from_proto_with(
+                    attribute_value_after_node,
+                    &mut path,
+                )?)
+            }
+            Query::GraphTraverse(graph_traverse_node) => {
+                let mut path = garde::util::nested_path!(parent, "graph_traverse");
+                EntityQueryNode::GraphTraverse(GraphTraverseNode::try_from_proto_with(
+                    *graph_traverse_node,
+                    &mut path,
+                )?)
+            }
+            Query::AttributeValueBefore(attribute_value_before_node) => {
+                let mut path = garde::util::nested_path!(parent, "attribute_value_before");
+                EntityQueryNode::AttributeValueBefore(
+                    AttributeValueBeforeNode::try_from_proto_with(
+                        attribute_value_before_node,
+                        &mut path,
+                    )?,
+                )
+            }
+            Query::ElementAt(element_at_node) => {
+                let mut path = garde::util::nested_path!(parent, "element_at");
+                EntityQueryNode::ElementAt(ElementAtNode::try_from_proto_with(
+                    element_at_node,
+                    &mut path,
+                )?)
+            }
+            Query::HasAttributeValue(has_attribute_value_node) => {
+                let mut path = garde::util::nested_path!(parent, "has_attribute_value");
+                EntityQueryNode::HasAttributeValue(HasAttributeValueNode::try_from_proto_with(
+                    has_attribute_value_node,
+                    &mut path,
+                )?)
+            }
+            Query::QueryString(query_string) => {
+                let mut path = garde::util::nested_path!(parent, "query_string");
+                EntityQueryNode::from_query_string(&query_string, &OpaqueEntityIds)
+                    .map_err(|err| FieldError::InvalidQueryString(err.into()).at_path(path()))?
+            }
         })
     }
 }
@@ -284,6 +573,32 @@ impl TryFromProto<pb::OrQueryNode> for OrQueryNode {
     }
 }
 
+impl TryFromProto<pb::GraphTraverseNode> for GraphTraverseNode {
+    fn try_from_proto_with(
+        value: pb::GraphTraverseNode,
+        mut parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        use FieldError::*;
+
+        Ok(GraphTraverseNode {
+            root: {
+                let mut path = garde::util::nested_path!(parent, "root");
+
+                let root_proto = value.root.ok_or_else(|| FieldMissing.at_path(path()))?;
+                Box::new(EntityQueryNode::try_from_proto_with(
+                    *root_proto,
+                    &mut path,
+                )?)
+            },
+            start_attribute_type: {
+                let mut path = garde::util::nested_path!(parent, "start_attribute_type");
+                Symbol::try_from_proto_with(value.start_attribute_type, &mut path)?
+            },
+            depth_limit: value.depth_limit,
+        })
+    }
+}
+
 impl TryFromProto<pb::HasAttributeTypesNode> for HasAttributeTypesNode {
     fn try_from_proto_with(
         value: pb::HasAttributeTypesNode,
@@ -296,6 +611,138 @@ impl TryFromProto<pb::HasAttributeTypesNode> for HasAttributeTypesNode {
     }
 }
 
+impl TryFromProto<pb::EntityIdInNode> for EntityIdInNode {
+    fn try_from_proto_with(
+        value: pb::EntityIdInNode,
+        mut parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        let mut path = garde::util::nested_path!(parent, "entity_ids");
+        Ok(EntityIdInNode {
+            entity_ids: Vec::try_from_proto_with(value.entity_ids, &mut path)?,
+        })
+    }
+}
+
+#[derive(TryFromProto)]
+#[try_from_proto(source = pb::TextContainsNode, target = TextContainsNode)]
+#[allow(dead_code)]
+struct TextContainsNodeDef {
+    #[proto_field("attribute_type")]
+    attribute_type: Symbol,
+    #[proto_field("substring")]
+    substring: String,
+}
+
+#[derive(TryFromProto)]
+#[try_from_proto(source = pb::TextStartsWithNode, target = TextStartsWithNode)]
+#[allow(dead_code)]
+struct TextStartsWithNodeDef {
+    #[proto_field("attribute_type")]
+    attribute_type: Symbol,
+    #[proto_field("prefix")]
+    prefix: String,
+}
+
+#[derive(TryFromProto)]
+#[try_from_proto(source = pb::AttributeValueAfterNode, target = AttributeValueAfterNode)]
+#[allow(dead_code)]
+struct AttributeValueAfterNodeDef {
+    #[proto_field("attribute_type")]
+    attribute_type: Symbol,
+    #[proto_field("timestamp", required)]
+    timestamp: SystemTime,
+}
+
+#[derive(TryFromProto)]
+#[try_from_proto(source = pb::AttributeValueBeforeNode, target = AttributeValueBeforeNode)]
+#[allow(dead_code)]
+struct AttributeValueBeforeNodeDef {
+    #[proto_field("attribute_type")]
+    attribute_type: Symbol,
+    #[proto_field("timestamp", required)]
+    timestamp: SystemTime,
+}
+
+#[derive(TryFromProto)]
+#[try_from_proto(source = pb::HasAttributeValueNode, target = HasAttributeValueNode)]
+#[allow(dead_code)]
+struct HasAttributeValueNodeDef {
+    #[proto_field("attribute_type")]
+    attribute_type: Symbol,
+    #[proto_field("value", required)]
+    value: AttributeValue,
+}
+
+#[derive(TryFromProto)]
+#[try_from_proto(source = pb::ElementAtNode, target = ElementAtNode)]
+#[allow(dead_code)]
+struct ElementAtNodeDef {
+    #[proto_field("attribute_type")]
+    attribute_type: Symbol,
+    #[proto_field("index")]
+    index: u32,
+    #[proto_field("value", required)]
+    value: AttributeValue,
+}
+
+impl TryFromProto<pb::TextMatchesRegexNode> for TextMatchesRegexNode {
+    fn try_from_proto_with(
+        value: pb::TextMatchesRegexNode,
+        mut parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        use FieldError::*;
+
+        let attribute_type = {
+            let mut path = garde::util::nested_path!(parent, "attribute_type");
+            Symbol::try_from_proto_with(value.attribute_type, &mut path)?
+        };
+        let mut path = garde::util::nested_path!(parent, "pattern");
+        TextMatchesRegexNode::new(attribute_type, &value.pattern)
+            .map_err(|err| InvalidRegex(err.into()).at_path(path()))
+    }
+}
+
+impl<A, B> TryFromProto<Option<A>> for Option<B>
+where
+    B: TryFromProto<A>,
+{
+    fn try_from_proto_with(
+        value: Option<A>,
+        parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        value
+            .map(|value| B::try_from_proto_with(value, parent))
+            .transpose()
+    }
+}
+
+impl TryFromProto<String> for String {
+    fn try_from_proto_with(
+        value: String,
+        _parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        Ok(value)
+    }
+}
+
+impl TryFromProto<bool> for bool {
+    fn try_from_proto_with(
+        value: bool,
+        _parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        Ok(value)
+    }
+}
+
+impl TryFromProto<u32> for u32 {
+    fn try_from_proto_with(
+        value: u32,
+        _parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        Ok(value)
+    }
+}
+
 impl<A, B> TryFromProto<Vec<A>> for Vec<B>
 where
     B: TryFromProto<A>,
@@ -304,21 +751,34 @@ where
         value: Vec<A>,
         mut parent: &mut dyn FnMut() -> garde::Path,
     ) -> ConversionResult<Self> {
-        value
+        let mut errors = ConversionErrorBuilder::default();
+        let elements: Vec<Option<B>> = value
             .into_iter()
             .enumerate()
             .map(|(idx, element)| {
                 let mut path = garde::util::nested_path!(parent, idx);
-                B::try_from_proto_with(element, &mut path)
+                errors.field(B::try_from_proto_with(element, &mut path))
             })
-            .collect()
+            .collect();
+
+        elements
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| errors.build())
     }
 }
 
-impl IntoProto<pb::EntityRow> for EntityRow {
+/// Entity rows are converted together with the attribute types that were used to produce them.
+impl IntoProto<pb::EntityRow> for (EntityRow, &[Symbol]) {
     fn into_proto(self) -> pb::EntityRow {
+        let (entity_row, attribute_types) = self;
         pb::EntityRow {
-            values: self
+            entity_id: entity_row.entity_id.into_proto(),
+            column_names: attribute_types
+                .iter()
+                .map(|attribute_type| attribute_type.as_str().into())
+                .collect(),
+            values: entity_row
                 .values
                 .into_iter()
                 .map(|value| pb::NullableAttributeValue {
@@ -348,6 +808,17 @@ impl TryFromProto<pb::CreateAttributeTypeRequest> for CreateAttributeTypeRequest
     }
 }
 
+impl TryFromProto<pb::CreateAttributeTypesRequest> for Vec<AttributeType> {
+    fn try_from_proto_with(
+        value: pb::CreateAttributeTypesRequest,
+        mut parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        let mut path = garde::util::nested_path!(parent, "attribute_types");
+
+        Vec::try_from_proto_with(value.attribute_types, &mut path)
+    }
+}
+
 impl TryFromProto<pb::AttributeType> for AttributeType {
     fn try_from_proto_with(
         value: pb::AttributeType,
@@ -364,12 +835,49 @@ impl TryFromProto<pb::AttributeType> for AttributeType {
                 let mut path = garde::util::nested_path!(parent, "value_type");
                 let value_type_proto = pb::ValueType::try_from(value.value_type)
                     .map_err(|err| InvalidValueType(err.into()).at_path(path()))?;
-                ValueType::try_from_proto_with(value_type_proto, &mut path)?
+                let value_type = ValueType::try_from_proto_with(value_type_proto, &mut path)?;
+                if value.list {
+                    ValueType::List(Box::new(value_type))
+                } else {
+                    value_type
+                }
             },
+            description: value.description,
         })
     }
 }
 
+impl IntoProto<pb::AttributeType> for AttributeType {
+    fn into_proto(self) -> pb::AttributeType {
+        let (value_type, list) = match self.value_type {
+            ValueType::List(element_type) => (*element_type, true),
+            value_type => (value_type, false),
+        };
+        let value_type: pb::ValueType = value_type.into_proto();
+
+        pb::AttributeType {
+            symbol: self.symbol.into(),
+            value_type: value_type.into(),
+            description: self.description,
+            list,
+        }
+    }
+}
+
+impl IntoProto<pb::ValueType> for ValueType {
+    fn into_proto(self) -> pb::ValueType {
+        match self {
+            ValueType::Text => pb::ValueType::Text,
+            ValueType::EntityReference => pb::ValueType::EntityReference,
+            ValueType::Bytes => pb::ValueType::Bytes,
+            ValueType::Timestamp => pb::ValueType::Timestamp,
+            ValueType::Int64 => pb::ValueType::Int64,
+            // Stores reject nested lists, which have no representation in the protocol
+            ValueType::List(_) => pb::ValueType::Invalid,
+        }
+    }
+}
+
 impl TryFromProto<pb::ValueType> for ValueType {
     fn try_from_proto_with(
         value: pb::ValueType,
@@ -384,65 +892,193 @@ impl TryFromProto<pb::ValueType> for ValueType {
             pb::ValueType::Text => Ok(ValueType::Text),
             pb::ValueType::EntityReference => Ok(ValueType::EntityReference),
             pb::ValueType::Bytes => Ok(ValueType::Bytes),
+            pb::ValueType::Timestamp => Ok(ValueType::Timestamp),
+            pb::ValueType::Int64 => Ok(ValueType::Int64),
         }
     }
 }
 
-impl TryFromProto<pb::UpdateEntityRequest> for UpdateEntityRequest {
+#[derive(TryFromProto)]
+#[try_from_proto(source = pb::UpdateEntityRequest, target = UpdateEntityRequest)]
+#[allow(dead_code)]
+struct UpdateEntityRequestDef {
+    #[proto_field("entity_locator", required)]
+    entity_locator: EntityLocator,
+    #[proto_field("attributes_to_update")]
+    attributes_to_update: Vec<AttributeToUpdate>,
+    #[proto_field("dry_run")]
+    dry_run: bool,
+}
+
+#[derive(TryFromProto)]
+#[try_from_proto(source = pb::MergeEntitiesRequest, target = MergeEntitiesRequest)]
+#[allow(dead_code)]
+struct MergeEntitiesRequestDef {
+    #[proto_field("source", required)]
+    source: EntityLocator,
+    #[proto_field("target", required)]
+    target: EntityLocator,
+    #[proto_field("attribute_types")]
+    attribute_types: Vec<Symbol>,
+}
+
+#[derive(TryFromProto)]
+#[try_from_proto(source = pb::AtomicQueryAndUpdateRequest, target = AtomicQueryAndUpdateRequest)]
+#[allow(dead_code)]
+struct AtomicQueryAndUpdateRequestDef {
+    #[proto_field("entity_locator", required)]
+    entity_locator: EntityLocator,
+    #[proto_field("attributes_to_update")]
+    attributes_to_update: Vec<AttributeUpdateExpression>,
+}
+
+impl TryFromProto<pb::AttributeUpdateExpression> for AttributeUpdateExpression {
     fn try_from_proto_with(
-        value: pb::UpdateEntityRequest,
+        value: pb::AttributeUpdateExpression,
         mut parent: &mut dyn FnMut() -> garde::Path,
     ) -> ConversionResult<Self> {
         use FieldError::*;
 
-        Ok(UpdateEntityRequest {
-            entity_locator: {
-                let mut path = garde::util::nested_path!(parent, "entity_locator");
+        let mut errors = ConversionErrorBuilder::default();
+        let symbol = {
+            let mut path = garde::util::nested_path!(parent, "attribute_type");
+            errors.field(Symbol::try_from_proto_with(value.attribute_type, &mut path))
+        };
+        // As with `AttributeToUpdate`, attributes are only removed when asked to
+        let expression = {
+            let mut path = garde::util::nested_path!(parent, "value");
+            errors.field(match (value.value, value.delete) {
+                (Some(expression), false) => {
+                    Expression::try_from_proto_with(expression, &mut path).map(Some)
+                }
+                (None, true) => Ok(None),
+                (None, false) => Err(FieldMissing.at_path(path())),
+                (Some(_), true) => Err(UnexpectedField.at_path(path())),
+            })
+        };
 
-                let entity_locator_proto = value
-                    .entity_locator
-                    .ok_or_else(|| FieldMissing.at_path(path()))?;
-                EntityLocator::try_from_proto_with(entity_locator_proto, &mut path)?
-            },
-            attributes_to_update: {
-                let mut path = garde::util::nested_path!(parent, "attributes_to_update");
-                let result: Result<Vec<_>, _> = value
-                    .attributes_to_update
-                    .into_iter()
-                    .enumerate()
-                    .map(|(idx, attribute_to_update)| {
-                        let mut attribute_to_update_path = garde::util::nested_path!(path, idx);
-                        AttributeToUpdate::try_from_proto_with(
-                            attribute_to_update,
-                            &mut attribute_to_update_path,
-                        )
-                    })
-                    .collect();
-                result?
-            },
+        match (symbol, expression) {
+            (Some(symbol), Some(value)) => Ok(AttributeUpdateExpression { symbol, value }),
+            _ => Err(errors.build()),
+        }
+    }
+}
+
+impl TryFromProto<pb::ExpressionTree> for Expression {
+    fn try_from_proto_with(
+        value: pb::ExpressionTree,
+        mut parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        use FieldError::*;
+
+        let mut path = garde::util::nested_path!(parent, "expression");
+
+        let expression_proto = value
+            .expression
+            .ok_or_else(|| FieldMissing.at_path(path()))?;
+        Expression::try_from_proto_with(expression_proto, &mut path)
+    }
+}
+
+impl TryFromProto<pb::expression_tree::Expression> for Expression {
+    fn try_from_proto_with(
+        value: pb::expression_tree::Expression,
+        mut parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        use pb::expression_tree::Expression as ExpressionProto;
+        use FieldError::*;
+
+        Ok(match value {
+            ExpressionProto::Literal(literal) => {
+                let mut path = garde::util::nested_path!(parent, "literal");
+                Expression::Literal(AttributeValue::try_from_proto_with(literal, &mut path)?)
+            }
+            ExpressionProto::Attribute(attribute_type) => {
+                let mut path = garde::util::nested_path!(parent, "attribute");
+                Expression::Attribute(Symbol::try_from_proto_with(attribute_type, &mut path)?)
+            }
+            ExpressionProto::Coalesce(expression_list) => {
+                let mut path = garde::util::nested_path!(parent, "coalesce");
+                let mut path = garde::util::nested_path!(path, "operands");
+                Expression::Coalesce(Vec::try_from_proto_with(
+                    expression_list.operands,
+                    &mut path,
+                )?)
+            }
+            ExpressionProto::Concat(expression_list) => {
+                let mut path = garde::util::nested_path!(parent, "concat");
+                let mut path = garde::util::nested_path!(path, "operands");
+                Expression::Concat(Vec::try_from_proto_with(
+                    expression_list.operands,
+                    &mut path,
+                )?)
+            }
+            ExpressionProto::Append(append_expression) => {
+                let mut path = garde::util::nested_path!(parent, "append");
+                let list = {
+                    let mut path = garde::util::nested_path!(path, "list");
+                    let list_proto = append_expression
+                        .list
+                        .ok_or_else(|| FieldMissing.at_path(path()))?;
+                    Expression::try_from_proto_with(*list_proto, &mut path)?
+                };
+                let element = {
+                    let mut path = garde::util::nested_path!(path, "element");
+                    let element_proto = append_expression
+                        .element
+                        .ok_or_else(|| FieldMissing.at_path(path()))?;
+                    Expression::try_from_proto_with(*element_proto, &mut path)?
+                };
+                Expression::Append {
+                    list: Box::new(list),
+                    element: Box::new(element),
+                }
+            }
         })
     }
 }
 
+#[derive(TryFromProto)]
+#[try_from_proto(source = pb::WatchEntityRequest, target = WatchEntityRequest)]
+#[allow(dead_code)]
+struct WatchEntityRequestDef {
+    #[proto_field("entity_locator", required)]
+    entity_locator: EntityLocator,
+    #[proto_field("attribute_types")]
+    attribute_types: Vec<Symbol>,
+    #[proto_field("send_initial_events")]
+    send_initial_events: bool,
+}
+
 impl TryFromProto<pb::AttributeToUpdate> for AttributeToUpdate {
     fn try_from_proto_with(
         value: pb::AttributeToUpdate,
         mut parent: &mut dyn FnMut() -> garde::Path,
     ) -> ConversionResult<Self> {
-        Ok(AttributeToUpdate {
-            symbol: {
-                let mut path = garde::util::nested_path!(parent, "attribute_type");
-                Symbol::try_from_proto_with(value.attribute_type, &mut path)?
-            },
-            value: {
-                let mut path = garde::util::nested_path!(parent, "attribute_value");
+        use FieldError::*;
 
-                value
-                    .attribute_value
-                    .map(|proto| AttributeValue::try_from_proto_with(proto, &mut path))
-                    .transpose()?
-            },
-        })
+        let mut errors = ConversionErrorBuilder::default();
+        let symbol = {
+            let mut path = garde::util::nested_path!(parent, "attribute_type");
+            errors.field(Symbol::try_from_proto_with(value.attribute_type, &mut path))
+        };
+        // Attributes are only removed when asked to, so that a forgotten value isn't a deletion
+        let attribute_value = {
+            let mut path = garde::util::nested_path!(parent, "attribute_value");
+            errors.field(match (value.attribute_value, value.delete) {
+                (Some(attribute_value), false) => {
+                    AttributeValue::try_from_proto_with(attribute_value, &mut path).map(Some)
+                }
+                (None, true) => Ok(None),
+                (None, false) => Err(FieldMissing.at_path(path())),
+                (Some(_), true) => Err(UnexpectedField.at_path(path())),
+            })
+        };
+
+        match (symbol, attribute_value) {
+            (Some(symbol), Some(value)) => Ok(AttributeToUpdate { symbol, value }),
+            _ => Err(errors.build()),
+        }
     }
 }
 
@@ -500,6 +1136,23 @@ impl TryFromProto<pb::attribute_value::AttributeValue> for AttributeValue {
             attribute_value::AttributeValue::BytesValue(bytes_value) => {
                 AttributeValue::Bytes(bytes_value)
             }
+            attribute_value::AttributeValue::TimestampValue(timestamp_value) => {
+                let mut path = garde::util::nested_path!(parent, "timestamp_value");
+
+                AttributeValue::Timestamp(SystemTime::try_from_proto_with(
+                    timestamp_value,
+                    &mut path,
+                )?)
+            }
+            attribute_value::AttributeValue::Int64Value(int64_value) => {
+                AttributeValue::Int64(int64_value)
+            }
+            attribute_value::AttributeValue::ListValue(list_value) => {
+                let mut path = garde::util::nested_path!(parent, "list_value");
+                let mut path = garde::util::nested_path!(path, "values");
+
+                AttributeValue::List(Vec::try_from_proto_with(list_value.values, &mut path)?)
+            }
         })
     }
 }
@@ -511,16 +1164,102 @@ impl TryFromProto<pb::WatchEntitiesRequest> for WatchEntitiesRequest {
     ) -> ConversionResult<Self> {
         use FieldError::*;
 
-        let mut path = garde::util::nested_path!(parent, "query");
+        Ok(WatchEntitiesRequest {
+            query: {
+                let mut path = garde::util::nested_path!(parent, "query");
 
+                let query_proto = value.query.ok_or_else(|| FieldMissing.at_path(path()))?;
+                EntityQueryNode::try_from_proto_with(query_proto, &mut path)?
+            },
+            send_initial_events: value.send_initial_events,
+            event_types: if value.event_types.is_empty() {
+                WatchEventType::ALL.to_vec()
+            } else {
+                let mut path = garde::util::nested_path!(parent, "event_types");
+
+                value
+                    .event_types
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, event_type)| {
+                        let mut path = garde::util::nested_path!(path, idx);
+                        let event_type_proto = pb::WatchEventType::try_from(event_type)
+                            .map_err(|err| InvalidWatchEventType(err.into()).at_path(path()))?;
+                        WatchEventType::try_from_proto_with(event_type_proto, &mut path)
+                    })
+                    .collect::<ConversionResult<_>>()?
+            },
+            attribute_types: {
+                let mut path = garde::util::nested_path!(parent, "attribute_types");
+
+                Vec::try_from_proto_with(value.attribute_types, &mut path)?
+            },
+            batch_window: (value.batch_window_ms != 0)
+                .then(|| std::time::Duration::from_millis(value.batch_window_ms.into())),
+        })
+    }
+}
+
+impl TryFromProto<pb::WatchEventType> for WatchEventType {
+    fn try_from_proto_with(
+        value: pb::WatchEventType,
+        parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        use FieldError::*;
+
+        match value {
+            pb::WatchEventType::Invalid => Err(InvalidWatchEventType(format_err!(
+                "event_type = 0 is not valid"
+            ))
+            .at_path(parent())),
+            pb::WatchEventType::Added => Ok(WatchEventType::Added),
+            pb::WatchEventType::Modified => Ok(WatchEventType::Modified),
+            pb::WatchEventType::Removed => Ok(WatchEventType::Removed),
+            pb::WatchEventType::Bookmark => Ok(WatchEventType::Bookmark),
+        }
+    }
+}
+
+impl TryFromProto<pb::BenchmarkQueryRequest> for BenchmarkQueryRequest {
+    fn try_from_proto_with(
+        value: pb::BenchmarkQueryRequest,
+        mut parent: &mut dyn FnMut() -> garde::Path,
+    ) -> ConversionResult<Self> {
+        use FieldError::*;
+
+        let iterations = {
+            let mut path = garde::util::nested_path!(parent, "iterations");
+            if !(1..=MAX_BENCHMARK_ITERATIONS).contains(&value.iterations) {
+                return Err(OutOfRange(format!(
+                    "expected between 1 and {MAX_BENCHMARK_ITERATIONS} iterations"
+                ))
+                .at_path(path()));
+            }
+            value.iterations
+        };
+
+        let mut path = garde::util::nested_path!(parent, "query");
         let query_proto = value.query.ok_or_else(|| FieldMissing.at_path(path()))?;
-        Ok(WatchEntitiesRequest {
+        Ok(BenchmarkQueryRequest {
+            iterations,
             query: EntityQueryNode::try_from_proto_with(query_proto, &mut path)?,
-            send_initial_events: value.send_initial_events,
         })
     }
 }
 
+impl IntoProto<pb::BenchmarkQueryResponse> for BenchmarkStatistics {
+    fn into_proto(self) -> pb::BenchmarkQueryResponse {
+        let nanos = |duration: std::time::Duration| -> u64 {
+            duration.as_nanos().try_into().unwrap_or(u64::MAX)
+        };
+        pb::BenchmarkQueryResponse {
+            total_duration_ns: nanos(self.total_duration),
+            mean_duration_ns: nanos(self.mean_duration),
+            p99_duration_ns: nanos(self.p99_duration),
+        }
+    }
+}
+
 impl IntoProto<pb::WatchEntitiesEvent> for WatchEntitiesEvent {
     fn into_proto(self) -> pb::WatchEntitiesEvent {
         pb::WatchEntitiesEvent {
@@ -530,8 +1269,9 @@ impl IntoProto<pb::WatchEntitiesEvent> for WatchEntitiesEvent {
                         entity: Some(after.as_ref().clone().into_proto()),
                     }))
                 }
-                (Some(_), Some(after)) => Some(pb::watch_entities_event::Event::Modified(
+                (Some(before), Some(after)) => Some(pb::watch_entities_event::Event::Modified(
                     pb::ModifiedEvent {
+                        diff: Some(Entity::diff(&before, &after).into_proto()),
                         entity: Some(after.as_ref().clone().into_proto()),
                     },
                 )),
@@ -573,23 +1313,37 @@ impl TryFromProto<pb::WatchEntityRowsRequest> for WatchEntityRowsRequest {
     }
 }
 
-impl IntoProto<pb::WatchEntityRowsEvent> for WatchEntityRowsEvent {
+impl IntoProto<pb::WatchEntityRowsEvent> for (WatchEntityRowsEvent, &[Symbol]) {
     fn into_proto(self) -> pb::WatchEntityRowsEvent {
+        let (watch_entity_rows_event, attribute_types) = self;
+        let entity_id: String = watch_entity_rows_event
+            .after
+            .as_ref()
+            .or(watch_entity_rows_event.before.as_ref())
+            .map(|entity_row| entity_row.entity_id.into_proto())
+            .unwrap_or_default();
         pb::WatchEntityRowsEvent {
-            event: match (self.before, self.after) {
+            entity_id: entity_id.clone(),
+            event: match (
+                watch_entity_rows_event.before,
+                watch_entity_rows_event.after,
+            ) {
                 (None, Some(after)) => Some(pb::watch_entity_rows_event::Event::Added(
                     pb::AddedEntityRowEvent {
-                        entity_row: Some(after.into_proto()),
+                        entity_row: Some((after, attribute_types).into_proto()),
+                        entity_id,
                     },
                 )),
                 (Some(_), Some(after)) => Some(pb::watch_entity_rows_event::Event::Modified(
                     pb::ModifiedEntityRowEvent {
-                        entity_row: Some(after.into_proto()),
+                        entity_row: Some((after, attribute_types).into_proto()),
+                        entity_id,
                     },
                 )),
                 (Some(before), None) => Some(pb::watch_entity_rows_event::Event::Removed(
                     pb::RemovedEntityRowEvent {
-                        entity_row: Some(before.into_proto()),
+                        entity_row: Some((before, attribute_types).into_proto()),
+                        entity_id,
                     },
                 )),
                 (before, after) => {
@@ -600,3 +1354,304 @@ impl IntoProto<pb::WatchEntityRowsEvent> for WatchEntityRowsEvent {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_update_entity_request() {
+        let entity_id = EntityId(7);
+        let update_entity_request = UpdateEntityRequest::try_from_proto(pb::UpdateEntityRequest {
+            entity_locator: Some(pb::EntityLocator {
+                locator: Some(pb::entity_locator::Locator::EntityId(
+                    entity_id.into_proto(),
+                )),
+            }),
+            attributes_to_update: vec![
+                pb::AttributeToUpdate {
+                    attribute_type: "@symbolName".to_string(),
+                    attribute_value: Some(AttributeValue::String("foo".into()).into_proto()),
+                    delete: false,
+                },
+                pb::AttributeToUpdate {
+                    attribute_type: "bar".to_string(),
+                    attribute_value: None,
+                    delete: true,
+                },
+            ],
+            dry_run: true,
+        })
+        .unwrap();
+
+        assert_eq!(
+            update_entity_request,
+            UpdateEntityRequest {
+                entity_locator: EntityLocator::EntityId(entity_id),
+                attributes_to_update: vec![
+                    AttributeToUpdate {
+                        symbol: Symbol::try_from("@symbolName").unwrap(),
+                        value: Some(AttributeValue::String("foo".into())),
+                    },
+                    AttributeToUpdate {
+                        symbol: Symbol::try_from("bar").unwrap(),
+                        value: None,
+                    },
+                ],
+                dry_run: true,
+            }
+        );
+    }
+
+    #[test]
+    fn reports_path_of_invalid_field() {
+        let missing_locator = UpdateEntityRequest::try_from_proto(pb::UpdateEntityRequest {
+            entity_locator: None,
+            attributes_to_update: vec![],
+            dry_run: false,
+        })
+        .unwrap_err();
+        let ConversionError::InField(path, field_error) = missing_locator else {
+            panic!("expected a single field error");
+        };
+        assert_eq!(path.to_string(), "entity_locator");
+        assert!(matches!(field_error, FieldError::FieldMissing));
+
+        let invalid_symbol = UpdateEntityRequest::try_from_proto(pb::UpdateEntityRequest {
+            entity_locator: Some(pb::EntityLocator {
+                locator: Some(pb::entity_locator::Locator::Symbol("foo".to_string())),
+            }),
+            attributes_to_update: vec![pb::AttributeToUpdate {
+                attribute_type: r"a\b".to_string(),
+                attribute_value: None,
+                delete: true,
+            }],
+            dry_run: false,
+        })
+        .unwrap_err();
+        let ConversionError::InField(path, field_error) = invalid_symbol else {
+            panic!("expected a single field error");
+        };
+        assert_eq!(path.to_string(), "attributes_to_update[0].attribute_type");
+        assert!(matches!(field_error, FieldError::InvalidSymbol(_)));
+    }
+
+    #[test]
+    fn requires_attribute_values_unless_deleting() {
+        let attribute_to_update = |attribute_value: Option<pb::AttributeValue>, delete| {
+            AttributeToUpdate::try_from_proto(pb::AttributeToUpdate {
+                attribute_type: "foo".to_string(),
+                attribute_value,
+                delete,
+            })
+        };
+        let value = || Some(AttributeValue::String("bar".into()).into_proto());
+
+        assert_eq!(
+            attribute_to_update(None, true).unwrap(),
+            AttributeToUpdate {
+                symbol: Symbol::try_from("foo").unwrap(),
+                value: None,
+            }
+        );
+        for (attribute_value, delete) in [(None, false), (value(), true)] {
+            let ConversionError::InField(path, _) =
+                attribute_to_update(attribute_value, delete).unwrap_err()
+            else {
+                panic!("expected a single field error");
+            };
+            assert_eq!(path.to_string(), "attribute_value");
+        }
+    }
+
+    #[test]
+    fn requires_update_expressions_unless_deleting() {
+        let update_expression = |value: Option<pb::ExpressionTree>, delete| {
+            AttributeUpdateExpression::try_from_proto(pb::AttributeUpdateExpression {
+                attribute_type: "foo".to_string(),
+                value,
+                delete,
+            })
+        };
+        let value = || {
+            Some(pb::ExpressionTree {
+                expression: Some(pb::expression_tree::Expression::Attribute(
+                    "bar".to_string(),
+                )),
+            })
+        };
+
+        assert_eq!(
+            update_expression(None, true).unwrap(),
+            AttributeUpdateExpression {
+                symbol: Symbol::try_from("foo").unwrap(),
+                value: None,
+            }
+        );
+        for (value, delete) in [(None, false), (value(), true)] {
+            let ConversionError::InField(path, _) = update_expression(value, delete).unwrap_err()
+            else {
+                panic!("expected a single field error");
+            };
+            assert_eq!(path.to_string(), "value");
+        }
+    }
+
+    #[test]
+    fn converts_list_attribute_types_and_values() {
+        let attribute_type = AttributeType::try_from_proto(pb::AttributeType {
+            symbol: "tags".to_string(),
+            value_type: pb::ValueType::Text.into(),
+            description: None,
+            list: true,
+        })
+        .unwrap();
+        assert_eq!(
+            attribute_type.value_type,
+            ValueType::List(Box::new(ValueType::Text))
+        );
+
+        let list_value = AttributeValue::List(vec![
+            AttributeValue::String("red".to_string()),
+            AttributeValue::EntityId(EntityId(7)),
+            AttributeValue::Int64(-3),
+        ]);
+        let list_value_proto: pb::AttributeValue = list_value.clone().into_proto();
+        assert_eq!(
+            AttributeValue::try_from_proto(list_value_proto).unwrap(),
+            list_value
+        );
+
+        let invalid_element = AttributeValue::try_from_proto(pb::AttributeValue {
+            attribute_value: Some(pb::attribute_value::AttributeValue::ListValue(
+                pb::AttributeValueList {
+                    values: vec![pb::AttributeValue {
+                        attribute_value: None,
+                    }],
+                },
+            )),
+        })
+        .unwrap_err();
+        let ConversionError::InField(path, field_error) = invalid_element else {
+            panic!("expected a single field error");
+        };
+        assert_eq!(
+            path.to_string(),
+            "attribute_value.list_value.values[0].attribute_value"
+        );
+        assert!(matches!(field_error, FieldError::FieldMissing));
+    }
+
+    #[test]
+    fn reports_every_invalid_field() {
+        let invalid_fields = UpdateEntityRequest::try_from_proto(pb::UpdateEntityRequest {
+            entity_locator: None,
+            attributes_to_update: vec![
+                pb::AttributeToUpdate {
+                    attribute_type: r"a\b".to_string(),
+                    attribute_value: None,
+                    delete: true,
+                },
+                pb::AttributeToUpdate {
+                    attribute_type: "@symbolName".to_string(),
+                    attribute_value: None,
+                    delete: true,
+                },
+                pb::AttributeToUpdate {
+                    attribute_type: r"c\d".to_string(),
+                    attribute_value: Some(pb::AttributeValue {
+                        attribute_value: None,
+                    }),
+                    delete: false,
+                },
+            ],
+            dry_run: false,
+        })
+        .unwrap_err();
+
+        let ConversionError::Multiple(field_errors) = invalid_fields else {
+            panic!("expected multiple field errors");
+        };
+        assert_eq!(
+            field_errors
+                .iter()
+                .map(|(path, _)| path.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "entity_locator",
+                "attributes_to_update[0].attribute_type",
+                "attributes_to_update[2].attribute_type",
+                "attributes_to_update[2].attribute_value.attribute_value",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_overly_complex_regex() {
+        let overly_complex_regex = EntityQueryNode::try_from_proto(pb::EntityQueryNode {
+            query: Some(pb::entity_query_node::Query::TextMatchesRegex(
+                pb::TextMatchesRegexNode {
+                    attribute_type: "@symbolName".to_string(),
+                    pattern: r"\w{1000}".repeat(10),
+                },
+            )),
+        })
+        .unwrap_err();
+        let ConversionError::InField(path, field_error) = overly_complex_regex else {
+            panic!("expected a single field error");
+        };
+        assert_eq!(path.to_string(), "query.text_matches_regex.pattern");
+        assert!(matches!(field_error, FieldError::InvalidRegex(_)));
+    }
+
+    #[test]
+    fn query_strings_write_entity_ids_as_sent_to_clients() {
+        let query_string = |query_string: String| {
+            EntityQueryNode::try_from_proto(pb::EntityQueryNode {
+                query: Some(pb::entity_query_node::Query::QueryString(query_string)),
+            })
+        };
+        let node = EntityQueryNode::EntityIdIn(EntityIdInNode {
+            entity_ids: vec![EntityId(7)],
+        });
+
+        let written = node.to_query_string(&OpaqueEntityIds);
+        assert_eq!(
+            written,
+            format!("entity_id_in(\"{}\")", EntityId(7).into_proto())
+        );
+        assert_eq!(query_string(written).unwrap(), node);
+
+        let ConversionError::InField(path, field_error) =
+            query_string("entity_id_in(\"7\")".to_string()).unwrap_err()
+        else {
+            panic!("expected a single field error");
+        };
+        assert_eq!(path.to_string(), "query.query_string");
+        assert!(matches!(field_error, FieldError::InvalidQueryString(_)));
+    }
+
+    #[test]
+    fn watch_entity_rows_events_carry_entity_id() {
+        let entity_id = EntityId(7);
+        let entity_row = EntityRow {
+            entity_id,
+            values: vec![],
+        };
+        let removed_event = WatchEntityRowsEvent {
+            entity_version: EntityVersion(3),
+            before: Some(entity_row),
+            after: None,
+        };
+
+        let removed_event_proto: pb::WatchEntityRowsEvent =
+            (removed_event, [].as_slice()).into_proto();
+
+        assert_eq!(removed_event_proto.entity_id, entity_id.into_proto());
+        let Some(pb::watch_entity_rows_event::Event::Removed(removed)) = removed_event_proto.event
+        else {
+            panic!("expected a removed event");
+        };
+        assert_eq!(removed.entity_id, entity_id.into_proto());
+    }
+}
@@ -0,0 +1,165 @@
+use attribute_store::store::{AttributeToUpdate, AttributeValue, Entity, EntityLocator, Symbol};
+use thiserror::Error;
+
+#[derive(Error, Debug, Eq, PartialEq, Clone)]
+pub enum ExpressionError {
+    #[error("concat operand is not text: `{0:?}`")]
+    ConcatOperandNotText(AttributeValue),
+    #[error("append list is not a list: `{0:?}`")]
+    AppendToNonList(AttributeValue),
+}
+
+/// An expression computing an attribute value from the current attributes of an entity. Evaluates
+/// to `None` (null) where the entity doesn't have an attribute it refers to.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Expression {
+    Literal(AttributeValue),
+    Attribute(Symbol),
+    Coalesce(Vec<Expression>),
+    Concat(Vec<Expression>),
+    Append {
+        list: Box<Expression>,
+        element: Box<Expression>,
+    },
+}
+
+impl Expression {
+    pub fn evaluate(&self, entity: &Entity) -> Result<Option<AttributeValue>, ExpressionError> {
+        match self {
+            Expression::Literal(value) => Ok(Some(value.clone())),
+            Expression::Attribute(attribute_type) => {
+                Ok(entity.attributes.get(attribute_type).cloned())
+            }
+            Expression::Coalesce(operands) => {
+                for operand in operands {
+                    if let Some(value) = operand.evaluate(entity)? {
+                        return Ok(Some(value));
+                    }
+                }
+                Ok(None)
+            }
+            Expression::Concat(operands) => {
+                let mut result = String::new();
+                for operand in operands {
+                    match operand.evaluate(entity)? {
+                        Some(AttributeValue::String(value)) => result.push_str(&value),
+                        Some(value) => return Err(ExpressionError::ConcatOperandNotText(value)),
+                        None => return Ok(None),
+                    }
+                }
+                Ok(Some(AttributeValue::String(result)))
+            }
+            Expression::Append { list, element } => {
+                let mut values = match list.evaluate(entity)? {
+                    Some(AttributeValue::List(values)) => values,
+                    Some(value) => return Err(ExpressionError::AppendToNonList(value)),
+                    None => vec![],
+                };
+                values.extend(element.evaluate(entity)?);
+                Ok(Some(AttributeValue::List(values)))
+            }
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct AttributeUpdateExpression {
+    pub symbol: Symbol,
+    /// Removes the attribute if `None`.
+    pub value: Option<Expression>,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct AtomicQueryAndUpdateRequest {
+    pub entity_locator: EntityLocator,
+    pub attributes_to_update: Vec<AttributeUpdateExpression>,
+}
+
+/// Evaluates each of `attributes_to_update` against `entity`, failing on the first error.
+pub fn evaluate_attributes_to_update(
+    attributes_to_update: &[AttributeUpdateExpression],
+    entity: &Entity,
+) -> Result<Vec<AttributeToUpdate>, ExpressionError> {
+    attributes_to_update
+        .iter()
+        .map(|attribute_to_update| {
+            let value = match &attribute_to_update.value {
+                Some(expression) => expression.evaluate(entity)?,
+                None => None,
+            };
+            Ok(AttributeToUpdate {
+                symbol: attribute_to_update.symbol.clone(),
+                value,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use attribute_store::store::{EntityId, EntityVersion};
+    use std::collections::HashMap;
+
+    fn symbol(name: &'static str) -> Symbol {
+        Symbol::try_from(name).unwrap()
+    }
+
+    fn string(value: &str) -> AttributeValue {
+        AttributeValue::String(value.into())
+    }
+
+    #[test]
+    fn evaluates_expressions_against_entity() {
+        let entity = Entity {
+            entity_id: EntityId(1),
+            entity_version: EntityVersion(1),
+            attributes: HashMap::from([
+                (symbol("name"), string("drone")),
+                (symbol("tags"), AttributeValue::List(vec![string("a")])),
+            ]),
+        };
+
+        let concat = Expression::Concat(vec![
+            Expression::Attribute(symbol("name")),
+            Expression::Literal(string("-1")),
+        ]);
+        assert_eq!(concat.evaluate(&entity), Ok(Some(string("drone-1"))));
+
+        let coalesce = Expression::Coalesce(vec![
+            Expression::Attribute(symbol("missing")),
+            Expression::Literal(string("default")),
+        ]);
+        assert_eq!(coalesce.evaluate(&entity), Ok(Some(string("default"))));
+
+        let missing_concat = Expression::Concat(vec![Expression::Attribute(symbol("missing"))]);
+        assert_eq!(missing_concat.evaluate(&entity), Ok(None));
+
+        let append = Expression::Append {
+            list: Box::new(Expression::Attribute(symbol("tags"))),
+            element: Box::new(Expression::Literal(string("b"))),
+        };
+        assert_eq!(
+            append.evaluate(&entity),
+            Ok(Some(AttributeValue::List(vec![string("a"), string("b")])))
+        );
+
+        let append_to_missing = Expression::Append {
+            list: Box::new(Expression::Attribute(symbol("missing"))),
+            element: Box::new(Expression::Literal(string("b"))),
+        };
+        assert_eq!(
+            append_to_missing.evaluate(&entity),
+            Ok(Some(AttributeValue::List(vec![string("b")])))
+        );
+
+        let append_to_text = Expression::Append {
+            list: Box::new(Expression::Attribute(symbol("name"))),
+            element: Box::new(Expression::Literal(string("b"))),
+        };
+        assert_eq!(
+            append_to_text.evaluate(&entity),
+            Err(ExpressionError::AppendToNonList(string("drone")))
+        );
+    }
+}
@@ -1,29 +1,92 @@
-use crate::convert::{ConversionError, IntoProto, TryFromProto};
+use crate::admin::{StreamKind, StreamRegistration, StreamRegistry};
+use crate::benchmark::{BenchmarkQueryRequest, BenchmarkStatistics};
+use crate::convert::{ConversionError, IntoProto, OpaqueEntityIds, TryFromProto};
+use crate::expression::{
+    evaluate_attributes_to_update, AtomicQueryAndUpdateRequest, ExpressionError,
+};
 use crate::pb;
 use attribute_store::store::{
-    AttributeStoreError, AttributeStoreErrorKind, CreateAttributeTypeRequest, Entity,
-    EntityLocator, EntityQuery, EntityQueryNode, EntityRowQuery, EntityVersion, Symbol,
-    UpdateEntityRequest, WatchEntitiesEvent, WatchEntitiesRequest, WatchEntityRowsEvent,
-    WatchEntityRowsRequest,
+    AttributeStoreError, AttributeStoreErrorKind, AttributeType, CreateAttributeTypeRequest,
+    Entity, EntityId, EntityIdInNode, EntityLocator, EntityQuery, EntityQueryNode, EntityRowQuery,
+    EntityVersion, MatchAllQueryNode, MergeEntitiesRequest, Symbol, UpdateEntityRequest,
+    WatchEntitiesEvent, WatchEntitiesRequest, WatchEntityRequest, WatchEntityRowsEvent,
+    WatchEntityRowsRequest, WatchEventType,
 };
+use futures::future::{self, BoxFuture};
+use futures::FutureExt;
+use parking_lot::Mutex;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::iter;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tokio_stream::wrappers::BroadcastStream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
+use tonic::body::BoxBody;
+use tonic::codegen::http;
 use tonic::codegen::tokio_stream::Stream;
+use tonic::transport::server::TcpConnectInfo;
+#[cfg(unix)]
+use tonic::transport::server::UdsConnectInfo;
 use tonic::{Code, Request, Response, Status};
 use tonic_types::{ErrorDetails, FieldViolation, StatusExt};
+use tower::{Layer, Service};
 use tracing::Level;
 
+/// Number of rows fetched from the store at a time by `stream_entity_rows`
+const STREAM_ENTITY_ROWS_PAGE_SIZE: usize = 256;
+
 pub struct AttributeServer<T> {
-    store: T,
+    store: Arc<T>,
+    benchmarks_enabled: bool,
+    stream_registry: Arc<Mutex<StreamRegistry>>,
+    /// `WatchEntities` streams with more events than this waiting to be sent are closed. Streams
+    /// that miss events are closed regardless.
+    watch_max_lag_events: Option<usize>,
 }
 
 impl<T: attribute_store::store::ThreadSafeAttributeStore> AttributeServer<T> {
     pub fn new(store: T) -> Self {
-        AttributeServer { store }
+        AttributeServer {
+            store: Arc::new(store),
+            benchmarks_enabled: false,
+            stream_registry: Arc::default(),
+            watch_max_lag_events: None,
+        }
+    }
+
+    pub fn store(&self) -> Arc<T> {
+        self.store.clone()
+    }
+
+    /// The `WatchEntities` and `WatchEntityRows` streams currently open
+    pub fn stream_registry(&self) -> Arc<Mutex<StreamRegistry>> {
+        self.stream_registry.clone()
+    }
+
+    /// Allow clients to run `BenchmarkQuery` requests against the store
+    pub fn with_benchmarks_enabled(self, benchmarks_enabled: bool) -> Self {
+        AttributeServer {
+            benchmarks_enabled,
+            ..self
+        }
+    }
+
+    pub fn with_watch_max_lag_events(self, watch_max_lag_events: usize) -> Self {
+        AttributeServer {
+            watch_max_lag_events: Some(watch_max_lag_events),
+            ..self
+        }
     }
 }
 
@@ -33,6 +96,8 @@ pub enum AttributeServerError {
     AttributeStoreError(#[from] AttributeStoreError),
     #[error("conversion error")]
     ConversionError(#[from] ConversionError),
+    #[error("invalid expression")]
+    InvalidExpression(#[from] ExpressionError),
 }
 
 impl From<AttributeServerError> for Status {
@@ -68,28 +133,140 @@ impl From<AttributeServerError> for Status {
                             ),
                         )
                     }
+                    AttributeStoreErrorKind::StoreFull { limit } => Status::resource_exhausted(
+                        format!("store is full (limit: {limit} entities)"),
+                    ),
                     err => Status::invalid_argument(format!("{:#}", anyhow::Error::from(err))),
                 }
             }
-            AttributeServerError::ConversionError(conversion_error) => {
-                let ConversionError::InField(path, field_error) = conversion_error;
-                let field = path.to_string();
-                let field_error_message = format!("{:#}", anyhow::Error::from(field_error));
-                Status::with_error_details(
-                    Code::InvalidArgument,
-                    "conversion error",
-                    ErrorDetails::with_bad_request_violation(field, field_error_message),
-                )
+            AttributeServerError::ConversionError(conversion_error) => Status::with_error_details(
+                Code::InvalidArgument,
+                "conversion error",
+                ErrorDetails::with_bad_request(
+                    conversion_error
+                        .into_field_errors()
+                        .into_iter()
+                        .map(|(path, field_error)| {
+                            FieldViolation::new(
+                                path.to_string(),
+                                format!("{:#}", anyhow::Error::from(field_error)),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+            ),
+            AttributeServerError::InvalidExpression(expression_error) => {
+                Status::invalid_argument(format!("{:#}", anyhow::Error::from(expression_error)))
             }
         }
     }
 }
 
+/// Responds with an `INTERNAL` status when a handler panics, rather than closing the connection.
+///
+/// Only panics while producing the response are caught; a panic while streaming response messages
+/// still ends the stream. The panic hook installed by `install_panic_hook` logs the backtrace.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CatchPanicLayer;
+
+impl<S> Layer<S> for CatchPanicLayer {
+    type Service = CatchPanic<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CatchPanic { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CatchPanic<S> {
+    inner: S,
+}
+
+impl<S, ReqBody> Service<http::Request<ReqBody>> for CatchPanic<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<BoxBody>>,
+    S::Error: Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let path = request.uri().path().to_string();
+        // Calling the inner service can itself panic, before any future is polled
+        let future = match panic::catch_unwind(AssertUnwindSafe(|| self.inner.call(request))) {
+            Ok(future) => future,
+            Err(panic_payload) => {
+                return Box::pin(future::ready(Ok(panic_response(&path, panic_payload))));
+            }
+        };
+
+        Box::pin(async move {
+            match AssertUnwindSafe(future).catch_unwind().await {
+                Ok(result) => result,
+                Err(panic_payload) => Ok(panic_response(&path, panic_payload)),
+            }
+        })
+    }
+}
+
+fn panic_response(path: &str, panic_payload: Box<dyn Any + Send>) -> http::Response<BoxBody> {
+    let message = panic_payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic_payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload");
+    tracing::error!(path, message, "Handler panicked");
+
+    Status::internal("unexpected server error").into_http()
+}
+
+/// Logs panics, with a backtrace, at `ERROR` level instead of printing them to stderr.
+pub fn install_panic_hook() {
+    panic::set_hook(Box::new(|panic_info| {
+        tracing::error!(
+            backtrace = %Backtrace::force_capture(),
+            "{panic_info}"
+        );
+    }));
+}
+
+/// Displays the client connection a request arrived on, for handler spans: the peer address for
+/// TCP connections, or the peer process for Unix domain socket connections.
+struct Client<'a, T>(&'a Request<T>);
+
+impl<T> fmt::Display for Client<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Client(request) = self;
+        if let Some(TcpConnectInfo {
+            remote_addr: Some(remote_addr),
+            ..
+        }) = request.extensions().get::<TcpConnectInfo>()
+        {
+            return write!(f, "{remote_addr}");
+        }
+        #[cfg(unix)]
+        if let Some(UdsConnectInfo { peer_cred, .. }) = request.extensions().get::<UdsConnectInfo>()
+        {
+            return match peer_cred.and_then(|peer_cred| peer_cred.pid()) {
+                Some(pid) => write!(f, "unix:pid={pid}"),
+                None => write!(f, "unix"),
+            };
+        }
+        write!(f, "unknown")
+    }
+}
+
 #[tonic::async_trait]
 impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_server::AttributeStore
     for AttributeServer<T>
 {
-    #[tracing::instrument(skip(self), ret(level = Level::TRACE), err(level = Level::WARN))]
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), ret(level = Level::TRACE), err(level = Level::WARN))]
     async fn ping(
         &self,
         request: Request<pb::PingRequest>,
@@ -102,7 +279,7 @@ impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_se
         Ok(Response::new(ping_response))
     }
 
-    #[tracing::instrument(skip(self), ret(level = Level::TRACE), err(level = Level::WARN))]
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), ret(level = Level::TRACE), err(level = Level::WARN))]
     async fn create_attribute_type(
         &self,
         request: Request<pb::CreateAttributeTypeRequest>,
@@ -129,7 +306,53 @@ impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_se
         Ok(Response::new(create_attribute_type_response))
     }
 
-    #[tracing::instrument(skip(self), ret(level = Level::TRACE), err(level = Level::WARN))]
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), ret(level = Level::TRACE), err(level = Level::WARN))]
+    async fn create_attribute_types(
+        &self,
+        request: Request<pb::CreateAttributeTypesRequest>,
+    ) -> Result<Response<pb::CreateAttributeTypesResponse>, Status> {
+        use AttributeServerError::*;
+
+        log::info!("Received create attribute types request");
+
+        let create_attribute_types_request = request.into_inner();
+        let attribute_types = Vec::<AttributeType>::try_from_proto(create_attribute_types_request)
+            .map_err(ConversionError)?;
+
+        let create_attribute_types_result = self
+            .store
+            .create_attribute_types(&attribute_types)
+            .await
+            .map_err(AttributeStoreError)?;
+
+        Ok(Response::new(create_attribute_types_result.into_proto()))
+    }
+
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), ret(level = Level::TRACE), err(level = Level::WARN))]
+    async fn get_attribute_type(
+        &self,
+        request: Request<pb::GetAttributeTypeRequest>,
+    ) -> Result<Response<pb::GetAttributeTypeResponse>, Status> {
+        use AttributeServerError::*;
+
+        log::info!("Received get attribute type request");
+
+        let get_attribute_type_request = request.into_inner();
+        let symbol = Symbol::try_from_proto(get_attribute_type_request).map_err(ConversionError)?;
+
+        let attribute_type = self
+            .store
+            .get_attribute_type(&symbol)
+            .await
+            .map_err(AttributeStoreError)?;
+        let get_attribute_type_response = pb::GetAttributeTypeResponse {
+            attribute_type: Some(attribute_type.into_proto()),
+        };
+
+        Ok(Response::new(get_attribute_type_response))
+    }
+
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), ret(level = Level::TRACE), err(level = Level::WARN))]
     async fn get_entity(
         &self,
         request: Request<pb::GetEntityRequest>,
@@ -154,7 +377,29 @@ impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_se
         Ok(Response::new(get_entity_response))
     }
 
-    #[tracing::instrument(skip(self), ret(level = Level::TRACE), err(level = Level::WARN))]
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), ret(level = Level::TRACE), err(level = Level::WARN))]
+    async fn batch_get_entities(
+        &self,
+        request: Request<pb::BatchGetEntitiesRequest>,
+    ) -> Result<Response<pb::BatchGetEntitiesResponse>, Status> {
+        use AttributeServerError::*;
+
+        log::info!("Received batch get entities request");
+
+        let batch_get_entities_request = request.into_inner();
+        let entity_locators = Vec::<EntityLocator>::try_from_proto(batch_get_entities_request)
+            .map_err(ConversionError)?;
+
+        let batch_get_entities_result = self
+            .store
+            .batch_get_entities(&entity_locators)
+            .await
+            .map_err(AttributeStoreError)?;
+
+        Ok(Response::new(batch_get_entities_result.into_proto()))
+    }
+
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), ret(level = Level::TRACE), err(level = Level::WARN))]
     async fn query_entity_rows(
         &self,
         request: Request<pb::QueryEntityRowsRequest>,
@@ -177,14 +422,113 @@ impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_se
             rows: entity_row_query_result
                 .entity_rows
                 .into_iter()
-                .map(|entity_row| entity_row.into_proto())
+                .map(|entity_row| {
+                    (entity_row, entity_query.attribute_types.as_slice()).into_proto()
+                })
                 .collect(),
+            entity_version: entity_row_query_result.entity_version.into_proto(),
         };
 
         Ok(Response::new(query_entity_rows_response))
     }
 
-    #[tracing::instrument(skip(self), ret(level = Level::TRACE), err(level = Level::WARN))]
+    type StreamEntityRowsStream =
+        Pin<Box<dyn Stream<Item = Result<pb::EntityRow, Status>> + Send + 'static>>;
+
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), err(level = Level::WARN))]
+    async fn stream_entity_rows(
+        &self,
+        request: Request<pb::QueryEntityRowsRequest>,
+    ) -> Result<Response<Self::StreamEntityRowsStream>, Status> {
+        use AttributeServerError::*;
+
+        log::info!("Received stream entity rows request");
+
+        let query_entity_rows_request = request.into_inner();
+        let entity_row_query =
+            EntityRowQuery::try_from_proto(query_entity_rows_request).map_err(ConversionError)?;
+
+        // Fetch the first page up front so that invalid queries fail the call itself
+        let first_page = self
+            .store
+            .query_entity_rows_page(&entity_row_query, None, STREAM_ENTITY_ROWS_PAGE_SIZE)
+            .await
+            .map_err(AttributeStoreError)?
+            .entity_rows;
+
+        let (tx, rx) = tokio::sync::mpsc::channel(STREAM_ENTITY_ROWS_PAGE_SIZE);
+        let store = self.store.clone();
+        tokio::spawn(async move {
+            let mut page = first_page;
+            loop {
+                let page_len = page.len();
+                let after = page.last().map(|entity_row| entity_row.entity_id);
+                for entity_row in page {
+                    let entity_row_proto =
+                        (entity_row, entity_row_query.attribute_types.as_slice()).into_proto();
+                    if tx.send(Ok(entity_row_proto)).await.is_err() {
+                        // The client has gone away
+                        return;
+                    }
+                }
+                if page_len < STREAM_ENTITY_ROWS_PAGE_SIZE {
+                    return;
+                }
+
+                page = match store
+                    .query_entity_rows_page(&entity_row_query, after, STREAM_ENTITY_ROWS_PAGE_SIZE)
+                    .await
+                {
+                    Ok(entity_row_query_result) => entity_row_query_result.entity_rows,
+                    Err(err) => {
+                        let _ = tx.send(Err(AttributeStoreError(err).into())).await;
+                        return;
+                    }
+                };
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), ret(level = Level::TRACE), err(level = Level::WARN))]
+    async fn benchmark_query(
+        &self,
+        request: Request<pb::BenchmarkQueryRequest>,
+    ) -> Result<Response<pb::BenchmarkQueryResponse>, Status> {
+        use AttributeServerError::*;
+
+        log::info!("Received benchmark query request");
+
+        if !self.benchmarks_enabled {
+            return Err(Status::permission_denied(
+                "benchmarks are disabled; start the server with --enable-benchmarks",
+            ));
+        }
+
+        let benchmark_query_request =
+            BenchmarkQueryRequest::try_from_proto(request.into_inner()).map_err(ConversionError)?;
+        let entity_query = EntityQuery {
+            root: benchmark_query_request.query,
+            include_deleted: false,
+        };
+
+        let mut durations = Vec::with_capacity(benchmark_query_request.iterations as usize);
+        for _ in 0..benchmark_query_request.iterations {
+            let start = Instant::now();
+            self.store
+                .query_entities(&entity_query)
+                .await
+                .map_err(AttributeStoreError)?;
+            durations.push(start.elapsed());
+        }
+
+        Ok(Response::new(
+            BenchmarkStatistics::from_durations(durations).into_proto(),
+        ))
+    }
+
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), ret(level = Level::TRACE), err(level = Level::WARN))]
     async fn update_entity(
         &self,
         request: Request<pb::UpdateEntityRequest>,
@@ -211,10 +555,69 @@ impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_se
         Ok(Response::new(update_entity_response))
     }
 
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), ret(level = Level::TRACE), err(level = Level::WARN))]
+    async fn merge_entities(
+        &self,
+        request: Request<pb::MergeEntitiesRequest>,
+    ) -> Result<Response<pb::MergeEntitiesResponse>, Status> {
+        use AttributeServerError::*;
+
+        log::info!("Received merge entities request");
+
+        let MergeEntitiesRequest {
+            source,
+            target,
+            attribute_types,
+        } = MergeEntitiesRequest::try_from_proto(request.into_inner()).map_err(ConversionError)?;
+
+        let merged_entity = self
+            .store
+            .merge_entities(&source, &target, &attribute_types)
+            .await
+            .map_err(AttributeStoreError)?;
+
+        let merge_entities_response = pb::MergeEntitiesResponse {
+            entity: Some(merged_entity.into_proto()),
+        };
+
+        Ok(Response::new(merge_entities_response))
+    }
+
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), ret(level = Level::TRACE), err(level = Level::WARN))]
+    async fn atomic_query_and_update(
+        &self,
+        request: Request<pb::AtomicQueryAndUpdateRequest>,
+    ) -> Result<Response<pb::AtomicQueryAndUpdateResponse>, Status> {
+        use AttributeServerError::*;
+
+        log::info!("Received atomic query and update request");
+
+        let AtomicQueryAndUpdateRequest {
+            entity_locator,
+            attributes_to_update,
+        } = AtomicQueryAndUpdateRequest::try_from_proto(request.into_inner())
+            .map_err(ConversionError)?;
+
+        // An expression that fails to evaluate leaves the entity unchanged
+        let updated_entity = self
+            .store
+            .atomic_query_and_update(&entity_locator, |entity| {
+                evaluate_attributes_to_update(&attributes_to_update, entity)
+                    .map_err(InvalidExpression)
+            })
+            .await?;
+
+        let atomic_query_and_update_response = pb::AtomicQueryAndUpdateResponse {
+            entity: Some(updated_entity.into_proto()),
+        };
+
+        Ok(Response::new(atomic_query_and_update_response))
+    }
+
     type WatchEntitiesStream =
         Pin<Box<dyn Stream<Item = Result<pb::WatchEntitiesEvent, Status>> + Send + 'static>>;
 
-    #[tracing::instrument(skip(self), err(level = Level::WARN))]
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), err(level = Level::WARN))]
     async fn watch_entities(
         &self,
         request: Request<pb::WatchEntitiesRequest>,
@@ -223,17 +626,35 @@ impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_se
 
         log::info!("Received watch entities request");
 
+        let client_address = request.remote_addr();
         let watch_entities_request_proto = request.into_inner();
         let watch_entities_request =
             WatchEntitiesRequest::try_from_proto(watch_entities_request_proto)
                 .map_err(ConversionError)?;
         let entity_query_node = watch_entities_request.query;
+        // Watch events are filtered entity by entity, which cannot follow references
+        if entity_query_node.has_graph_traversal() {
+            return Err(Status::invalid_argument(
+                "graph traversals cannot be watched",
+            ));
+        }
+        let event_types = watch_entities_request.event_types;
+        let attribute_types = watch_entities_request.attribute_types;
+        let batch_window = watch_entities_request.batch_window;
 
+        let registration = Arc::new(StreamRegistry::register(
+            &self.stream_registry,
+            StreamKind::WatchEntities,
+            client_address,
+            entity_query_node.to_query_string(&OpaqueEntityIds),
+        ));
         let receiver = self.store.watch_entities_receiver();
 
         let (initial_events, min_entity_version) = if watch_entities_request.send_initial_events {
             let entity_query = EntityQuery {
                 root: entity_query_node.clone(),
+                // Watch events are sent for deleted entities too
+                include_deleted: true,
             };
             let entity_query_result = self
                 .store
@@ -241,23 +662,33 @@ impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_se
                 .await
                 .map_err(AttributeStoreError)?;
 
+            // Initial events are added events, so none are sent unless those are requested
+            let initial_entities = if event_types.contains(&WatchEventType::Added) {
+                entity_query_result.entities
+            } else {
+                vec![]
+            };
             let bookmark_event = pb::WatchEntitiesEvent {
                 event: Some(pb::watch_entities_event::Event::Bookmark(
                     pb::BookmarkEvent {
                         entity_version: entity_query_result.entity_version.into_proto(),
+                        entity_count: u64::try_from(initial_entities.len()).unwrap_or(u64::MAX),
+                        forced_resync: false,
                     },
                 )),
             };
-            let initial_events = entity_query_result
-                .entities
+            let initial_events = initial_entities
                 .into_iter()
                 .map(|entity| WatchEntitiesEvent {
                     entity_version: entity_query_result.entity_version,
                     before: None,
-                    after: Some(Arc::new(entity)),
+                    after: Some(project_entity(Arc::new(entity), &attribute_types)),
                 })
                 .map(|event| event.into_proto())
-                .chain(iter::once(bookmark_event))
+                .chain(
+                    iter::once(bookmark_event)
+                        .filter(|_| event_types.contains(&WatchEventType::Bookmark)),
+                )
                 .collect();
 
             (initial_events, Some(entity_query_result.entity_version))
@@ -265,15 +696,56 @@ impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_se
             (vec![], None)
         };
 
-        let ongoing_events = BroadcastStream::new(receiver)
-            .filter_map(|v| v.ok())
-            .filter_map(move |event| filter_event(event, &entity_query_node, min_entity_version))
+        let lag_limit = LagLimit {
+            max_lag: self.watch_max_lag_events.unwrap_or(usize::MAX),
+            exceeded_lag: Arc::default(),
+        };
+        let exceeded_lag = lag_limit.exceeded_lag.clone();
+        let events = receive_events(receiver, registration.clone(), Some(lag_limit));
+        let events: Pin<Box<dyn Stream<Item = WatchEntitiesEvent> + Send>> = match batch_window {
+            Some(batch_window) => Box::pin(coalesce_events(
+                events.filter(move |event| is_after_snapshot(event, min_entity_version)),
+                batch_window,
+            )),
+            None => Box::pin(events),
+        };
+        let ongoing_events = events
+            .filter_map(move |event| {
+                filter_event(
+                    event,
+                    &entity_query_node,
+                    min_entity_version,
+                    &attribute_types,
+                )
+            })
             .filter(|WatchEntitiesEvent { before, after, .. }| before != after)
+            .filter(move |event| {
+                event
+                    .event_type()
+                    .is_some_and(|event_type| event_types.contains(&event_type))
+            })
             .map(|event| event.into_proto());
 
+        // Checked once the ongoing events end, in case the client fell too far behind
+        let forced_resync =
+            futures::stream::once(future::lazy(move |_| exceeded_lag.get().copied()))
+                .filter_map(|lag| lag);
+        let forced_resync = futures::StreamExt::flat_map(forced_resync, move |lag| {
+            tokio_stream::iter(forced_resync_events(lag, client_address, |bookmark| {
+                pb::WatchEntitiesEvent {
+                    event: Some(pb::watch_entities_event::Event::Bookmark(bookmark)),
+                }
+            }))
+        });
+
         let response_stream = tokio_stream::iter(initial_events)
             .chain(ongoing_events)
-            .map(Ok);
+            .map(move |event| {
+                registration.record_event_sent();
+                event
+            })
+            .map(Ok)
+            .chain(forced_resync);
 
         Ok(Response::new(Box::pin(response_stream)))
     }
@@ -281,6 +753,7 @@ impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_se
     type WatchEntityRowsStream =
         Pin<Box<dyn Stream<Item = Result<pb::WatchEntityRowsEvent, Status>> + Send + 'static>>;
 
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), err(level = Level::WARN))]
     async fn watch_entity_rows(
         &self,
         request: Request<pb::WatchEntityRowsRequest>,
@@ -289,12 +762,25 @@ impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_se
 
         log::info!("Received watch entities request");
 
+        let client_address = request.remote_addr();
         let watch_entity_rows_request_proto = request.into_inner();
         let watch_entity_rows_request =
             WatchEntityRowsRequest::try_from_proto(watch_entity_rows_request_proto)
                 .map_err(ConversionError)?;
         let entity_query_node = watch_entity_rows_request.query;
+        // Watch events are filtered entity by entity, which cannot follow references
+        if entity_query_node.has_graph_traversal() {
+            return Err(Status::invalid_argument(
+                "graph traversals cannot be watched",
+            ));
+        }
 
+        let registration = Arc::new(StreamRegistry::register(
+            &self.stream_registry,
+            StreamKind::WatchEntityRows,
+            client_address,
+            entity_query_node.to_query_string(&OpaqueEntityIds),
+        ));
         let receiver = self.store.watch_entities_receiver();
 
         let (initial_events, min_entity_version) = if watch_entity_rows_request.send_initial_events
@@ -302,6 +788,10 @@ impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_se
             let entity_row_query = EntityRowQuery {
                 root: entity_query_node.clone(),
                 attribute_types: watch_entity_rows_request.attribute_types.clone(),
+                // Watch events are sent for deleted entities too
+                include_deleted: true,
+                filter_nulls: false,
+                filter_rows_where_null: vec![],
             };
             let entity_rows_query_result = self
                 .store
@@ -310,21 +800,26 @@ impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_se
                 .map_err(AttributeStoreError)?;
 
             let bookmark_event = pb::WatchEntityRowsEvent {
+                entity_id: String::new(),
                 event: Some(pb::watch_entity_rows_event::Event::Bookmark(
                     pb::BookmarkEvent {
                         entity_version: entity_rows_query_result.entity_version.into_proto(),
+                        entity_count: u64::try_from(entity_rows_query_result.entity_rows.len())
+                            .unwrap_or(u64::MAX),
+                        forced_resync: false,
                     },
                 )),
             };
             let initial_events = entity_rows_query_result
                 .entity_rows
                 .into_iter()
-                .map(|entity_row| pb::WatchEntityRowsEvent {
-                    event: Some(pb::watch_entity_rows_event::Event::Added(
-                        pb::AddedEntityRowEvent {
-                            entity_row: Some(entity_row.into_proto()),
-                        },
-                    )),
+                .map(|entity_row| {
+                    let added_event = WatchEntityRowsEvent {
+                        entity_version: entity_rows_query_result.entity_version,
+                        before: None,
+                        after: Some(entity_row),
+                    };
+                    (added_event, entity_row_query.attribute_types.as_slice()).into_proto()
                 })
                 .chain(iter::once(bookmark_event))
                 .collect();
@@ -337,21 +832,268 @@ impl<T: attribute_store::store::ThreadSafeAttributeStore> pb::attribute_store_se
             (vec![], None)
         };
 
-        let ongoing_events = BroadcastStream::new(receiver)
-            .filter_map(|v| v.ok())
-            .filter_map(move |event| filter_event(event, &entity_query_node, min_entity_version))
-            .map(move |event| {
-                to_watch_entity_row_event(event, &watch_entity_rows_request.attribute_types)
+        let attribute_types: Arc<[Symbol]> = watch_entity_rows_request.attribute_types.into();
+        let ongoing_events = receive_events(receiver, registration.clone(), None)
+            .filter_map(move |event| {
+                filter_event(event, &entity_query_node, min_entity_version, &[])
+            })
+            .map({
+                let attribute_types = attribute_types.clone();
+                move |event| to_watch_entity_row_event(event, &attribute_types)
             })
             .filter(|WatchEntityRowsEvent { before, after, .. }| before != after)
-            .map(|event| event.into_proto());
+            .map(move |event| (event, attribute_types.as_ref()).into_proto());
 
         let response_stream = tokio_stream::iter(initial_events)
             .chain(ongoing_events)
+            .map(move |event| {
+                registration.record_event_sent();
+                event
+            })
             .map(Ok);
 
         Ok(Response::new(Box::pin(response_stream)))
     }
+
+    type WatchEntityStream =
+        Pin<Box<dyn Stream<Item = Result<pb::WatchEntityRowsEvent, Status>> + Send + 'static>>;
+
+    #[tracing::instrument(skip(self), fields(client = %Client(&request)), err(level = Level::WARN))]
+    async fn watch_entity(
+        &self,
+        request: Request<pb::WatchEntityRequest>,
+    ) -> Result<Response<Self::WatchEntityStream>, Status> {
+        use AttributeServerError::*;
+
+        log::info!("Received watch entity request");
+
+        let client_address = request.remote_addr();
+        let watch_entity_request_proto = request.into_inner();
+        let watch_entity_request = WatchEntityRequest::try_from_proto(watch_entity_request_proto)
+            .map_err(ConversionError)?;
+        let entity_id = self
+            .store
+            .get_entity(&watch_entity_request.entity_locator)
+            .await
+            .map_err(AttributeStoreError)?
+            .entity_id;
+
+        let registration = Arc::new(StreamRegistry::register(
+            &self.stream_registry,
+            StreamKind::WatchEntity,
+            client_address,
+            EntityQueryNode::EntityIdIn(EntityIdInNode {
+                entity_ids: vec![entity_id],
+            })
+            .to_query_string(&OpaqueEntityIds),
+        ));
+        // Subscribe before reading the entity so that no change is missed in between
+        let receiver = self.store.watch_entity_receiver(entity_id);
+
+        let (initial_events, min_entity_version) = if watch_entity_request.send_initial_events {
+            let entity = self
+                .store
+                .get_entity(&EntityLocator::EntityId(entity_id))
+                .await
+                .map_err(AttributeStoreError)?;
+
+            let bookmark_event = pb::WatchEntityRowsEvent {
+                entity_id: String::new(),
+                event: Some(pb::watch_entity_rows_event::Event::Bookmark(
+                    pb::BookmarkEvent {
+                        entity_version: entity.entity_version.into_proto(),
+                        entity_count: 1,
+                        forced_resync: false,
+                    },
+                )),
+            };
+            let added_event = WatchEntityRowsEvent {
+                entity_version: entity.entity_version,
+                before: None,
+                after: Some(entity.to_entity_row(&watch_entity_request.attribute_types)),
+            };
+            let added_event =
+                (added_event, watch_entity_request.attribute_types.as_slice()).into_proto();
+
+            (
+                vec![added_event, bookmark_event],
+                Some(entity.entity_version),
+            )
+        } else {
+            (vec![], None)
+        };
+
+        let entity_query_node = EntityQueryNode::MatchAll(MatchAllQueryNode);
+        let attribute_types: Arc<[Symbol]> = watch_entity_request.attribute_types.into();
+        let lag_limit = LagLimit {
+            max_lag: self.watch_max_lag_events.unwrap_or(usize::MAX),
+            exceeded_lag: Arc::default(),
+        };
+        let exceeded_lag = lag_limit.exceeded_lag.clone();
+        let ongoing_events = receive_events(receiver, registration.clone(), Some(lag_limit))
+            .filter_map(move |event| {
+                filter_event(event, &entity_query_node, min_entity_version, &[])
+            })
+            .map({
+                let attribute_types = attribute_types.clone();
+                move |event| to_watch_entity_row_event(event, &attribute_types)
+            })
+            .filter(|WatchEntityRowsEvent { before, after, .. }| before != after)
+            .map(move |event| (event, attribute_types.as_ref()).into_proto());
+
+        // Checked once the ongoing events end, in case the client fell too far behind
+        let forced_resync =
+            futures::stream::once(future::lazy(move |_| exceeded_lag.get().copied()))
+                .filter_map(|lag| lag);
+        let forced_resync = futures::StreamExt::flat_map(forced_resync, move |lag| {
+            tokio_stream::iter(forced_resync_events(lag, client_address, |bookmark| {
+                pb::WatchEntityRowsEvent {
+                    entity_id: String::new(),
+                    event: Some(pb::watch_entity_rows_event::Event::Bookmark(bookmark)),
+                }
+            }))
+        });
+
+        let response_stream = tokio_stream::iter(initial_events)
+            .chain(ongoing_events)
+            .map(move |event| {
+                registration.record_event_sent();
+                event
+            })
+            .map(Ok)
+            .chain(forced_resync);
+
+        Ok(Response::new(Box::pin(response_stream)))
+    }
+}
+
+/// Ends the watch stream of a client that fell `lag` events behind, asking it to watch again.
+fn forced_resync_events<T>(
+    lag: usize,
+    client_address: Option<SocketAddr>,
+    bookmark_event: impl FnOnce(pb::BookmarkEvent) -> T,
+) -> [Result<T, Status>; 2] {
+    tracing::warn!(lag, ?client_address, "Closing watch stream of slow client");
+    let bookmark_event = bookmark_event(pb::BookmarkEvent {
+        entity_version: String::new(),
+        entity_count: 0,
+        forced_resync: true,
+    });
+    [
+        Ok(bookmark_event),
+        Err(Status::resource_exhausted(format!(
+            "watch stream fell {lag} events behind; watch again to resync"
+        ))),
+    ]
+}
+
+/// Ends a watch stream once more than `max_lag` events are waiting to be received by it, or once
+/// it has missed events
+struct LagLimit {
+    max_lag: usize,
+    /// Set to the stream's lag if it was ended for falling behind
+    exceeded_lag: Arc<OnceLock<usize>>,
+}
+
+/// Receives the events broadcast by the store, recording how far behind the stream is. Without a
+/// `lag_limit`, events dropped because the stream fell too far behind are skipped.
+fn receive_events(
+    receiver: Receiver<WatchEntitiesEvent>,
+    registration: Arc<StreamRegistration>,
+    lag_limit: Option<LagLimit>,
+) -> impl Stream<Item = WatchEntitiesEvent> {
+    Box::pin(futures::stream::unfold(
+        (receiver, registration, lag_limit),
+        |(mut receiver, registration, lag_limit)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => {
+                        let lag = receiver.len();
+                        registration.record_lag(lag);
+                        if let Some(lag_limit) = &lag_limit {
+                            if lag > lag_limit.max_lag {
+                                let _ = lag_limit.exceeded_lag.set(lag);
+                                return None;
+                            }
+                        }
+                        return Some((event, (receiver, registration, lag_limit)));
+                    }
+                    Err(RecvError::Lagged(missed_events)) => {
+                        if let Some(lag_limit) = &lag_limit {
+                            let lag = receiver.len().saturating_add(
+                                usize::try_from(missed_events).unwrap_or(usize::MAX),
+                            );
+                            let _ = lag_limit.exceeded_lag.set(lag);
+                            return None;
+                        }
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        },
+    ))
+}
+
+/// Collects the events received within `batch_window` of the first, and sends a single event for
+/// each entity changed, from its state before the first change to its state after the last.
+fn coalesce_events(
+    events: impl Stream<Item = WatchEntitiesEvent> + Send + 'static,
+    batch_window: Duration,
+) -> impl Stream<Item = WatchEntitiesEvent> {
+    let events = Box::pin(events.fuse());
+    futures::stream::unfold(
+        (events, VecDeque::new()),
+        move |(mut events, mut batch)| async move {
+            loop {
+                if let Some(event) = batch.pop_front() {
+                    return Some((event, (events, batch)));
+                }
+
+                let mut pending: HashMap<EntityId, WatchEntitiesEvent> = HashMap::new();
+                accumulate_event(&mut pending, events.next().await?);
+                let flush = tokio::time::sleep(batch_window);
+                tokio::pin!(flush);
+                loop {
+                    tokio::select! {
+                        () = &mut flush => break,
+                        event = events.next() => match event {
+                            Some(event) => accumulate_event(&mut pending, event),
+                            None => break,
+                        },
+                    }
+                }
+
+                let mut events_by_version: Vec<_> = pending.into_values().collect();
+                events_by_version.sort_by_key(|event| event.entity_version);
+                batch.extend(events_by_version);
+            }
+        },
+    )
+}
+
+fn accumulate_event(
+    pending: &mut HashMap<EntityId, WatchEntitiesEvent>,
+    event: WatchEntitiesEvent,
+) {
+    let Some(entity_id) = event
+        .after
+        .as_ref()
+        .or(event.before.as_ref())
+        .map(|entity| entity.entity_id)
+    else {
+        return;
+    };
+    match pending.entry(entity_id) {
+        Entry::Occupied(mut entry) => {
+            let pending_event = entry.get_mut();
+            pending_event.entity_version = event.entity_version;
+            pending_event.after = event.after;
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(event);
+        }
+    }
 }
 
 fn to_watch_entity_row_event(
@@ -370,28 +1112,926 @@ fn to_watch_entity_row_event(
     }
 }
 
+/// The initial snapshot already reflects every change up to and including its entity version
+fn is_after_snapshot(
+    event: &WatchEntitiesEvent,
+    min_entity_version: Option<EntityVersion>,
+) -> bool {
+    min_entity_version.is_none_or(|min_entity_version| event.entity_version > min_entity_version)
+}
+
 fn filter_event(
     watch_entities_event: WatchEntitiesEvent,
     entity_query_node: &EntityQueryNode,
     min_entity_version: Option<EntityVersion>,
+    attribute_types: &[Symbol],
 ) -> Option<WatchEntitiesEvent> {
+    if !is_after_snapshot(&watch_entities_event, min_entity_version) {
+        return None;
+    }
+
     let WatchEntitiesEvent {
         before,
         after,
         entity_version,
     } = watch_entities_event;
 
-    if let Some(min_entity_version) = min_entity_version {
-        if entity_version < min_entity_version {
-            return None;
-        }
-    }
-
     let matches_query = |entity: &Arc<Entity>| -> bool { entity_query_node.matches(entity) };
 
+    let project = |entity: Arc<Entity>| project_entity(entity, attribute_types);
+
     Some(WatchEntitiesEvent {
         entity_version,
-        before: before.filter(matches_query),
-        after: after.filter(matches_query),
+        before: before.filter(matches_query).map(project),
+        after: after.filter(matches_query).map(project),
     })
 }
+
+/// Strips all but `attribute_types` from `entity`, or leaves it unchanged if `attribute_types` is
+/// empty.
+fn project_entity(entity: Arc<Entity>, attribute_types: &[Symbol]) -> Arc<Entity> {
+    if attribute_types.is_empty() {
+        return entity;
+    }
+
+    let mut entity = Arc::unwrap_or_clone(entity);
+    entity
+        .attributes
+        .retain(|attribute_type, _| attribute_types.contains(attribute_type));
+    Arc::new(entity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use attribute_store::inmemory::InMemoryAttributeStore;
+    use attribute_store::store::{
+        AttributeToUpdate, AttributeType, BatchGetEntitiesResult, CreateAttributeTypesResult,
+        EntityId, EntityQueryResult, EntityRowQueryResult, ThreadSafeAttributeStore,
+    };
+    use parking_lot::Mutex;
+    use pb::attribute_store_server::AttributeStore;
+    use tokio::sync::broadcast::Receiver;
+
+    /// Applies queued updates immediately before the next query, as if they were committed by
+    /// another request while a watch was taking its initial snapshot.
+    struct InFlightUpdateStore {
+        store: Mutex<InMemoryAttributeStore>,
+        in_flight_updates: Mutex<Vec<UpdateEntityRequest>>,
+    }
+
+    impl InFlightUpdateStore {
+        fn apply_in_flight_updates(&self) {
+            for update_entity_request in self.in_flight_updates.lock().drain(..) {
+                attribute_store::store::AttributeStore::update_entity(
+                    &mut *self.store.lock(),
+                    &update_entity_request,
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    #[tonic::async_trait]
+    impl ThreadSafeAttributeStore for InFlightUpdateStore {
+        async fn create_attribute_type(
+            &self,
+            create_attribute_type_request: &CreateAttributeTypeRequest,
+        ) -> Result<Entity, AttributeStoreError> {
+            self.store
+                .create_attribute_type(create_attribute_type_request)
+                .await
+        }
+
+        async fn create_attribute_types(
+            &self,
+            attribute_types: &[AttributeType],
+        ) -> Result<CreateAttributeTypesResult, AttributeStoreError> {
+            self.store.create_attribute_types(attribute_types).await
+        }
+
+        async fn get_attribute_type(
+            &self,
+            symbol: &Symbol,
+        ) -> Result<AttributeType, AttributeStoreError> {
+            self.store.get_attribute_type(symbol).await
+        }
+
+        async fn get_entity(
+            &self,
+            entity_locator: &EntityLocator,
+        ) -> Result<Entity, AttributeStoreError> {
+            self.store.get_entity(entity_locator).await
+        }
+
+        async fn batch_get_entities(
+            &self,
+            entity_locators: &[EntityLocator],
+        ) -> Result<BatchGetEntitiesResult, AttributeStoreError> {
+            self.store.batch_get_entities(entity_locators).await
+        }
+
+        async fn query_entities(
+            &self,
+            entity_query: &EntityQuery,
+        ) -> Result<EntityQueryResult, AttributeStoreError> {
+            self.apply_in_flight_updates();
+            self.store.query_entities(entity_query).await
+        }
+
+        async fn query_entity_rows(
+            &self,
+            entity_row_query: &EntityRowQuery,
+        ) -> Result<EntityRowQueryResult, AttributeStoreError> {
+            self.apply_in_flight_updates();
+            self.store.query_entity_rows(entity_row_query).await
+        }
+
+        async fn query_entity_rows_page(
+            &self,
+            entity_row_query: &EntityRowQuery,
+            after: Option<EntityId>,
+            limit: usize,
+        ) -> Result<EntityRowQueryResult, AttributeStoreError> {
+            self.apply_in_flight_updates();
+            self.store
+                .query_entity_rows_page(entity_row_query, after, limit)
+                .await
+        }
+
+        async fn validate_update_entity(
+            &self,
+            update_entity_request: &UpdateEntityRequest,
+        ) -> Result<(), AttributeStoreError> {
+            self.store
+                .validate_update_entity(update_entity_request)
+                .await
+        }
+
+        async fn update_entity(
+            &self,
+            update_entity_request: &UpdateEntityRequest,
+        ) -> Result<Entity, AttributeStoreError> {
+            self.store.update_entity(update_entity_request).await
+        }
+
+        async fn merge_entities(
+            &self,
+            source: &EntityLocator,
+            target: &EntityLocator,
+            attribute_types: &[Symbol],
+        ) -> Result<Entity, AttributeStoreError> {
+            self.store
+                .merge_entities(source, target, attribute_types)
+                .await
+        }
+
+        async fn atomic_query_and_update<F, E>(
+            &self,
+            entity_locator: &EntityLocator,
+            f: F,
+        ) -> Result<Entity, E>
+        where
+            F: FnOnce(&Entity) -> Result<Vec<AttributeToUpdate>, E> + Send,
+            E: From<AttributeStoreError> + fmt::Display + Send,
+        {
+            self.store.atomic_query_and_update(entity_locator, f).await
+        }
+
+        fn watch_entities_receiver(&self) -> Receiver<WatchEntitiesEvent> {
+            self.store.watch_entities_receiver()
+        }
+
+        fn current_entity_version(&self) -> EntityVersion {
+            self.store.current_entity_version()
+        }
+
+        fn entity_count(&self) -> usize {
+            self.store.entity_count()
+        }
+
+        fn watch_entities_channel_len(&self) -> usize {
+            self.store.watch_entities_channel_len()
+        }
+
+        fn memory_usage_bytes(&self) -> usize {
+            self.store.memory_usage_bytes()
+        }
+
+        fn watch_entity_receiver(&self, entity_id: EntityId) -> Receiver<WatchEntitiesEvent> {
+            self.store.watch_entity_receiver(entity_id)
+        }
+    }
+
+    fn update_count_request(count: usize) -> pb::UpdateEntityRequest {
+        let string_value = |value: String| pb::AttributeValue {
+            attribute_value: Some(pb::attribute_value::AttributeValue::StringValue(value)),
+        };
+        pb::UpdateEntityRequest {
+            entity_locator: Some(pb::EntityLocator {
+                locator: Some(pb::entity_locator::Locator::Symbol("counter".to_string())),
+            }),
+            attributes_to_update: vec![
+                pb::AttributeToUpdate {
+                    attribute_type: "@symbolName".to_string(),
+                    attribute_value: Some(string_value("counter".to_string())),
+                    delete: false,
+                },
+                pb::AttributeToUpdate {
+                    attribute_type: "count".to_string(),
+                    attribute_value: Some(string_value(count.to_string())),
+                    delete: false,
+                },
+            ],
+            dry_run: false,
+        }
+    }
+
+    fn count_value(entity_row: Option<pb::EntityRow>) -> usize {
+        match entity_row.unwrap().values[0].value.as_ref().unwrap() {
+            pb::AttributeValue {
+                attribute_value: Some(pb::attribute_value::AttributeValue::StringValue(count)),
+            } => count.parse().unwrap(),
+            value => panic!("unexpected count value: {value:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn watch_entity_rows_neither_misses_nor_duplicates_updates_during_snapshot() {
+        let server = AttributeServer::new(InFlightUpdateStore {
+            store: Mutex::new(InMemoryAttributeStore::new()),
+            in_flight_updates: Mutex::new(vec![]),
+        });
+        server
+            .create_attribute_type(Request::new(pb::CreateAttributeTypeRequest {
+                attribute_type: Some(pb::AttributeType {
+                    symbol: "count".to_string(),
+                    value_type: pb::ValueType::Text.into(),
+                    description: None,
+                    list: false,
+                }),
+            }))
+            .await
+            .unwrap();
+        server
+            .update_entity(Request::new(update_count_request(0)))
+            .await
+            .unwrap();
+
+        // Lands after the watch subscribes to events but before its snapshot is taken
+        server
+            .store
+            .in_flight_updates
+            .lock()
+            .push(UpdateEntityRequest::try_from_proto(update_count_request(1)).unwrap());
+        let mut stream = server
+            .watch_entity_rows(Request::new(pb::WatchEntityRowsRequest {
+                query: Some(pb::EntityQueryNode {
+                    query: Some(pb::entity_query_node::Query::HasAttributeTypes(
+                        pb::HasAttributeTypesNode {
+                            attribute_types: vec!["count".to_string()],
+                        },
+                    )),
+                }),
+                attribute_types: vec!["count".to_string()],
+                send_initial_events: true,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        server
+            .update_entity(Request::new(update_count_request(2)))
+            .await
+            .unwrap();
+
+        let mut counts = vec![];
+        while counts.last() != Some(&2) {
+            match stream.next().await.unwrap().unwrap().event.unwrap() {
+                pb::watch_entity_rows_event::Event::Added(added) => {
+                    counts.push(count_value(added.entity_row));
+                }
+                pb::watch_entity_rows_event::Event::Modified(modified) => {
+                    counts.push(count_value(modified.entity_row));
+                }
+                pb::watch_entity_rows_event::Event::Bookmark(bookmark) => {
+                    assert_eq!(bookmark.entity_count, 1);
+                }
+                event => panic!("unexpected watch event: {event:?}"),
+            }
+        }
+
+        assert_eq!(counts, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn watch_entities_only_sends_requested_event_types() {
+        let server = AttributeServer::new(InFlightUpdateStore {
+            store: Mutex::new(InMemoryAttributeStore::new()),
+            in_flight_updates: Mutex::new(vec![]),
+        });
+        server
+            .create_attribute_type(Request::new(pb::CreateAttributeTypeRequest {
+                attribute_type: Some(pb::AttributeType {
+                    symbol: "count".to_string(),
+                    value_type: pb::ValueType::Text.into(),
+                    description: None,
+                    list: false,
+                }),
+            }))
+            .await
+            .unwrap();
+        server
+            .update_entity(Request::new(update_count_request(0)))
+            .await
+            .unwrap();
+
+        let mut stream = server
+            .watch_entities(Request::new(pb::WatchEntitiesRequest {
+                query: Some(pb::EntityQueryNode {
+                    query: Some(pb::entity_query_node::Query::HasAttributeTypes(
+                        pb::HasAttributeTypesNode {
+                            attribute_types: vec!["count".to_string()],
+                        },
+                    )),
+                }),
+                send_initial_events: true,
+                event_types: vec![pb::WatchEventType::Modified.into()],
+                attribute_types: vec![],
+                batch_window_ms: 0,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        server
+            .update_entity(Request::new(update_count_request(1)))
+            .await
+            .unwrap();
+
+        // Neither the initial added event nor the bookmark are sent
+        match stream.next().await.unwrap().unwrap().event.unwrap() {
+            pb::watch_entities_event::Event::Modified(_) => {}
+            event => panic!("unexpected watch event: {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn watch_entities_bookmark_only_counts_sent_entities() {
+        let server = AttributeServer::new(InFlightUpdateStore {
+            store: Mutex::new(InMemoryAttributeStore::new()),
+            in_flight_updates: Mutex::new(vec![]),
+        });
+
+        let mut stream = server
+            .watch_entities(Request::new(pb::WatchEntitiesRequest {
+                query: Some(pb::EntityQueryNode {
+                    query: Some(pb::entity_query_node::Query::MatchAll(
+                        pb::MatchAllQueryNode {},
+                    )),
+                }),
+                send_initial_events: true,
+                event_types: vec![pb::WatchEventType::Bookmark.into()],
+                attribute_types: vec![],
+                batch_window_ms: 0,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        match stream.next().await.unwrap().unwrap().event.unwrap() {
+            pb::watch_entities_event::Event::Bookmark(bookmark) => {
+                assert_eq!(bookmark.entity_count, 0)
+            }
+            event => panic!("unexpected watch event: {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn watch_entities_only_sends_requested_attribute_types() {
+        let server = AttributeServer::new(InFlightUpdateStore {
+            store: Mutex::new(InMemoryAttributeStore::new()),
+            in_flight_updates: Mutex::new(vec![]),
+        });
+        server
+            .create_attribute_type(Request::new(pb::CreateAttributeTypeRequest {
+                attribute_type: Some(pb::AttributeType {
+                    symbol: "count".to_string(),
+                    value_type: pb::ValueType::Text.into(),
+                    description: None,
+                    list: false,
+                }),
+            }))
+            .await
+            .unwrap();
+        server
+            .update_entity(Request::new(update_count_request(0)))
+            .await
+            .unwrap();
+
+        let mut stream = server
+            .watch_entities(Request::new(pb::WatchEntitiesRequest {
+                query: Some(pb::EntityQueryNode {
+                    query: Some(pb::entity_query_node::Query::HasAttributeTypes(
+                        pb::HasAttributeTypesNode {
+                            attribute_types: vec!["count".to_string()],
+                        },
+                    )),
+                }),
+                send_initial_events: true,
+                event_types: vec![
+                    pb::WatchEventType::Added.into(),
+                    pb::WatchEventType::Modified.into(),
+                ],
+                attribute_types: vec!["count".to_string()],
+                batch_window_ms: 0,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        server
+            .update_entity(Request::new(update_count_request(1)))
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            let entity = match stream.next().await.unwrap().unwrap().event.unwrap() {
+                pb::watch_entities_event::Event::Added(pb::AddedEvent { entity })
+                | pb::watch_entities_event::Event::Modified(pb::ModifiedEvent { entity, .. }) => {
+                    entity.unwrap()
+                }
+                event => panic!("unexpected watch event: {event:?}"),
+            };
+            // The entity's `@symbolName` is stripped
+            assert_eq!(
+                entity.attributes.keys().collect::<Vec<_>>(),
+                vec![&"count".to_string()]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn watch_entities_coalesces_changes_within_the_batch_window() {
+        let server = AttributeServer::new(InFlightUpdateStore {
+            store: Mutex::new(InMemoryAttributeStore::new()),
+            in_flight_updates: Mutex::new(vec![]),
+        });
+        server
+            .create_attribute_type(Request::new(pb::CreateAttributeTypeRequest {
+                attribute_type: Some(pb::AttributeType {
+                    symbol: "count".to_string(),
+                    value_type: pb::ValueType::Text.into(),
+                    description: None,
+                    list: false,
+                }),
+            }))
+            .await
+            .unwrap();
+        server
+            .update_entity(Request::new(update_count_request(0)))
+            .await
+            .unwrap();
+
+        let mut stream = server
+            .watch_entities(Request::new(pb::WatchEntitiesRequest {
+                query: Some(pb::EntityQueryNode {
+                    query: Some(pb::entity_query_node::Query::HasAttributeTypes(
+                        pb::HasAttributeTypesNode {
+                            attribute_types: vec!["count".to_string()],
+                        },
+                    )),
+                }),
+                send_initial_events: false,
+                event_types: vec![],
+                attribute_types: vec!["count".to_string()],
+                batch_window_ms: 50,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let mut next_count = async || match stream.next().await.unwrap().unwrap().event.unwrap() {
+            pb::watch_entities_event::Event::Modified(pb::ModifiedEvent { entity, .. }) => {
+                entity.unwrap().attributes["count"].clone()
+            }
+            event => panic!("unexpected watch event: {event:?}"),
+        };
+
+        for count in 1..=3 {
+            server
+                .update_entity(Request::new(update_count_request(count)))
+                .await
+                .unwrap();
+        }
+        let last_update = update_count_request(3).attributes_to_update[1].clone();
+        assert_eq!(Some(next_count().await), last_update.attribute_value);
+
+        // The earlier changes were dropped rather than sent late
+        server
+            .update_entity(Request::new(update_count_request(4)))
+            .await
+            .unwrap();
+        let next_update = update_count_request(4).attributes_to_update[1].clone();
+        assert_eq!(Some(next_count().await), next_update.attribute_value);
+    }
+
+    #[tokio::test]
+    async fn watch_entities_closes_streams_that_fall_behind() {
+        let server = AttributeServer::new(InFlightUpdateStore {
+            store: Mutex::new(InMemoryAttributeStore::new()),
+            in_flight_updates: Mutex::new(vec![]),
+        })
+        .with_watch_max_lag_events(1);
+        server
+            .create_attribute_type(Request::new(pb::CreateAttributeTypeRequest {
+                attribute_type: Some(pb::AttributeType {
+                    symbol: "count".to_string(),
+                    value_type: pb::ValueType::Text.into(),
+                    description: None,
+                    list: false,
+                }),
+            }))
+            .await
+            .unwrap();
+
+        let mut stream = server
+            .watch_entities(Request::new(pb::WatchEntitiesRequest {
+                query: Some(pb::EntityQueryNode {
+                    query: Some(pb::entity_query_node::Query::MatchAll(
+                        pb::MatchAllQueryNode {},
+                    )),
+                }),
+                send_initial_events: false,
+                event_types: vec![],
+                attribute_types: vec![],
+                batch_window_ms: 0,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        for count in 0..3 {
+            server
+                .update_entity(Request::new(update_count_request(count)))
+                .await
+                .unwrap();
+        }
+
+        match stream.next().await.unwrap().unwrap().event.unwrap() {
+            pb::watch_entities_event::Event::Bookmark(bookmark) => assert!(bookmark.forced_resync),
+            event => panic!("unexpected watch event: {event:?}"),
+        }
+        assert_eq!(
+            stream.next().await.unwrap().unwrap_err().code(),
+            Code::ResourceExhausted
+        );
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn watch_entities_closes_streams_that_miss_events() {
+        let server = AttributeServer::new(InFlightUpdateStore {
+            store: Mutex::new(InMemoryAttributeStore::new().with_channel_capacity(2)),
+            in_flight_updates: Mutex::new(vec![]),
+        });
+        server
+            .create_attribute_type(Request::new(pb::CreateAttributeTypeRequest {
+                attribute_type: Some(pb::AttributeType {
+                    symbol: "count".to_string(),
+                    value_type: pb::ValueType::Text.into(),
+                    description: None,
+                    list: false,
+                }),
+            }))
+            .await
+            .unwrap();
+
+        let mut stream = server
+            .watch_entities(Request::new(pb::WatchEntitiesRequest {
+                query: Some(pb::EntityQueryNode {
+                    query: Some(pb::entity_query_node::Query::MatchAll(
+                        pb::MatchAllQueryNode {},
+                    )),
+                }),
+                send_initial_events: false,
+                event_types: vec![],
+                attribute_types: vec![],
+                batch_window_ms: 0,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        for count in 0..4 {
+            server
+                .update_entity(Request::new(update_count_request(count)))
+                .await
+                .unwrap();
+        }
+
+        match stream.next().await.unwrap().unwrap().event.unwrap() {
+            pb::watch_entities_event::Event::Bookmark(bookmark) => assert!(bookmark.forced_resync),
+            event => panic!("unexpected watch event: {event:?}"),
+        }
+        assert_eq!(
+            stream.next().await.unwrap().unwrap_err().code(),
+            Code::ResourceExhausted
+        );
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn watch_streams_are_registered_until_dropped() {
+        let server = AttributeServer::new(InFlightUpdateStore {
+            store: Mutex::new(InMemoryAttributeStore::new()),
+            in_flight_updates: Mutex::new(vec![]),
+        });
+        let stream_registry = server.stream_registry();
+
+        let mut stream = server
+            .watch_entities(Request::new(pb::WatchEntitiesRequest {
+                query: Some(pb::EntityQueryNode {
+                    query: Some(pb::entity_query_node::Query::MatchAll(
+                        pb::MatchAllQueryNode {},
+                    )),
+                }),
+                send_initial_events: true,
+                event_types: vec![pb::WatchEventType::Bookmark.into()],
+                attribute_types: vec![],
+                batch_window_ms: 0,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        stream.next().await.unwrap().unwrap();
+        let streams = stream_registry.lock().to_json();
+        assert_eq!(streams.as_array().unwrap().len(), 1);
+        assert_eq!(streams[0]["kind"], "watch_entities");
+        assert_eq!(streams[0]["events_sent"], 1);
+
+        drop(stream);
+        assert_eq!(stream_registry.lock().to_json(), serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn watch_entity_only_sends_changes_to_the_watched_entity() {
+        let server = AttributeServer::new(InFlightUpdateStore {
+            store: Mutex::new(InMemoryAttributeStore::new()),
+            in_flight_updates: Mutex::new(vec![]),
+        });
+        server
+            .create_attribute_type(Request::new(pb::CreateAttributeTypeRequest {
+                attribute_type: Some(pb::AttributeType {
+                    symbol: "count".to_string(),
+                    value_type: pb::ValueType::Text.into(),
+                    description: None,
+                    list: false,
+                }),
+            }))
+            .await
+            .unwrap();
+        server
+            .update_entity(Request::new(update_count_request(0)))
+            .await
+            .unwrap();
+
+        let mut stream = server
+            .watch_entity(Request::new(pb::WatchEntityRequest {
+                entity_locator: Some(pb::EntityLocator {
+                    locator: Some(pb::entity_locator::Locator::Symbol("counter".to_string())),
+                }),
+                attribute_types: vec!["count".to_string()],
+                send_initial_events: true,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let mut other_entity_request = update_count_request(0);
+        other_entity_request.entity_locator = Some(pb::EntityLocator {
+            locator: Some(pb::entity_locator::Locator::Symbol("other".to_string())),
+        });
+        other_entity_request.attributes_to_update[0].attribute_value = Some(pb::AttributeValue {
+            attribute_value: Some(pb::attribute_value::AttributeValue::StringValue(
+                "other".to_string(),
+            )),
+        });
+        server
+            .update_entity(Request::new(other_entity_request))
+            .await
+            .unwrap();
+        server
+            .update_entity(Request::new(update_count_request(1)))
+            .await
+            .unwrap();
+
+        match stream.next().await.unwrap().unwrap().event.unwrap() {
+            pb::watch_entity_rows_event::Event::Added(added) => {
+                assert_eq!(count_value(added.entity_row), 0);
+            }
+            event => panic!("unexpected watch event: {event:?}"),
+        }
+        match stream.next().await.unwrap().unwrap().event.unwrap() {
+            pb::watch_entity_rows_event::Event::Bookmark(bookmark) => {
+                assert_eq!(bookmark.entity_count, 1);
+            }
+            event => panic!("unexpected watch event: {event:?}"),
+        }
+        match stream.next().await.unwrap().unwrap().event.unwrap() {
+            pb::watch_entity_rows_event::Event::Modified(modified) => {
+                assert_eq!(count_value(modified.entity_row), 1);
+            }
+            event => panic!("unexpected watch event: {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn watch_entity_closes_streams_that_miss_events() {
+        let server = AttributeServer::new(InFlightUpdateStore {
+            store: Mutex::new(InMemoryAttributeStore::new().with_channel_capacity(2)),
+            in_flight_updates: Mutex::new(vec![]),
+        });
+        let stream_registry = server.stream_registry();
+        server
+            .create_attribute_type(Request::new(pb::CreateAttributeTypeRequest {
+                attribute_type: Some(pb::AttributeType {
+                    symbol: "count".to_string(),
+                    value_type: pb::ValueType::Text.into(),
+                    description: None,
+                    list: false,
+                }),
+            }))
+            .await
+            .unwrap();
+        server
+            .update_entity(Request::new(update_count_request(0)))
+            .await
+            .unwrap();
+
+        let mut stream = server
+            .watch_entity(Request::new(pb::WatchEntityRequest {
+                entity_locator: Some(pb::EntityLocator {
+                    locator: Some(pb::entity_locator::Locator::Symbol("counter".to_string())),
+                }),
+                attribute_types: vec!["count".to_string()],
+                send_initial_events: false,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(stream_registry.lock().to_json()[0]["kind"], "watch_entity");
+        for count in 1..5 {
+            server
+                .update_entity(Request::new(update_count_request(count)))
+                .await
+                .unwrap();
+        }
+
+        match stream.next().await.unwrap().unwrap().event.unwrap() {
+            pb::watch_entity_rows_event::Event::Bookmark(bookmark) => {
+                assert!(bookmark.forced_resync);
+            }
+            event => panic!("unexpected watch event: {event:?}"),
+        }
+        assert_eq!(
+            stream.next().await.unwrap().unwrap_err().code(),
+            Code::ResourceExhausted
+        );
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn query_entity_rows_reports_entity_version() {
+        let server = AttributeServer::new(Mutex::new(InMemoryAttributeStore::new()));
+        let entity = server
+            .update_entity(Request::new(pb::UpdateEntityRequest {
+                entity_locator: Some(pb::EntityLocator {
+                    locator: Some(pb::entity_locator::Locator::Symbol("foo".to_string())),
+                }),
+                attributes_to_update: vec![pb::AttributeToUpdate {
+                    attribute_type: "@symbolName".to_string(),
+                    attribute_value: Some(pb::AttributeValue {
+                        attribute_value: Some(pb::attribute_value::AttributeValue::StringValue(
+                            "foo".to_string(),
+                        )),
+                    }),
+                    delete: false,
+                }],
+                dry_run: false,
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .entity
+            .unwrap();
+
+        let response = server
+            .query_entity_rows(Request::new(pb::QueryEntityRowsRequest {
+                root: Some(pb::EntityQueryNode {
+                    query: Some(pb::entity_query_node::Query::MatchAll(
+                        pb::MatchAllQueryNode {},
+                    )),
+                }),
+                attribute_types: vec![],
+                include_deleted: false,
+                filter_nulls: false,
+                filter_rows_where_null: vec![],
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.entity_version, entity.entity_version);
+    }
+
+    #[tokio::test]
+    async fn atomic_query_and_update_evaluates_expressions_against_current_entity() {
+        use pb::expression_tree::Expression;
+
+        let server = AttributeServer::new(Mutex::new(InMemoryAttributeStore::new()));
+        let string_value = |value: &str| pb::AttributeValue {
+            attribute_value: Some(pb::attribute_value::AttributeValue::StringValue(
+                value.to_string(),
+            )),
+        };
+        let entity_locator = Some(pb::EntityLocator {
+            locator: Some(pb::entity_locator::Locator::Symbol("foo".to_string())),
+        });
+        server
+            .update_entity(Request::new(pb::UpdateEntityRequest {
+                entity_locator: entity_locator.clone(),
+                attributes_to_update: vec![pb::AttributeToUpdate {
+                    attribute_type: "@symbolName".to_string(),
+                    attribute_value: Some(string_value("foo")),
+                    delete: false,
+                }],
+                dry_run: false,
+            }))
+            .await
+            .unwrap();
+        let atomic_update = |expression: Expression| pb::AtomicQueryAndUpdateRequest {
+            entity_locator: entity_locator.clone(),
+            attributes_to_update: vec![pb::AttributeUpdateExpression {
+                attribute_type: "@description".to_string(),
+                value: Some(pb::ExpressionTree {
+                    expression: Some(expression),
+                }),
+                delete: false,
+            }],
+        };
+        let attribute = |attribute_type: &str| {
+            Box::new(pb::ExpressionTree {
+                expression: Some(Expression::Attribute(attribute_type.to_string())),
+            })
+        };
+
+        let entity = server
+            .atomic_query_and_update(Request::new(atomic_update(Expression::Concat(
+                pb::ExpressionList {
+                    operands: vec![
+                        *attribute("@symbolName"),
+                        pb::ExpressionTree {
+                            expression: Some(Expression::Literal(string_value(" entity"))),
+                        },
+                    ],
+                },
+            ))))
+            .await
+            .unwrap()
+            .into_inner()
+            .entity
+            .unwrap();
+        assert_eq!(
+            entity.attributes.get("@description"),
+            Some(&string_value("foo entity"))
+        );
+
+        let entity_version = server.store().current_entity_version();
+        let status = server
+            .atomic_query_and_update(Request::new(atomic_update(Expression::Append(Box::new(
+                pb::AppendExpression {
+                    list: Some(attribute("@symbolName")),
+                    element: Some(attribute("@symbolName")),
+                },
+            )))))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(server.store().current_entity_version(), entity_version);
+    }
+
+    #[tokio::test]
+    async fn catch_panic_responds_with_internal_status() {
+        use tower::ServiceExt;
+
+        let panicking_service = tower::service_fn(|_: http::Request<()>| async move {
+            if true {
+                panic!("handler bug");
+            }
+            Ok::<_, std::convert::Infallible>(http::Response::new(tonic::body::empty_body()))
+        });
+
+        let response = CatchPanicLayer
+            .layer(panicking_service)
+            .oneshot(http::Request::new(()))
+            .await
+            .unwrap();
+
+        let status = Status::from_header_map(response.headers()).unwrap();
+        assert_eq!(status.code(), Code::Internal);
+        assert_eq!(status.message(), "unexpected server error");
+    }
+}
@@ -0,0 +1,253 @@
+use attribute_store::store::{
+    AttributeStoreError, AttributeToUpdate, AttributeType, AttributeValue, BootstrapSymbol,
+    EntityLocator, Symbol, ThreadSafeAttributeStore, UpdateEntityRequest, ValueType,
+};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+/// Symbol of the entity that reflects the server's liveness. Clients can watch it to monitor the
+/// server, and to notice restarts and upgrades.
+pub const HEALTH_ENTITY_SYMBOL: &str = "@server/health";
+
+const STARTED_AT: &str = "@server/health/startedAt";
+const VERSION: &str = "@server/health/version";
+const ENTITY_COUNT: &str = "@server/health/entityCount";
+
+/// Symbol of the entity that identifies the server. Clients can get it to check which server and
+/// version they are connected to.
+pub const SERVER_ENTITY_SYMBOL: &str = "@server";
+
+const SERVER_STARTED_AT: &str = "@server/startedAt";
+const SERVER_VERSION: &str = "@server/version";
+const SERVER_RUST_VERSION: &str = "@server/rustVersion";
+
+/// Creates the attribute types that don't already exist, as on a restart with a persistent store.
+async fn create_missing_attribute_types<T: ThreadSafeAttributeStore>(
+    store: &T,
+    attribute_types: &[(&'static str, ValueType, &str)],
+) -> Result<(), AttributeStoreError> {
+    let attribute_types = attribute_types
+        .iter()
+        .map(|(symbol, value_type, description)| {
+            Ok(AttributeType {
+                symbol: Symbol::try_from(*symbol)?,
+                value_type: value_type.clone(),
+                description: Some(description.to_string()),
+            })
+        })
+        .collect::<Result<Vec<_>, AttributeStoreError>>()?;
+    store.create_attribute_types(&attribute_types).await?;
+
+    Ok(())
+}
+
+/// Creates the health entity, recording when the server started, its version and the number of
+/// entities in the store.
+pub async fn create_health_entity<T: ThreadSafeAttributeStore>(
+    store: &T,
+    started_at: SystemTime,
+) -> Result<(), AttributeStoreError> {
+    create_missing_attribute_types(
+        store,
+        &[
+            (STARTED_AT, ValueType::Timestamp, "When the server started"),
+            (VERSION, ValueType::Text, "Version of the server binary"),
+            (
+                ENTITY_COUNT,
+                ValueType::Int64,
+                "Number of entities in the store",
+            ),
+        ],
+    )
+    .await?;
+
+    update_health_entity(
+        store,
+        vec![
+            AttributeToUpdate {
+                symbol: BootstrapSymbol::SymbolName.into(),
+                value: Some(AttributeValue::String(HEALTH_ENTITY_SYMBOL.to_string())),
+            },
+            AttributeToUpdate {
+                symbol: STARTED_AT.try_into()?,
+                value: Some(AttributeValue::Timestamp(started_at)),
+            },
+            AttributeToUpdate {
+                symbol: VERSION.try_into()?,
+                value: Some(AttributeValue::String(
+                    env!("CARGO_PKG_VERSION").to_string(),
+                )),
+            },
+            entity_count_update(store)?,
+        ],
+    )
+    .await
+}
+
+/// Creates the `@server` entity, recording when the server started and how it was built.
+pub async fn create_server_entity<T: ThreadSafeAttributeStore>(
+    store: &T,
+    started_at: SystemTime,
+) -> Result<(), AttributeStoreError> {
+    create_missing_attribute_types(
+        store,
+        &[
+            (
+                SERVER_STARTED_AT,
+                ValueType::Timestamp,
+                "When the server started",
+            ),
+            (
+                SERVER_VERSION,
+                ValueType::Text,
+                "Version of the server binary",
+            ),
+            (
+                SERVER_RUST_VERSION,
+                ValueType::Text,
+                "Version of the Rust compiler the server was built with",
+            ),
+        ],
+    )
+    .await?;
+
+    store
+        .update_entity(&UpdateEntityRequest {
+            entity_locator: EntityLocator::Symbol(Symbol::try_from(SERVER_ENTITY_SYMBOL)?),
+            attributes_to_update: vec![
+                AttributeToUpdate {
+                    symbol: BootstrapSymbol::SymbolName.into(),
+                    value: Some(AttributeValue::String(SERVER_ENTITY_SYMBOL.to_string())),
+                },
+                AttributeToUpdate {
+                    symbol: SERVER_STARTED_AT.try_into()?,
+                    value: Some(AttributeValue::Timestamp(started_at)),
+                },
+                AttributeToUpdate {
+                    symbol: SERVER_VERSION.try_into()?,
+                    value: Some(AttributeValue::String(
+                        env!("CARGO_PKG_VERSION").to_string(),
+                    )),
+                },
+                AttributeToUpdate {
+                    symbol: SERVER_RUST_VERSION.try_into()?,
+                    value: Some(AttributeValue::String(env!("RUSTC_VERSION").to_string())),
+                },
+            ],
+            dry_run: false,
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Updates the health entity's entity count every `interval`, until the server shuts down.
+pub async fn update_health_entity_periodically<T: ThreadSafeAttributeStore>(
+    store: Arc<T>,
+    interval: Duration,
+) {
+    let mut interval = tokio::time::interval(interval);
+    // The first tick completes immediately, and the entity count was set on creation
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if let Err(err) = refresh_entity_count(store.as_ref()).await {
+            warn!("Failed to update the health entity: {err:#}");
+        }
+    }
+}
+
+async fn refresh_entity_count<T: ThreadSafeAttributeStore>(
+    store: &T,
+) -> Result<(), AttributeStoreError> {
+    let attribute_to_update = entity_count_update(store)?;
+    update_health_entity(store, vec![attribute_to_update]).await
+}
+
+fn entity_count_update<T: ThreadSafeAttributeStore>(
+    store: &T,
+) -> Result<AttributeToUpdate, AttributeStoreError> {
+    let entity_count = i64::try_from(store.entity_count()).unwrap_or(i64::MAX);
+
+    Ok(AttributeToUpdate {
+        symbol: ENTITY_COUNT.try_into()?,
+        value: Some(AttributeValue::Int64(entity_count)),
+    })
+}
+
+async fn update_health_entity<T: ThreadSafeAttributeStore>(
+    store: &T,
+    attributes_to_update: Vec<AttributeToUpdate>,
+) -> Result<(), AttributeStoreError> {
+    store
+        .update_entity(&UpdateEntityRequest {
+            entity_locator: EntityLocator::Symbol(Symbol::try_from(HEALTH_ENTITY_SYMBOL)?),
+            attributes_to_update,
+            dry_run: false,
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use attribute_store::inmemory::InMemoryAttributeStore;
+    use parking_lot::Mutex;
+
+    #[tokio::test]
+    async fn health_entity_records_start_time_version_and_entity_count() {
+        let store = Mutex::new(InMemoryAttributeStore::new());
+        let started_at = SystemTime::now();
+        create_health_entity(&store, started_at).await.unwrap();
+        // Creating the entity again, as on a restart with a persistent store, updates it
+        create_health_entity(&store, started_at).await.unwrap();
+
+        let entity = store
+            .get_entity(&EntityLocator::Symbol(
+                Symbol::try_from(HEALTH_ENTITY_SYMBOL).unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            entity.attribute_value(&STARTED_AT.try_into().unwrap()),
+            Some(&AttributeValue::Timestamp(started_at))
+        );
+        assert_eq!(
+            entity.string_attribute(&VERSION.try_into().unwrap()),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(
+            entity.attribute_value(&ENTITY_COUNT.try_into().unwrap()),
+            Some(&AttributeValue::Int64(store.entity_count() as i64))
+        );
+    }
+
+    #[tokio::test]
+    async fn server_entity_records_server_metadata() {
+        let store = Mutex::new(InMemoryAttributeStore::new());
+        let started_at = SystemTime::now();
+        create_server_entity(&store, started_at).await.unwrap();
+        create_server_entity(&store, started_at).await.unwrap();
+
+        let entity = store
+            .get_entity(&EntityLocator::Symbol(
+                Symbol::try_from(SERVER_ENTITY_SYMBOL).unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            entity.attribute_value(&SERVER_STARTED_AT.try_into().unwrap()),
+            Some(&AttributeValue::Timestamp(started_at))
+        );
+        assert_eq!(
+            entity.string_attribute(&SERVER_VERSION.try_into().unwrap()),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+        assert!(entity
+            .string_attribute(&SERVER_RUST_VERSION.try_into().unwrap())
+            .is_some_and(|rust_version| rust_version.starts_with("rustc ")));
+    }
+}
@@ -1,21 +1,180 @@
 use crate::grpc::AttributeServer;
 use crate::pb::attribute_store_server;
-use attribute_store::inmemory::InMemoryAttributeStore;
+use crate::rate_limit::{RateLimitConfig, RateLimiter};
+use anyhow::format_err;
+use attribute_store::caching::CachingAttributeStore;
+use attribute_store::inmemory::{InMemoryAttributeStore, DEFAULT_WATCH_CHANNEL_CAPACITY};
+use attribute_store::store::SymbolPolicy;
+use clap::{Parser, ValueEnum};
+use futures::future::BoxFuture;
+use futures::FutureExt;
 use parking_lot::Mutex;
-use std::time::Duration;
+use std::net::SocketAddr;
+use std::num::{NonZeroU32, NonZeroU64, NonZeroUsize};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpSocket};
+use tonic::service::Routes;
+use tonic::transport::server::TcpIncoming;
 use tonic::transport::Server;
-use tracing::info;
 use tracing::level_filters::LevelFilter;
+use tracing::{info, warn};
+use tracing_error::ErrorLayer;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
 
+mod admin;
+mod audit;
+mod benchmark;
 mod convert;
+mod expression;
 mod grpc;
+mod health;
+mod metrics;
 mod pb {
+    #![allow(dead_code, clippy::enum_variant_names)]
     tonic::include_proto!("me.grahamdennis.attribute");
 }
 mod internal_pb {
     tonic::include_proto!("me.grahamdennis.attribute.internal");
 }
+mod rate_limit;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// TCP address to listen on.
+    #[arg(long, default_value = "[::1]:50051")]
+    address: SocketAddr,
+
+    /// Maximum number of pending TCP connections.
+    #[arg(long, default_value_t = 1024)]
+    backlog: u32,
+
+    /// Also listen on a Unix domain socket at this path. Requests over the socket are not rate
+    /// limited.
+    #[arg(long)]
+    unix_socket: Option<PathBuf>,
+
+    /// Maximum sustained requests per second from a single client IP. Unlimited if unset.
+    #[arg(long, value_parser = parse_positive_rate)]
+    rate_limit_requests_per_second: Option<f64>,
+
+    /// Maximum burst of requests from a single client IP. Defaults to one second's worth of requests.
+    #[arg(long, requires = "rate_limit_requests_per_second")]
+    rate_limit_burst: Option<NonZeroU32>,
+
+    /// Serve the audit log of store mutations as JSON at `GET /audit`.
+    #[arg(long)]
+    enable_audit_log: bool,
+
+    /// Number of the most recent mutations kept in the audit log.
+    #[arg(long, default_value_t = NonZeroUsize::new(10_000).unwrap(), requires = "enable_audit_log")]
+    audit_log_capacity: NonZeroUsize,
+
+    /// Serve Prometheus metrics at `GET /metrics`.
+    #[arg(long)]
+    enable_metrics: bool,
+
+    /// Serve the open watch streams as JSON at `GET /admin/streams`.
+    #[arg(long)]
+    enable_admin: bool,
+
+    /// Allow clients to measure query latency with the `BenchmarkQuery` endpoint.
+    #[arg(long)]
+    enable_benchmarks: bool,
+
+    /// Maximum number of entities the store may hold, including bootstrap entities. Unlimited if unset.
+    #[arg(long)]
+    max_entities: Option<usize>,
+
+    /// Number of watch events buffered for each subscriber. Slower subscribers miss events.
+    #[arg(long, default_value_t = NonZeroUsize::new(DEFAULT_WATCH_CHANNEL_CAPACITY).unwrap())]
+    watch_channel_capacity: NonZeroUsize,
+
+    /// Close `WatchEntities` streams with more than this many events waiting to be sent, asking
+    /// their clients to watch again. Must be below `--watch-channel-capacity`. If unset, streams
+    /// are closed once they miss events because the channel is full.
+    #[arg(long)]
+    watch_max_lag_events: Option<usize>,
+
+    /// Milliseconds to cache `QueryEntityRows` results for. Results are invalidated early by changes
+    /// to the entities they match. Caching is disabled if zero.
+    #[arg(long, default_value_t = 0)]
+    query_cache_ttl_ms: u64,
+
+    /// Characters allowed in the names of new symbols.
+    #[arg(long, value_enum, default_value_t = SymbolPolicyArg::Ascii)]
+    symbol_policy: SymbolPolicyArg,
+
+    /// Seconds between updates to the entity count of the `@server/health` entity.
+    #[arg(long, default_value_t = NonZeroU64::new(30).unwrap())]
+    health_entity_update_interval_s: NonZeroU64,
+
+    /// Seconds to wait for in-flight requests to complete after a shutdown signal.
+    #[arg(long, default_value_t = 30)]
+    shutdown_drain_timeout_s: u64,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SymbolPolicyArg {
+    /// Printable ASCII, excluding `\` and `"`
+    Ascii,
+    /// Printable Unicode, excluding `\` and `"`
+    Unicode,
+}
+
+impl From<SymbolPolicyArg> for SymbolPolicy {
+    fn from(value: SymbolPolicyArg) -> Self {
+        match value {
+            SymbolPolicyArg::Ascii => SymbolPolicy::ascii(),
+            SymbolPolicyArg::Unicode => SymbolPolicy::unicode(),
+        }
+    }
+}
+
+fn bind_tcp(addr: SocketAddr, backlog: u32) -> std::io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    socket.listen(backlog)
+}
+
+fn parse_positive_rate(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!("`{value}` is not a positive number")),
+    }
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -25,26 +184,159 @@ async fn main() -> anyhow::Result<()> {
                 .with_default_directive(LevelFilter::INFO.into())
                 .from_env_lossy(),
         )
+        .finish()
+        // Captures span traces in store errors
+        .with(ErrorLayer::default())
         .init();
 
-    let addr = "[::1]:50051".parse().unwrap();
+    grpc::install_panic_hook();
+
+    let args = Args::parse();
+    if let Some(watch_max_lag_events) = args.watch_max_lag_events {
+        if watch_max_lag_events >= args.watch_channel_capacity.get() {
+            return Err(format_err!(
+                "--watch-max-lag-events must be below --watch-channel-capacity ({})",
+                args.watch_channel_capacity
+            ));
+        }
+    }
+
+    let mut store = InMemoryAttributeStore::new()
+        .with_channel_capacity(args.watch_channel_capacity.get())
+        .with_symbol_policy(args.symbol_policy.into());
+    if let Some(max_entities) = args.max_entities {
+        store = store.with_max_entities(max_entities);
+    }
+    if args.enable_audit_log {
+        store = store.with_audit_log(args.audit_log_capacity);
+    }
+    let audit_log = store.audit_log();
+    let mut attribute_server = AttributeServer::new(CachingAttributeStore::new(
+        Mutex::new(store),
+        Duration::from_millis(args.query_cache_ttl_ms),
+    ))
+    .with_benchmarks_enabled(args.enable_benchmarks);
+    if let Some(watch_max_lag_events) = args.watch_max_lag_events {
+        attribute_server = attribute_server.with_watch_max_lag_events(watch_max_lag_events);
+    }
+    let store = attribute_server.store();
+    let stream_registry = attribute_server.stream_registry();
 
-    let attribute_server = AttributeServer::new(Mutex::new(InMemoryAttributeStore::new()));
+    let started_at = SystemTime::now();
+    health::create_health_entity(store.as_ref(), started_at).await?;
+    health::create_server_entity(store.as_ref(), started_at).await?;
+    tokio::spawn(health::update_health_entity_periodically(
+        store.clone(),
+        Duration::from_secs(args.health_entity_update_interval_s.get()),
+    ));
+
+    let rate_limiter = RateLimiter::new(args.rate_limit_requests_per_second.map(
+        |requests_per_second| RateLimitConfig {
+            requests_per_second,
+            burst: args.rate_limit_burst.unwrap_or(
+                NonZeroU32::new(requests_per_second.ceil() as u32).unwrap_or(NonZeroU32::MIN),
+            ),
+        },
+    ));
 
     let layer = tower::ServiceBuilder::new()
         // Apply middleware from tower
         .timeout(Duration::from_secs(30))
+        .layer(grpc::CatchPanicLayer)
         .into_inner();
 
-    info!("attribute-server listening on {}", addr);
+    info!("attribute-server listening on {}", args.address);
 
-    Server::builder()
-        .layer(layer)
-        .add_service(attribute_store_server::AttributeStoreServer::new(
+    let mut routes = Routes::new(
+        attribute_store_server::AttributeStoreServer::with_interceptor(
             attribute_server,
-        ))
-        .serve(addr)
-        .await?;
+            rate_limiter,
+        ),
+    );
+    if let Some(audit_log) = audit_log {
+        info!("Serving audit log at GET /audit");
+        routes = routes
+            .into_axum_router()
+            .merge(audit::audit_log_router(audit_log))
+            .into();
+    }
+    if args.enable_metrics {
+        info!("Serving metrics at GET /metrics");
+        routes = routes
+            .into_axum_router()
+            .merge(metrics::metrics_router(
+                store,
+                args.watch_channel_capacity.get(),
+            ))
+            .into();
+    }
+    if args.enable_admin {
+        info!("Serving open watch streams at GET /admin/streams");
+        routes = routes
+            .into_axum_router()
+            .merge(admin::admin_router(stream_registry))
+            .into();
+    }
+
+    let server = Server::builder()
+        .accept_http1(args.enable_audit_log || args.enable_metrics || args.enable_admin)
+        .layer(layer);
+    let shutdown = shutdown_signal().shared();
+
+    let tcp_incoming =
+        TcpIncoming::from_listener(bind_tcp(args.address, args.backlog)?, true, None)
+            .map_err(|err| format_err!(err))?;
+    let mut serves: Vec<BoxFuture<Result<(), tonic::transport::Error>>> = vec![server
+        .clone()
+        .add_routes(routes.clone())
+        .serve_with_incoming_shutdown(tcp_incoming, shutdown.clone())
+        .boxed()];
+    #[cfg(unix)]
+    if let Some(unix_socket) = &args.unix_socket {
+        info!("attribute-server listening on {}", unix_socket.display());
+        let unix_incoming = tokio_stream::wrappers::UnixListenerStream::new(
+            tokio::net::UnixListener::bind(unix_socket)?,
+        );
+        serves.push(
+            server
+                .clone()
+                .add_routes(routes.clone())
+                .serve_with_incoming_shutdown(unix_incoming, shutdown.clone())
+                .boxed(),
+        );
+    }
+    #[cfg(not(unix))]
+    if args.unix_socket.is_some() {
+        return Err(format_err!(
+            "Unix domain sockets are not supported on this platform"
+        ));
+    }
+    let mut serve = futures::future::try_join_all(serves);
+
+    tokio::select! {
+        result = &mut serve => {
+            result?;
+        }
+        _ = shutdown.clone() => {
+            info!("Shutting down, refusing new connections and draining in-flight requests");
+            let drain_timeout = Duration::from_secs(args.shutdown_drain_timeout_s);
+            info!("Waiting up to {:?} for in-flight requests", drain_timeout);
+            match tokio::time::timeout(drain_timeout, &mut serve).await {
+                Ok(result) => {
+                    result?;
+                }
+                Err(_) => warn!("Drain timeout elapsed, dropping remaining requests"),
+            }
+        }
+    }
+
+    if let Some(unix_socket) = &args.unix_socket {
+        if let Err(err) = std::fs::remove_file(unix_socket) {
+            warn!("Failed to remove {}: {err}", unix_socket.display());
+        }
+    }
+
+    info!("attribute-server shut down");
 
     Ok(())
 }
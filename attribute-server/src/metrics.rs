@@ -0,0 +1,49 @@
+use attribute_store::store::ThreadSafeAttributeStore;
+use axum::http::header;
+use axum::routing::get;
+use axum::Router;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Serves store metrics in the Prometheus text exposition format at `GET /metrics`.
+pub fn metrics_router<T: ThreadSafeAttributeStore>(
+    store: Arc<T>,
+    watch_channel_capacity: usize,
+) -> Router {
+    Router::new().route(
+        "/metrics",
+        get(move || {
+            let store = store.clone();
+            async move {
+                log::trace!("Received metrics request");
+                let mut body = String::new();
+                write_gauge(
+                    &mut body,
+                    "attribute_server_watch_channel_len",
+                    "Watch events not yet received by every subscriber.",
+                    store.watch_entities_channel_len(),
+                );
+                write_gauge(
+                    &mut body,
+                    "attribute_server_watch_channel_capacity",
+                    "Watch events buffered per subscriber before the oldest are dropped.",
+                    watch_channel_capacity,
+                );
+                write_gauge(
+                    &mut body,
+                    "attribute_store_memory_bytes",
+                    "Estimated bytes used by the store's entities and attribute types.",
+                    store.memory_usage_bytes(),
+                );
+                ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+            }
+        }),
+    )
+}
+
+fn write_gauge(body: &mut String, name: &str, help: &str, value: usize) {
+    // Writing to a String cannot fail
+    let _ = writeln!(body, "# HELP {name} {help}");
+    let _ = writeln!(body, "# TYPE {name} gauge");
+    let _ = writeln!(body, "{name} {value}");
+}
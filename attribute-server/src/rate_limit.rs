@@ -0,0 +1,202 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tonic::service::Interceptor;
+use tonic::{Code, Request, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+
+/// Per-client token bucket rate limiter.
+///
+/// Applied as a tonic interceptor, so it only runs when a call is started. Tokens are not consumed
+/// by messages on an already-established watch stream.
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: Option<RateLimitConfig>,
+    buckets: Arc<Mutex<TokenBuckets>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitConfig {
+    pub requests_per_second: f64,
+    pub burst: NonZeroU32,
+}
+
+struct TokenBuckets {
+    by_ip: HashMap<IpAddr, TokenBucket>,
+    last_pruned: Instant,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: Option<RateLimitConfig>) -> Self {
+        RateLimiter {
+            config,
+            buckets: Arc::new(Mutex::new(TokenBuckets {
+                by_ip: HashMap::new(),
+                last_pruned: Instant::now(),
+            })),
+        }
+    }
+
+    /// Takes a token for `ip`, or returns how long until one becomes available.
+    fn try_acquire(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let Some(RateLimitConfig {
+            requests_per_second,
+            burst,
+        }) = self.config
+        else {
+            return Ok(());
+        };
+        let burst = f64::from(burst.get());
+
+        let mut buckets = self.buckets.lock();
+        // A full bucket is the same as no bucket, so buckets that have refilled are dropped rather
+        // than kept for every client ever seen. Pruning at most once per refill period bounds its
+        // cost.
+        let refill_period = saturating_secs(burst / requests_per_second);
+        if !buckets.by_ip.contains_key(&ip)
+            && now.saturating_duration_since(buckets.last_pruned) >= refill_period
+        {
+            buckets.by_ip.retain(|_, bucket| {
+                let elapsed = now.saturating_duration_since(bucket.last_refill);
+                bucket.tokens + elapsed.as_secs_f64() * requests_per_second < burst
+            });
+            buckets.last_pruned = now;
+        }
+        let bucket = buckets.by_ip.entry(ip).or_insert(TokenBucket {
+            tokens: burst,
+            last_refill: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * requests_per_second).min(burst);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(saturating_secs((1.0 - bucket.tokens) / requests_per_second))
+        }
+    }
+}
+
+/// Converts seconds to a `Duration`, saturating the periods of very low rates that don't fit.
+fn saturating_secs(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+}
+
+impl Interceptor for RateLimiter {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(remote_addr) = request.remote_addr() else {
+            return Ok(request);
+        };
+
+        match self.try_acquire(remote_addr.ip(), Instant::now()) {
+            Ok(()) => Ok(request),
+            Err(retry_after) => {
+                log::warn!("Rate limit exceeded for {}", remote_addr.ip());
+                Err(Status::with_error_details(
+                    Code::ResourceExhausted,
+                    format!(
+                        "rate limit exceeded, retry after {}ms",
+                        retry_after.as_millis()
+                    ),
+                    ErrorDetails::with_retry_info(Some(retry_after)),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_requests_per_ip() {
+        let rate_limiter = RateLimiter::new(Some(RateLimitConfig {
+            requests_per_second: 1.0,
+            burst: NonZeroU32::new(2).unwrap(),
+        }));
+        let now = Instant::now();
+        let ip: IpAddr = "::1".parse().unwrap();
+        let other_ip: IpAddr = "127.0.0.1".parse().unwrap();
+
+        assert_eq!(rate_limiter.try_acquire(ip, now), Ok(()));
+        assert_eq!(rate_limiter.try_acquire(ip, now), Ok(()));
+        assert_eq!(
+            rate_limiter.try_acquire(ip, now),
+            Err(Duration::from_secs(1))
+        );
+        assert_eq!(rate_limiter.try_acquire(other_ip, now), Ok(()));
+        assert_eq!(
+            rate_limiter.try_acquire(ip, now + Duration::from_secs(1)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn drops_refilled_buckets() {
+        let rate_limiter = RateLimiter::new(Some(RateLimitConfig {
+            requests_per_second: 1.0,
+            burst: NonZeroU32::new(2).unwrap(),
+        }));
+        let now = Instant::now();
+        let ip: IpAddr = "::1".parse().unwrap();
+        let other_ip: IpAddr = "127.0.0.1".parse().unwrap();
+
+        assert_eq!(rate_limiter.try_acquire(ip, now), Ok(()));
+        for _ in 0..2 {
+            assert_eq!(
+                rate_limiter.try_acquire(other_ip, now + Duration::from_millis(500)),
+                Ok(())
+            );
+        }
+        assert_eq!(rate_limiter.buckets.lock().by_ip.len(), 2);
+
+        // `ip` has refilled by the time a new client arrives, but `other_ip` has not
+        let later = now + Duration::from_millis(2200);
+        assert_eq!(
+            rate_limiter.try_acquire("::2".parse().unwrap(), later),
+            Ok(())
+        );
+        let buckets = rate_limiter.buckets.lock();
+        assert!(!buckets.by_ip.contains_key(&ip));
+        assert!(buckets.by_ip.contains_key(&other_ip));
+    }
+
+    #[test]
+    fn tiny_rates_saturate_the_retry_delay() {
+        let rate_limiter = RateLimiter::new(Some(RateLimitConfig {
+            requests_per_second: 1e-20,
+            burst: NonZeroU32::new(1).unwrap(),
+        }));
+        let now = Instant::now();
+        let ip: IpAddr = "::1".parse().unwrap();
+
+        assert_eq!(rate_limiter.try_acquire(ip, now), Ok(()));
+        assert_eq!(rate_limiter.try_acquire(ip, now), Err(Duration::MAX));
+        assert_eq!(
+            rate_limiter.try_acquire("127.0.0.1".parse().unwrap(), now),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn unlimited_without_config() {
+        let rate_limiter = RateLimiter::new(None);
+        let ip: IpAddr = "::1".parse().unwrap();
+        for _ in 0..100 {
+            assert_eq!(rate_limiter.try_acquire(ip, Instant::now()), Ok(()));
+        }
+    }
+}
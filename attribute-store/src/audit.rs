@@ -0,0 +1,49 @@
+use crate::store::{AttributeValue, EntityLocator, Symbol};
+use std::collections::{HashMap, VecDeque};
+use std::num::NonZeroUsize;
+use std::time::SystemTime;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum AuditOperation {
+    CreateAttributeType,
+    UpdateEntity,
+}
+
+/// A successful mutation of the store.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct AuditEntry {
+    pub timestamp: SystemTime,
+    pub operation: AuditOperation,
+    pub entity_locator: EntityLocator,
+    /// The attributes of the entity after the mutation was applied
+    pub attributes: HashMap<Symbol, AttributeValue>,
+}
+
+/// The most recent mutations of the store, up to a fixed number of entries.
+#[derive(Debug)]
+pub struct AuditLog {
+    entries: VecDeque<AuditEntry>,
+    capacity: NonZeroUsize,
+}
+
+impl AuditLog {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        AuditLog {
+            entries: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Appends `entry`, dropping the oldest entry if the log is full.
+    pub fn push(&mut self, entry: AuditEntry) {
+        if self.entries.len() == self.capacity.get() {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// The entries, oldest first.
+    pub fn entries(&self) -> impl ExactSizeIterator<Item = &AuditEntry> {
+        self.entries.iter()
+    }
+}
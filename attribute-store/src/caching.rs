@@ -0,0 +1,407 @@
+use crate::store::{
+    AttributeStoreError, AttributeToUpdate, AttributeType, BatchGetEntitiesResult,
+    CreateAttributeTypeRequest, CreateAttributeTypesResult, Entity, EntityId, EntityLocator,
+    EntityQuery, EntityQueryResult, EntityRowQuery, EntityRowQueryResult, EntityVersion, Symbol,
+    ThreadSafeAttributeStore, UpdateEntityRequest, WatchEntitiesEvent,
+};
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::broadcast::Receiver;
+
+/// Caches `query_entity_rows` results of the wrapped store for up to `ttl`.
+///
+/// Cached results are invalidated by the watch events of entities they match, before or after the
+/// change, so a cached result is never older than the last change to the entities it covers. Its
+/// `entity_version` may be older than the store's current version.
+pub struct CachingAttributeStore<T> {
+    store: T,
+    ttl: Duration,
+    /// `None` if caching is disabled, so that no watch events are buffered for the cache
+    cache: Option<Mutex<QueryCache>>,
+}
+
+struct QueryCache {
+    entries: HashMap<EntityRowQuery, (EntityRowQueryResult, Instant)>,
+    watch_entities_receiver: Receiver<WatchEntitiesEvent>,
+    /// Version of the latest change applied to the cache, or that may have been missed
+    latest_entity_version: Option<EntityVersion>,
+}
+
+impl QueryCache {
+    /// Drops the entries affected by the watch events received since the last call.
+    fn apply_watch_events(&mut self, store: &impl ThreadSafeAttributeStore) {
+        loop {
+            match self.watch_entities_receiver.try_recv() {
+                Ok(event) => {
+                    self.entries.retain(|entity_row_query, _| {
+                        let root = &entity_row_query.root;
+                        let affected = event.before.iter().any(|entity| root.matches(entity))
+                            || event.after.iter().any(|entity| root.matches(entity));
+                        !affected
+                    });
+                    self.latest_entity_version =
+                        self.latest_entity_version.max(Some(event.entity_version));
+                }
+                Err(TryRecvError::Lagged(missed_events)) => {
+                    log::debug!("Missed {missed_events} watch events, clearing query cache");
+                    self.entries.clear();
+                    // The missed events are no newer than the store's current version
+                    self.latest_entity_version = Some(store.current_entity_version());
+                }
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return,
+            }
+        }
+    }
+}
+
+impl<T: ThreadSafeAttributeStore> CachingAttributeStore<T> {
+    /// Caching is disabled if `ttl` is zero.
+    pub fn new(store: T, ttl: Duration) -> Self {
+        let cache = (!ttl.is_zero()).then(|| {
+            Mutex::new(QueryCache {
+                entries: HashMap::new(),
+                watch_entities_receiver: store.watch_entities_receiver(),
+                latest_entity_version: None,
+            })
+        });
+        CachingAttributeStore { store, ttl, cache }
+    }
+
+    fn cached_query_entity_rows(
+        &self,
+        cache: &Mutex<QueryCache>,
+        entity_row_query: &EntityRowQuery,
+    ) -> Option<EntityRowQueryResult> {
+        let mut cache = cache.lock();
+        cache.apply_watch_events(&self.store);
+        let ttl = self.ttl;
+        cache
+            .entries
+            .retain(|_, (_, cached_at)| cached_at.elapsed() < ttl);
+
+        cache
+            .entries
+            .get(entity_row_query)
+            .map(|(entity_row_query_result, _)| entity_row_query_result.clone())
+    }
+}
+
+#[async_trait]
+impl<T: ThreadSafeAttributeStore> ThreadSafeAttributeStore for CachingAttributeStore<T> {
+    async fn create_attribute_type(
+        &self,
+        create_attribute_type_request: &CreateAttributeTypeRequest,
+    ) -> Result<Entity, AttributeStoreError> {
+        self.store
+            .create_attribute_type(create_attribute_type_request)
+            .await
+    }
+
+    async fn create_attribute_types(
+        &self,
+        attribute_types: &[AttributeType],
+    ) -> Result<CreateAttributeTypesResult, AttributeStoreError> {
+        self.store.create_attribute_types(attribute_types).await
+    }
+
+    async fn get_attribute_type(
+        &self,
+        symbol: &Symbol,
+    ) -> Result<AttributeType, AttributeStoreError> {
+        self.store.get_attribute_type(symbol).await
+    }
+
+    async fn get_entity(
+        &self,
+        entity_locator: &EntityLocator,
+    ) -> Result<Entity, AttributeStoreError> {
+        self.store.get_entity(entity_locator).await
+    }
+
+    async fn batch_get_entities(
+        &self,
+        entity_locators: &[EntityLocator],
+    ) -> Result<BatchGetEntitiesResult, AttributeStoreError> {
+        self.store.batch_get_entities(entity_locators).await
+    }
+
+    async fn query_entities(
+        &self,
+        entity_query: &EntityQuery,
+    ) -> Result<EntityQueryResult, AttributeStoreError> {
+        self.store.query_entities(entity_query).await
+    }
+
+    async fn query_entity_rows(
+        &self,
+        entity_row_query: &EntityRowQuery,
+    ) -> Result<EntityRowQueryResult, AttributeStoreError> {
+        // Results of graph traversals can change without any entity they match changing
+        let Some(cache) = &self.cache else {
+            return self.store.query_entity_rows(entity_row_query).await;
+        };
+        if entity_row_query.root.has_graph_traversal() {
+            return self.store.query_entity_rows(entity_row_query).await;
+        }
+        if let Some(entity_row_query_result) =
+            self.cached_query_entity_rows(cache, entity_row_query)
+        {
+            log::trace!("Query cache hit");
+            return Ok(entity_row_query_result);
+        }
+
+        let cached_at = Instant::now();
+        let entity_row_query_result = self.store.query_entity_rows(entity_row_query).await?;
+
+        // Changes committed after the query ran either have an event still to be applied, which
+        // invalidates this entry, or have already been applied, in which case the result is not
+        // cached because it may be stale.
+        let mut cache = cache.lock();
+        cache.apply_watch_events(&self.store);
+        if cache.latest_entity_version <= Some(entity_row_query_result.entity_version) {
+            cache.entries.insert(
+                entity_row_query.clone(),
+                (entity_row_query_result.clone(), cached_at),
+            );
+        }
+
+        Ok(entity_row_query_result)
+    }
+
+    async fn query_entity_rows_page(
+        &self,
+        entity_row_query: &EntityRowQuery,
+        after: Option<EntityId>,
+        limit: usize,
+    ) -> Result<EntityRowQueryResult, AttributeStoreError> {
+        self.store
+            .query_entity_rows_page(entity_row_query, after, limit)
+            .await
+    }
+
+    async fn validate_update_entity(
+        &self,
+        update_entity_request: &UpdateEntityRequest,
+    ) -> Result<(), AttributeStoreError> {
+        self.store
+            .validate_update_entity(update_entity_request)
+            .await
+    }
+
+    async fn update_entity(
+        &self,
+        update_entity_request: &UpdateEntityRequest,
+    ) -> Result<Entity, AttributeStoreError> {
+        self.store.update_entity(update_entity_request).await
+    }
+
+    async fn merge_entities(
+        &self,
+        source: &EntityLocator,
+        target: &EntityLocator,
+        attribute_types: &[Symbol],
+    ) -> Result<Entity, AttributeStoreError> {
+        self.store
+            .merge_entities(source, target, attribute_types)
+            .await
+    }
+
+    async fn atomic_query_and_update<F, E>(
+        &self,
+        entity_locator: &EntityLocator,
+        f: F,
+    ) -> Result<Entity, E>
+    where
+        F: FnOnce(&Entity) -> Result<Vec<AttributeToUpdate>, E> + Send,
+        E: From<AttributeStoreError> + std::fmt::Display + Send,
+    {
+        self.store.atomic_query_and_update(entity_locator, f).await
+    }
+
+    fn watch_entities_receiver(&self) -> Receiver<WatchEntitiesEvent> {
+        self.store.watch_entities_receiver()
+    }
+
+    fn current_entity_version(&self) -> EntityVersion {
+        self.store.current_entity_version()
+    }
+
+    fn entity_count(&self) -> usize {
+        self.store.entity_count()
+    }
+
+    fn watch_entities_channel_len(&self) -> usize {
+        self.store.watch_entities_channel_len()
+    }
+
+    fn memory_usage_bytes(&self) -> usize {
+        self.store.memory_usage_bytes()
+    }
+
+    fn watch_entity_receiver(&self, entity_id: EntityId) -> Receiver<WatchEntitiesEvent> {
+        self.store.watch_entity_receiver(entity_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inmemory::InMemoryAttributeStore;
+    use crate::store::{
+        AttributeToUpdate, AttributeValue, BootstrapSymbol, EntityQueryNode, HasAttributeTypesNode,
+    };
+
+    fn update_symbol_request(name: &str, description: Option<&str>) -> UpdateEntityRequest {
+        let mut attributes_to_update = vec![AttributeToUpdate {
+            symbol: BootstrapSymbol::SymbolName.into(),
+            value: Some(AttributeValue::String(name.into())),
+        }];
+        attributes_to_update.extend(description.map(|description| AttributeToUpdate {
+            symbol: BootstrapSymbol::Description.into(),
+            value: Some(AttributeValue::String(description.into())),
+        }));
+        UpdateEntityRequest {
+            entity_locator: EntityLocator::Symbol(Symbol::try_from(name.to_string()).unwrap()),
+            attributes_to_update,
+            dry_run: false,
+        }
+    }
+
+    fn described_entities_query() -> EntityRowQuery {
+        EntityRowQuery {
+            root: EntityQueryNode::HasAttributeTypes(HasAttributeTypesNode {
+                attribute_types: vec![BootstrapSymbol::Description.into()],
+            }),
+            attribute_types: vec![BootstrapSymbol::Description.into()],
+            include_deleted: false,
+            filter_nulls: false,
+            filter_rows_where_null: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn unrelated_updates_do_not_invalidate_cached_results() {
+        let store = CachingAttributeStore::new(
+            parking_lot::Mutex::new(InMemoryAttributeStore::new()),
+            Duration::from_secs(60),
+        );
+        let cached = store
+            .query_entity_rows(&described_entities_query())
+            .await
+            .unwrap();
+
+        store
+            .update_entity(&update_symbol_request("undescribed", None))
+            .await
+            .unwrap();
+
+        // A fresh query would report the store's new entity version
+        assert_eq!(
+            store
+                .query_entity_rows(&described_entities_query())
+                .await
+                .unwrap(),
+            cached
+        );
+    }
+
+    #[tokio::test]
+    async fn updates_to_matching_entities_invalidate_cached_results() {
+        let store = CachingAttributeStore::new(
+            parking_lot::Mutex::new(InMemoryAttributeStore::new()),
+            Duration::from_secs(60),
+        );
+        let cached = store
+            .query_entity_rows(&described_entities_query())
+            .await
+            .unwrap();
+
+        store
+            .update_entity(&update_symbol_request("described", Some("description")))
+            .await
+            .unwrap();
+
+        let refreshed = store
+            .query_entity_rows(&described_entities_query())
+            .await
+            .unwrap();
+        assert_eq!(refreshed.entity_rows.len(), cached.entity_rows.len() + 1);
+    }
+
+    #[tokio::test]
+    async fn cached_results_expire_after_ttl() {
+        let store = CachingAttributeStore::new(
+            parking_lot::Mutex::new(InMemoryAttributeStore::new()),
+            Duration::from_millis(10),
+        );
+        let cached = store
+            .query_entity_rows(&described_entities_query())
+            .await
+            .unwrap();
+        store
+            .update_entity(&update_symbol_request("undescribed", None))
+            .await
+            .unwrap();
+
+        std::thread::sleep(Duration::from_millis(20));
+
+        let refreshed = store
+            .query_entity_rows(&described_entities_query())
+            .await
+            .unwrap();
+        assert!(refreshed.entity_version > cached.entity_version);
+    }
+
+    #[tokio::test]
+    async fn missed_watch_events_clear_the_cache_without_disabling_it() {
+        let store = CachingAttributeStore::new(
+            parking_lot::Mutex::new(InMemoryAttributeStore::new().with_channel_capacity(1)),
+            Duration::from_secs(60),
+        );
+        store
+            .query_entity_rows(&described_entities_query())
+            .await
+            .unwrap();
+        for name in ["first", "second", "third"] {
+            store
+                .update_entity(&update_symbol_request(name, Some("description")))
+                .await
+                .unwrap();
+        }
+
+        let refreshed = store
+            .query_entity_rows(&described_entities_query())
+            .await
+            .unwrap();
+        assert_eq!(refreshed.entity_version, store.current_entity_version());
+
+        store
+            .update_entity(&update_symbol_request("undescribed", None))
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .query_entity_rows(&described_entities_query())
+                .await
+                .unwrap(),
+            refreshed
+        );
+    }
+
+    #[tokio::test]
+    async fn disabled_cache_does_not_subscribe_to_watch_events() {
+        let store = CachingAttributeStore::new(
+            parking_lot::Mutex::new(InMemoryAttributeStore::new()),
+            Duration::ZERO,
+        );
+        store
+            .update_entity(&update_symbol_request("undescribed", None))
+            .await
+            .unwrap();
+
+        assert_eq!(store.watch_entities_channel_len(), 0);
+    }
+}
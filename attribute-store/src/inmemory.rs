@@ -1,24 +1,53 @@
-use crate::store::AttributeStoreErrorKind::AttributeTypeAlreadyExists;
+#[cfg(feature = "audit-log")]
+use crate::audit::{AuditEntry, AuditLog, AuditOperation};
+use crate::store::AttributeStoreErrorKind::{AttributeTypeAlreadyExists, UnsupportedValueType};
 use crate::store::{
-    AttributeStore, AttributeStoreError, AttributeStoreErrorKind, AttributeToUpdate,
-    AttributeTypes, AttributeValue, BootstrapSymbol, CreateAttributeTypeRequest, Entity, EntityId,
-    EntityLocator, EntityQuery, EntityQueryResult, EntityRowQuery, EntityRowQueryResult,
-    EntityVersion, Symbol, UpdateEntityRequest, ValueType, WatchEntitiesEvent,
+    AndQueryNode, AttributeStore, AttributeStoreError, AttributeStoreErrorKind, AttributeToUpdate,
+    AttributeType, AttributeTypes, AttributeValue, BatchGetEntitiesResult, BootstrapSymbol,
+    CreateAttributeTypeRequest, CreateAttributeTypesResult, Entity, EntityId, EntityIdInNode,
+    EntityLocator, EntityQuery, EntityQueryNode, EntityQueryResult, EntityRowQuery,
+    EntityRowQueryResult, EntityVersion, GraphTraverseNode, HasAttributeValueNode, OrQueryNode,
+    Symbol, SymbolPolicy, SymbolRef, UpdateEntityRequest, ValueType, WatchEntitiesEvent,
 };
 use garde::Unvalidated;
-use std::collections::HashMap;
+#[cfg(feature = "audit-log")]
+use parking_lot::Mutex;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "audit-log")]
+use std::num::NonZeroUsize;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::SendError;
 use tokio::sync::broadcast::{Receiver, Sender};
 use tracing::Level;
 
+/// Number of watch events buffered for each subscriber unless configured otherwise
+pub const DEFAULT_WATCH_CHANNEL_CAPACITY: usize = 16;
+
+type AttributeValueIndex = HashMap<(Symbol, AttributeValue), HashSet<EntityId>>;
+
 #[derive(Debug)]
 pub struct InMemoryAttributeStore {
     attribute_types: AttributeTypes,
     entities: Vec<Entity>,
+    /// IDs of the entities holding each attribute value, for `HasAttributeValue` queries
+    attribute_value_index: AttributeValueIndex,
     watch_entities_channel: Sender<WatchEntitiesEvent>,
+    /// Channels of the entities watched individually. A channel is dropped once it has no
+    /// receivers, when its entity next changes or another entity is first watched.
+    entity_watch_channels: HashMap<EntityId, Sender<WatchEntitiesEvent>>,
+    watch_channel_capacity: usize,
     // entity version, transaction ID or store version?
     entity_version_sequence: std::ops::RangeFrom<i64>,
+    /// Maximum number of entities, including bootstrap entities. Unlimited if `None`.
+    max_entities: Option<usize>,
+    /// Names accepted for new attribute types and for entities created by symbol
+    symbol_policy: SymbolPolicy,
+    #[cfg(feature = "audit-log")]
+    /// Recent mutations, if enabled with `with_audit_log`
+    audit_log: Option<Arc<Mutex<AuditLog>>>,
 }
 
 impl InMemoryAttributeStore {
@@ -30,12 +59,232 @@ impl InMemoryAttributeStore {
             assert_eq!(usize::try_from(database_id).unwrap(), idx);
         }
 
+        let attribute_types = Self::bootstrap_attribute_types(&entities);
+        let mut attribute_value_index = AttributeValueIndex::new();
+        for entity in &entities {
+            Self::index_attribute_values(&mut attribute_value_index, entity);
+        }
+        let (tx, _) = broadcast::channel(DEFAULT_WATCH_CHANNEL_CAPACITY);
+        InMemoryAttributeStore {
+            attribute_types,
+            entities,
+            attribute_value_index,
+            watch_entities_channel: tx,
+            entity_watch_channels: HashMap::new(),
+            watch_channel_capacity: DEFAULT_WATCH_CHANNEL_CAPACITY,
+            entity_version_sequence: 0..,
+            max_entities: None,
+            symbol_policy: SymbolPolicy::ascii(),
+            #[cfg(feature = "audit-log")]
+            audit_log: None,
+        }
+    }
+
+    /// Gives the store's next change entity version `start`, so that a store restored from
+    /// persisted data doesn't reuse versions that data already holds. A `start` at or below the
+    /// current entity version is ignored, as those versions are already in use.
+    pub fn with_starting_version(self, start: i64) -> Self {
+        let current_version = self.current_entity_version().0;
+        let previous_version = start
+            .checked_sub(1)
+            .map_or(current_version, |version| version.max(current_version));
+        InMemoryAttributeStore {
+            entity_version_sequence: previous_version..,
+            ..self
+        }
+    }
+
+    /// Creates a store holding `entities`, for concise test setup. Attribute types that aren't
+    /// already registered are created with the value type of their first value.
+    ///
+    /// Entities are inserted as given, so a `@symbolName` shared by several entities is not merged.
+    pub fn with_entities_preloaded(
+        entities: Vec<Vec<(Symbol, AttributeValue)>>,
+    ) -> Result<Self, AttributeStoreError> {
+        let mut store = Self::new();
+
+        for (symbol, value) in entities.iter().flatten() {
+            // The element type of an empty list is unknown, so it is left to a later value
+            let Some(value_type) = value.value_type() else {
+                continue;
+            };
+            if store.attribute_types.contains_key(symbol) {
+                continue;
+            }
+            store.create_attribute_type(&CreateAttributeTypeRequest {
+                attribute_type: AttributeType {
+                    symbol: symbol.clone(),
+                    value_type,
+                    description: None,
+                },
+            })?;
+        }
+
+        for attributes in entities {
+            let attributes_to_update: Vec<AttributeToUpdate> = attributes
+                .into_iter()
+                .map(|(symbol, value)| AttributeToUpdate {
+                    symbol,
+                    value: Some(value),
+                })
+                .collect();
+            let validated_attributes_to_update =
+                Unvalidated::from(attributes_to_update).validate_with(&store.attribute_types)?;
+            store.validate_symbol_names(&validated_attributes_to_update)?;
+            store.insert_new_entity_with_attributes(Self::attributes_for_new_entity(
+                &validated_attributes_to_update,
+            ))?;
+        }
+
+        Ok(store)
+    }
+
+    /// Rejects new entities once the store holds `max_entities` entities.
+    pub fn with_max_entities(self, max_entities: usize) -> Self {
+        InMemoryAttributeStore {
+            max_entities: Some(max_entities),
+            ..self
+        }
+    }
+
+    /// Restricts the names of new attribute types and of entities created by symbol. Defaults to
+    /// [`SymbolPolicy::ascii`].
+    pub fn with_symbol_policy(self, symbol_policy: SymbolPolicy) -> Self {
+        InMemoryAttributeStore {
+            symbol_policy,
+            ..self
+        }
+    }
+
+    /// Buffers up to `capacity` watch events for each subscriber. Subscribers that fall further
+    /// behind miss the oldest events.
+    ///
+    /// Panics if `capacity` is zero, and must be called before any subscribers are created.
+    pub fn with_channel_capacity(self, capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        InMemoryAttributeStore {
+            watch_entities_channel: tx,
+            watch_channel_capacity: capacity,
+            ..self
+        }
+    }
+
+    fn send_watch_entities_event(
+        watch_entities_channel: &Sender<WatchEntitiesEvent>,
+        entity_watch_channels: &mut HashMap<EntityId, Sender<WatchEntitiesEvent>>,
+        event: WatchEntitiesEvent,
+    ) {
+        let entity_id = event
+            .after
+            .as_ref()
+            .or(event.before.as_ref())
+            .map(|entity| entity.entity_id);
+        if let Some(Entry::Occupied(entity_watch_channel)) =
+            entity_id.map(|entity_id| entity_watch_channels.entry(entity_id))
+        {
+            if entity_watch_channel.get().send(event.clone()).is_err() {
+                // Every receiver has been dropped
+                entity_watch_channel.remove();
+            }
+        }
+
+        if let Err(SendError(event)) = watch_entities_channel.send(event) {
+            log::trace!(
+                "No watch subscribers for event at entity version {:?}",
+                event.entity_version
+            );
+        }
+    }
+
+    /// Marks the entity as deleted by setting `@deletedAt` to the current time, which hides it from
+    /// queries that don't include deleted entities. An entity that is already deleted keeps its
+    /// original `@deletedAt`.
+    pub fn soft_delete_entity(
+        &mut self,
+        entity_locator: &EntityLocator,
+    ) -> Result<Entity, AttributeStoreError> {
+        let entity = self.get_entity(entity_locator)?;
+        if entity.is_deleted() {
+            return Ok(entity);
+        }
+
+        let deleted_at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        self.update_deleted_at(
+            entity.entity_id,
+            Some(AttributeValue::String(deleted_at_ms.to_string())),
+        )
+    }
+
+    /// Removes `@deletedAt` from a soft-deleted entity.
+    pub fn restore_entity(
+        &mut self,
+        entity_locator: &EntityLocator,
+    ) -> Result<Entity, AttributeStoreError> {
+        let entity = self.get_entity(entity_locator)?;
+        self.update_deleted_at(entity.entity_id, None)
+    }
+
+    fn update_deleted_at(
+        &mut self,
+        entity_id: EntityId,
+        deleted_at: Option<AttributeValue>,
+    ) -> Result<Entity, AttributeStoreError> {
+        self.update_entity(&UpdateEntityRequest {
+            entity_locator: EntityLocator::EntityId(entity_id),
+            attributes_to_update: vec![AttributeToUpdate {
+                symbol: BootstrapSymbol::DeletedAt.into(),
+                value: deleted_at,
+            }],
+            dry_run: false,
+        })
+    }
+
+    /// Records the most recent `capacity` mutations in an audit log.
+    #[cfg(feature = "audit-log")]
+    pub fn with_audit_log(self, capacity: NonZeroUsize) -> Self {
+        InMemoryAttributeStore {
+            audit_log: Some(Arc::new(Mutex::new(AuditLog::new(capacity)))),
+            ..self
+        }
+    }
+
+    #[cfg(feature = "audit-log")]
+    pub fn audit_log(&self) -> Option<Arc<Mutex<AuditLog>>> {
+        self.audit_log.clone()
+    }
+
+    #[cfg(feature = "audit-log")]
+    fn record_audit_entry(
+        &self,
+        operation: AuditOperation,
+        entity_locator: EntityLocator,
+        entity: &Entity,
+    ) {
+        let Some(audit_log) = &self.audit_log else {
+            return;
+        };
+        audit_log.lock().push(AuditEntry {
+            timestamp: std::time::SystemTime::now(),
+            operation,
+            entity_locator,
+            attributes: entity.attributes.clone(),
+        });
+    }
+
+    /// Builds the attribute type map from bootstrap entities.
+    ///
+    /// Panics if any bootstrap entity with a `@valueType` is malformed, as that would leave the
+    /// attribute type unvalidated.
+    fn bootstrap_attribute_types(entities: &[Entity]) -> AttributeTypes {
         let value_type_symbol: Symbol = BootstrapSymbol::ValueType.into();
         let symbol_name_symbol: Symbol = BootstrapSymbol::SymbolName.into();
 
-        let attribute_types = entities
+        entities
             .iter()
-            .filter(|entity| entity.attributes.get(&value_type_symbol).is_some())
+            .filter(|entity| entity.attributes.contains_key(&value_type_symbol))
             .map(|entity| {
                 match (
                     entity.attributes.get(&symbol_name_symbol),
@@ -44,33 +293,99 @@ impl InMemoryAttributeStore {
                     (
                         Some(AttributeValue::String(symbol_name)),
                         Some(AttributeValue::EntityId(value_type_entity_id)),
-                    ) => (
-                        Symbol::try_from(symbol_name.clone()).ok(),
-                        ValueType::try_from(*value_type_entity_id).ok(),
-                    ),
-                    _ => (None, None),
+                    ) => {
+                        let symbol = Symbol::try_from(symbol_name.clone()).unwrap_or_else(|err| {
+                            panic!("invalid bootstrap symbol name {symbol_name:?}: {err:#}")
+                        });
+                        let value_type =
+                            ValueType::try_from(*value_type_entity_id).unwrap_or_else(|err| {
+                                panic!("invalid value type for bootstrap symbol {symbol_name:?}: {err:#}")
+                            });
+                        (symbol, value_type)
+                    }
+                    _ => panic!("malformed bootstrap attribute type entity: {entity:?}"),
                 }
             })
-            .flat_map(|entry| match entry {
-                (Some(key), Some(value)) => Some((key, value)),
-                _ => None,
-            })
-            .collect();
-        let (tx, _) = broadcast::channel(16);
-        InMemoryAttributeStore {
-            attribute_types,
-            entities,
-            watch_entities_channel: tx,
-            entity_version_sequence: 0..,
-        }
+            .collect()
     }
 
-    fn current_entity_version(&self) -> EntityVersion {
-        EntityVersion(self.entity_version_sequence.start)
+    fn next_entity_version(&mut self) -> EntityVersion {
+        Self::advance_entity_version(&mut self.entity_version_sequence)
     }
 
-    fn next_entity_version(&mut self) -> EntityVersion {
-        EntityVersion(self.entity_version_sequence.next().unwrap() + 1)
+    /// Advances the sequence so that `current_entity_version` is the returned version.
+    fn advance_entity_version(
+        entity_version_sequence: &mut std::ops::RangeFrom<i64>,
+    ) -> EntityVersion {
+        EntityVersion(entity_version_sequence.next().unwrap() + 1)
+    }
+
+    /// Finds the index of the entity an update applies to, or `None` if the update creates it.
+    fn locate_entity_for_update(
+        &self,
+        entity_locator: &EntityLocator,
+        attributes_to_update: &[AttributeToUpdate],
+    ) -> Result<Option<usize>, AttributeStoreError> {
+        use AttributeStoreErrorKind::*;
+
+        match entity_locator {
+            EntityLocator::EntityId(entity_id) => {
+                let index = usize::try_from(*entity_id)?;
+                if index >= self.entities.len() {
+                    return Err(EntityNotFound(entity_locator.clone()))?;
+                }
+                Ok(Some(index))
+            }
+            EntityLocator::Symbol(symbol) => {
+                self.symbol_policy.validate(symbol)?;
+                let symbol_name_symbol: Symbol = BootstrapSymbol::SymbolName.into();
+                let expected_attribute_value = AttributeValue::String(symbol.as_str().into());
+                let index = self.entities.iter().position(|entity| {
+                    entity
+                        .attributes
+                        .get(&symbol_name_symbol)
+                        .is_some_and(|attribute_value| {
+                            attribute_value.eq(&expected_attribute_value)
+                        })
+                });
+                if index.is_none() {
+                    let expected_symbol_attribute = AttributeToUpdate {
+                        symbol: symbol_name_symbol,
+                        value: Some(expected_attribute_value),
+                    };
+                    if !attributes_to_update.contains(&expected_symbol_attribute) {
+                        return Err(UpdateNotIdempotent {
+                            missing_attribute_to_update: expected_symbol_attribute,
+                            entity_locator: entity_locator.clone(),
+                        })?;
+                    }
+                }
+                Ok(index)
+            }
+            EntityLocator::CreateNew => Ok(None),
+        }
+    }
+
+    /// Checks the `@symbolName` values being set against the symbol policy, however the entity is
+    /// located.
+    fn validate_symbol_names(
+        &self,
+        attributes_to_update: &[AttributeToUpdate],
+    ) -> Result<(), AttributeStoreError> {
+        use AttributeStoreErrorKind::*;
+
+        let symbol_name_symbol: Symbol = BootstrapSymbol::SymbolName.into();
+        for attribute_to_update in attributes_to_update {
+            if attribute_to_update.symbol != symbol_name_symbol {
+                continue;
+            }
+            if let Some(AttributeValue::String(name)) = &attribute_to_update.value {
+                if !self.symbol_policy.is_valid(name) {
+                    Err(InvalidSymbolName(name.clone().into()))?
+                }
+            }
+        }
+        Ok(())
     }
 
     fn bootstrap_entities() -> Vec<Entity> {
@@ -81,34 +396,83 @@ impl InMemoryAttributeStore {
             BootstrapSymbol::ValueTypeEnum(ValueType::Text).into(),
             BootstrapSymbol::ValueTypeEnum(ValueType::EntityReference).into(),
             BootstrapSymbol::ValueTypeEnum(ValueType::Bytes).into(),
+            BootstrapSymbol::Description.into(),
+            BootstrapSymbol::DeletedAt.into(),
+            BootstrapSymbol::ValueTypeEnum(ValueType::Timestamp).into(),
+            BootstrapSymbol::ValueTypeEnum(ValueType::List(Box::new(ValueType::Text))).into(),
+            BootstrapSymbol::ValueTypeEnum(ValueType::List(Box::new(ValueType::EntityReference)))
+                .into(),
+            BootstrapSymbol::ValueTypeEnum(ValueType::List(Box::new(ValueType::Bytes))).into(),
+            BootstrapSymbol::ValueTypeEnum(ValueType::List(Box::new(ValueType::Timestamp))).into(),
+            BootstrapSymbol::ValueTypeEnum(ValueType::Int64).into(),
+            BootstrapSymbol::ValueTypeEnum(ValueType::List(Box::new(ValueType::Int64))).into(),
         ]
     }
 
-    fn insert_new_entity_with_attributes(
-        &mut self,
+    /// Checks that `attribute_type` may be created, returning the entity ID of its value type.
+    fn validate_new_attribute_type(
+        &self,
+        attribute_type: &AttributeType,
+    ) -> Result<EntityId, AttributeStoreError> {
+        self.symbol_policy.validate(&attribute_type.symbol)?;
+
+        Ok(attribute_type
+            .value_type
+            .entity_id()
+            .ok_or_else(|| UnsupportedValueType(attribute_type.value_type.clone()))?)
+    }
+
+    fn new_entity_with_attributes(
+        &self,
         attributes: HashMap<Symbol, AttributeValue>,
+        entity_version: EntityVersion,
     ) -> Result<Entity, AttributeStoreError> {
         use AttributeStoreErrorKind::*;
 
         let database_id = self.entities.len();
-        let entity = Entity {
+        Ok(Entity {
             entity_id: EntityId(i64::try_from(database_id).map_err(|err| Other {
                 message: format!(
                     "Failed to convert database id `{database_id}` to EntityId due to error `{err:?}`"
                 ),
                 source: err.into(),
             })?),
-            entity_version: self.next_entity_version(),
+            entity_version,
             attributes,
-        };
+        })
+    }
+
+    /// Rejects adding `new_entities` entities if that would exceed `max_entities`.
+    fn check_capacity_for(&self, new_entities: usize) -> Result<(), AttributeStoreError> {
+        if let Some(limit) = self.max_entities {
+            if self.entities.len() + new_entities > limit {
+                return Err(AttributeStoreErrorKind::StoreFull { limit })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_new_entity_with_attributes(
+        &mut self,
+        attributes: HashMap<Symbol, AttributeValue>,
+    ) -> Result<Entity, AttributeStoreError> {
+        self.check_capacity_for(1)?;
+
+        let entity_version = self.next_entity_version();
+        let entity = self.new_entity_with_attributes(attributes, entity_version)?;
 
         self.entities.push(entity.clone());
+        Self::index_attribute_values(&mut self.attribute_value_index, &entity);
 
-        let _ = self.watch_entities_channel.send(WatchEntitiesEvent {
-            entity_version: entity.entity_version,
-            before: None,
-            after: Some(Arc::new(entity.clone())),
-        });
+        Self::send_watch_entities_event(
+            &self.watch_entities_channel,
+            &mut self.entity_watch_channels,
+            WatchEntitiesEvent {
+                entity_version: entity.entity_version,
+                before: None,
+                after: Some(Arc::new(entity.clone())),
+            },
+        );
 
         Ok(entity)
     }
@@ -116,10 +480,201 @@ impl InMemoryAttributeStore {
     fn update_existing_entity(
         entity: &mut Entity,
         attributes_to_update: &[AttributeToUpdate],
+        attribute_value_index: &mut AttributeValueIndex,
         watch_entities_channel: &Sender<WatchEntitiesEvent>,
+        entity_watch_channels: &mut HashMap<EntityId, Sender<WatchEntitiesEvent>>,
         entity_version_sequence: &mut std::ops::RangeFrom<i64>,
     ) -> Result<Entity, AttributeStoreError> {
         let before = entity.clone();
+        Self::apply_attributes_to_update(entity, attributes_to_update);
+        if before != *entity {
+            Self::unindex_attribute_values(attribute_value_index, &before);
+            Self::index_attribute_values(attribute_value_index, entity);
+            entity.entity_version = Self::advance_entity_version(entity_version_sequence);
+            Self::send_watch_entities_event(
+                watch_entities_channel,
+                entity_watch_channels,
+                WatchEntitiesEvent {
+                    entity_version: entity.entity_version,
+                    before: Some(Arc::new(before)),
+                    after: Some(Arc::new(entity.clone())),
+                },
+            );
+        }
+
+        Ok(entity.clone())
+    }
+
+    fn index_attribute_values(attribute_value_index: &mut AttributeValueIndex, entity: &Entity) {
+        for (symbol, value) in &entity.attributes {
+            attribute_value_index
+                .entry((symbol.clone(), value.clone()))
+                .or_default()
+                .insert(entity.entity_id);
+        }
+    }
+
+    fn unindex_attribute_values(attribute_value_index: &mut AttributeValueIndex, entity: &Entity) {
+        for (symbol, value) in &entity.attributes {
+            if let Entry::Occupied(mut entry) =
+                attribute_value_index.entry((symbol.clone(), value.clone()))
+            {
+                entry.get_mut().remove(&entity.entity_id);
+                if entry.get().is_empty() {
+                    entry.remove();
+                }
+            }
+        }
+    }
+
+    /// IDs of the only entities that can match `node`, if they can be found without scanning the
+    /// whole store. The IDs are unordered and may repeat.
+    fn candidate_entity_ids(&self, node: &EntityQueryNode) -> Option<Vec<EntityId>> {
+        match node {
+            EntityQueryNode::EntityIdIn(EntityIdInNode { entity_ids }) => Some(entity_ids.clone()),
+            EntityQueryNode::HasAttributeValue(HasAttributeValueNode {
+                attribute_type,
+                value,
+            }) => Some(
+                self.attribute_value_index
+                    .get(&(attribute_type.clone(), value.clone()))
+                    .map(|entity_ids| entity_ids.iter().copied().collect())
+                    .unwrap_or_default(),
+            ),
+            // Any indexed clause narrows the candidates of a conjunction
+            EntityQueryNode::And(AndQueryNode { clauses }) => clauses
+                .iter()
+                .filter_map(|clause| self.candidate_entity_ids(clause))
+                .min_by_key(Vec::len),
+            _ => None,
+        }
+    }
+
+    fn matching_entities<'a>(
+        &'a self,
+        root: &'a EntityQueryNode,
+        include_deleted: bool,
+    ) -> Box<dyn Iterator<Item = &'a Entity> + 'a> {
+        self.matching_entities_from(root, include_deleted, 0)
+    }
+
+    /// Matching entities in entity ID order, starting from the entity at index `start`.
+    fn matching_entities_from<'a>(
+        &'a self,
+        root: &'a EntityQueryNode,
+        include_deleted: bool,
+        start: usize,
+    ) -> Box<dyn Iterator<Item = &'a Entity> + 'a> {
+        let matching_entities = self.matching_entities_including_deleted_from(root, start);
+        if include_deleted {
+            matching_entities
+        } else {
+            Box::new(matching_entities.filter(|entity| !entity.is_deleted()))
+        }
+    }
+
+    fn matching_entities_including_deleted_from<'a>(
+        &'a self,
+        root: &'a EntityQueryNode,
+        start: usize,
+    ) -> Box<dyn Iterator<Item = &'a Entity> + 'a> {
+        if root.has_graph_traversal() {
+            let root = self.resolve_graph_traversals(root);
+            return Box::new(
+                self.entities
+                    .iter()
+                    .skip(start)
+                    .filter(move |entity| root.matches(entity)),
+            );
+        }
+
+        match self.candidate_entity_ids(root) {
+            // Look entities up directly rather than scanning the whole store
+            Some(mut entity_ids) => {
+                entity_ids.sort_by_key(|EntityId(database_id)| *database_id);
+                entity_ids.dedup();
+                Box::new(
+                    entity_ids
+                        .into_iter()
+                        .filter_map(move |entity_id| {
+                            let idx = usize::try_from(entity_id).ok()?;
+                            if idx < start {
+                                return None;
+                            }
+                            self.entities.get(idx)
+                        })
+                        // The candidates of an `EntityIdIn` root are exactly its matches
+                        .filter(move |entity| {
+                            matches!(root, EntityQueryNode::EntityIdIn(_)) || root.matches(entity)
+                        }),
+                )
+            }
+            None => Box::new(
+                self.entities
+                    .iter()
+                    .skip(start)
+                    .filter(|entity| root.matches(entity)),
+            ),
+        }
+    }
+
+    /// Replaces the graph traversals in `node` with the IDs of the entities they reach.
+    fn resolve_graph_traversals(&self, node: &EntityQueryNode) -> EntityQueryNode {
+        let resolve_clauses = |clauses: &[EntityQueryNode]| {
+            clauses
+                .iter()
+                .map(|clause| self.resolve_graph_traversals(clause))
+                .collect()
+        };
+        match node {
+            EntityQueryNode::And(AndQueryNode { clauses }) => EntityQueryNode::And(AndQueryNode {
+                clauses: resolve_clauses(clauses),
+            }),
+            EntityQueryNode::Or(OrQueryNode { clauses }) => EntityQueryNode::Or(OrQueryNode {
+                clauses: resolve_clauses(clauses),
+            }),
+            EntityQueryNode::GraphTraverse(graph_traverse_node) => {
+                EntityQueryNode::EntityIdIn(EntityIdInNode {
+                    entity_ids: self.graph_traverse(graph_traverse_node),
+                })
+            }
+            node => node.clone(),
+        }
+    }
+
+    /// Breadth-first search from the entities matching the node's root.
+    fn graph_traverse(
+        &self,
+        GraphTraverseNode {
+            root,
+            start_attribute_type,
+            depth_limit,
+        }: &GraphTraverseNode,
+    ) -> Vec<EntityId> {
+        let mut frontier: Vec<EntityId> = self
+            .matching_entities_including_deleted_from(root, 0)
+            .map(|entity| entity.entity_id)
+            .collect();
+        let mut visited: HashSet<EntityId> = frontier.iter().copied().collect();
+        let mut entity_ids = frontier.clone();
+
+        for _ in 0..*depth_limit {
+            frontier = frontier
+                .into_iter()
+                .filter_map(|entity_id| self.entities.get(usize::try_from(entity_id).ok()?))
+                .filter_map(|entity| entity.entity_id_attribute(start_attribute_type))
+                .filter(|entity_id| visited.insert(*entity_id))
+                .collect();
+            if frontier.is_empty() {
+                break;
+            }
+            entity_ids.extend(&frontier);
+        }
+
+        entity_ids
+    }
+
+    fn apply_attributes_to_update(entity: &mut Entity, attributes_to_update: &[AttributeToUpdate]) {
         for attribute_to_update in attributes_to_update {
             match &attribute_to_update.value {
                 None => entity.attributes.remove(&attribute_to_update.symbol),
@@ -128,21 +683,36 @@ impl InMemoryAttributeStore {
                     .insert(attribute_to_update.symbol.clone(), attribute_value.clone()),
             };
         }
-        if before != *entity {
-            entity.entity_version = EntityVersion(entity_version_sequence.next().unwrap());
-            let _ = watch_entities_channel.send(WatchEntitiesEvent {
-                entity_version: entity.entity_version,
-                before: Some(Arc::new(before)),
-                after: Some(Arc::new(entity.clone())),
-            });
-        }
+    }
 
-        Ok(entity.clone())
+    fn attributes_for_new_entity(
+        attributes_to_update: &[AttributeToUpdate],
+    ) -> HashMap<Symbol, AttributeValue> {
+        attributes_to_update
+            .iter()
+            .filter_map(|attribute_to_update| {
+                attribute_to_update
+                    .value
+                    .clone()
+                    .map(|value| (attribute_to_update.symbol.clone(), value))
+            })
+            .collect()
+    }
+}
+
+impl Default for InMemoryAttributeStore {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl AttributeStore for InMemoryAttributeStore {
-    #[tracing::instrument(skip(self), ret(level = Level::TRACE), err(level = Level::WARN))]
+    #[tracing::instrument(
+        skip(self),
+        fields(entity_id = tracing::field::Empty),
+        ret(level = Level::TRACE),
+        err(level = Level::WARN)
+    )]
     fn create_attribute_type(
         &mut self,
         create_attribute_type_request: &CreateAttributeTypeRequest,
@@ -155,67 +725,183 @@ impl AttributeStore for InMemoryAttributeStore {
         let validated_request =
             Unvalidated::new(create_attribute_type_request).validate_with(&self.attribute_types)?;
         let CreateAttributeTypeRequest { attribute_type } = validated_request.into_inner();
+        let value_type_entity_id = self.validate_new_attribute_type(attribute_type)?;
 
         if let Ok(entity) = self.get_entity(&EntityLocator::Symbol(attribute_type.symbol.clone())) {
             return Err(AttributeTypeAlreadyExists(entity))?;
         }
 
-        let entity = self.insert_new_entity_with_attributes(HashMap::from([
+        let mut attributes = HashMap::from([
             (
                 symbol_name_symbol,
                 AttributeValue::String(attribute_type.symbol.to_string()),
             ),
             (
                 BootstrapSymbol::ValueType.into(),
-                AttributeValue::EntityId(attribute_type.value_type.into()),
+                AttributeValue::EntityId(value_type_entity_id),
             ),
-        ]))?;
+        ]);
+        if let Some(description) = &attribute_type.description {
+            attributes.insert(
+                BootstrapSymbol::Description.into(),
+                AttributeValue::String(description.clone()),
+            );
+        }
+        let entity = self.insert_new_entity_with_attributes(attributes)?;
+        record_entity_id(entity.entity_id);
+
+        self.attribute_types.insert(
+            attribute_type.symbol.clone(),
+            attribute_type.value_type.clone(),
+        );
 
-        self.attribute_types
-            .insert(attribute_type.symbol.clone(), attribute_type.value_type);
+        #[cfg(feature = "audit-log")]
+        self.record_audit_entry(
+            AuditOperation::CreateAttributeType,
+            EntityLocator::Symbol(attribute_type.symbol.clone()),
+            &entity,
+        );
 
         Ok(entity)
     }
 
     #[tracing::instrument(skip(self), ret(level = Level::TRACE), err(level = Level::WARN))]
+    fn create_attribute_types(
+        &mut self,
+        attribute_types: &[AttributeType],
+    ) -> Result<CreateAttributeTypesResult, AttributeStoreError> {
+        log::trace!("Received create_attribute_types request");
+
+        let mut result = CreateAttributeTypesResult {
+            entities: vec![],
+            already_existed: vec![],
+        };
+        // Check every new type before creating any, so that none are created if one is invalid
+        let mut new_attribute_types: Vec<&AttributeType> = vec![];
+        for attribute_type in attribute_types {
+            let is_new = self
+                .get_entity(&EntityLocator::Symbol(attribute_type.symbol.clone()))
+                .is_err()
+                && new_attribute_types
+                    .iter()
+                    .all(|new_attribute_type| new_attribute_type.symbol != attribute_type.symbol);
+            if !is_new {
+                result.already_existed.push(attribute_type.symbol.clone());
+                continue;
+            }
+            self.validate_new_attribute_type(attribute_type)?;
+            new_attribute_types.push(attribute_type);
+        }
+        self.check_capacity_for(new_attribute_types.len())?;
+
+        for attribute_type in new_attribute_types {
+            result
+                .entities
+                .push(self.create_attribute_type(&CreateAttributeTypeRequest {
+                    attribute_type: attribute_type.clone(),
+                })?);
+        }
+
+        Ok(result)
+    }
+
+    #[tracing::instrument(
+        skip(self),
+        fields(entity_id = tracing::field::Empty),
+        ret(level = Level::TRACE),
+        err(level = Level::WARN)
+    )]
+    fn get_attribute_type(&self, symbol: &Symbol) -> Result<AttributeType, AttributeStoreError> {
+        log::trace!("Received get_attribute_type request");
+
+        let entity_locator = EntityLocator::Symbol(symbol.clone());
+        let value_type = self
+            .attribute_types
+            .get(symbol)
+            .ok_or_else(|| AttributeStoreErrorKind::EntityNotFound(entity_locator.clone()))?;
+        let entity = self.get_entity(&entity_locator)?;
+        record_entity_id(entity.entity_id);
+        let description = entity
+            .string_attribute(&BootstrapSymbol::Description.into())
+            .map(str::to_string);
+
+        Ok(AttributeType {
+            symbol: symbol.clone(),
+            value_type: value_type.clone(),
+            description,
+        })
+    }
+
+    #[tracing::instrument(
+        skip(self),
+        fields(entity_id = tracing::field::Empty),
+        ret(level = Level::TRACE),
+        err(level = Level::WARN)
+    )]
     fn get_entity(&self, entity_locator: &EntityLocator) -> Result<Entity, AttributeStoreError> {
         use AttributeStoreErrorKind::*;
 
         log::trace!("Received get_entity request");
 
-        let symbol_name_symbol: Symbol = BootstrapSymbol::SymbolName.into();
+        let symbol_name_symbol = SymbolRef::from(BootstrapSymbol::SymbolName);
         let entity = match entity_locator {
             EntityLocator::EntityId(entity_id) => self.entities.get(usize::try_from(*entity_id)?),
-            EntityLocator::Symbol(symbol) => {
-                let expected_attribute_value = AttributeValue::String(symbol.clone().into());
-                self.entities.iter().find(|entity| {
-                    entity
-                        .attributes
-                        .get(&symbol_name_symbol)
-                        .is_some_and(|attribute_value| {
-                            attribute_value.eq(&expected_attribute_value)
-                        })
-                })
-            }
+            EntityLocator::Symbol(symbol) => self.entities.iter().find(|entity| {
+                entity
+                    .attributes
+                    .get(symbol_name_symbol.as_str())
+                    .is_some_and(|attribute_value| match attribute_value {
+                        AttributeValue::String(symbol_name) => symbol_name == symbol.as_str(),
+                        _ => false,
+                    })
+            }),
+            EntityLocator::CreateNew => None,
         }
         .ok_or_else(|| EntityNotFound(entity_locator.clone()))?;
+        record_entity_id(entity.entity_id);
 
         Ok(entity.clone())
     }
 
+    #[tracing::instrument(skip(self), err(level = Level::WARN))]
+    fn batch_get_entities(
+        &self,
+        entity_locators: &[EntityLocator],
+    ) -> Result<BatchGetEntitiesResult, AttributeStoreError> {
+        log::trace!("Received batch_get_entities request");
+
+        let mut result = BatchGetEntitiesResult {
+            entities: vec![],
+            not_found: vec![],
+        };
+        for entity_locator in entity_locators {
+            match self.get_entity(entity_locator) {
+                Ok(entity) => result.entities.push(entity),
+                Err(AttributeStoreError {
+                    kind: AttributeStoreErrorKind::EntityNotFound(entity_locator),
+                    ..
+                }) => result.not_found.push(entity_locator),
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(result)
+    }
+
     #[tracing::instrument(skip(self), ret(level = Level::TRACE), err(level = Level::WARN))]
     fn query_entities(
         &self,
         entity_query: &EntityQuery,
     ) -> Result<EntityQueryResult, AttributeStoreError> {
-        log::trace!("Received query_entity request");
+        log::trace!("Received query_entities request");
 
-        let EntityQuery { root } = entity_query;
+        let EntityQuery {
+            root,
+            include_deleted,
+        } = entity_query;
 
         let entities = self
-            .entities
-            .iter()
-            .filter(|entity| root.matches(entity))
+            .matching_entities(root, *include_deleted)
             .cloned()
             .collect();
 
@@ -235,16 +921,18 @@ impl AttributeStore for InMemoryAttributeStore {
         // validate
         let validated_entity_query =
             Unvalidated::new(entity_row_query).validate_with(&self.attribute_types)?;
+        let entity_row_query = validated_entity_query.into_inner();
         let EntityRowQuery {
             root,
             attribute_types,
-        } = validated_entity_query.into_inner();
+            include_deleted,
+            ..
+        } = entity_row_query;
 
         let entity_rows = self
-            .entities
-            .iter()
-            .filter(|entity| root.matches(entity))
+            .matching_entities(root, *include_deleted)
             .map(|entity| entity.to_entity_row(attribute_types))
+            .filter(|entity_row| entity_row_query.includes_row(entity_row))
             .collect();
 
         Ok(EntityRowQueryResult {
@@ -254,14 +942,80 @@ impl AttributeStore for InMemoryAttributeStore {
     }
 
     #[tracing::instrument(skip(self), ret(level = Level::TRACE), err(level = Level::WARN))]
-    fn update_entity(
-        &mut self,
-        update_entity_request: &UpdateEntityRequest,
-    ) -> Result<Entity, AttributeStoreError> {
-        use AttributeStoreErrorKind::*;
-        log::trace!("Received query_entities request");
+    fn query_entity_rows_page(
+        &self,
+        entity_row_query: &EntityRowQuery,
+        after: Option<EntityId>,
+        limit: usize,
+    ) -> Result<EntityRowQueryResult, AttributeStoreError> {
+        log::trace!("Received query_entity_rows_page request");
 
-        let symbol_name_symbol: Symbol = BootstrapSymbol::SymbolName.into();
+        // validate
+        let validated_entity_query =
+            Unvalidated::new(entity_row_query).validate_with(&self.attribute_types)?;
+        let entity_row_query = validated_entity_query.into_inner();
+        let EntityRowQuery {
+            root,
+            attribute_types,
+            include_deleted,
+            ..
+        } = entity_row_query;
+
+        let start = match after {
+            None => 0,
+            Some(entity_id) => usize::try_from(entity_id)? + 1,
+        };
+        let entity_rows = self
+            .matching_entities_from(root, *include_deleted, start)
+            .map(|entity| entity.to_entity_row(attribute_types))
+            .filter(|entity_row| entity_row_query.includes_row(entity_row))
+            .take(limit)
+            .collect();
+
+        Ok(EntityRowQueryResult {
+            entity_rows,
+            entity_version: self.current_entity_version(),
+        })
+    }
+
+    #[tracing::instrument(
+        skip(self),
+        fields(entity_id = tracing::field::Empty),
+        err(level = Level::WARN)
+    )]
+    fn validate_update_entity(
+        &self,
+        update_entity_request: &UpdateEntityRequest,
+    ) -> Result<(), AttributeStoreError> {
+        log::trace!("Received validate_update_entity request");
+
+        let validated_update_entity_request =
+            Unvalidated::from(update_entity_request).validate_with(&self.attribute_types)?;
+        let UpdateEntityRequest {
+            entity_locator,
+            attributes_to_update,
+            ..
+        } = validated_update_entity_request.into_inner();
+
+        self.validate_symbol_names(attributes_to_update)?;
+        match self.locate_entity_for_update(entity_locator, attributes_to_update)? {
+            Some(index) => record_entity_id(self.entities[index].entity_id),
+            None => self.check_capacity_for(1)?,
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        skip(self),
+        fields(entity_id = tracing::field::Empty),
+        ret(level = Level::TRACE),
+        err(level = Level::WARN)
+    )]
+    fn update_entity(
+        &mut self,
+        update_entity_request: &UpdateEntityRequest,
+    ) -> Result<Entity, AttributeStoreError> {
+        log::trace!("Received update_entity request");
 
         // Validate
         let validated_update_entity_request =
@@ -269,77 +1023,202 @@ impl AttributeStore for InMemoryAttributeStore {
         let UpdateEntityRequest {
             entity_locator,
             attributes_to_update,
+            dry_run,
         } = validated_update_entity_request.into_inner();
+        self.validate_symbol_names(attributes_to_update)?;
 
         // Update entity
-        let existing_entity =
-            match entity_locator {
-                EntityLocator::EntityId(entity_id) => {
-                    let Some(entity) = self.entities.get_mut(usize::try_from(*entity_id)?) else {
-                        return Err(EntityNotFound(entity_locator.clone()))?;
-                    };
-                    Some(entity)
+        let existing_entity = self
+            .locate_entity_for_update(entity_locator, attributes_to_update)?
+            .map(|index| &mut self.entities[index]);
+        if let Some(entity) = &existing_entity {
+            record_entity_id(entity.entity_id);
+        }
+
+        if *dry_run {
+            // Nothing is committed, so the store's entity version is not advanced
+            return match existing_entity {
+                None => {
+                    self.check_capacity_for(1)?;
+                    self.new_entity_with_attributes(
+                        Self::attributes_for_new_entity(attributes_to_update),
+                        self.current_entity_version(),
+                    )
                 }
-                EntityLocator::Symbol(symbol) => {
-                    let expected_attribute_value = AttributeValue::String(symbol.clone().into());
-                    let entity =
-                        self.entities.iter_mut().find(|entity| {
-                            entity.attributes.get(&symbol_name_symbol).is_some_and(
-                                |attribute_value| attribute_value.eq(&expected_attribute_value),
-                            )
-                        });
-                    if entity.is_none() {
-                        let expected_symbol_attribute = AttributeToUpdate {
-                            symbol: symbol_name_symbol,
-                            value: Some(expected_attribute_value),
-                        };
-                        if !attributes_to_update.contains(&expected_symbol_attribute) {
-                            return Err(UpdateNotIdempotent {
-                                missing_attribute_to_update: expected_symbol_attribute,
-                                entity_locator: entity_locator.clone(),
-                            })?;
-                        }
-                    }
-                    entity
+                Some(entity) => {
+                    let mut entity = entity.clone();
+                    Self::apply_attributes_to_update(&mut entity, attributes_to_update);
+                    Ok(entity)
                 }
             };
+        }
 
-        match existing_entity {
+        let entity = match existing_entity {
             None =>
             // FIXME: Validate that the new entity matches the provided locator
             {
-                self.insert_new_entity_with_attributes(
-                    update_entity_request
-                        .attributes_to_update
-                        .iter()
-                        .filter_map(|attribute_to_update| {
-                            attribute_to_update
-                                .value
-                                .clone()
-                                .map(|value| (attribute_to_update.symbol.clone(), value))
-                        })
-                        .collect(),
-                )
+                let entity = self.insert_new_entity_with_attributes(
+                    Self::attributes_for_new_entity(attributes_to_update),
+                )?;
+                record_entity_id(entity.entity_id);
+                entity
             }
             Some(entity) => Self::update_existing_entity(
                 entity,
                 attributes_to_update,
+                &mut self.attribute_value_index,
                 &self.watch_entities_channel,
+                &mut self.entity_watch_channels,
                 &mut self.entity_version_sequence,
-            ),
-        }
+            )?,
+        };
+
+        #[cfg(feature = "audit-log")]
+        self.record_audit_entry(
+            AuditOperation::UpdateEntity,
+            entity_locator.clone(),
+            &entity,
+        );
+
+        Ok(entity)
+    }
+
+    #[tracing::instrument(
+        skip(self),
+        fields(entity_id = tracing::field::Empty),
+        ret(level = Level::TRACE),
+        err(level = Level::WARN)
+    )]
+    fn merge_entities(
+        &mut self,
+        source: &EntityLocator,
+        target: &EntityLocator,
+        attribute_types: &[Symbol],
+    ) -> Result<Entity, AttributeStoreError> {
+        log::trace!("Received merge_entities request");
+
+        let source_entity = self.get_entity(source)?;
+        let attributes_to_update = attribute_types
+            .iter()
+            .filter_map(|attribute_type| {
+                let value = source_entity.attributes.get(attribute_type)?;
+                Some(AttributeToUpdate {
+                    symbol: attribute_type.clone(),
+                    value: Some(value.clone()),
+                })
+            })
+            .collect();
+
+        let entity = self.update_entity(&UpdateEntityRequest {
+            entity_locator: target.clone(),
+            attributes_to_update,
+            dry_run: false,
+        })?;
+        record_entity_id(entity.entity_id);
+
+        Ok(entity)
+    }
+
+    #[tracing::instrument(
+        skip(self, f),
+        fields(entity_id = tracing::field::Empty),
+        ret(level = Level::TRACE),
+        err(level = Level::WARN)
+    )]
+    fn atomic_query_and_update<F, E>(
+        &mut self,
+        entity_locator: &EntityLocator,
+        f: F,
+    ) -> Result<Entity, E>
+    where
+        F: FnOnce(&Entity) -> Result<Vec<AttributeToUpdate>, E>,
+        E: From<AttributeStoreError> + std::fmt::Display,
+    {
+        log::trace!("Received atomic_query_and_update request");
+
+        let current_entity = self.get_entity(entity_locator)?;
+        let attributes_to_update = f(&current_entity)?;
+
+        let entity = self.update_entity(&UpdateEntityRequest {
+            entity_locator: EntityLocator::EntityId(current_entity.entity_id),
+            attributes_to_update,
+            dry_run: false,
+        })?;
+        record_entity_id(entity.entity_id);
+
+        Ok(entity)
     }
 
     #[tracing::instrument(skip(self))]
     fn watch_entities_receiver(&self) -> Receiver<WatchEntitiesEvent> {
         self.watch_entities_channel.subscribe()
     }
+
+    fn current_entity_version(&self) -> EntityVersion {
+        EntityVersion(self.entity_version_sequence.start)
+    }
+
+    fn entity_count(&self) -> usize {
+        self.entities
+            .iter()
+            .filter(|entity| !entity.is_deleted())
+            .count()
+    }
+
+    fn watch_entities_channel_len(&self) -> usize {
+        self.watch_entities_channel.len()
+    }
+
+    /// Counts the allocated capacity of `entities` and `attribute_types`, and of the attributes of
+    /// each entity, but only the length of attribute names and values.
+    fn memory_usage_bytes(&self) -> usize {
+        let entities_bytes = self.entities.capacity() * size_of::<Entity>();
+        let attributes_bytes: usize = self
+            .entities
+            .iter()
+            .map(|entity| {
+                entity.attributes.capacity() * size_of::<(Symbol, AttributeValue)>()
+                    + entity
+                        .attributes
+                        .iter()
+                        .map(|(symbol, value)| symbol.len() + value.heap_size())
+                        .sum::<usize>()
+            })
+            .sum();
+        let attribute_types_bytes = self.attribute_types.capacity()
+            * size_of::<(Symbol, ValueType)>()
+            + self
+                .attribute_types
+                .keys()
+                .map(|symbol| symbol.len())
+                .sum::<usize>();
+        entities_bytes + attributes_bytes + attribute_types_bytes
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn watch_entity_receiver(&mut self, entity_id: EntityId) -> Receiver<WatchEntitiesEvent> {
+        if !self.entity_watch_channels.contains_key(&entity_id) {
+            // Entities that were watched but never changed would otherwise keep their channels
+            self.entity_watch_channels
+                .retain(|_, entity_watch_channel| entity_watch_channel.receiver_count() > 0);
+        }
+        let watch_channel_capacity = self.watch_channel_capacity;
+        self.entity_watch_channels
+            .entry(entity_id)
+            .or_insert_with(|| broadcast::channel(watch_channel_capacity).0)
+            .subscribe()
+    }
+}
+
+/// Records the ID of the entity that an operation resolved its locator to on the operation's span.
+fn record_entity_id(entity_id: EntityId) {
+    tracing::Span::current().record("entity_id", entity_id.0);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::store::{EntityQueryNode, EntityRow, MatchAllQueryNode};
+    use crate::store::{EntityRow, MatchAllQueryNode};
 
     #[test]
     fn can_fetch_by_entity_id() {
@@ -359,6 +1238,330 @@ mod tests {
         assert_eq!(entity_id_entity, BootstrapSymbol::EntityId.into());
     }
 
+    #[test]
+    fn batch_get_entities_collects_not_found_locators() {
+        let store = InMemoryAttributeStore::new();
+        let missing_symbol = EntityLocator::Symbol(Symbol::try_from("missing").unwrap());
+        let missing_entity_id = EntityLocator::EntityId(EntityId(1000));
+
+        let result = store
+            .batch_get_entities(&[
+                EntityLocator::Symbol(BootstrapSymbol::ValueType.into()),
+                missing_symbol.clone(),
+                EntityLocator::EntityId(BootstrapSymbol::EntityId.into()),
+                missing_entity_id.clone(),
+            ])
+            .unwrap();
+
+        assert_eq!(
+            result,
+            BatchGetEntitiesResult {
+                entities: vec![
+                    BootstrapSymbol::ValueType.into(),
+                    BootstrapSymbol::EntityId.into()
+                ],
+                not_found: vec![missing_symbol, missing_entity_id],
+            }
+        );
+    }
+
+    #[test]
+    fn with_entities_preloaded_infers_attribute_types() {
+        let name = Symbol::try_from("name").unwrap();
+        let parent = Symbol::try_from("parent").unwrap();
+        let store = InMemoryAttributeStore::with_entities_preloaded(vec![
+            vec![
+                (
+                    BootstrapSymbol::SymbolName.into(),
+                    AttributeValue::String("foo".into()),
+                ),
+                (name.clone(), AttributeValue::String("Foo".into())),
+            ],
+            vec![(parent.clone(), AttributeValue::EntityId(EntityId(0)))],
+        ])
+        .unwrap();
+
+        assert_eq!(store.attribute_types.get(&name), Some(&ValueType::Text));
+        assert_eq!(
+            store.attribute_types.get(&parent),
+            Some(&ValueType::EntityReference)
+        );
+        let foo = store
+            .get_entity(&EntityLocator::Symbol(Symbol::try_from("foo").unwrap()))
+            .unwrap();
+        assert_eq!(
+            foo.attributes.get(&name),
+            Some(&AttributeValue::String("Foo".into()))
+        );
+    }
+
+    #[test]
+    fn with_entities_preloaded_rejects_mismatched_value_types() {
+        let name = Symbol::try_from("name").unwrap();
+        let result = InMemoryAttributeStore::with_entities_preloaded(vec![
+            vec![(name.clone(), AttributeValue::String("Foo".into()))],
+            vec![(name, AttributeValue::Bytes(vec![]))],
+        ]);
+
+        assert_matches!(
+            result,
+            Err(AttributeStoreError {
+                kind: AttributeStoreErrorKind::ValidationError(_),
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn soft_deleted_entities_are_excluded_from_queries_until_restored() {
+        let mut store = InMemoryAttributeStore::new();
+        let entity_locator = EntityLocator::Symbol(Symbol::try_from("foo").unwrap());
+        store
+            .update_entity(&UpdateEntityRequest {
+                entity_locator: entity_locator.clone(),
+                attributes_to_update: vec![AttributeToUpdate {
+                    symbol: BootstrapSymbol::SymbolName.into(),
+                    value: Some(AttributeValue::String("foo".into())),
+                }],
+                dry_run: false,
+            })
+            .unwrap();
+        let query_symbol_names = |store: &InMemoryAttributeStore, include_deleted: bool| {
+            store
+                .query_entity_rows(&EntityRowQuery {
+                    attribute_types: vec![BootstrapSymbol::SymbolName.into()],
+                    root: EntityQueryNode::HasAttributeTypes(crate::store::HasAttributeTypesNode {
+                        attribute_types: vec![BootstrapSymbol::SymbolName.into()],
+                    }),
+                    include_deleted,
+                    filter_nulls: false,
+                    filter_rows_where_null: vec![],
+                })
+                .unwrap()
+                .entity_rows
+                .into_iter()
+                .flat_map(|entity_row| entity_row.values)
+                .filter(|value| value == &Some(AttributeValue::String("foo".into())))
+                .count()
+        };
+
+        let deleted = store.soft_delete_entity(&entity_locator).unwrap();
+        assert!(deleted.is_deleted());
+        assert_eq!(query_symbol_names(&store, false), 0);
+        assert_eq!(query_symbol_names(&store, true), 1);
+        // Deleting again keeps the original deletion time
+        assert_eq!(store.soft_delete_entity(&entity_locator).unwrap(), deleted);
+
+        let restored = store.restore_entity(&entity_locator).unwrap();
+        assert!(!restored.is_deleted());
+        assert_eq!(query_symbol_names(&store, false), 1);
+    }
+
+    #[test]
+    fn symbol_policy_restricts_new_symbols() {
+        let create_attribute_type_request = CreateAttributeTypeRequest {
+            attribute_type: AttributeType {
+                symbol: Symbol::try_from("couleur préférée").unwrap(),
+                value_type: ValueType::Text,
+                description: None,
+            },
+        };
+
+        let mut ascii_store = InMemoryAttributeStore::new();
+        assert_matches!(
+            ascii_store
+                .create_attribute_type(&create_attribute_type_request)
+                .unwrap_err()
+                .kind,
+            AttributeStoreErrorKind::InvalidSymbolName(_)
+        );
+
+        let mut unicode_store =
+            InMemoryAttributeStore::new().with_symbol_policy(SymbolPolicy::unicode());
+        unicode_store
+            .create_attribute_type(&create_attribute_type_request)
+            .unwrap();
+    }
+
+    #[test]
+    fn symbol_policy_restricts_symbol_names_set_by_entity_id() {
+        let mut store = InMemoryAttributeStore::new();
+        let entity = store
+            .update_entity(&UpdateEntityRequest {
+                entity_locator: EntityLocator::CreateNew,
+                attributes_to_update: vec![AttributeToUpdate {
+                    symbol: BootstrapSymbol::Description.into(),
+                    value: Some(AttributeValue::String("unnamed".into())),
+                }],
+                dry_run: false,
+            })
+            .unwrap();
+
+        let rename_request = UpdateEntityRequest {
+            entity_locator: EntityLocator::EntityId(entity.entity_id),
+            attributes_to_update: vec![AttributeToUpdate {
+                symbol: BootstrapSymbol::SymbolName.into(),
+                value: Some(AttributeValue::String("couleur préférée".into())),
+            }],
+            dry_run: false,
+        };
+        assert_matches!(
+            store.update_entity(&rename_request).unwrap_err().kind,
+            AttributeStoreErrorKind::InvalidSymbolName(_)
+        );
+        assert_matches!(
+            store
+                .validate_update_entity(&rename_request)
+                .unwrap_err()
+                .kind,
+            AttributeStoreErrorKind::InvalidSymbolName(_)
+        );
+        assert_matches!(
+            InMemoryAttributeStore::with_entities_preloaded(vec![vec![(
+                BootstrapSymbol::SymbolName.into(),
+                AttributeValue::String("couleur préférée".into()),
+            )]])
+            .unwrap_err()
+            .kind,
+            AttributeStoreErrorKind::InvalidSymbolName(_)
+        );
+    }
+
+    #[test]
+    fn merge_entities_copies_attributes_the_source_has() {
+        let mut store = InMemoryAttributeStore::new();
+        let description = |text: &str| AttributeToUpdate {
+            symbol: BootstrapSymbol::Description.into(),
+            value: Some(AttributeValue::String(text.into())),
+        };
+        let symbol_name = |name: &str| AttributeToUpdate {
+            symbol: BootstrapSymbol::SymbolName.into(),
+            value: Some(AttributeValue::String(name.into())),
+        };
+        let source = EntityLocator::Symbol(Symbol::try_from("source").unwrap());
+        let target = EntityLocator::Symbol(Symbol::try_from("target").unwrap());
+        store
+            .update_entity(&UpdateEntityRequest {
+                entity_locator: source.clone(),
+                attributes_to_update: vec![symbol_name("source"), description("copied")],
+                dry_run: false,
+            })
+            .unwrap();
+        store
+            .update_entity(&UpdateEntityRequest {
+                entity_locator: target.clone(),
+                attributes_to_update: vec![symbol_name("target")],
+                dry_run: false,
+            })
+            .unwrap();
+
+        let merged = store
+            .merge_entities(
+                &source,
+                &target,
+                &[
+                    BootstrapSymbol::Description.into(),
+                    BootstrapSymbol::ValueType.into(),
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(
+            merged.attributes,
+            HashMap::from([
+                (
+                    BootstrapSymbol::SymbolName.into(),
+                    AttributeValue::String("target".into())
+                ),
+                (
+                    BootstrapSymbol::Description.into(),
+                    AttributeValue::String("copied".into())
+                ),
+            ])
+        );
+        assert_eq!(store.get_entity(&target).unwrap(), merged);
+    }
+
+    #[test]
+    fn atomic_query_and_update_computes_update_from_current_entity() {
+        let mut store = InMemoryAttributeStore::new();
+        let locator = EntityLocator::Symbol(Symbol::try_from("counter").unwrap());
+        store
+            .update_entity(&UpdateEntityRequest {
+                entity_locator: locator.clone(),
+                attributes_to_update: vec![AttributeToUpdate {
+                    symbol: BootstrapSymbol::SymbolName.into(),
+                    value: Some(AttributeValue::String("counter".into())),
+                }],
+                dry_run: false,
+            })
+            .unwrap();
+
+        let updated = store
+            .atomic_query_and_update(&locator, |entity| {
+                let Some(AttributeValue::String(name)) = entity
+                    .attributes
+                    .get(&Symbol::from(BootstrapSymbol::SymbolName))
+                else {
+                    panic!("entity has no symbol name");
+                };
+                Ok::<_, AttributeStoreError>(vec![AttributeToUpdate {
+                    symbol: BootstrapSymbol::Description.into(),
+                    value: Some(AttributeValue::String(format!("{name} description"))),
+                }])
+            })
+            .unwrap();
+
+        assert_eq!(
+            updated
+                .attributes
+                .get(&Symbol::from(BootstrapSymbol::Description)),
+            Some(&AttributeValue::String("counter description".into()))
+        );
+        assert_eq!(store.get_entity(&locator).unwrap(), updated);
+
+        let missing = EntityLocator::Symbol(Symbol::try_from("missing").unwrap());
+        assert_matches!(
+            store.atomic_query_and_update::<_, AttributeStoreError>(&missing, |_| unreachable!()),
+            Err(AttributeStoreError {
+                kind: AttributeStoreErrorKind::EntityNotFound(_),
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn query_entities_returns_entities_and_current_version() {
+        let mut store = InMemoryAttributeStore::new();
+        let entity = store
+            .update_entity(&UpdateEntityRequest {
+                entity_locator: EntityLocator::Symbol(Symbol::try_from("foo").unwrap()),
+                attributes_to_update: vec![AttributeToUpdate {
+                    symbol: BootstrapSymbol::SymbolName.into(),
+                    value: Some(AttributeValue::String("foo".into())),
+                }],
+                dry_run: false,
+            })
+            .unwrap();
+
+        let entity_query_result = store
+            .query_entities(&EntityQuery {
+                root: EntityQueryNode::EntityIdIn(EntityIdInNode {
+                    entity_ids: vec![entity.entity_id],
+                }),
+                include_deleted: false,
+            })
+            .unwrap();
+
+        assert_eq!(
+            entity_query_result,
+            EntityQueryResult {
+                entity_version: entity.entity_version,
+                entities: vec![entity],
+            }
+        );
+    }
+
     #[test]
     fn can_query_all() {
         let store = InMemoryAttributeStore::new();
@@ -369,6 +1572,9 @@ mod tests {
                     BootstrapSymbol::SymbolName.into(),
                 ],
                 root: EntityQueryNode::MatchAll(MatchAllQueryNode),
+                include_deleted: false,
+                filter_nulls: false,
+                filter_rows_where_null: vec![],
             })
             .unwrap();
         assert_eq!(
@@ -376,15 +1582,989 @@ mod tests {
             InMemoryAttributeStore::bootstrap_entities()
                 .into_iter()
                 .map(|entity| EntityRow {
+                    entity_id: entity.entity_id,
                     values: vec![
                         Some(AttributeValue::EntityId(entity.entity_id)),
                         entity
                             .attributes
-                            .get(&BootstrapSymbol::SymbolName.into())
+                            .get(&Symbol::from(BootstrapSymbol::SymbolName))
                             .cloned()
                     ]
                 })
                 .collect::<Vec<_>>()
         );
     }
+
+    #[test]
+    fn dry_run_update_does_not_modify_store() {
+        use AttributeStoreErrorKind::EntityNotFound;
+
+        let mut store = InMemoryAttributeStore::new();
+        let entity_locator = EntityLocator::Symbol(Symbol::try_from("foo").unwrap());
+        let update_entity_request = UpdateEntityRequest {
+            entity_locator: entity_locator.clone(),
+            attributes_to_update: vec![AttributeToUpdate {
+                symbol: BootstrapSymbol::SymbolName.into(),
+                value: Some(AttributeValue::String("foo".into())),
+            }],
+            dry_run: true,
+        };
+
+        let dry_run_entity = store.update_entity(&update_entity_request).unwrap();
+        assert_matches!(
+            store.get_entity(&entity_locator).unwrap_err().kind,
+            EntityNotFound(_)
+        );
+
+        let entity = store
+            .update_entity(&UpdateEntityRequest {
+                dry_run: false,
+                ..update_entity_request
+            })
+            .unwrap();
+        assert_eq!(dry_run_entity.entity_id, entity.entity_id);
+        assert_eq!(dry_run_entity.attributes, entity.attributes);
+        assert_eq!(store.get_entity(&entity_locator).unwrap(), entity);
+    }
+
+    #[test]
+    fn validate_update_entity_does_not_modify_store() {
+        use AttributeStoreErrorKind::{EntityNotFound, UpdateNotIdempotent, ValidationError};
+
+        let store = InMemoryAttributeStore::new();
+        let entity_version = store.current_entity_version();
+        let symbol_name_update = AttributeToUpdate {
+            symbol: BootstrapSymbol::SymbolName.into(),
+            value: Some(AttributeValue::String("foo".into())),
+        };
+        let entity_locator = EntityLocator::Symbol(Symbol::try_from("foo").unwrap());
+
+        assert_matches!(
+            store
+                .validate_update_entity(&UpdateEntityRequest {
+                    entity_locator: entity_locator.clone(),
+                    attributes_to_update: vec![],
+                    dry_run: false,
+                })
+                .unwrap_err()
+                .kind,
+            UpdateNotIdempotent { .. }
+        );
+        assert_matches!(
+            store
+                .validate_update_entity(&UpdateEntityRequest {
+                    entity_locator: EntityLocator::EntityId(EntityId(1000)),
+                    attributes_to_update: vec![symbol_name_update.clone()],
+                    dry_run: false,
+                })
+                .unwrap_err()
+                .kind,
+            EntityNotFound(_)
+        );
+        assert_matches!(
+            store
+                .validate_update_entity(&UpdateEntityRequest {
+                    entity_locator: entity_locator.clone(),
+                    attributes_to_update: vec![
+                        symbol_name_update.clone(),
+                        AttributeToUpdate {
+                            symbol: Symbol::try_from("unknown").unwrap(),
+                            value: Some(AttributeValue::String("bar".into())),
+                        },
+                    ],
+                    dry_run: false,
+                })
+                .unwrap_err()
+                .kind,
+            ValidationError(_)
+        );
+
+        store
+            .validate_update_entity(&UpdateEntityRequest {
+                entity_locator: entity_locator.clone(),
+                attributes_to_update: vec![symbol_name_update],
+                dry_run: false,
+            })
+            .unwrap();
+        assert_matches!(
+            store.get_entity(&entity_locator).unwrap_err().kind,
+            EntityNotFound(_)
+        );
+        assert_eq!(store.current_entity_version(), entity_version);
+    }
+
+    #[test]
+    fn store_with_starting_version_generates_versions_from_start() {
+        let restored_max_version = 41;
+        let mut store =
+            InMemoryAttributeStore::new().with_starting_version(restored_max_version + 1);
+        let update_entity_request = |name: &'static str| UpdateEntityRequest {
+            entity_locator: EntityLocator::Symbol(Symbol::try_from(name).unwrap()),
+            attributes_to_update: vec![AttributeToUpdate {
+                symbol: BootstrapSymbol::SymbolName.into(),
+                value: Some(AttributeValue::String(name.into())),
+            }],
+            dry_run: false,
+        };
+
+        let foo = store.update_entity(&update_entity_request("foo")).unwrap();
+        let bar = store.update_entity(&update_entity_request("bar")).unwrap();
+
+        assert_eq!(foo.entity_version, EntityVersion(restored_max_version + 1));
+        assert_eq!(bar.entity_version, EntityVersion(restored_max_version + 2));
+        assert_eq!(store.current_entity_version(), bar.entity_version);
+    }
+
+    #[test]
+    fn store_ignores_starting_versions_already_in_use() {
+        for start in [i64::MIN, -1, 0] {
+            let store = InMemoryAttributeStore::new();
+            let current_version = store.current_entity_version();
+            let store = store.with_starting_version(start);
+            assert_eq!(store.current_entity_version(), current_version);
+        }
+    }
+
+    #[test]
+    fn store_rejects_new_entities_beyond_max_entities() {
+        use AttributeStoreErrorKind::StoreFull;
+
+        let limit = InMemoryAttributeStore::bootstrap_entities().len() + 1;
+        let mut store = InMemoryAttributeStore::new().with_max_entities(limit);
+        let update_entity_request = |name: &'static str| UpdateEntityRequest {
+            entity_locator: EntityLocator::Symbol(Symbol::try_from(name).unwrap()),
+            attributes_to_update: vec![AttributeToUpdate {
+                symbol: BootstrapSymbol::SymbolName.into(),
+                value: Some(AttributeValue::String(name.into())),
+            }],
+            dry_run: false,
+        };
+
+        store
+            .validate_update_entity(&update_entity_request("foo"))
+            .unwrap();
+        store.update_entity(&update_entity_request("foo")).unwrap();
+        let entity_version = store.current_entity_version();
+
+        assert_matches!(
+            store
+                .validate_update_entity(&update_entity_request("bar"))
+                .unwrap_err()
+                .kind,
+            StoreFull { limit: l } if l == limit
+        );
+        assert_matches!(
+            store
+                .update_entity(&UpdateEntityRequest {
+                    dry_run: true,
+                    ..update_entity_request("bar")
+                })
+                .unwrap_err()
+                .kind,
+            StoreFull { limit: l } if l == limit
+        );
+        assert_matches!(
+            store
+                .update_entity(&update_entity_request("bar"))
+                .unwrap_err()
+                .kind,
+            StoreFull { limit: l } if l == limit
+        );
+        assert_eq!(store.current_entity_version(), entity_version);
+        // Existing entities can still be updated
+        store.update_entity(&update_entity_request("foo")).unwrap();
+    }
+
+    #[test]
+    fn watch_entity_receiver_only_receives_events_for_its_entity() {
+        let update_entity_request = |name: &str, description: &str| UpdateEntityRequest {
+            entity_locator: EntityLocator::Symbol(Symbol::try_from(name.to_string()).unwrap()),
+            attributes_to_update: vec![
+                AttributeToUpdate {
+                    symbol: BootstrapSymbol::SymbolName.into(),
+                    value: Some(AttributeValue::String(name.into())),
+                },
+                AttributeToUpdate {
+                    symbol: BootstrapSymbol::Description.into(),
+                    value: Some(AttributeValue::String(description.into())),
+                },
+            ],
+            dry_run: false,
+        };
+        let mut store = InMemoryAttributeStore::new();
+        let foo = store
+            .update_entity(&update_entity_request("foo", "1"))
+            .unwrap();
+        store
+            .update_entity(&update_entity_request("bar", "1"))
+            .unwrap();
+
+        let mut receiver = store.watch_entity_receiver(foo.entity_id);
+        store
+            .update_entity(&update_entity_request("bar", "2"))
+            .unwrap();
+        let updated_foo = store
+            .update_entity(&update_entity_request("foo", "2"))
+            .unwrap();
+
+        let event = receiver.try_recv().unwrap();
+        assert_eq!(event.after.as_deref(), Some(&updated_foo));
+        assert_matches!(
+            receiver.try_recv(),
+            Err(tokio::sync::broadcast::error::TryRecvError::Empty)
+        );
+
+        drop(receiver);
+        store
+            .update_entity(&update_entity_request("foo", "3"))
+            .unwrap();
+        assert!(store.entity_watch_channels.is_empty());
+    }
+
+    #[test]
+    fn unchanged_entities_do_not_keep_their_watch_channels() {
+        let mut store = InMemoryAttributeStore::new();
+        let entity_ids: Vec<EntityId> = store
+            .query_entities(&EntityQuery {
+                root: EntityQueryNode::MatchAll(MatchAllQueryNode),
+                include_deleted: false,
+            })
+            .unwrap()
+            .entities
+            .iter()
+            .map(|entity| entity.entity_id)
+            .collect();
+
+        for entity_id in entity_ids {
+            drop(store.watch_entity_receiver(entity_id));
+        }
+
+        assert_eq!(store.entity_watch_channels.len(), 1);
+    }
+
+    #[test]
+    fn watch_entities_channel_len_is_bounded_by_channel_capacity() {
+        let mut store = InMemoryAttributeStore::new().with_channel_capacity(2);
+        let mut receiver = store.watch_entities_receiver();
+        for name in ["foo", "bar", "baz"] {
+            store
+                .update_entity(&UpdateEntityRequest {
+                    entity_locator: EntityLocator::Symbol(Symbol::try_from(name).unwrap()),
+                    attributes_to_update: vec![AttributeToUpdate {
+                        symbol: BootstrapSymbol::SymbolName.into(),
+                        value: Some(AttributeValue::String(name.into())),
+                    }],
+                    dry_run: false,
+                })
+                .unwrap();
+        }
+
+        assert_eq!(store.watch_entities_channel_len(), 2);
+        assert_matches!(
+            receiver.try_recv(),
+            Err(tokio::sync::broadcast::error::TryRecvError::Lagged(1))
+        );
+    }
+
+    #[test]
+    fn memory_usage_counts_attribute_values() {
+        let mut store = InMemoryAttributeStore::new();
+        let memory_usage_bytes = store.memory_usage_bytes();
+
+        store
+            .update_entity(&UpdateEntityRequest {
+                entity_locator: EntityLocator::EntityId(BootstrapSymbol::Description.into()),
+                attributes_to_update: vec![AttributeToUpdate {
+                    symbol: BootstrapSymbol::Description.into(),
+                    value: Some(AttributeValue::String("x".repeat(10_000))),
+                }],
+                dry_run: false,
+            })
+            .unwrap();
+
+        assert!(store.memory_usage_bytes() >= memory_usage_bytes + 10_000);
+    }
+
+    #[test]
+    fn virtual_attributes_cannot_be_set() {
+        use AttributeStoreErrorKind::ValidationError;
+
+        let mut store = InMemoryAttributeStore::new();
+        let result = store.update_entity(&UpdateEntityRequest {
+            entity_locator: EntityLocator::EntityId(BootstrapSymbol::Description.into()),
+            attributes_to_update: vec![AttributeToUpdate {
+                symbol: BootstrapSymbol::EntityId.into(),
+                value: Some(AttributeValue::EntityId(EntityId(1000))),
+            }],
+            dry_run: false,
+        });
+
+        assert_matches!(result.unwrap_err().kind, ValidationError(_));
+    }
+
+    #[test]
+    fn can_query_by_entity_ids() {
+        let store = InMemoryAttributeStore::new();
+        let entity_row_query_result = store
+            .query_entity_rows(&EntityRowQuery {
+                attribute_types: vec![BootstrapSymbol::SymbolName.into()],
+                root: EntityQueryNode::EntityIdIn(EntityIdInNode {
+                    entity_ids: vec![
+                        BootstrapSymbol::ValueType.into(),
+                        BootstrapSymbol::EntityId.into(),
+                        BootstrapSymbol::ValueType.into(),
+                        EntityId(1000),
+                    ],
+                }),
+                include_deleted: false,
+                filter_nulls: false,
+                filter_rows_where_null: vec![],
+            })
+            .unwrap();
+        assert_eq!(
+            entity_row_query_result.entity_rows,
+            [BootstrapSymbol::EntityId, BootstrapSymbol::ValueType]
+                .into_iter()
+                .map(|bootstrap_symbol| EntityRow {
+                    entity_id: bootstrap_symbol.clone().into(),
+                    values: vec![Some(AttributeValue::String(
+                        Symbol::from(bootstrap_symbol).into()
+                    ))],
+                })
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn graph_traversals_follow_entity_references() {
+        use crate::store::GraphTraverseNode;
+
+        let parent = Symbol::try_from("parent").unwrap();
+        let name = |name: &str| {
+            (
+                BootstrapSymbol::SymbolName.into(),
+                AttributeValue::String(name.into()),
+            )
+        };
+        // Entities 16 to 18, after the bootstrap entities and the `parent` attribute type
+        let store = InMemoryAttributeStore::with_entities_preloaded(vec![
+            vec![name("root")],
+            vec![
+                name("child"),
+                (parent.clone(), AttributeValue::EntityId(EntityId(16))),
+            ],
+            vec![
+                name("grandchild"),
+                (parent.clone(), AttributeValue::EntityId(EntityId(17))),
+            ],
+        ])
+        .unwrap();
+        let ancestors = |depth_limit| {
+            store
+                .query_entity_rows(&EntityRowQuery {
+                    attribute_types: vec![BootstrapSymbol::SymbolName.into()],
+                    root: EntityQueryNode::GraphTraverse(GraphTraverseNode {
+                        root: Box::new(EntityQueryNode::EntityIdIn(EntityIdInNode {
+                            entity_ids: vec![EntityId(18)],
+                        })),
+                        start_attribute_type: parent.clone(),
+                        depth_limit,
+                    }),
+                    include_deleted: false,
+                    filter_nulls: false,
+                    filter_rows_where_null: vec![],
+                })
+                .unwrap()
+                .entity_rows
+                .into_iter()
+                .flat_map(|entity_row| entity_row.values)
+                .collect::<Vec<_>>()
+        };
+        let symbol_names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| Some(AttributeValue::String(name.to_string())))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ancestors(0), symbol_names(&["grandchild"]));
+        assert_eq!(ancestors(1), symbol_names(&["child", "grandchild"]));
+        assert_eq!(ancestors(5), symbol_names(&["root", "child", "grandchild"]));
+    }
+
+    #[test]
+    fn watch_events_carry_entity_version() {
+        let mut store = InMemoryAttributeStore::new();
+        let mut receiver = store.watch_entities_receiver();
+        let update_entity_request = |entity_locator, symbol_name: &str| UpdateEntityRequest {
+            entity_locator,
+            attributes_to_update: vec![AttributeToUpdate {
+                symbol: BootstrapSymbol::SymbolName.into(),
+                value: Some(AttributeValue::String(symbol_name.into())),
+            }],
+            dry_run: false,
+        };
+
+        let inserted = store
+            .update_entity(&update_entity_request(
+                EntityLocator::Symbol(Symbol::try_from("foo").unwrap()),
+                "foo",
+            ))
+            .unwrap();
+        let updated = store
+            .update_entity(&update_entity_request(
+                EntityLocator::EntityId(inserted.entity_id),
+                "bar",
+            ))
+            .unwrap();
+
+        let inserted_event = receiver.try_recv().unwrap();
+        assert_eq!(inserted_event.entity_version, inserted.entity_version);
+        let updated_event = receiver.try_recv().unwrap();
+        assert_eq!(updated_event.entity_version, updated.entity_version);
+        assert!(inserted.entity_version < updated.entity_version);
+        assert_eq!(store.current_entity_version(), updated.entity_version);
+    }
+
+    #[test]
+    fn bootstrap_attribute_types_are_registered() {
+        let store = InMemoryAttributeStore::new();
+        assert_eq!(
+            store.attribute_types,
+            AttributeTypes::from([
+                (BootstrapSymbol::EntityId.into(), ValueType::EntityReference),
+                (BootstrapSymbol::SymbolName.into(), ValueType::Text),
+                (
+                    BootstrapSymbol::ValueType.into(),
+                    ValueType::EntityReference
+                ),
+                (BootstrapSymbol::Description.into(), ValueType::Text),
+                (BootstrapSymbol::DeletedAt.into(), ValueType::Text),
+            ])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "malformed bootstrap attribute type entity")]
+    fn malformed_bootstrap_attribute_type_panics() {
+        let mut entities = InMemoryAttributeStore::bootstrap_entities();
+        entities[0].attributes.insert(
+            BootstrapSymbol::ValueType.into(),
+            AttributeValue::String("not an entity reference".into()),
+        );
+        InMemoryAttributeStore::bootstrap_attribute_types(&entities);
+    }
+
+    #[cfg(feature = "audit-log")]
+    #[test]
+    fn audit_log_records_mutations() {
+        let mut store = InMemoryAttributeStore::new().with_audit_log(NonZeroUsize::new(2).unwrap());
+        let audit_log = store.audit_log().unwrap();
+        let colour_symbol = Symbol::try_from("colour").unwrap();
+
+        store
+            .create_attribute_type(&CreateAttributeTypeRequest {
+                attribute_type: crate::store::AttributeType {
+                    symbol: colour_symbol.clone(),
+                    value_type: ValueType::Text,
+                    description: None,
+                },
+            })
+            .unwrap();
+        let update_entity_request = UpdateEntityRequest {
+            entity_locator: EntityLocator::Symbol(Symbol::try_from("foo").unwrap()),
+            attributes_to_update: vec![
+                AttributeToUpdate {
+                    symbol: BootstrapSymbol::SymbolName.into(),
+                    value: Some(AttributeValue::String("foo".into())),
+                },
+                AttributeToUpdate {
+                    symbol: colour_symbol.clone(),
+                    value: Some(AttributeValue::String("red".into())),
+                },
+            ],
+            dry_run: true,
+        };
+        store.update_entity(&update_entity_request).unwrap();
+        let entity = store
+            .update_entity(&UpdateEntityRequest {
+                dry_run: false,
+                ..update_entity_request.clone()
+            })
+            .unwrap();
+
+        let audit_log = audit_log.lock();
+        assert_eq!(
+            audit_log
+                .entries()
+                .map(|entry| (entry.operation, entry.entity_locator.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    AuditOperation::CreateAttributeType,
+                    EntityLocator::Symbol(colour_symbol)
+                ),
+                (
+                    AuditOperation::UpdateEntity,
+                    update_entity_request.entity_locator
+                ),
+            ]
+        );
+        assert_eq!(
+            audit_log.entries().last().unwrap().attributes,
+            entity.attributes
+        );
+    }
+
+    #[cfg(feature = "audit-log")]
+    #[test]
+    fn audit_log_keeps_most_recent_entries() {
+        let mut store = InMemoryAttributeStore::new().with_audit_log(NonZeroUsize::new(2).unwrap());
+        for name in ["foo", "bar", "baz"] {
+            store
+                .update_entity(&UpdateEntityRequest {
+                    entity_locator: EntityLocator::Symbol(Symbol::try_from(name).unwrap()),
+                    attributes_to_update: vec![AttributeToUpdate {
+                        symbol: BootstrapSymbol::SymbolName.into(),
+                        value: Some(AttributeValue::String(name.into())),
+                    }],
+                    dry_run: false,
+                })
+                .unwrap();
+        }
+
+        let audit_log = store.audit_log().unwrap();
+        assert_eq!(
+            audit_log
+                .lock()
+                .entries()
+                .map(|entry| entry.entity_locator.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                EntityLocator::Symbol(Symbol::try_from("bar").unwrap()),
+                EntityLocator::Symbol(Symbol::try_from("baz").unwrap()),
+            ]
+        );
+    }
+
+    #[cfg(feature = "audit-log")]
+    #[test]
+    fn audit_log_is_disabled_by_default() {
+        assert!(InMemoryAttributeStore::new().audit_log().is_none());
+    }
+
+    #[test]
+    fn can_query_entity_rows_in_pages() {
+        let store = InMemoryAttributeStore::new();
+        let entity_row_query = EntityRowQuery {
+            attribute_types: vec![BootstrapSymbol::SymbolName.into()],
+            root: EntityQueryNode::MatchAll(MatchAllQueryNode),
+            include_deleted: false,
+            filter_nulls: false,
+            filter_rows_where_null: vec![],
+        };
+        let all_entity_rows = store
+            .query_entity_rows(&entity_row_query)
+            .unwrap()
+            .entity_rows;
+
+        let mut paged_entity_rows = vec![];
+        let mut after = None;
+        loop {
+            let page = store
+                .query_entity_rows_page(&entity_row_query, after, 2)
+                .unwrap()
+                .entity_rows;
+            let Some(last_entity_row) = page.last() else {
+                break;
+            };
+            after = Some(last_entity_row.entity_id);
+            paged_entity_rows.extend(page);
+        }
+
+        assert_eq!(paged_entity_rows, all_entity_rows);
+    }
+
+    #[test]
+    fn create_new_locator_always_creates_an_entity() {
+        let mut store = InMemoryAttributeStore::new();
+        let update_entity_request = UpdateEntityRequest {
+            entity_locator: EntityLocator::CreateNew,
+            attributes_to_update: vec![AttributeToUpdate {
+                symbol: BootstrapSymbol::Description.into(),
+                value: Some(AttributeValue::String("ephemeral".into())),
+            }],
+            dry_run: false,
+        };
+
+        let first = store.update_entity(&update_entity_request).unwrap();
+        let second = store.update_entity(&update_entity_request).unwrap();
+
+        assert_ne!(first.entity_id, second.entity_id);
+        assert_eq!(first.attributes, second.attributes);
+        assert_matches!(
+            store
+                .get_entity(&EntityLocator::CreateNew)
+                .unwrap_err()
+                .kind,
+            AttributeStoreErrorKind::EntityNotFound(EntityLocator::CreateNew)
+        );
+    }
+
+    #[test]
+    fn can_filter_rows_with_null_values() {
+        let colour = Symbol::try_from("colour").unwrap();
+        let size = Symbol::try_from("size").unwrap();
+        let store = InMemoryAttributeStore::with_entities_preloaded(vec![
+            vec![(colour.clone(), AttributeValue::String("red".into()))],
+            vec![(size.clone(), AttributeValue::String("big".into()))],
+        ])
+        .unwrap();
+        let rows_matching = |filter_nulls: bool, filter_rows_where_null: Vec<usize>| {
+            store.query_entity_rows(&EntityRowQuery {
+                attribute_types: vec![colour.clone(), size.clone()],
+                root: EntityQueryNode::MatchAll(MatchAllQueryNode),
+                include_deleted: false,
+                filter_nulls,
+                filter_rows_where_null,
+            })
+        };
+        let red = Some(AttributeValue::String("red".into()));
+        let big = Some(AttributeValue::String("big".into()));
+
+        let values = |entity_rows: Vec<EntityRow>| {
+            entity_rows
+                .into_iter()
+                .map(|entity_row| entity_row.values)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            values(rows_matching(true, vec![]).unwrap().entity_rows),
+            vec![vec![red.clone(), None], vec![None, big]]
+        );
+        assert_eq!(
+            values(rows_matching(false, vec![0]).unwrap().entity_rows),
+            vec![vec![red, None]]
+        );
+        assert_matches!(
+            rows_matching(false, vec![2]).unwrap_err().kind,
+            AttributeStoreErrorKind::ValidationError(_)
+        );
+    }
+
+    #[test]
+    fn create_attribute_type_sets_description() {
+        let mut store = InMemoryAttributeStore::new();
+        let entity = store
+            .create_attribute_type(&CreateAttributeTypeRequest {
+                attribute_type: crate::store::AttributeType {
+                    symbol: Symbol::try_from("colour").unwrap(),
+                    value_type: ValueType::Text,
+                    description: Some("The colour of the entity".into()),
+                },
+            })
+            .unwrap();
+        assert_eq!(
+            entity
+                .attributes
+                .get(&Symbol::from(BootstrapSymbol::Description)),
+            Some(&AttributeValue::String("The colour of the entity".into()))
+        );
+    }
+
+    #[test]
+    fn create_attribute_types_skips_existing_types_and_is_atomic() {
+        let mut store = InMemoryAttributeStore::new();
+        let attribute_type = |symbol: &'static str, value_type| crate::store::AttributeType {
+            symbol: Symbol::try_from(symbol).unwrap(),
+            value_type,
+            description: None,
+        };
+        let entity_count = store.entities.len();
+
+        // Nested lists are unsupported, so neither type is created
+        let err = store
+            .create_attribute_types(&[
+                attribute_type("colour", ValueType::Text),
+                attribute_type(
+                    "matrix",
+                    ValueType::List(Box::new(ValueType::List(Box::new(ValueType::Text)))),
+                ),
+            ])
+            .unwrap_err();
+        assert!(matches!(err.kind, UnsupportedValueType(_)));
+        assert_eq!(store.entities.len(), entity_count);
+
+        let result = store
+            .create_attribute_types(&[
+                attribute_type("colour", ValueType::Text),
+                attribute_type("@symbolName", ValueType::Text),
+                attribute_type("colour", ValueType::Bytes),
+                attribute_type("size", ValueType::Text),
+            ])
+            .unwrap();
+        assert_eq!(
+            result
+                .entities
+                .iter()
+                .map(|entity| entity.string_attribute(&BootstrapSymbol::SymbolName.into()))
+                .collect::<Vec<_>>(),
+            vec![Some("colour"), Some("size")]
+        );
+        assert_eq!(
+            result.already_existed,
+            vec![
+                BootstrapSymbol::SymbolName.into(),
+                Symbol::try_from("colour").unwrap()
+            ]
+        );
+        assert_eq!(
+            store
+                .get_attribute_type(&Symbol::try_from("colour").unwrap())
+                .unwrap()
+                .value_type,
+            ValueType::Text
+        );
+    }
+
+    #[test]
+    fn get_attribute_type_looks_up_a_single_type() {
+        let mut store = InMemoryAttributeStore::new();
+        let attribute_type = crate::store::AttributeType {
+            symbol: Symbol::try_from("colour").unwrap(),
+            value_type: ValueType::Text,
+            description: Some("The colour of the entity".into()),
+        };
+        store
+            .create_attribute_type(&CreateAttributeTypeRequest {
+                attribute_type: attribute_type.clone(),
+            })
+            .unwrap();
+
+        assert_eq!(
+            store.get_attribute_type(&attribute_type.symbol).unwrap(),
+            attribute_type
+        );
+        assert_eq!(
+            store
+                .get_attribute_type(&BootstrapSymbol::SymbolName.into())
+                .unwrap()
+                .value_type,
+            ValueType::Text
+        );
+        assert!(matches!(
+            store
+                .get_attribute_type(&Symbol::try_from("size").unwrap())
+                .unwrap_err()
+                .kind,
+            AttributeStoreErrorKind::EntityNotFound(EntityLocator::Symbol(_))
+        ));
+    }
+
+    #[test]
+    fn list_attributes_hold_values_of_their_element_type() {
+        use crate::store::ElementAtNode;
+
+        let mut store = InMemoryAttributeStore::new();
+        let tags = Symbol::try_from("tags").unwrap();
+        let create_attribute_type = |store: &mut InMemoryAttributeStore, value_type| {
+            store.create_attribute_type(&CreateAttributeTypeRequest {
+                attribute_type: crate::store::AttributeType {
+                    symbol: tags.clone(),
+                    value_type,
+                    description: None,
+                },
+            })
+        };
+        assert!(matches!(
+            create_attribute_type(
+                &mut store,
+                ValueType::List(Box::new(ValueType::List(Box::new(ValueType::Text))))
+            )
+            .unwrap_err()
+            .kind,
+            UnsupportedValueType(_)
+        ));
+        create_attribute_type(&mut store, ValueType::List(Box::new(ValueType::Text))).unwrap();
+
+        let set_tags = |store: &mut InMemoryAttributeStore, values| {
+            store.update_entity(&UpdateEntityRequest {
+                entity_locator: EntityLocator::Symbol(Symbol::try_from("tagged").unwrap()),
+                attributes_to_update: vec![
+                    AttributeToUpdate {
+                        symbol: BootstrapSymbol::SymbolName.into(),
+                        value: Some(AttributeValue::String("tagged".into())),
+                    },
+                    AttributeToUpdate {
+                        symbol: tags.clone(),
+                        value: Some(AttributeValue::List(values)),
+                    },
+                ],
+                dry_run: false,
+            })
+        };
+        assert!(matches!(
+            set_tags(&mut store, vec![AttributeValue::Bytes(vec![1])])
+                .unwrap_err()
+                .kind,
+            AttributeStoreErrorKind::ValidationError(_)
+        ));
+        let entity = set_tags(
+            &mut store,
+            vec![
+                AttributeValue::String("red".into()),
+                AttributeValue::String("large".into()),
+            ],
+        )
+        .unwrap();
+
+        let entities_with_element_at = |index, value: &str| {
+            store
+                .query_entities(&EntityQuery {
+                    root: EntityQueryNode::ElementAt(ElementAtNode {
+                        attribute_type: tags.clone(),
+                        index,
+                        value: AttributeValue::String(value.into()),
+                    }),
+                    include_deleted: false,
+                })
+                .unwrap()
+                .entities
+                .into_iter()
+                .map(|entity| entity.entity_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(entities_with_element_at(1, "large"), vec![entity.entity_id]);
+        assert!(entities_with_element_at(0, "large").is_empty());
+        assert!(entities_with_element_at(2, "large").is_empty());
+    }
+
+    #[test]
+    fn has_attribute_value_queries_follow_updates() {
+        use crate::store::HasAttributeValueNode;
+
+        let mut store = InMemoryAttributeStore::new();
+        let symbol_name: Symbol = BootstrapSymbol::SymbolName.into();
+        let description: Symbol = BootstrapSymbol::Description.into();
+        let set_description =
+            |store: &mut InMemoryAttributeStore, name: &'static str, value: &str| {
+                store
+                    .update_entity(&UpdateEntityRequest {
+                        entity_locator: EntityLocator::Symbol(Symbol::try_from(name).unwrap()),
+                        attributes_to_update: vec![
+                            AttributeToUpdate {
+                                symbol: symbol_name.clone(),
+                                value: Some(AttributeValue::String(name.into())),
+                            },
+                            AttributeToUpdate {
+                                symbol: description.clone(),
+                                value: Some(AttributeValue::String(value.into())),
+                            },
+                        ],
+                        dry_run: false,
+                    })
+                    .unwrap()
+                    .entity_id
+            };
+        let first = set_description(&mut store, "first", "red");
+        let second = set_description(&mut store, "second", "red");
+
+        let has_description = |value: &str| {
+            EntityQueryNode::HasAttributeValue(HasAttributeValueNode {
+                attribute_type: description.clone(),
+                value: AttributeValue::String(value.into()),
+            })
+        };
+        let matching = |store: &InMemoryAttributeStore, root: EntityQueryNode| {
+            store
+                .query_entities(&EntityQuery {
+                    root,
+                    include_deleted: false,
+                })
+                .unwrap()
+                .entities
+                .into_iter()
+                .map(|entity| entity.entity_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            matching(&store, has_description("red")),
+            vec![first, second]
+        );
+
+        set_description(&mut store, "first", "blue");
+        assert_eq!(matching(&store, has_description("red")), vec![second]);
+        assert_eq!(matching(&store, has_description("blue")), vec![first]);
+
+        // Candidates from the index are still checked against the other clauses
+        let red_and_first = EntityQueryNode::And(AndQueryNode {
+            clauses: vec![
+                has_description("red"),
+                EntityQueryNode::EntityIdIn(EntityIdInNode {
+                    entity_ids: vec![first],
+                }),
+            ],
+        });
+        assert!(matching(&store, red_and_first).is_empty());
+    }
+
+    #[test]
+    fn can_query_by_partial_symbol_name() {
+        use crate::store::{TextContainsNode, TextMatchesRegexNode, TextStartsWithNode};
+
+        let store = InMemoryAttributeStore::new();
+        let symbol_names_matching = |root: EntityQueryNode| {
+            store
+                .query_entity_rows(&EntityRowQuery {
+                    attribute_types: vec![BootstrapSymbol::SymbolName.into()],
+                    root,
+                    include_deleted: false,
+                    filter_nulls: false,
+                    filter_rows_where_null: vec![],
+                })
+                .unwrap()
+                .entity_rows
+                .into_iter()
+                .flat_map(|entity_row| entity_row.values)
+                .collect::<Vec<_>>()
+        };
+        let symbol_names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| Some(AttributeValue::String(name.to_string())))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            symbol_names_matching(EntityQueryNode::TextContains(TextContainsNode {
+                attribute_type: BootstrapSymbol::SymbolName.into(),
+                substring: "Type/".into(),
+            })),
+            symbol_names(&[
+                "@valueType/text",
+                "@valueType/entityRef",
+                "@valueType/bytes",
+                "@valueType/timestamp",
+                "@valueType/list/text",
+                "@valueType/list/entityRef",
+                "@valueType/list/bytes",
+                "@valueType/list/timestamp",
+                "@valueType/int64",
+                "@valueType/list/int64",
+            ])
+        );
+        assert_eq!(
+            symbol_names_matching(EntityQueryNode::TextStartsWith(TextStartsWithNode {
+                attribute_type: BootstrapSymbol::SymbolName.into(),
+                prefix: "@s".into(),
+            })),
+            symbol_names(&["@symbolName"])
+        );
+        assert_eq!(
+            symbol_names_matching(EntityQueryNode::TextMatchesRegex(
+                TextMatchesRegexNode::new(BootstrapSymbol::SymbolName.into(), "^@[a-z]+$").unwrap()
+            )),
+            symbol_names(&["@id", "@description"])
+        );
+    }
 }
@@ -2,7 +2,11 @@
 #[macro_use]
 extern crate assert_matches;
 
+#[cfg(feature = "audit-log")]
+pub mod audit;
+pub mod caching;
 pub mod inmemory;
+pub mod query_language;
 pub mod store;
 
 pub fn add(left: usize, right: usize) -> usize {
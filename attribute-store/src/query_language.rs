@@ -0,0 +1,846 @@
+//! A human-readable syntax for entity queries, for example
+//! `has(@symbolName, description) AND (text_contains(@symbolName, "pump") OR match_all())`.
+//!
+//! `AND` binds more tightly than `OR`, and parentheses group clauses. Symbols may be written bare
+//! unless they contain whitespace or one of `(),[]"`, in which case they are quoted like text.
+//! Timestamps are seconds since the Unix epoch, with an optional fractional part. Entity IDs are
+//! quoted like text, in the form given by an [`EntityIdFormat`].
+
+use crate::store::{
+    AndQueryNode, AttributeValue, AttributeValueAfterNode, AttributeValueBeforeNode, ElementAtNode,
+    EntityId, EntityIdInNode, EntityQueryNode, GraphTraverseNode, HasAttributeTypesNode,
+    HasAttributeValueNode, MatchAllQueryNode, MatchNoneQueryNode, OrQueryNode, Symbol,
+    TextContainsNode, TextMatchesRegexNode, TextStartsWithNode,
+};
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Error, Debug, Eq, PartialEq, Clone)]
+pub enum ParseError {
+    #[error("unexpected end of query")]
+    UnexpectedEnd,
+    #[error("unexpected `{token}` at offset {offset}")]
+    UnexpectedToken { token: String, offset: usize },
+    #[error("unterminated text starting at offset {0}")]
+    UnterminatedText(usize),
+    #[error("invalid escape `\\{escape}` at offset {offset}")]
+    InvalidEscape { escape: char, offset: usize },
+    #[error("unknown query function `{0}`")]
+    UnknownFunction(String),
+    #[error("unknown value function `{0}`")]
+    UnknownValueFunction(String),
+    #[error("invalid symbol `{0}`")]
+    InvalidSymbol(String),
+    #[error("invalid number `{0}`")]
+    InvalidNumber(String),
+    #[error("invalid timestamp `{0}`")]
+    InvalidTimestamp(String),
+    #[error("invalid hex bytes `{0}`")]
+    InvalidBytes(String),
+    #[error("invalid regex: {0}")]
+    InvalidRegex(String),
+    #[error("invalid entity ID `{0}`")]
+    InvalidEntityId(String),
+    #[error("query nested more than {MAX_NESTING_DEPTH} levels deep")]
+    NestedTooDeeply,
+}
+
+/// Deepest nesting of queries and lists that is parsed, so that the parser's recursion can't
+/// overflow the stack
+const MAX_NESTING_DEPTH: usize = 100;
+
+/// How entity IDs are written in queries. The IDs clients see are opaque, so queries written for
+/// clients use the same form.
+pub trait EntityIdFormat {
+    fn parse_entity_id(&self, text: &str) -> Option<EntityId>;
+
+    fn format_entity_id(&self, entity_id: EntityId) -> String;
+}
+
+impl EntityQueryNode {
+    /// Parses a query written in the query language, with entity IDs in `id_format`.
+    pub fn from_query_string(
+        query: &str,
+        id_format: &dyn EntityIdFormat,
+    ) -> Result<EntityQueryNode, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(query)?,
+            position: 0,
+            depth: 0,
+            id_format,
+        };
+        let node = parser.query()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(node),
+            Some((offset, token)) => Err(ParseError::UnexpectedToken {
+                token: token.to_string(),
+                offset: *offset,
+            }),
+        }
+    }
+
+    /// Writes this query in the query language, with entity IDs in `id_format`.
+    ///
+    /// Empty `AND` and `OR` clauses are written as `match_all()` and `match_none()`, which they
+    /// are equivalent to.
+    pub fn to_query_string(&self, id_format: &dyn EntityIdFormat) -> String {
+        let mut output = String::new();
+        write_node(&mut output, self, id_format);
+        output
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+enum Token {
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    Comma,
+    Word(String),
+    Text(String),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::OpenParen => f.write_str("("),
+            Token::CloseParen => f.write_str(")"),
+            Token::OpenBracket => f.write_str("["),
+            Token::CloseBracket => f.write_str("]"),
+            Token::Comma => f.write_str(","),
+            Token::Word(word) => f.write_str(word),
+            Token::Text(text) => f.write_str(&quote(text)),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '(' | ')' | '[' | ']' | ',' | '"')
+}
+
+fn tokenize(query: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = query.char_indices().peekable();
+
+    while let Some((offset, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '[' => Token::OpenBracket,
+            ']' => Token::CloseBracket,
+            ',' => Token::Comma,
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(ParseError::UnterminatedText(offset)),
+                        Some((_, '"')) => break,
+                        Some((escape_offset, '\\')) => match chars.next() {
+                            None => return Err(ParseError::UnterminatedText(offset)),
+                            Some((_, '"')) => text.push('"'),
+                            Some((_, '\\')) => text.push('\\'),
+                            Some((_, 'n')) => text.push('\n'),
+                            Some((_, 'r')) => text.push('\r'),
+                            Some((_, 't')) => text.push('\t'),
+                            Some((_, escape)) => {
+                                return Err(ParseError::InvalidEscape {
+                                    escape,
+                                    offset: escape_offset,
+                                })
+                            }
+                        },
+                        Some((_, c)) => text.push(c),
+                    }
+                }
+                Token::Text(text)
+            }
+            c => {
+                let mut word = String::from(c);
+                while let Some((_, c)) = chars.next_if(|(_, c)| is_word_char(*c)) {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push((offset, token));
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive-descent parser over the tokens of a query.
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    /// Number of queries and lists being parsed that enclose the current token
+    depth: usize,
+    id_format: &'a dyn EntityIdFormat,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Result<(usize, Token), ParseError> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or(ParseError::UnexpectedEnd)?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        match self.next()? {
+            (_, token) if token == expected => Ok(()),
+            (offset, token) => Err(unexpected(offset, &token)),
+        }
+    }
+
+    fn next_is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    /// Parses with `parse` one level of nesting deeper.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth == MAX_NESTING_DEPTH {
+            return Err(ParseError::NestedTooDeeply);
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// `query := conjunction ("OR" conjunction)*`
+    fn query(&mut self) -> Result<EntityQueryNode, ParseError> {
+        self.nested(Self::disjunction)
+    }
+
+    fn disjunction(&mut self) -> Result<EntityQueryNode, ParseError> {
+        let mut clauses = vec![self.conjunction()?];
+        while self.next_is_keyword("OR") {
+            self.position += 1;
+            clauses.push(self.conjunction()?);
+        }
+        Ok(match clauses.len() {
+            1 => clauses.pop().unwrap(),
+            _ => EntityQueryNode::Or(OrQueryNode { clauses }),
+        })
+    }
+
+    /// `conjunction := primary ("AND" primary)*`
+    fn conjunction(&mut self) -> Result<EntityQueryNode, ParseError> {
+        let mut clauses = vec![self.primary()?];
+        while self.next_is_keyword("AND") {
+            self.position += 1;
+            clauses.push(self.primary()?);
+        }
+        Ok(match clauses.len() {
+            1 => clauses.pop().unwrap(),
+            _ => EntityQueryNode::And(AndQueryNode { clauses }),
+        })
+    }
+
+    /// `primary := "(" query ")" | function "(" arguments ")"`
+    fn primary(&mut self) -> Result<EntityQueryNode, ParseError> {
+        let name = match self.next()? {
+            (_, Token::OpenParen) => {
+                let node = self.query()?;
+                self.expect(Token::CloseParen)?;
+                return Ok(node);
+            }
+            (_, Token::Word(name)) => name,
+            (offset, token) => return Err(unexpected(offset, &token)),
+        };
+
+        self.expect(Token::OpenParen)?;
+        let node = match name.as_str() {
+            "match_all" => EntityQueryNode::MatchAll(MatchAllQueryNode),
+            "match_none" => EntityQueryNode::MatchNone(MatchNoneQueryNode),
+            // Lists consume their closing parenthesis
+            "has" => {
+                return Ok(EntityQueryNode::HasAttributeTypes(HasAttributeTypesNode {
+                    attribute_types: self.list(Token::CloseParen, Self::symbol)?,
+                }))
+            }
+            "entity_id_in" => {
+                return Ok(EntityQueryNode::EntityIdIn(EntityIdInNode {
+                    entity_ids: self.list(Token::CloseParen, Self::entity_id)?,
+                }))
+            }
+            "text_contains" => {
+                let attribute_type = self.symbol()?;
+                self.expect(Token::Comma)?;
+                EntityQueryNode::TextContains(TextContainsNode {
+                    attribute_type,
+                    substring: self.text()?,
+                })
+            }
+            "text_starts_with" => {
+                let attribute_type = self.symbol()?;
+                self.expect(Token::Comma)?;
+                EntityQueryNode::TextStartsWith(TextStartsWithNode {
+                    attribute_type,
+                    prefix: self.text()?,
+                })
+            }
+            "text_matches_regex" => {
+                let attribute_type = self.symbol()?;
+                self.expect(Token::Comma)?;
+                let pattern = self.text()?;
+                EntityQueryNode::TextMatchesRegex(
+                    TextMatchesRegexNode::new(attribute_type, &pattern)
+                        .map_err(|err| ParseError::InvalidRegex(err.to_string()))?,
+                )
+            }
+            "attribute_value_after" => {
+                let attribute_type = self.symbol()?;
+                self.expect(Token::Comma)?;
+                EntityQueryNode::AttributeValueAfter(AttributeValueAfterNode {
+                    attribute_type,
+                    timestamp: self.timestamp()?,
+                })
+            }
+            "attribute_value_before" => {
+                let attribute_type = self.symbol()?;
+                self.expect(Token::Comma)?;
+                EntityQueryNode::AttributeValueBefore(AttributeValueBeforeNode {
+                    attribute_type,
+                    timestamp: self.timestamp()?,
+                })
+            }
+            "graph_traverse" => {
+                let root = Box::new(self.query()?);
+                self.expect(Token::Comma)?;
+                let start_attribute_type = self.symbol()?;
+                self.expect(Token::Comma)?;
+                EntityQueryNode::GraphTraverse(GraphTraverseNode {
+                    root,
+                    start_attribute_type,
+                    depth_limit: self.number()?,
+                })
+            }
+            "element_at" => {
+                let attribute_type = self.symbol()?;
+                self.expect(Token::Comma)?;
+                let index = self.number()?;
+                self.expect(Token::Comma)?;
+                EntityQueryNode::ElementAt(ElementAtNode {
+                    attribute_type,
+                    index,
+                    value: self.value()?,
+                })
+            }
+            "has_attribute_value" => {
+                let attribute_type = self.symbol()?;
+                self.expect(Token::Comma)?;
+                EntityQueryNode::HasAttributeValue(HasAttributeValueNode {
+                    attribute_type,
+                    value: self.value()?,
+                })
+            }
+            _ => return Err(ParseError::UnknownFunction(name)),
+        };
+        self.expect(Token::CloseParen)?;
+        Ok(node)
+    }
+
+    /// Parses comma-separated items up to and including `close`.
+    fn list<T>(
+        &mut self,
+        close: Token,
+        mut item: impl FnMut(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<Vec<T>, ParseError> {
+        let mut items = vec![];
+        if self.peek() == Some(&close) {
+            self.position += 1;
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            match self.next()? {
+                (_, Token::Comma) => continue,
+                (_, token) if token == close => return Ok(items),
+                (offset, token) => return Err(unexpected(offset, &token)),
+            }
+        }
+    }
+
+    fn symbol(&mut self) -> Result<Symbol, ParseError> {
+        let name = match self.next()? {
+            (_, Token::Word(name) | Token::Text(name)) => name,
+            (offset, token) => return Err(unexpected(offset, &token)),
+        };
+        Symbol::try_from(name.clone()).map_err(|_| ParseError::InvalidSymbol(name))
+    }
+
+    fn text(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            (_, Token::Text(text)) => Ok(text),
+            (offset, token) => Err(unexpected(offset, &token)),
+        }
+    }
+
+    fn entity_id(&mut self) -> Result<EntityId, ParseError> {
+        let text = self.text()?;
+        self.id_format
+            .parse_entity_id(&text)
+            .ok_or(ParseError::InvalidEntityId(text))
+    }
+
+    fn word(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            (_, Token::Word(word)) => Ok(word),
+            (offset, token) => Err(unexpected(offset, &token)),
+        }
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, ParseError> {
+        let word = self.word()?;
+        word.parse().map_err(|_| ParseError::InvalidNumber(word))
+    }
+
+    fn timestamp(&mut self) -> Result<SystemTime, ParseError> {
+        let word = self.word()?;
+        parse_timestamp(&word).ok_or(ParseError::InvalidTimestamp(word))
+    }
+
+    /// `value := text | "[" values "]" | "entity(" id ")" | "bytes(" hex ")" | "timestamp(" seconds ")"
+    ///     | "int64(" integer ")"`
+    fn value(&mut self) -> Result<AttributeValue, ParseError> {
+        let name = match self.next()? {
+            (_, Token::Text(text)) => return Ok(AttributeValue::String(text)),
+            (_, Token::OpenBracket) => {
+                return Ok(AttributeValue::List(
+                    self.nested(|parser| parser.list(Token::CloseBracket, Self::value))?,
+                ))
+            }
+            (_, Token::Word(name)) => name,
+            (offset, token) => return Err(unexpected(offset, &token)),
+        };
+
+        self.expect(Token::OpenParen)?;
+        let value = match name.as_str() {
+            "entity" => AttributeValue::EntityId(self.entity_id()?),
+            "bytes" => {
+                let hex = self.text()?;
+                AttributeValue::Bytes(parse_hex(&hex).ok_or(ParseError::InvalidBytes(hex))?)
+            }
+            "timestamp" => AttributeValue::Timestamp(self.timestamp()?),
+            "int64" => AttributeValue::Int64(self.number()?),
+            _ => return Err(ParseError::UnknownValueFunction(name)),
+        };
+        self.expect(Token::CloseParen)?;
+        Ok(value)
+    }
+}
+
+fn unexpected(offset: usize, token: &Token) -> ParseError {
+    ParseError::UnexpectedToken {
+        token: token.to_string(),
+        offset,
+    }
+}
+
+/// Parses `[-]seconds[.fraction]` relative to the Unix epoch, to nanosecond precision.
+fn parse_timestamp(word: &str) -> Option<SystemTime> {
+    let (negative, unsigned) = match word.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, word),
+    };
+    let (seconds, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if !seconds.bytes().all(|b| b.is_ascii_digit())
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+        || fraction.len() > 9
+    {
+        return None;
+    }
+    let nanos = if fraction.is_empty() {
+        0
+    } else {
+        format!("{fraction:0<9}").parse().ok()?
+    };
+    let duration = Duration::new(seconds.parse().ok()?, nanos);
+
+    if negative {
+        UNIX_EPOCH.checked_sub(duration)
+    } else {
+        UNIX_EPOCH.checked_add(duration)
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(&hex[idx..idx + 2], 16).ok())
+        .collect()
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn write_symbol(output: &mut String, symbol: &Symbol) {
+    if symbol.chars().all(is_word_char) {
+        output.push_str(symbol);
+    } else {
+        output.push_str(&quote(symbol));
+    }
+}
+
+fn write_timestamp(output: &mut String, timestamp: SystemTime) {
+    let (sign, duration) = match timestamp.duration_since(UNIX_EPOCH) {
+        Ok(duration) => ("", duration),
+        Err(err) => ("-", err.duration()),
+    };
+    write!(output, "{sign}{}", duration.as_secs()).unwrap();
+    if duration.subsec_nanos() != 0 {
+        let fraction = format!("{:09}", duration.subsec_nanos());
+        write!(output, ".{}", fraction.trim_end_matches('0')).unwrap();
+    }
+}
+
+fn write_value(output: &mut String, value: &AttributeValue, id_format: &dyn EntityIdFormat) {
+    match value {
+        AttributeValue::String(text) => output.push_str(&quote(text)),
+        AttributeValue::EntityId(entity_id) => write!(
+            output,
+            "entity({})",
+            quote(&id_format.format_entity_id(*entity_id))
+        )
+        .unwrap(),
+        AttributeValue::Bytes(bytes) => {
+            output.push_str("bytes(\"");
+            for byte in bytes {
+                write!(output, "{byte:02x}").unwrap();
+            }
+            output.push_str("\")");
+        }
+        AttributeValue::Timestamp(timestamp) => {
+            output.push_str("timestamp(");
+            write_timestamp(output, *timestamp);
+            output.push(')');
+        }
+        AttributeValue::Int64(value) => write!(output, "int64({value})").unwrap(),
+        AttributeValue::List(values) => {
+            output.push('[');
+            for (idx, value) in values.iter().enumerate() {
+                if idx > 0 {
+                    output.push_str(", ");
+                }
+                write_value(output, value, id_format);
+            }
+            output.push(']');
+        }
+    }
+}
+
+fn write_clauses(
+    output: &mut String,
+    clauses: &[EntityQueryNode],
+    operator: &str,
+    empty: &str,
+    id_format: &dyn EntityIdFormat,
+) {
+    if clauses.is_empty() {
+        output.push_str(empty);
+        return;
+    }
+    for (idx, clause) in clauses.iter().enumerate() {
+        if idx > 0 {
+            write!(output, " {operator} ").unwrap();
+        }
+        // Nested clauses are always parenthesised, so that their structure survives a round trip
+        if matches!(clause, EntityQueryNode::And(_) | EntityQueryNode::Or(_)) {
+            output.push('(');
+            write_node(output, clause, id_format);
+            output.push(')');
+        } else {
+            write_node(output, clause, id_format);
+        }
+    }
+}
+
+fn write_node(output: &mut String, node: &EntityQueryNode, id_format: &dyn EntityIdFormat) {
+    match node {
+        EntityQueryNode::MatchAll(_) => output.push_str("match_all()"),
+        EntityQueryNode::MatchNone(_) => output.push_str("match_none()"),
+        EntityQueryNode::And(AndQueryNode { clauses }) => {
+            write_clauses(output, clauses, "AND", "match_all()", id_format)
+        }
+        EntityQueryNode::Or(OrQueryNode { clauses }) => {
+            write_clauses(output, clauses, "OR", "match_none()", id_format)
+        }
+        EntityQueryNode::HasAttributeTypes(HasAttributeTypesNode { attribute_types }) => {
+            output.push_str("has(");
+            for (idx, attribute_type) in attribute_types.iter().enumerate() {
+                if idx > 0 {
+                    output.push_str(", ");
+                }
+                write_symbol(output, attribute_type);
+            }
+            output.push(')');
+        }
+        EntityQueryNode::EntityIdIn(EntityIdInNode { entity_ids }) => {
+            output.push_str("entity_id_in(");
+            for (idx, entity_id) in entity_ids.iter().enumerate() {
+                if idx > 0 {
+                    output.push_str(", ");
+                }
+                output.push_str(&quote(&id_format.format_entity_id(*entity_id)));
+            }
+            output.push(')');
+        }
+        EntityQueryNode::TextContains(TextContainsNode {
+            attribute_type,
+            substring,
+        }) => {
+            output.push_str("text_contains(");
+            write_symbol(output, attribute_type);
+            write!(output, ", {})", quote(substring)).unwrap();
+        }
+        EntityQueryNode::TextStartsWith(TextStartsWithNode {
+            attribute_type,
+            prefix,
+        }) => {
+            output.push_str("text_starts_with(");
+            write_symbol(output, attribute_type);
+            write!(output, ", {})", quote(prefix)).unwrap();
+        }
+        EntityQueryNode::TextMatchesRegex(node) => {
+            output.push_str("text_matches_regex(");
+            write_symbol(output, &node.attribute_type);
+            write!(output, ", {})", quote(node.regex().as_str())).unwrap();
+        }
+        EntityQueryNode::AttributeValueAfter(AttributeValueAfterNode {
+            attribute_type,
+            timestamp,
+        }) => {
+            output.push_str("attribute_value_after(");
+            write_symbol(output, attribute_type);
+            output.push_str(", ");
+            write_timestamp(output, *timestamp);
+            output.push(')');
+        }
+        EntityQueryNode::AttributeValueBefore(AttributeValueBeforeNode {
+            attribute_type,
+            timestamp,
+        }) => {
+            output.push_str("attribute_value_before(");
+            write_symbol(output, attribute_type);
+            output.push_str(", ");
+            write_timestamp(output, *timestamp);
+            output.push(')');
+        }
+        EntityQueryNode::GraphTraverse(GraphTraverseNode {
+            root,
+            start_attribute_type,
+            depth_limit,
+        }) => {
+            output.push_str("graph_traverse(");
+            write_node(output, root, id_format);
+            output.push_str(", ");
+            write_symbol(output, start_attribute_type);
+            write!(output, ", {depth_limit})").unwrap();
+        }
+        EntityQueryNode::ElementAt(ElementAtNode {
+            attribute_type,
+            index,
+            value,
+        }) => {
+            output.push_str("element_at(");
+            write_symbol(output, attribute_type);
+            write!(output, ", {index}, ").unwrap();
+            write_value(output, value, id_format);
+            output.push(')');
+        }
+        EntityQueryNode::HasAttributeValue(HasAttributeValueNode {
+            attribute_type,
+            value,
+        }) => {
+            output.push_str("has_attribute_value(");
+            write_symbol(output, attribute_type);
+            output.push_str(", ");
+            write_value(output, value, id_format);
+            output.push(')');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes entity IDs as their database IDs
+    struct DatabaseIds;
+
+    impl EntityIdFormat for DatabaseIds {
+        fn parse_entity_id(&self, text: &str) -> Option<EntityId> {
+            text.parse().ok().map(EntityId)
+        }
+
+        fn format_entity_id(&self, EntityId(database_id): EntityId) -> String {
+            database_id.to_string()
+        }
+    }
+
+    fn parse(query: &str) -> Result<EntityQueryNode, ParseError> {
+        EntityQueryNode::from_query_string(query, &DatabaseIds)
+    }
+
+    fn symbol(name: &'static str) -> Symbol {
+        Symbol::try_from(name).unwrap()
+    }
+
+    fn has(names: &[&'static str]) -> EntityQueryNode {
+        EntityQueryNode::HasAttributeTypes(HasAttributeTypesNode {
+            attribute_types: names.iter().copied().map(symbol).collect(),
+        })
+    }
+
+    #[test]
+    fn and_binds_more_tightly_than_or() {
+        assert_eq!(
+            parse("has(a) OR has(b, c) and (has(d) OR match_all())"),
+            Ok(EntityQueryNode::Or(OrQueryNode {
+                clauses: vec![
+                    has(&["a"]),
+                    EntityQueryNode::And(AndQueryNode {
+                        clauses: vec![
+                            has(&["b", "c"]),
+                            EntityQueryNode::Or(OrQueryNode {
+                                clauses: vec![
+                                    has(&["d"]),
+                                    EntityQueryNode::MatchAll(MatchAllQueryNode),
+                                ]
+                            }),
+                        ]
+                    }),
+                ]
+            }))
+        );
+    }
+
+    #[test]
+    fn query_strings_round_trip() {
+        let node = EntityQueryNode::And(AndQueryNode {
+            clauses: vec![
+                has(&["@symbolName", "with space"]),
+                EntityQueryNode::Or(OrQueryNode {
+                    clauses: vec![
+                        EntityQueryNode::TextContains(TextContainsNode {
+                            attribute_type: symbol("name"),
+                            substring: "say \"hi\"\n".into(),
+                        }),
+                        EntityQueryNode::TextMatchesRegex(
+                            TextMatchesRegexNode::new(symbol("name"), r"^\d+$").unwrap(),
+                        ),
+                        EntityQueryNode::EntityIdIn(EntityIdInNode {
+                            entity_ids: vec![EntityId(3), EntityId(4)],
+                        }),
+                    ],
+                }),
+                EntityQueryNode::AttributeValueBefore(AttributeValueBeforeNode {
+                    attribute_type: symbol("startedAt"),
+                    timestamp: UNIX_EPOCH - Duration::from_millis(1500),
+                }),
+                EntityQueryNode::GraphTraverse(GraphTraverseNode {
+                    root: Box::new(EntityQueryNode::MatchNone(MatchNoneQueryNode)),
+                    start_attribute_type: symbol("parent"),
+                    depth_limit: 2,
+                }),
+                EntityQueryNode::HasAttributeValue(HasAttributeValueNode {
+                    attribute_type: symbol("values"),
+                    value: AttributeValue::List(vec![
+                        AttributeValue::EntityId(EntityId(7)),
+                        AttributeValue::Bytes(vec![0x0a, 0xff]),
+                        AttributeValue::Timestamp(UNIX_EPOCH + Duration::new(10, 500)),
+                        AttributeValue::Int64(-3),
+                    ]),
+                }),
+            ],
+        });
+
+        let query_string = node.to_query_string(&DatabaseIds);
+        assert_eq!(
+            query_string,
+            r#"has(@symbolName, "with space") AND (text_contains(name, "say \"hi\"\n") OR text_matches_regex(name, "^\\d+$") OR entity_id_in("3", "4")) AND attribute_value_before(startedAt, -1.5) AND graph_traverse(match_none(), parent, 2) AND has_attribute_value(values, [entity("7"), bytes("0aff"), timestamp(10.0000005), int64(-3)])"#
+        );
+        assert_eq!(parse(&query_string), Ok(node));
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        assert_eq!(parse("has(a) AND"), Err(ParseError::UnexpectedEnd));
+        assert_eq!(
+            parse("has(a) has(b)"),
+            Err(ParseError::UnexpectedToken {
+                token: "has".into(),
+                offset: 7
+            })
+        );
+        assert_eq!(
+            parse("matches(a)"),
+            Err(ParseError::UnknownFunction("matches".into()))
+        );
+        assert_eq!(
+            parse(r#"text_contains(a, "b)"#),
+            Err(ParseError::UnterminatedText(17))
+        );
+        assert_matches!(
+            parse(r#"text_matches_regex(a, "(")"#),
+            Err(ParseError::InvalidRegex(_))
+        );
+    }
+
+    #[test]
+    fn rejects_deeply_nested_queries() {
+        let nested =
+            |depth: usize| format!("{}match_all(){}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse(&nested(MAX_NESTING_DEPTH - 1)).is_ok());
+        assert_eq!(parse(&nested(40_000)), Err(ParseError::NestedTooDeeply));
+
+        let nested_lists = format!("has_attribute_value(a, {})", "[".repeat(40_000));
+        assert_eq!(parse(&nested_lists), Err(ParseError::NestedTooDeeply));
+    }
+
+    #[test]
+    fn rejects_entity_ids_in_the_wrong_form() {
+        assert_eq!(
+            parse(r#"entity_id_in("not an id")"#),
+            Err(ParseError::InvalidEntityId("not an id".into()))
+        );
+        assert_eq!(
+            parse("entity_id_in(3)"),
+            Err(ParseError::UnexpectedToken {
+                token: "3".into(),
+                offset: 13
+            })
+        );
+    }
+}
@@ -1,16 +1,19 @@
 use async_trait::async_trait;
 use parking_lot::Mutex;
-use regex::Regex;
-use std::borrow::Cow;
+use regex::{Regex, RegexBuilder};
+use std::borrow::{Borrow, Cow};
 use std::boxed::Box;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::Into;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
 use std::ops::Deref;
 use std::sync::{Arc, LazyLock};
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 use tokio::sync::broadcast::Receiver;
+use tracing_error::{SpanTrace, SpanTraceStatus};
 
 #[derive(Error, Debug)]
 pub enum AttributeStoreErrorKind {
@@ -22,6 +25,8 @@ pub enum AttributeStoreErrorKind {
     AttributeTypeAlreadyExists(Entity),
     #[error("invalid value type entity ID: `{0:?}`")]
     InvalidValueType(EntityId),
+    #[error("value type `{0:?}` is not supported")]
+    UnsupportedValueType(ValueType),
     #[error("validation error")]
     ValidationError(#[from] garde::Report),
     #[error(
@@ -32,6 +37,8 @@ pub enum AttributeStoreErrorKind {
         missing_attribute_to_update: AttributeToUpdate,
         entity_locator: EntityLocator,
     },
+    #[error("store is full (limit: {limit} entities)")]
+    StoreFull { limit: usize },
     #[error("internal error: `{message}`")]
     Other {
         message: String,
@@ -42,13 +49,17 @@ pub enum AttributeStoreErrorKind {
 #[derive(Debug)]
 pub struct AttributeStoreError {
     pub kind: AttributeStoreErrorKind,
-    // put SpanTrace and similar here
+    /// The spans the error was created in. Only captured when the subscriber has an `ErrorLayer`.
+    pub span_trace: SpanTrace,
 }
 
 impl Display for AttributeStoreError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        // Is this correct?
-        std::fmt::Display::fmt(&self.kind, f)
+        std::fmt::Display::fmt(&self.kind, f)?;
+        if self.span_trace.status() == SpanTraceStatus::CAPTURED {
+            write!(f, "\n{}", self.span_trace)?;
+        }
+        Ok(())
     }
 }
 
@@ -60,14 +71,24 @@ impl std::error::Error for AttributeStoreError {
 
 impl<T: Into<AttributeStoreErrorKind>> From<T> for AttributeStoreError {
     fn from(value: T) -> Self {
-        AttributeStoreError { kind: value.into() }
+        AttributeStoreError {
+            kind: value.into(),
+            span_trace: SpanTrace::capture(),
+        }
     }
 }
 
 #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct EntityId(pub i64);
 
+/// A store-wide counter that advances on every mutation. An entity's version is the store's
+/// version when the entity was last modified, and query results and bookmarks report the store's
+/// current version.
 #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct EntityVersion(pub i64);
 
 impl From<i64> for EntityId {
@@ -89,11 +110,58 @@ impl TryFrom<EntityId> for usize {
 }
 
 #[derive(Eq, PartialEq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
 pub struct Symbol(Cow<'static, str>);
 
-static SYMBOL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r#"^[[:print:]--[\\"]]{1,60}$"#).expect("Failed to compile symbol regex")
-});
+/// The names a store accepts for symbols. Every policy is at most as permissive as
+/// [`SymbolPolicy::unicode`], which every `Symbol` satisfies.
+#[derive(Debug, Clone)]
+pub struct SymbolPolicy {
+    /// Matched against the whole name
+    pub pattern: Regex,
+    /// Maximum length in characters
+    pub max_len: usize,
+}
+
+const MAX_SYMBOL_LEN: usize = 60;
+
+static UNICODE_SYMBOL_POLICY: LazyLock<SymbolPolicy> = LazyLock::new(SymbolPolicy::unicode);
+
+impl SymbolPolicy {
+    /// Printable ASCII, excluding `\` and `"`
+    pub fn ascii() -> Self {
+        SymbolPolicy {
+            pattern: Regex::new(r#"^[[:print:]--[\\"]]+$"#)
+                .expect("Failed to compile symbol regex"),
+            max_len: MAX_SYMBOL_LEN,
+        }
+    }
+
+    /// Printable Unicode (letters, marks, numbers, punctuation, symbols and spaces), excluding `\`
+    /// and `"`
+    pub fn unicode() -> Self {
+        SymbolPolicy {
+            pattern: Regex::new(r#"^[[\p{L}\p{M}\p{N}\p{P}\p{S}\p{Zs}]--[\\"]]+$"#)
+                .expect("Failed to compile symbol regex"),
+            max_len: MAX_SYMBOL_LEN,
+        }
+    }
+
+    pub fn is_valid(&self, name: &str) -> bool {
+        name.chars().count() <= self.max_len && self.pattern.is_match(name)
+    }
+
+    pub fn validate(&self, symbol: &Symbol) -> Result<(), AttributeStoreError> {
+        use AttributeStoreErrorKind::*;
+
+        if !self.is_valid(symbol) {
+            Err(InvalidSymbolName(symbol.0.clone()))?
+        } else {
+            Ok(())
+        }
+    }
+}
 
 impl TryFrom<Cow<'static, str>> for Symbol {
     type Error = AttributeStoreError;
@@ -101,7 +169,7 @@ impl TryFrom<Cow<'static, str>> for Symbol {
     fn try_from(string: Cow<'static, str>) -> Result<Self, Self::Error> {
         use AttributeStoreErrorKind::*;
 
-        if !SYMBOL_REGEX.is_match(&string) {
+        if !UNICODE_SYMBOL_POLICY.is_valid(&string) {
             Err(InvalidSymbolName(string))?
         } else {
             Ok(Symbol(string))
@@ -134,6 +202,34 @@ impl From<Symbol> for String {
     }
 }
 
+impl Symbol {
+    pub fn as_str(&self) -> &str {
+        self
+    }
+
+    /// Joins `namespace` and `local_name` with a `/`. The namespace must be non-empty and must not
+    /// contain a `/` itself.
+    pub fn with_namespace(namespace: &str, local_name: &str) -> Result<Self, AttributeStoreError> {
+        use AttributeStoreErrorKind::*;
+
+        let symbol = format!("{namespace}/{local_name}");
+        if namespace.is_empty() || namespace.contains('/') {
+            return Err(InvalidSymbolName(symbol.into()))?;
+        }
+        Symbol::try_from(symbol)
+    }
+
+    /// The part of the symbol before its first `/`, if it has one
+    pub fn namespace(&self) -> Option<&str> {
+        self.as_symbol_ref().namespace()
+    }
+
+    /// The part of the symbol after its first `/`, or the whole symbol if it has no namespace
+    pub fn local_name(&self) -> &str {
+        self.as_symbol_ref().local_name()
+    }
+}
+
 impl Deref for Symbol {
     type Target = str;
 
@@ -143,21 +239,101 @@ impl Deref for Symbol {
     }
 }
 
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+/// Allows maps keyed by `Symbol` to be looked up by `&str`. `Symbol` hashes and compares exactly
+/// as its underlying `str`.
+impl Borrow<str> for Symbol {
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
+/// A validated symbol that borrows its name, so that `Symbol`-keyed maps can be looked up without
+/// allocating a `Symbol`.
+#[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
+pub struct SymbolRef<'a>(&'a str);
+
+impl<'a> TryFrom<&'a str> for SymbolRef<'a> {
+    type Error = AttributeStoreError;
+
+    fn try_from(string: &'a str) -> Result<Self, Self::Error> {
+        use AttributeStoreErrorKind::*;
+
+        if !UNICODE_SYMBOL_POLICY.is_valid(string) {
+            Err(InvalidSymbolName(string.to_owned().into()))?
+        } else {
+            Ok(SymbolRef(string))
+        }
+    }
+}
+
+impl<'a> SymbolRef<'a> {
+    pub fn as_str(&self) -> &'a str {
+        let SymbolRef(inner) = self;
+        inner
+    }
+
+    /// The part of the symbol before its first `/`, if it has one
+    pub fn namespace(&self) -> Option<&'a str> {
+        self.as_str()
+            .split_once('/')
+            .map(|(namespace, _)| namespace)
+    }
+
+    /// The part of the symbol after its first `/`, or the whole symbol if it has no namespace
+    pub fn local_name(&self) -> &'a str {
+        self.as_str()
+            .split_once('/')
+            .map_or(self.as_str(), |(_, local_name)| local_name)
+    }
+}
+
+impl Deref for SymbolRef<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl<'a> From<SymbolRef<'a>> for Symbol {
+    fn from(value: SymbolRef<'a>) -> Self {
+        Symbol(value.as_str().to_owned().into())
+    }
+}
+
+impl Symbol {
+    pub fn as_symbol_ref(&self) -> SymbolRef<'_> {
+        SymbolRef(self)
+    }
+}
+
 pub type AttributeTypes = HashMap<Symbol, ValueType>;
 
 #[derive(Eq, PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AttributeType {
     pub symbol: Symbol,
     pub value_type: ValueType,
+    pub description: Option<String>,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum EntityLocator {
     EntityId(EntityId),
     Symbol(Symbol),
+    /// Updates with this locator always create a new entity, which needs no `@symbolName`. No
+    /// existing entity is located by it.
+    CreateNew,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Entity {
     pub entity_id: EntityId,
     pub entity_version: EntityVersion,
@@ -165,42 +341,203 @@ pub struct Entity {
     pub attributes: HashMap<Symbol, AttributeValue>,
 }
 
-static ENTITY_ID_SYMBOL: LazyLock<Symbol> = LazyLock::new(|| BootstrapSymbol::EntityId.into());
-
 impl Entity {
+    pub fn has_attribute(&self, symbol: &Symbol) -> bool {
+        self.attributes.contains_key(symbol)
+    }
+
+    pub fn attribute_value(&self, symbol: &Symbol) -> Option<&AttributeValue> {
+        self.attributes.get(symbol)
+    }
+
+    /// The value of a text attribute. `None` if the attribute is unset or not text.
+    pub fn string_attribute(&self, symbol: &Symbol) -> Option<&str> {
+        match self.attribute_value(symbol) {
+            Some(AttributeValue::String(text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The value of an entity reference attribute. `None` if the attribute is unset or not an
+    /// entity reference.
+    pub fn entity_id_attribute(&self, symbol: &Symbol) -> Option<EntityId> {
+        match self.attribute_value(symbol) {
+            Some(AttributeValue::EntityId(entity_id)) => Some(*entity_id),
+            _ => None,
+        }
+    }
+
+    /// The value of a bytes attribute. `None` if the attribute is unset or not bytes.
+    pub fn bytes_attribute(&self, symbol: &Symbol) -> Option<&[u8]> {
+        match self.attribute_value(symbol) {
+            Some(AttributeValue::Bytes(bytes)) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// The elements of a list attribute. `None` if the attribute is unset or not a list.
+    pub fn list_attribute(&self, symbol: &Symbol) -> Option<&[AttributeValue]> {
+        match self.attribute_value(symbol) {
+            Some(AttributeValue::List(values)) => Some(values),
+            _ => None,
+        }
+    }
+
+    fn timestamp_attribute(&self, attribute_type: &Symbol) -> Option<SystemTime> {
+        match self.attribute_value(attribute_type) {
+            Some(AttributeValue::Timestamp(timestamp)) => Some(*timestamp),
+            _ => None,
+        }
+    }
+
     pub fn to_entity_row<'a, I: IntoIterator<Item = &'a Symbol>>(
         &self,
         attribute_types: I,
     ) -> EntityRow {
         EntityRow {
+            entity_id: self.entity_id,
             values: attribute_types
                 .into_iter()
-                .map(|attribute_type| {
-                    if attribute_type == ENTITY_ID_SYMBOL.deref() {
-                        Some(AttributeValue::EntityId(self.entity_id))
-                    } else {
-                        self.attributes.get(attribute_type).cloned()
-                    }
-                })
+                .map(
+                    |attribute_type| match VirtualAttribute::from_symbol(attribute_type) {
+                        Some(virtual_attribute) => Some(virtual_attribute.value(self)),
+                        None => self.attributes.get(attribute_type).cloned(),
+                    },
+                )
                 .collect(),
         }
     }
+
+    /// Whether the entity has been soft-deleted, i.e. has a `@deletedAt` attribute
+    pub fn is_deleted(&self) -> bool {
+        self.attributes
+            .contains_key(SymbolRef::from(BootstrapSymbol::DeletedAt).as_str())
+    }
+
+    /// The attribute changes that turn `before` into `after`
+    pub fn diff(before: &Entity, after: &Entity) -> EntityDiff {
+        let mut entity_diff = EntityDiff::default();
+        for (symbol, after_value) in &after.attributes {
+            match before.attributes.get(symbol) {
+                None => {
+                    entity_diff
+                        .added
+                        .insert(symbol.clone(), after_value.clone());
+                }
+                Some(before_value) if before_value != after_value => {
+                    entity_diff
+                        .modified
+                        .insert(symbol.clone(), (before_value.clone(), after_value.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+        entity_diff.removed = before
+            .attributes
+            .keys()
+            .filter(|symbol| !after.attributes.contains_key(*symbol))
+            .cloned()
+            .collect();
+        entity_diff
+    }
+}
+
+/// Attribute changes between two states of an entity
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct EntityDiff {
+    pub added: HashMap<Symbol, AttributeValue>,
+    pub removed: HashSet<Symbol>,
+    /// The values before and after each modified attribute was changed
+    pub modified: HashMap<Symbol, (AttributeValue, AttributeValue)>,
 }
 
 #[derive(Eq, PartialEq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 pub enum AttributeValue {
     String(String),
     EntityId(EntityId),
     Bytes(Vec<u8>),
+    Timestamp(SystemTime),
+    Int64(i64),
+    List(Vec<AttributeValue>),
 }
 
-#[derive(Eq, PartialEq, Debug, Clone, garde::Validate)]
+impl AttributeValue {
+    /// The value type of the value. `None` for empty lists, whose element type is unknown.
+    pub fn value_type(&self) -> Option<ValueType> {
+        match self {
+            AttributeValue::String(_) => Some(ValueType::Text),
+            AttributeValue::EntityId(_) => Some(ValueType::EntityReference),
+            AttributeValue::Bytes(_) => Some(ValueType::Bytes),
+            AttributeValue::Timestamp(_) => Some(ValueType::Timestamp),
+            AttributeValue::List(values) => {
+                Some(ValueType::List(Box::new(values.first()?.value_type()?)))
+            }
+            AttributeValue::Int64(_) => Some(ValueType::Int64),
+        }
+    }
+
+    /// Number of bytes of the value stored outside of the `AttributeValue` itself.
+    pub fn heap_size(&self) -> usize {
+        match self {
+            AttributeValue::String(value) => value.len(),
+            AttributeValue::Bytes(value) => value.len(),
+            AttributeValue::EntityId(_)
+            | AttributeValue::Timestamp(_)
+            | AttributeValue::Int64(_) => 0,
+            AttributeValue::List(values) => {
+                values.capacity() * size_of::<AttributeValue>()
+                    + values.iter().map(AttributeValue::heap_size).sum::<usize>()
+            }
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone, garde::Validate)]
 #[garde(context(AttributeTypes))]
 pub struct EntityRowQuery {
     #[garde(skip)]
     pub root: EntityQueryNode,
     #[garde(inner(custom(is_known_attribute_type)))]
     pub attribute_types: Vec<Symbol>,
+    /// Whether soft-deleted entities match
+    #[garde(skip)]
+    pub include_deleted: bool,
+    /// Whether rows in which every requested attribute is null are excluded
+    #[garde(skip)]
+    pub filter_nulls: bool,
+    /// Indices into `attribute_types` of the columns that must not be null for a row to be included
+    #[garde(inner(custom(is_column_index(self.attribute_types.len()))))]
+    pub filter_rows_where_null: Vec<usize>,
+}
+
+impl EntityRowQuery {
+    /// Whether `entity_row`, produced by this query, passes the query's null filters.
+    pub fn includes_row(&self, entity_row: &EntityRow) -> bool {
+        if self.filter_nulls && entity_row.values.iter().all(Option::is_none) {
+            return false;
+        }
+
+        self.filter_rows_where_null
+            .iter()
+            .all(|&idx| entity_row.attribute_value(idx).is_some())
+    }
+}
+
+fn is_column_index(column_count: usize) -> impl FnOnce(&usize, &AttributeTypes) -> garde::Result {
+    move |idx, _| {
+        if *idx >= column_count {
+            return Err(garde::Error::new(format!(
+                "column index out of range for {column_count} attribute types"
+            )));
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -212,6 +549,22 @@ pub struct EntityRowQueryResult {
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct EntityQuery {
     pub root: EntityQueryNode,
+    /// Whether soft-deleted entities match
+    pub include_deleted: bool,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct CreateAttributeTypesResult {
+    /// Entities of the attribute types that were created, in the order they were requested
+    pub entities: Vec<Entity>,
+    /// Symbols that already named an entity, so were not created
+    pub already_existed: Vec<Symbol>,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct BatchGetEntitiesResult {
+    pub entities: Vec<Entity>,
+    pub not_found: Vec<EntityLocator>,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -220,16 +573,29 @@ pub struct EntityQueryResult {
     pub entity_version: EntityVersion,
 }
 
-#[derive(Eq, PartialEq, Debug, Clone)]
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
 pub enum EntityQueryNode {
     MatchAll(MatchAllQueryNode),
     MatchNone(MatchNoneQueryNode),
     And(AndQueryNode),
     Or(OrQueryNode),
     HasAttributeTypes(HasAttributeTypesNode),
+    EntityIdIn(EntityIdInNode),
+    TextContains(TextContainsNode),
+    TextStartsWith(TextStartsWithNode),
+    TextMatchesRegex(TextMatchesRegexNode),
+    AttributeValueAfter(AttributeValueAfterNode),
+    AttributeValueBefore(AttributeValueBeforeNode),
+    GraphTraverse(GraphTraverseNode),
+    ElementAt(ElementAtNode),
+    HasAttributeValue(HasAttributeValueNode),
 }
 
 impl EntityQueryNode {
+    /// Whether `entity` matches this node.
+    ///
+    /// Graph traversals depend on other entities, so only their `root` is considered here. Stores
+    /// resolve graph traversals before matching entities against a query.
     pub fn matches(&self, entity: &Entity) -> bool {
         match self {
             EntityQueryNode::MatchAll(_) => true,
@@ -243,38 +609,223 @@ impl EntityQueryNode {
             EntityQueryNode::HasAttributeTypes(HasAttributeTypesNode { attribute_types }) => {
                 attribute_types
                     .iter()
-                    .all(|attribute_type| entity.attributes.contains_key(attribute_type))
+                    .all(|attribute_type| entity.has_attribute(attribute_type))
+            }
+            EntityQueryNode::EntityIdIn(EntityIdInNode { entity_ids }) => {
+                entity_ids.contains(&entity.entity_id)
+            }
+            EntityQueryNode::TextContains(TextContainsNode {
+                attribute_type,
+                substring,
+            }) => entity
+                .string_attribute(attribute_type)
+                .is_some_and(|text| text.contains(substring.as_str())),
+            EntityQueryNode::TextStartsWith(TextStartsWithNode {
+                attribute_type,
+                prefix,
+            }) => entity
+                .string_attribute(attribute_type)
+                .is_some_and(|text| text.starts_with(prefix.as_str())),
+            EntityQueryNode::TextMatchesRegex(TextMatchesRegexNode {
+                attribute_type,
+                regex,
+            }) => entity
+                .string_attribute(attribute_type)
+                .is_some_and(|text| regex.is_match(text)),
+            EntityQueryNode::AttributeValueAfter(AttributeValueAfterNode {
+                attribute_type,
+                timestamp,
+            }) => entity
+                .timestamp_attribute(attribute_type)
+                .is_some_and(|value| value > *timestamp),
+            EntityQueryNode::AttributeValueBefore(AttributeValueBeforeNode {
+                attribute_type,
+                timestamp,
+            }) => entity
+                .timestamp_attribute(attribute_type)
+                .is_some_and(|value| value < *timestamp),
+            EntityQueryNode::GraphTraverse(GraphTraverseNode { root, .. }) => root.matches(entity),
+            EntityQueryNode::ElementAt(ElementAtNode {
+                attribute_type,
+                index,
+                value,
+            }) => entity
+                .list_attribute(attribute_type)
+                .and_then(|values| values.get(usize::try_from(*index).ok()?))
+                .is_some_and(|element| element == value),
+            EntityQueryNode::HasAttributeValue(HasAttributeValueNode {
+                attribute_type,
+                value,
+            }) => entity.attribute_value(attribute_type) == Some(value),
+        }
+    }
+
+    /// Whether this node, or any of its clauses, is a graph traversal
+    pub fn has_graph_traversal(&self) -> bool {
+        match self {
+            EntityQueryNode::And(AndQueryNode { clauses })
+            | EntityQueryNode::Or(OrQueryNode { clauses }) => {
+                clauses.iter().any(EntityQueryNode::has_graph_traversal)
             }
+            EntityQueryNode::GraphTraverse(_) => true,
+            _ => false,
         }
     }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct EntityRow {
+    pub entity_id: EntityId,
     pub values: Vec<Option<AttributeValue>>,
 }
 
-#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+impl EntityRow {
+    fn attribute_value(&self, idx: usize) -> Option<&AttributeValue> {
+        self.values.get(idx)?.as_ref()
+    }
+
+    pub fn string_value(&self, idx: usize) -> Option<&str> {
+        match self.attribute_value(idx)? {
+            AttributeValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn entity_id_value(&self, idx: usize) -> Option<EntityId> {
+        match self.attribute_value(idx)? {
+            AttributeValue::EntityId(entity_id) => Some(*entity_id),
+            _ => None,
+        }
+    }
+
+    pub fn bytes_value(&self, idx: usize) -> Option<&[u8]> {
+        match self.attribute_value(idx)? {
+            AttributeValue::Bytes(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
 pub struct MatchAllQueryNode;
 
-#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+#[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
 pub struct MatchNoneQueryNode;
 
-#[derive(Eq, PartialEq, Debug, Clone)]
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
 pub struct AndQueryNode {
     pub clauses: Vec<EntityQueryNode>,
 }
 
-#[derive(Eq, PartialEq, Debug, Clone)]
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
 pub struct OrQueryNode {
     pub clauses: Vec<EntityQueryNode>,
 }
 
-#[derive(Eq, PartialEq, Debug, Clone)]
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
 pub struct HasAttributeTypesNode {
     pub attribute_types: Vec<Symbol>,
 }
 
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+pub struct EntityIdInNode {
+    pub entity_ids: Vec<EntityId>,
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+pub struct TextContainsNode {
+    pub attribute_type: Symbol,
+    pub substring: String,
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+pub struct TextStartsWithNode {
+    pub attribute_type: Symbol,
+    pub prefix: String,
+}
+
+/// Matches entities whose timestamp attribute is strictly after `timestamp`
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+pub struct AttributeValueAfterNode {
+    pub attribute_type: Symbol,
+    pub timestamp: SystemTime,
+}
+
+/// Matches entities whose timestamp attribute is strictly before `timestamp`
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+pub struct AttributeValueBeforeNode {
+    pub attribute_type: Symbol,
+    pub timestamp: SystemTime,
+}
+
+/// Matches the entities matching `root`, and the entities reachable from them by following their
+/// `start_attribute_type` entity references up to `depth_limit` times
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+pub struct GraphTraverseNode {
+    pub root: Box<EntityQueryNode>,
+    pub start_attribute_type: Symbol,
+    pub depth_limit: u32,
+}
+
+/// Matches entities whose `attribute_type` attribute is equal to `value`
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+pub struct HasAttributeValueNode {
+    pub attribute_type: Symbol,
+    pub value: AttributeValue,
+}
+
+/// Matches entities whose list attribute has `value` at position `index`
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+pub struct ElementAtNode {
+    pub attribute_type: Symbol,
+    pub index: u32,
+    pub value: AttributeValue,
+}
+
+/// Limit on the compiled size of a query regex, so that clients cannot make the server build
+/// arbitrarily large automata.
+const TEXT_REGEX_SIZE_LIMIT: usize = 1 << 16;
+const TEXT_REGEX_NEST_LIMIT: u32 = 32;
+
+/// Built with [`TextMatchesRegexNode::new`], which limits the size of the regex.
+#[derive(Debug, Clone)]
+pub struct TextMatchesRegexNode {
+    pub attribute_type: Symbol,
+    regex: Regex,
+}
+
+impl TextMatchesRegexNode {
+    pub fn new(attribute_type: Symbol, pattern: &str) -> Result<Self, regex::Error> {
+        let regex = RegexBuilder::new(pattern)
+            .size_limit(TEXT_REGEX_SIZE_LIMIT)
+            .nest_limit(TEXT_REGEX_NEST_LIMIT)
+            .build()?;
+        Ok(TextMatchesRegexNode {
+            attribute_type,
+            regex,
+        })
+    }
+
+    pub fn regex(&self) -> &Regex {
+        &self.regex
+    }
+}
+
+impl PartialEq for TextMatchesRegexNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.attribute_type == other.attribute_type && self.regex.as_str() == other.regex.as_str()
+    }
+}
+
+impl Eq for TextMatchesRegexNode {}
+
+impl Hash for TextMatchesRegexNode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.attribute_type.hash(state);
+        self.regex.as_str().hash(state);
+    }
+}
+
 #[derive(Eq, PartialEq, Debug, Clone, garde::Validate)]
 #[garde(context(AttributeTypes))]
 pub struct AttributeToUpdate {
@@ -291,20 +842,15 @@ fn attribute_value_matches_attribute_type(
         let expected_attribute_type = attribute_types
             .get(symbol)
             .ok_or_else(|| garde::Error::new("cannot find value type for attribute type"))?;
-        match (value, expected_attribute_type) {
-            (None, _) => (),
-            (Some(AttributeValue::String(_)), ValueType::Text) => (),
-            (Some(AttributeValue::EntityId(_)), ValueType::EntityReference) => (),
-            (Some(AttributeValue::Bytes(_)), ValueType::Bytes) => (),
-            _ => {
-                return Err(garde::Error::new(format!(
+        match value {
+            Some(value) if !expected_attribute_type.matches(value) => {
+                Err(garde::Error::new(format!(
                     "incorrect value type, expected {:?}",
                     expected_attribute_type
-                )));
+                )))
             }
-        };
-
-        Ok(())
+            _ => Ok(()),
+        }
     }
 }
 
@@ -313,6 +859,9 @@ fn not_immutable_attribute_type(symbol: &Symbol, _: &AttributeTypes) -> garde::R
     if *symbol == value_type_symbol {
         return Err(garde::Error::new("immutable attribute type"));
     }
+    if VirtualAttribute::from_symbol(symbol).is_some() {
+        return Err(garde::Error::new("virtual attribute type cannot be set"));
+    }
 
     Ok(())
 }
@@ -332,6 +881,15 @@ pub struct UpdateEntityRequest {
     pub entity_locator: EntityLocator,
     #[garde(dive)]
     pub attributes_to_update: Vec<AttributeToUpdate>,
+    #[garde(skip)]
+    pub dry_run: bool,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct MergeEntitiesRequest {
+    pub source: EntityLocator,
+    pub target: EntityLocator,
+    pub attribute_types: Vec<Symbol>,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone, garde::Validate)]
@@ -345,6 +903,29 @@ pub struct CreateAttributeTypeRequest {
 pub struct WatchEntitiesRequest {
     pub query: EntityQueryNode,
     pub send_initial_events: bool,
+    pub event_types: Vec<WatchEventType>,
+    /// Attributes included in the entities sent. All attributes are included if empty.
+    pub attribute_types: Vec<Symbol>,
+    /// Changes to an entity within this window are coalesced into a single event. Events are sent
+    /// as they happen if `None`.
+    pub batch_window: Option<Duration>,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
+pub enum WatchEventType {
+    Added,
+    Modified,
+    Removed,
+    Bookmark,
+}
+
+impl WatchEventType {
+    pub const ALL: [WatchEventType; 4] = [
+        WatchEventType::Added,
+        WatchEventType::Modified,
+        WatchEventType::Removed,
+        WatchEventType::Bookmark,
+    ];
 }
 
 #[derive(Eq, PartialEq, Debug, Clone, garde::Validate)]
@@ -358,6 +939,13 @@ pub struct WatchEntityRowsRequest {
     pub send_initial_events: bool,
 }
 
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct WatchEntityRequest {
+    pub entity_locator: EntityLocator,
+    pub attribute_types: Vec<Symbol>,
+    pub send_initial_events: bool,
+}
+
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct WatchEntitiesEvent {
     pub entity_version: EntityVersion,
@@ -365,6 +953,17 @@ pub struct WatchEntitiesEvent {
     pub after: Option<Arc<Entity>>,
 }
 
+impl WatchEntitiesEvent {
+    pub fn event_type(&self) -> Option<WatchEventType> {
+        match (&self.before, &self.after) {
+            (None, Some(_)) => Some(WatchEventType::Added),
+            (Some(_), Some(_)) => Some(WatchEventType::Modified),
+            (Some(_), None) => Some(WatchEventType::Removed),
+            (None, None) => None,
+        }
+    }
+}
+
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct WatchEntityRowsEvent {
     pub entity_version: EntityVersion,
@@ -379,11 +978,26 @@ pub trait ThreadSafeAttributeStore: Send + Sync + 'static {
         create_attribute_type_request: &CreateAttributeTypeRequest,
     ) -> Result<Entity, AttributeStoreError>;
 
+    async fn create_attribute_types(
+        &self,
+        attribute_types: &[AttributeType],
+    ) -> Result<CreateAttributeTypesResult, AttributeStoreError>;
+
+    async fn get_attribute_type(
+        &self,
+        symbol: &Symbol,
+    ) -> Result<AttributeType, AttributeStoreError>;
+
     async fn get_entity(
         &self,
         entity_locator: &EntityLocator,
     ) -> Result<Entity, AttributeStoreError>;
 
+    async fn batch_get_entities(
+        &self,
+        entity_locators: &[EntityLocator],
+    ) -> Result<BatchGetEntitiesResult, AttributeStoreError>;
+
     async fn query_entities(
         &self,
         entity_query: &EntityQuery,
@@ -394,12 +1008,56 @@ pub trait ThreadSafeAttributeStore: Send + Sync + 'static {
         entity_row_query: &EntityRowQuery,
     ) -> Result<EntityRowQueryResult, AttributeStoreError>;
 
+    async fn query_entity_rows_page(
+        &self,
+        entity_row_query: &EntityRowQuery,
+        after: Option<EntityId>,
+        limit: usize,
+    ) -> Result<EntityRowQueryResult, AttributeStoreError>;
+
+    async fn validate_update_entity(
+        &self,
+        update_entity_request: &UpdateEntityRequest,
+    ) -> Result<(), AttributeStoreError>;
+
     async fn update_entity(
         &self,
         update_entity_request: &UpdateEntityRequest,
     ) -> Result<Entity, AttributeStoreError>;
 
+    async fn merge_entities(
+        &self,
+        source: &EntityLocator,
+        target: &EntityLocator,
+        attribute_types: &[Symbol],
+    ) -> Result<Entity, AttributeStoreError>;
+
+    /// Updates the entity with the attributes computed from it by `f`, without any other change
+    /// in between. The entity is left unchanged if `f` fails.
+    async fn atomic_query_and_update<F, E>(
+        &self,
+        entity_locator: &EntityLocator,
+        f: F,
+    ) -> Result<Entity, E>
+    where
+        F: FnOnce(&Entity) -> Result<Vec<AttributeToUpdate>, E> + Send,
+        E: From<AttributeStoreError> + Display + Send;
+
     fn watch_entities_receiver(&self) -> Receiver<WatchEntitiesEvent>;
+
+    /// Version of the latest change committed to the store.
+    fn current_entity_version(&self) -> EntityVersion;
+
+    /// Number of entities that have not been soft-deleted, including bootstrap entities.
+    fn entity_count(&self) -> usize;
+
+    /// Number of watch events that have not yet been received by every subscriber.
+    fn watch_entities_channel_len(&self) -> usize;
+
+    /// Estimated number of bytes used by the store's entities and attribute types.
+    fn memory_usage_bytes(&self) -> usize;
+
+    fn watch_entity_receiver(&self, entity_id: EntityId) -> Receiver<WatchEntitiesEvent>;
 }
 
 pub trait AttributeStore {
@@ -408,8 +1066,26 @@ pub trait AttributeStore {
         create_attribute_type_request: &CreateAttributeTypeRequest,
     ) -> Result<Entity, AttributeStoreError>;
 
+    /// Creates each attribute type whose symbol doesn't already name an entity. The others are
+    /// reported in `already_existed` rather than failing the whole batch. Either every new type is
+    /// created or, if any is invalid, none are.
+    fn create_attribute_types(
+        &mut self,
+        attribute_types: &[AttributeType],
+    ) -> Result<CreateAttributeTypesResult, AttributeStoreError>;
+
+    /// Looks up a single attribute type, failing with `EntityNotFound` if `symbol` isn't one.
+    fn get_attribute_type(&self, symbol: &Symbol) -> Result<AttributeType, AttributeStoreError>;
+
     fn get_entity(&self, entity_locator: &EntityLocator) -> Result<Entity, AttributeStoreError>;
 
+    /// Fetches each entity in turn. Locators that don't match an entity are reported in
+    /// `not_found` rather than failing the whole batch.
+    fn batch_get_entities(
+        &self,
+        entity_locators: &[EntityLocator],
+    ) -> Result<BatchGetEntitiesResult, AttributeStoreError>;
+
     fn query_entities(
         &self,
         entity_query: &EntityQuery,
@@ -420,12 +1096,64 @@ pub trait AttributeStore {
         entity_row_query: &EntityRowQuery,
     ) -> Result<EntityRowQueryResult, AttributeStoreError>;
 
+    /// Queries at most `limit` rows, in entity ID order, for entities with IDs greater than `after`.
+    ///
+    /// Pages are not a consistent snapshot: entities may change between successive pages.
+    fn query_entity_rows_page(
+        &self,
+        entity_row_query: &EntityRowQuery,
+        after: Option<EntityId>,
+        limit: usize,
+    ) -> Result<EntityRowQueryResult, AttributeStoreError>;
+
+    /// Checks that `update_entity` would accept the request, without modifying the store.
+    fn validate_update_entity(
+        &self,
+        update_entity_request: &UpdateEntityRequest,
+    ) -> Result<(), AttributeStoreError>;
+
     fn update_entity(
         &mut self,
         update_entity_request: &UpdateEntityRequest,
     ) -> Result<Entity, AttributeStoreError>;
 
+    /// Copies `attribute_types` from `source` to `target` with `update_entity`, as a single
+    /// operation. Attribute types that `source` doesn't have are left unchanged on `target`.
+    fn merge_entities(
+        &mut self,
+        source: &EntityLocator,
+        target: &EntityLocator,
+        attribute_types: &[Symbol],
+    ) -> Result<Entity, AttributeStoreError>;
+
+    /// Fetches the entity at `entity_locator` and updates it with the attributes `f` computes from
+    /// it, with no other change to the entity in between. The entity is left unchanged if `f` fails.
+    fn atomic_query_and_update<F, E>(
+        &mut self,
+        entity_locator: &EntityLocator,
+        f: F,
+    ) -> Result<Entity, E>
+    where
+        F: FnOnce(&Entity) -> Result<Vec<AttributeToUpdate>, E>,
+        E: From<AttributeStoreError> + Display;
+
     fn watch_entities_receiver(&self) -> Receiver<WatchEntitiesEvent>;
+
+    /// Version of the latest change committed to the store.
+    fn current_entity_version(&self) -> EntityVersion;
+
+    /// Number of entities that have not been soft-deleted, including bootstrap entities.
+    fn entity_count(&self) -> usize;
+
+    /// Number of watch events that have not yet been received by every subscriber.
+    fn watch_entities_channel_len(&self) -> usize;
+
+    /// Estimated number of bytes used by the store's entities and attribute types.
+    fn memory_usage_bytes(&self) -> usize;
+
+    /// Receives the watch events of the entity with `entity_id` only, without being sent the
+    /// events of every other entity.
+    fn watch_entity_receiver(&mut self, entity_id: EntityId) -> Receiver<WatchEntitiesEvent>;
 }
 
 #[async_trait]
@@ -438,6 +1166,20 @@ impl<T: AttributeStore + Send + 'static> ThreadSafeAttributeStore for Mutex<T> {
             .create_attribute_type(create_attribute_type_request)
     }
 
+    async fn create_attribute_types(
+        &self,
+        attribute_types: &[AttributeType],
+    ) -> Result<CreateAttributeTypesResult, AttributeStoreError> {
+        self.lock().create_attribute_types(attribute_types)
+    }
+
+    async fn get_attribute_type(
+        &self,
+        symbol: &Symbol,
+    ) -> Result<AttributeType, AttributeStoreError> {
+        self.lock().get_attribute_type(symbol)
+    }
+
     async fn get_entity(
         &self,
         entity_locator: &EntityLocator,
@@ -445,6 +1187,13 @@ impl<T: AttributeStore + Send + 'static> ThreadSafeAttributeStore for Mutex<T> {
         self.lock().get_entity(entity_locator)
     }
 
+    async fn batch_get_entities(
+        &self,
+        entity_locators: &[EntityLocator],
+    ) -> Result<BatchGetEntitiesResult, AttributeStoreError> {
+        self.lock().batch_get_entities(entity_locators)
+    }
+
     async fn query_entities(
         &self,
         entity_query: &EntityQuery,
@@ -459,6 +1208,23 @@ impl<T: AttributeStore + Send + 'static> ThreadSafeAttributeStore for Mutex<T> {
         self.lock().query_entity_rows(entity_query)
     }
 
+    async fn query_entity_rows_page(
+        &self,
+        entity_row_query: &EntityRowQuery,
+        after: Option<EntityId>,
+        limit: usize,
+    ) -> Result<EntityRowQueryResult, AttributeStoreError> {
+        self.lock()
+            .query_entity_rows_page(entity_row_query, after, limit)
+    }
+
+    async fn validate_update_entity(
+        &self,
+        update_entity_request: &UpdateEntityRequest,
+    ) -> Result<(), AttributeStoreError> {
+        self.lock().validate_update_entity(update_entity_request)
+    }
+
     async fn update_entity(
         &self,
         update_entity_request: &UpdateEntityRequest,
@@ -466,24 +1232,130 @@ impl<T: AttributeStore + Send + 'static> ThreadSafeAttributeStore for Mutex<T> {
         self.lock().update_entity(update_entity_request)
     }
 
+    async fn merge_entities(
+        &self,
+        source: &EntityLocator,
+        target: &EntityLocator,
+        attribute_types: &[Symbol],
+    ) -> Result<Entity, AttributeStoreError> {
+        self.lock().merge_entities(source, target, attribute_types)
+    }
+
+    async fn atomic_query_and_update<F, E>(
+        &self,
+        entity_locator: &EntityLocator,
+        f: F,
+    ) -> Result<Entity, E>
+    where
+        F: FnOnce(&Entity) -> Result<Vec<AttributeToUpdate>, E> + Send,
+        E: From<AttributeStoreError> + Display + Send,
+    {
+        self.lock().atomic_query_and_update(entity_locator, f)
+    }
+
     fn watch_entities_receiver(&self) -> Receiver<WatchEntitiesEvent> {
         self.lock().watch_entities_receiver()
     }
+
+    fn current_entity_version(&self) -> EntityVersion {
+        self.lock().current_entity_version()
+    }
+
+    fn entity_count(&self) -> usize {
+        self.lock().entity_count()
+    }
+
+    fn watch_entities_channel_len(&self) -> usize {
+        self.lock().watch_entities_channel_len()
+    }
+
+    fn memory_usage_bytes(&self) -> usize {
+        self.lock().memory_usage_bytes()
+    }
+
+    fn watch_entity_receiver(&self, entity_id: EntityId) -> Receiver<WatchEntitiesEvent> {
+        self.lock().watch_entity_receiver(entity_id)
+    }
 }
 
-#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum BootstrapSymbol {
+    /// `@id`, the attribute type of `VirtualAttribute::EntityId`
     EntityId,
     SymbolName,
     ValueType,
     ValueTypeEnum(ValueType),
+    Description,
+    /// `@deletedAt`, the time an entity was soft-deleted, as milliseconds since the UNIX epoch
+    DeletedAt,
 }
 
-#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum ValueType {
     Text,
     EntityReference,
     Bytes,
+    Timestamp,
+    Int64,
+    /// A list of values of the element type. Lists cannot be nested.
+    List(Box<ValueType>),
+}
+
+impl ValueType {
+    /// Whether `value` is of this value type, including every element of a list.
+    pub fn matches(&self, value: &AttributeValue) -> bool {
+        match (value, self) {
+            (AttributeValue::String(_), ValueType::Text)
+            | (AttributeValue::EntityId(_), ValueType::EntityReference)
+            | (AttributeValue::Bytes(_), ValueType::Bytes)
+            | (AttributeValue::Timestamp(_), ValueType::Timestamp)
+            | (AttributeValue::Int64(_), ValueType::Int64) => true,
+            (AttributeValue::List(values), ValueType::List(element_type)) => {
+                values.iter().all(|value| element_type.matches(value))
+            }
+            _ => false,
+        }
+    }
+
+    /// The bootstrap entity representing this value type. `None` for nested lists, which are not
+    /// supported.
+    pub fn entity_id(&self) -> Option<EntityId> {
+        match self {
+            ValueType::Text => Some(EntityId(3)),
+            ValueType::EntityReference => Some(EntityId(4)),
+            ValueType::Bytes => Some(EntityId(5)),
+            ValueType::Timestamp => Some(EntityId(8)),
+            ValueType::List(element_type) => match **element_type {
+                ValueType::Text => Some(EntityId(9)),
+                ValueType::EntityReference => Some(EntityId(10)),
+                ValueType::Bytes => Some(EntityId(11)),
+                ValueType::Timestamp => Some(EntityId(12)),
+                ValueType::Int64 => Some(EntityId(14)),
+                ValueType::List(_) => None,
+            },
+            ValueType::Int64 => Some(EntityId(13)),
+        }
+    }
+
+    fn symbol_ref(&self) -> Option<SymbolRef<'static>> {
+        match self {
+            ValueType::Text => Some(SymbolRef("@valueType/text")),
+            ValueType::EntityReference => Some(SymbolRef("@valueType/entityRef")),
+            ValueType::Bytes => Some(SymbolRef("@valueType/bytes")),
+            ValueType::Timestamp => Some(SymbolRef("@valueType/timestamp")),
+            ValueType::List(element_type) => match **element_type {
+                ValueType::Text => Some(SymbolRef("@valueType/list/text")),
+                ValueType::EntityReference => Some(SymbolRef("@valueType/list/entityRef")),
+                ValueType::Bytes => Some(SymbolRef("@valueType/list/bytes")),
+                ValueType::Timestamp => Some(SymbolRef("@valueType/list/timestamp")),
+                ValueType::Int64 => Some(SymbolRef("@valueType/list/int64")),
+                ValueType::List(_) => None,
+            },
+            ValueType::Int64 => Some(SymbolRef("@valueType/int64")),
+        }
+    }
 }
 
 impl From<BootstrapSymbol> for EntityId {
@@ -493,18 +1365,11 @@ impl From<BootstrapSymbol> for EntityId {
             BootstrapSymbol::EntityId => EntityId(0),
             BootstrapSymbol::SymbolName => EntityId(1),
             BootstrapSymbol::ValueType => EntityId(2),
-            BootstrapSymbol::ValueTypeEnum(value_type) => EntityId::from(value_type),
-        }
-    }
-}
-
-impl From<ValueType> for EntityId {
-    #[inline]
-    fn from(value: ValueType) -> Self {
-        match value {
-            ValueType::Text => EntityId(3),
-            ValueType::EntityReference => EntityId(4),
-            ValueType::Bytes => EntityId(5),
+            BootstrapSymbol::ValueTypeEnum(value_type) => value_type
+                .entity_id()
+                .expect("bootstrap value types are supported"),
+            BootstrapSymbol::Description => EntityId(6),
+            BootstrapSymbol::DeletedAt => EntityId(7),
         }
     }
 }
@@ -519,46 +1384,95 @@ impl TryFrom<EntityId> for ValueType {
             EntityId(3) => Ok(Text),
             EntityId(4) => Ok(EntityReference),
             EntityId(5) => Ok(Bytes),
+            EntityId(8) => Ok(Timestamp),
+            EntityId(9) => Ok(List(Box::new(Text))),
+            EntityId(10) => Ok(List(Box::new(EntityReference))),
+            EntityId(11) => Ok(List(Box::new(Bytes))),
+            EntityId(12) => Ok(List(Box::new(Timestamp))),
+            EntityId(13) => Ok(Int64),
+            EntityId(14) => Ok(List(Box::new(Int64))),
             other_entity_id => Err(InvalidValueType(other_entity_id))?,
         }
     }
 }
 
-impl From<BootstrapSymbol> for Symbol {
+impl From<BootstrapSymbol> for SymbolRef<'static> {
     fn from(value: BootstrapSymbol) -> Self {
         match value {
-            BootstrapSymbol::EntityId => Symbol("@id".into()),
-            BootstrapSymbol::SymbolName => Symbol("@symbolName".into()),
-            BootstrapSymbol::ValueType => Symbol("@valueType".into()),
-            BootstrapSymbol::ValueTypeEnum(value_type) => Symbol::from(value_type),
+            BootstrapSymbol::EntityId => SymbolRef("@id"),
+            BootstrapSymbol::SymbolName => SymbolRef("@symbolName"),
+            BootstrapSymbol::ValueType => SymbolRef("@valueType"),
+            BootstrapSymbol::ValueTypeEnum(value_type) => value_type
+                .symbol_ref()
+                .expect("bootstrap value types are supported"),
+            BootstrapSymbol::Description => SymbolRef("@description"),
+            BootstrapSymbol::DeletedAt => SymbolRef("@deletedAt"),
         }
     }
 }
 
-impl From<ValueType> for Symbol {
-    fn from(value: ValueType) -> Self {
+impl From<BootstrapSymbol> for Symbol {
+    fn from(value: BootstrapSymbol) -> Self {
+        Symbol(SymbolRef::from(value).as_str().into())
+    }
+}
+
+/// Attribute types whose values are derived from the entity itself rather than stored with it.
+///
+/// Virtual attributes are registered attribute types, so they can be requested in entity rows like
+/// any other attribute type, but they cannot be set by `update_entity`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum VirtualAttribute {
+    /// `@id`: the ID of the entity
+    EntityId,
+}
+
+impl VirtualAttribute {
+    const ALL: [VirtualAttribute; 1] = [VirtualAttribute::EntityId];
+
+    pub fn from_symbol(symbol: &Symbol) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|virtual_attribute| SymbolRef::from(*virtual_attribute) == symbol.as_symbol_ref())
+    }
+
+    pub fn value(self, entity: &Entity) -> AttributeValue {
+        match self {
+            VirtualAttribute::EntityId => AttributeValue::EntityId(entity.entity_id),
+        }
+    }
+}
+
+impl From<VirtualAttribute> for SymbolRef<'static> {
+    fn from(value: VirtualAttribute) -> Self {
         match value {
-            ValueType::Text => Symbol("@valueType/text".into()),
-            ValueType::EntityReference => Symbol("@valueType/entityRef".into()),
-            ValueType::Bytes => Symbol("@valueType/bytes".into()),
+            VirtualAttribute::EntityId => BootstrapSymbol::EntityId.into(),
         }
     }
 }
 
+impl From<VirtualAttribute> for Symbol {
+    fn from(value: VirtualAttribute) -> Self {
+        Symbol(SymbolRef::from(value).as_str().into())
+    }
+}
+
 impl BootstrapSymbol {
-    fn value_type(self: BootstrapSymbol) -> Option<EntityId> {
+    fn value_type(&self) -> Option<EntityId> {
         match self {
-            BootstrapSymbol::EntityId => Some(ValueType::EntityReference.into()),
-            BootstrapSymbol::SymbolName => Some(ValueType::Text.into()),
-            BootstrapSymbol::ValueType => Some(ValueType::EntityReference.into()),
+            BootstrapSymbol::EntityId => ValueType::EntityReference.entity_id(),
+            BootstrapSymbol::SymbolName => ValueType::Text.entity_id(),
+            BootstrapSymbol::ValueType => ValueType::EntityReference.entity_id(),
             BootstrapSymbol::ValueTypeEnum(_) => None,
+            BootstrapSymbol::Description => ValueType::Text.entity_id(),
+            BootstrapSymbol::DeletedAt => ValueType::Text.entity_id(),
         }
     }
 }
 
 impl From<BootstrapSymbol> for Entity {
     fn from(value: BootstrapSymbol) -> Self {
-        let symbol: Symbol = value.into();
+        let symbol: Symbol = value.clone().into();
         let mut attributes = HashMap::from([(
             BootstrapSymbol::SymbolName.into(),
             AttributeValue::String(symbol.into()),
@@ -601,6 +1515,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn symbol_namespaces() {
+        let symbol = Symbol::try_from("mavlink/id/1:1").unwrap();
+        assert_eq!(symbol.namespace(), Some("mavlink"));
+        assert_eq!(symbol.local_name(), "id/1:1");
+
+        let symbol: Symbol = BootstrapSymbol::SymbolName.into();
+        assert_eq!(symbol.namespace(), None);
+        assert_eq!(symbol.local_name(), "@symbolName");
+
+        assert_eq!(
+            Symbol::with_namespace("mavlink", "globalPosition").unwrap(),
+            Symbol::try_from("mavlink/globalPosition").unwrap()
+        );
+        for (namespace, local_name) in [("", "x"), ("a/b", "c"), ("a", "b\\c")] {
+            assert_matches!(
+                Symbol::with_namespace(namespace, local_name)
+                    .unwrap_err()
+                    .kind,
+                AttributeStoreErrorKind::InvalidSymbolName(_)
+            );
+        }
+    }
+
+    #[test]
+    fn symbol_policies() {
+        let ascii = SymbolPolicy::ascii();
+        let unicode = SymbolPolicy::unicode();
+
+        assert!(ascii.is_valid("colour name"));
+        assert!(unicode.is_valid("colour name"));
+        assert!(!ascii.is_valid("couleur préférée"));
+        assert!(unicode.is_valid("couleur préférée"));
+        assert!(unicode.is_valid("色"));
+        assert!(!unicode.is_valid("tab\tseparated"));
+        assert!(!unicode.is_valid(r#"ab"c"#));
+        // Length is measured in characters rather than bytes
+        assert!(unicode.is_valid(&"色".repeat(60)));
+        assert!(!unicode.is_valid(&"色".repeat(61)));
+    }
+
     #[test]
     fn valid_symbols() {
         assert_eq!(Symbol::try_from("abc").unwrap(), Symbol("abc".into()));
@@ -610,4 +1565,218 @@ mod tests {
             Symbol("@valueType/text".into())
         );
     }
+
+    #[test]
+    fn entity_rows_include_virtual_attributes() {
+        let entity: Entity = BootstrapSymbol::SymbolName.into();
+
+        assert_eq!(
+            entity.to_entity_row(&[
+                BootstrapSymbol::EntityId.into(),
+                BootstrapSymbol::SymbolName.into()
+            ]),
+            EntityRow {
+                entity_id: entity.entity_id,
+                values: vec![
+                    Some(AttributeValue::EntityId(entity.entity_id)),
+                    Some(AttributeValue::String("@symbolName".into())),
+                ],
+            }
+        );
+        assert_eq!(
+            VirtualAttribute::from_symbol(&BootstrapSymbol::EntityId.into()),
+            Some(VirtualAttribute::EntityId)
+        );
+        assert_eq!(
+            VirtualAttribute::from_symbol(&BootstrapSymbol::SymbolName.into()),
+            None
+        );
+    }
+
+    #[test]
+    fn typed_attribute_accessors() {
+        let entity: Entity = BootstrapSymbol::SymbolName.into();
+        let symbol_name: Symbol = BootstrapSymbol::SymbolName.into();
+        let value_type: Symbol = BootstrapSymbol::ValueType.into();
+        let description: Symbol = BootstrapSymbol::Description.into();
+
+        assert!(entity.has_attribute(&symbol_name));
+        assert!(!entity.has_attribute(&description));
+        assert_eq!(
+            entity.attribute_value(&symbol_name),
+            Some(&AttributeValue::String("@symbolName".into()))
+        );
+        assert_eq!(entity.string_attribute(&symbol_name), Some("@symbolName"));
+        assert_eq!(entity.string_attribute(&value_type), None);
+        assert!(entity.entity_id_attribute(&value_type).is_some());
+        assert_eq!(entity.entity_id_attribute(&symbol_name), None);
+        assert_eq!(entity.bytes_attribute(&symbol_name), None);
+        assert_eq!(entity.bytes_attribute(&description), None);
+    }
+
+    #[test]
+    fn entity_diff_classifies_attribute_changes() {
+        let before: Entity = BootstrapSymbol::SymbolName.into();
+        let symbol_name: Symbol = BootstrapSymbol::SymbolName.into();
+        let value_type: Symbol = BootstrapSymbol::ValueType.into();
+        let description: Symbol = BootstrapSymbol::Description.into();
+
+        let mut after = before.clone();
+        after.attributes.remove(&value_type);
+        after.attributes.insert(
+            symbol_name.clone(),
+            AttributeValue::String("renamed".into()),
+        );
+        after.attributes.insert(
+            description.clone(),
+            AttributeValue::String("A description".into()),
+        );
+
+        let entity_diff = Entity::diff(&before, &after);
+        assert_eq!(
+            entity_diff.added,
+            HashMap::from([(description, AttributeValue::String("A description".into()))])
+        );
+        assert_eq!(entity_diff.removed, HashSet::from([value_type]));
+        assert_eq!(
+            entity_diff.modified,
+            HashMap::from([(
+                symbol_name,
+                (
+                    AttributeValue::String("@symbolName".into()),
+                    AttributeValue::String("renamed".into())
+                )
+            )])
+        );
+        assert_eq!(Entity::diff(&after, &after), EntityDiff::default());
+    }
+
+    #[test]
+    fn symbol_keyed_maps_can_be_looked_up_by_str() {
+        let attribute_types: AttributeTypes =
+            HashMap::from([(BootstrapSymbol::EntityId.into(), ValueType::EntityReference)]);
+
+        assert_eq!(
+            attribute_types.get("@id"),
+            Some(&ValueType::EntityReference)
+        );
+        assert_eq!(attribute_types.get("@symbolName"), None);
+    }
+
+    #[test]
+    fn symbol_refs_are_validated_like_symbols() {
+        let symbol_ref = SymbolRef::try_from("colour").unwrap();
+
+        assert_eq!(
+            Symbol::from(symbol_ref),
+            Symbol::try_from("colour").unwrap()
+        );
+        assert_eq!(
+            Symbol::from(BootstrapSymbol::EntityId).as_symbol_ref(),
+            SymbolRef::from(BootstrapSymbol::EntityId)
+        );
+        assert_matches!(
+            SymbolRef::try_from(r"a\b").unwrap_err().kind,
+            AttributeStoreErrorKind::InvalidSymbolName(_)
+        );
+    }
+
+    #[test]
+    fn entity_row_accessors_match_value_type() {
+        let entity_row = EntityRow {
+            entity_id: EntityId(7),
+            values: vec![
+                Some(AttributeValue::String("red".into())),
+                Some(AttributeValue::EntityId(EntityId(3))),
+                Some(AttributeValue::Bytes(vec![1, 2, 3])),
+                None,
+            ],
+        };
+
+        assert_eq!(entity_row.string_value(0), Some("red"));
+        assert_eq!(entity_row.entity_id_value(1), Some(EntityId(3)));
+        assert_eq!(entity_row.bytes_value(2), Some([1, 2, 3].as_slice()));
+        assert_eq!(entity_row.string_value(1), None);
+        assert_eq!(entity_row.string_value(3), None);
+        assert_eq!(entity_row.string_value(4), None);
+    }
+
+    #[test]
+    fn errors_display_span_trace_when_captured() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let not_found = || {
+            AttributeStoreError::from(AttributeStoreErrorKind::EntityNotFound(
+                EntityLocator::EntityId(EntityId(7)),
+            ))
+        };
+        let uncaptured = not_found();
+        assert_eq!(uncaptured.to_string(), uncaptured.kind.to_string());
+
+        let subscriber = tracing_subscriber::registry().with(tracing_error::ErrorLayer::default());
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("get_entity").entered();
+            let captured = not_found();
+            assert_eq!(captured.span_trace.status(), SpanTraceStatus::CAPTURED);
+            assert!(captured.to_string().contains("get_entity"));
+        });
+    }
+
+    #[test]
+    fn timestamp_nodes_compare_strictly() {
+        let deadline = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000);
+        let attribute_type = Symbol::try_from("deadline").unwrap();
+        let entity = Entity {
+            entity_id: EntityId(7),
+            entity_version: EntityVersion(3),
+            attributes: HashMap::from([(
+                attribute_type.clone(),
+                AttributeValue::Timestamp(deadline),
+            )]),
+        };
+        let after = |timestamp| {
+            EntityQueryNode::AttributeValueAfter(AttributeValueAfterNode {
+                attribute_type: attribute_type.clone(),
+                timestamp,
+            })
+        };
+        let before = |timestamp| {
+            EntityQueryNode::AttributeValueBefore(AttributeValueBeforeNode {
+                attribute_type: attribute_type.clone(),
+                timestamp,
+            })
+        };
+        let earlier = deadline - std::time::Duration::from_secs(1);
+
+        assert!(after(earlier).matches(&entity));
+        assert!(!after(deadline).matches(&entity));
+        assert!(before(deadline + std::time::Duration::from_secs(1)).matches(&entity));
+        assert!(!before(deadline).matches(&entity));
+        assert!(!after(earlier).matches(&BootstrapSymbol::SymbolName.into()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let entity = Entity {
+            entity_id: EntityId(7),
+            entity_version: EntityVersion(3),
+            attributes: HashMap::from([(
+                BootstrapSymbol::SymbolName.into(),
+                AttributeValue::String("foo".into()),
+            )]),
+        };
+        let json = serde_json::to_value(&entity).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "entity_id": 7,
+                "entity_version": 3,
+                "attributes": {"@symbolName": {"type": "string", "value": "foo"}},
+            })
+        );
+        assert_eq!(serde_json::from_value::<Entity>(json).unwrap(), entity);
+
+        assert!(serde_json::from_value::<Symbol>(serde_json::json!(r"a\b")).is_err());
+    }
 }
@@ -0,0 +1,214 @@
+use attribute_store::inmemory::InMemoryAttributeStore;
+use attribute_store::store::{
+    AttributeToUpdate, AttributeType, AttributeValue, BootstrapSymbol, CreateAttributeTypeRequest,
+    Entity, EntityLocator, EntityQuery, EntityQueryNode, EntityRowQuery, HasAttributeTypesNode,
+    Symbol, ThreadSafeAttributeStore, UpdateEntityRequest, ValueType, WatchEntitiesEvent,
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::broadcast::Receiver;
+
+fn symbol(name: &'static str) -> Symbol {
+    Symbol::try_from(name).unwrap()
+}
+
+async fn create_text_attribute_type<S: ThreadSafeAttributeStore>(store: &S, name: &'static str) {
+    store
+        .create_attribute_type(&CreateAttributeTypeRequest {
+            attribute_type: AttributeType {
+                symbol: symbol(name),
+                value_type: ValueType::Text,
+                description: None,
+            },
+        })
+        .await
+        .unwrap();
+}
+
+async fn upsert_entity<S: ThreadSafeAttributeStore>(
+    store: &S,
+    symbol_name: &'static str,
+    attributes: &[(&'static str, &str)],
+) -> Entity {
+    let attributes_to_update = std::iter::once(AttributeToUpdate {
+        symbol: BootstrapSymbol::SymbolName.into(),
+        value: Some(AttributeValue::String(symbol_name.into())),
+    })
+    .chain(attributes.iter().map(|(name, value)| AttributeToUpdate {
+        symbol: symbol(name),
+        value: Some(AttributeValue::String(value.to_string())),
+    }))
+    .collect();
+
+    store
+        .update_entity(&UpdateEntityRequest {
+            entity_locator: EntityLocator::Symbol(symbol(symbol_name)),
+            attributes_to_update,
+            dry_run: false,
+        })
+        .await
+        .unwrap()
+}
+
+fn drain_events(receiver: &mut Receiver<WatchEntitiesEvent>) -> Vec<WatchEntitiesEvent> {
+    let mut events = vec![];
+    loop {
+        match receiver.try_recv() {
+            Ok(event) => events.push(event),
+            Err(TryRecvError::Empty) => return events,
+            Err(err) => panic!("failed to receive watch event: {err:?}"),
+        }
+    }
+}
+
+async fn watch_receives_added_event<S: ThreadSafeAttributeStore>(store: S) {
+    create_text_attribute_type(&store, "colour").await;
+    let mut receiver = store.watch_entities_receiver();
+
+    let entity = upsert_entity(&store, "foo", &[("colour", "red")]).await;
+
+    let events = drain_events(&mut receiver);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].before, None);
+    assert_eq!(events[0].after.as_deref(), Some(&entity));
+    assert_eq!(events[0].entity_version, entity.entity_version);
+}
+
+async fn watch_receives_modified_event<S: ThreadSafeAttributeStore>(store: S) {
+    create_text_attribute_type(&store, "colour").await;
+    let before = upsert_entity(&store, "foo", &[("colour", "red")]).await;
+    let mut receiver = store.watch_entities_receiver();
+
+    let after = upsert_entity(&store, "foo", &[("colour", "blue")]).await;
+
+    let events = drain_events(&mut receiver);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].before.as_deref(), Some(&before));
+    assert_eq!(events[0].after.as_deref(), Some(&after));
+    assert_eq!(events[0].entity_version, after.entity_version);
+}
+
+async fn concurrent_updates_are_serialized<S: ThreadSafeAttributeStore>(store: S) {
+    const UPDATES_PER_TASK: usize = 5;
+
+    create_text_attribute_type(&store, "a").await;
+    create_text_attribute_type(&store, "b").await;
+    upsert_entity(&store, "foo", &[]).await;
+    let mut receiver = store.watch_entities_receiver();
+
+    let store = Arc::new(store);
+    let tasks = ["a", "b"].map(|attribute_type| {
+        let store = store.clone();
+        tokio::spawn(async move {
+            for idx in 0..UPDATES_PER_TASK {
+                upsert_entity(store.as_ref(), "foo", &[(attribute_type, &idx.to_string())]).await;
+            }
+        })
+    });
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let entity = store
+        .get_entity(&EntityLocator::Symbol(symbol("foo")))
+        .await
+        .unwrap();
+    let last_value = AttributeValue::String((UPDATES_PER_TASK - 1).to_string());
+    assert_eq!(entity.attributes.get(&symbol("a")), Some(&last_value));
+    assert_eq!(entity.attributes.get(&symbol("b")), Some(&last_value));
+
+    let events = drain_events(&mut receiver);
+    assert_eq!(events.len(), 2 * UPDATES_PER_TASK);
+    for window in events.windows(2) {
+        assert!(window[0].entity_version < window[1].entity_version);
+        assert_eq!(window[0].after, window[1].before);
+    }
+    assert_eq!(events.last().unwrap().after.as_deref(), Some(&entity));
+}
+
+async fn query_filters_by_attribute_types<S: ThreadSafeAttributeStore>(store: S) {
+    create_text_attribute_type(&store, "colour").await;
+    create_text_attribute_type(&store, "size").await;
+    upsert_entity(&store, "red", &[("colour", "red")]).await;
+    let large_red = upsert_entity(&store, "large-red", &[("colour", "red"), ("size", "L")]).await;
+    upsert_entity(&store, "large", &[("size", "L")]).await;
+
+    let entity_row_query_result = store
+        .query_entity_rows(&EntityRowQuery {
+            root: EntityQueryNode::HasAttributeTypes(HasAttributeTypesNode {
+                attribute_types: vec![symbol("colour"), symbol("size")],
+            }),
+            attribute_types: vec![BootstrapSymbol::SymbolName.into()],
+            include_deleted: false,
+            filter_nulls: false,
+            filter_rows_where_null: vec![],
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(entity_row_query_result.entity_rows.len(), 1);
+    assert_eq!(
+        entity_row_query_result.entity_rows[0].entity_id,
+        large_red.entity_id
+    );
+    assert_eq!(
+        entity_row_query_result.entity_rows[0].values,
+        vec![Some(AttributeValue::String("large-red".into()))]
+    );
+}
+
+async fn snapshot_precedes_live_events<S: ThreadSafeAttributeStore>(store: S) {
+    create_text_attribute_type(&store, "colour").await;
+    let existing = upsert_entity(&store, "existing", &[("colour", "red")]).await;
+
+    // Subscribe before taking the snapshot, as the watch handlers do for `send_initial_events`
+    let mut receiver = store.watch_entities_receiver();
+    let query = EntityQueryNode::HasAttributeTypes(HasAttributeTypesNode {
+        attribute_types: vec![symbol("colour")],
+    });
+    let snapshot = store
+        .query_entities(&EntityQuery {
+            root: query.clone(),
+            include_deleted: false,
+        })
+        .await
+        .unwrap();
+    assert_eq!(snapshot.entities, vec![existing]);
+
+    let live = upsert_entity(&store, "live", &[("colour", "blue")]).await;
+
+    let events = drain_events(&mut receiver);
+    assert_eq!(events.len(), 1);
+    assert!(events[0].entity_version > snapshot.entity_version);
+    assert_eq!(events[0].after.as_deref(), Some(&live));
+}
+
+fn in_memory_store() -> Mutex<InMemoryAttributeStore> {
+    Mutex::new(InMemoryAttributeStore::new())
+}
+
+#[tokio::test]
+async fn in_memory_watch_receives_added_event() {
+    watch_receives_added_event(in_memory_store()).await;
+}
+
+#[tokio::test]
+async fn in_memory_watch_receives_modified_event() {
+    watch_receives_modified_event(in_memory_store()).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn in_memory_concurrent_updates_are_serialized() {
+    concurrent_updates_are_serialized(in_memory_store()).await;
+}
+
+#[tokio::test]
+async fn in_memory_query_filters_by_attribute_types() {
+    query_filters_by_attribute_types(in_memory_store()).await;
+}
+
+#[tokio::test]
+async fn in_memory_snapshot_precedes_live_events() {
+    snapshot_precedes_live_events(in_memory_store()).await;
+}